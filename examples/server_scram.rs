@@ -1,14 +1,11 @@
-use digest::generic_array::GenericArray;
-use digest::{Digest, Output};
 use rsasl::callback::{Context, Request, SessionCallback, SessionData};
-use rsasl::mechanisms::scram;
+use rsasl::mechanisms::scram::credentials::{ScramCredentials, ScramDigest};
 use rsasl::mechanisms::scram::properties::ScramStoredPassword;
 use rsasl::mechname::Mechname;
 use rsasl::prelude::{SASLClient, SASLServer};
 use rsasl::prelude::{SASLConfig, SessionError};
 use rsasl::property::{AuthId, AuthzId};
 use rsasl::validate::{Validate, Validation, ValidationError};
-use sha2::Sha256;
 use std::io::Cursor;
 use thiserror::Error;
 
@@ -16,9 +13,7 @@ use thiserror::Error;
 struct OurCallback {
     // This could also store shared data, e.g. a DB-handle to look up users.
     // It's passed as &self in callbacks.
-    stored_key: Output<Sha256>,
-    server_key: Output<Sha256>,
-    salt: &'static [u8],
+    credentials: ScramCredentials,
 }
 
 #[derive(Debug, Error)]
@@ -71,12 +66,7 @@ impl SessionCallback for OurCallback {
         request: &mut Request,
     ) -> Result<(), SessionError> {
         if context.get_ref::<AuthId>() == Some("username") {
-            request.satisfy::<ScramStoredPassword>(&ScramStoredPassword::new(
-                4096,
-                self.salt,
-                self.stored_key.as_slice(),
-                self.server_key.as_slice(),
-            ))?;
+            request.satisfy::<ScramStoredPassword>(&self.credentials.as_stored_password())?;
         }
         Ok(())
     }
@@ -104,21 +94,11 @@ pub fn main() {
      * As a showcase, we hash & salt the password on startup. You should of course do this at
      * registration time instead.
      */
-    let plain_password = b"secret";
-    let salt = b"bad salt";
-    let mut salted_password = GenericArray::default();
-    // Derive the PBKDF2 key from the password and salt. This is the expensive part
-    scram::tools::hash_password::<Sha256>(plain_password, 4096, &salt[..], &mut salted_password);
-    let (client_key, server_key) = scram::tools::derive_keys::<Sha256>(salted_password.as_slice());
-    let stored_key = Sha256::digest(client_key);
+    let credentials = ScramCredentials::generate(ScramDigest::Sha256, b"secret", 4096);
 
     let config = SASLConfig::builder()
         .with_defaults()
-        .with_callback(OurCallback {
-            stored_key,
-            server_key,
-            salt,
-        })
+        .with_callback(OurCallback { credentials })
         .unwrap();
     let server = SASLServer::<TestValidation>::new(config);
 
@@ -0,0 +1,455 @@
+//! GSSAPI interoperability tests against a throwaway MIT krb5 KDC
+//!
+//! The test creates a realm `EXAMPLE.COM` in a temporary directory with its own `krb5.conf`,
+//! database, keytabs and credential cache, starts `krb5kdc` on a free port and runs the `GSSAPI`
+//! mechanism with every security layer:
+//!
+//! - rsasl client against rsasl server, delegating the client's credentials
+//! - rsasl client against a GNU SASL (`gsasl --server`) server
+//! - GNU SASL (`gsasl --client`) client against rsasl server
+//!
+//! Interoperability with Cyrus SASL is not covered by this test.
+//!
+//! It requires the MIT KDC programs (`kdb5_util`, `kadmin.local`, `krb5kdc`, `kinit`), on Debian
+//! and Ubuntu provided by `krb5-kdc`, `krb5-admin-server` and `krb5-user`. The GNU SASL scenarios
+//! are skipped if `gsasl` is not installed. Run with:
+//!
+//! ```text
+//! cargo test --test gssapi -- --ignored --nocapture
+//! ```
+
+use rsasl::callback::{Context, Request, SessionCallback, SessionData};
+use rsasl::mechanisms::gssapi::properties::{
+    GssCredentialCache, GssDelegateCredentials, GssDelegatedCredential, GssKeytab,
+    GssSecurityLayer, GssService, SecurityLayer,
+};
+use rsasl::mechanisms::gssapi::{StoreCred, GSSAPI};
+use rsasl::prelude::*;
+use rsasl::property::{GssapiDisplayName, Hostname};
+use rsasl::validate::{Validate, ValidationError};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fs};
+
+const REALM: &str = "EXAMPLE.COM";
+const CLIENT: &str = "alice@EXAMPLE.COM";
+const SERVICE: &str = "imap";
+const HOST: &str = "localhost";
+
+static MECHANISMS: [Mechanism; 1] = [GSSAPI];
+
+/// Find `name` in `PATH` or the `sbin` directories the KDC programs are usually installed to
+fn find_program(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::split_paths(&path)
+        .chain(["/usr/sbin", "/usr/local/sbin", "/sbin"].map(PathBuf::from))
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn run(program: &Path, args: &[&str]) {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .unwrap_or_else(|error| panic!("failed to run {}: {error}", program.display()));
+    assert!(
+        output.status.success(),
+        "{} {:?} failed: {}",
+        program.display(),
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// A running KDC for the realm `EXAMPLE.COM`, removed again on drop
+struct Kdc {
+    dir: PathBuf,
+    process: Child,
+    service_keytab: PathBuf,
+    ccache: String,
+}
+
+impl Kdc {
+    /// Set up the realm and start the KDC, returning `None` if MIT krb5 is not installed
+    ///
+    /// The Kerberos environment variables of this process are pointed to the new realm so that
+    /// both libgssapi and spawned programs use it.
+    fn start() -> Option<Self> {
+        let kdb5_util = find_program("kdb5_util")?;
+        let kadmin = find_program("kadmin.local")?;
+        let krb5kdc = find_program("krb5kdc")?;
+        let kinit = find_program("kinit")?;
+
+        let dir = env::temp_dir().join(format!("rsasl-gssapi-interop-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create KDC directory");
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("failed to find a free port")
+            .port();
+
+        let krb5_conf = dir.join("krb5.conf");
+        fs::write(
+            &krb5_conf,
+            format!(
+                "[libdefaults]
+    default_realm = {REALM}
+    dns_lookup_kdc = false
+    dns_lookup_realm = false
+    dns_canonicalize_hostname = false
+    rdns = false
+    forwardable = true
+    udp_preference_limit = 1
+
+[realms]
+    {REALM} = {{
+        kdc = 127.0.0.1:{port}
+    }}
+"
+            ),
+        )
+        .expect("failed to write krb5.conf");
+
+        let kdc_conf = dir.join("kdc.conf");
+        fs::write(
+            &kdc_conf,
+            format!(
+                "[kdcdefaults]
+    kdc_listen = {port}
+    kdc_tcp_listen = {port}
+
+[realms]
+    {REALM} = {{
+        database_name = {dir}/principal
+        key_stash_file = {dir}/stash
+        kdc_listen = {port}
+        kdc_tcp_listen = {port}
+    }}
+
+[logging]
+    kdc = FILE:{dir}/kdc.log
+",
+                dir = dir.display()
+            ),
+        )
+        .expect("failed to write kdc.conf");
+
+        let service_keytab = dir.join("service.keytab");
+        let client_keytab = dir.join("client.keytab");
+        let ccache = format!("FILE:{}", dir.join("ccache").display());
+        env::set_var("KRB5_CONFIG", &krb5_conf);
+        env::set_var("KRB5_KDC_PROFILE", &kdc_conf);
+        env::set_var("KRB5_KTNAME", format!("FILE:{}", service_keytab.display()));
+        env::set_var("KRB5CCNAME", &ccache);
+
+        run(&kdb5_util, &["create", "-s", "-r", REALM, "-P", "master"]);
+        let service = format!("{SERVICE}/{HOST}@{REALM}");
+        for query in [
+            format!("addprinc -randkey {CLIENT}"),
+            format!("addprinc -randkey {service}"),
+            format!("ktadd -k {} {service}", service_keytab.display()),
+            format!("ktadd -k {} {CLIENT}", client_keytab.display()),
+        ] {
+            run(&kadmin, &["-r", REALM, "-q", &query]);
+        }
+
+        let process = Command::new(krb5kdc)
+            .args(["-n", "-r", REALM])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("failed to start krb5kdc");
+        let kdc = Self {
+            dir,
+            process,
+            service_keytab,
+            ccache,
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(Instant::now() < deadline, "krb5kdc did not start listening");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        let client_keytab = client_keytab.display().to_string();
+        run(&kinit, &["-k", "-t", &client_keytab, CLIENT]);
+        Some(kdc)
+    }
+}
+
+impl Drop for Kdc {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The authenticated principal and whether its credentials were delegated
+struct GssValidation;
+impl Validation for GssValidation {
+    type Value = (String, bool);
+}
+
+/// Callback for either side, selecting the credentials of `alice` or the `imap` service
+struct Callback {
+    layer: SecurityLayer,
+    keytab: Option<String>,
+    ccache: Option<String>,
+    delegate: bool,
+}
+
+impl SessionCallback for Callback {
+    fn callback(
+        &self,
+        _session_data: &SessionData,
+        _context: &Context,
+        request: &mut Request<'_>,
+    ) -> Result<(), SessionError> {
+        request
+            .satisfy::<GssService>(SERVICE)?
+            .satisfy::<Hostname>(HOST)?
+            .satisfy::<GssSecurityLayer>(&self.layer)?
+            .satisfy::<GssDelegateCredentials>(&self.delegate)?;
+        if let Some(ref ccache) = self.ccache {
+            request.satisfy::<GssCredentialCache>(ccache)?;
+        }
+        if let Some(ref keytab) = self.keytab {
+            request.satisfy::<GssKeytab>(keytab)?;
+        }
+        Ok(())
+    }
+
+    fn validate(
+        &self,
+        _session_data: &SessionData,
+        context: &Context,
+        validate: &mut Validate<'_>,
+    ) -> Result<(), ValidationError> {
+        let name = context
+            .get_ref::<GssapiDisplayName>()
+            .ok_or(ValidationError::MissingRequiredProperty)?;
+        // The acceptor credentials are read from a keytab, so delegated credentials are owned by
+        // a `StoreCred`
+        let delegated = context
+            .get_ref::<GssDelegatedCredential>()
+            .and_then(|cell| cell.take())
+            .map(|credential| credential.downcast::<StoreCred>().is_ok());
+        validate.finalize::<GssValidation>((name.to_string(), delegated.unwrap_or(false)));
+        Ok(())
+    }
+}
+
+fn config(callback: Callback) -> Arc<SASLConfig> {
+    SASLConfig::builder()
+        .with_registry(Registry::with_mechanisms(&MECHANISMS))
+        .with_callback(callback)
+        .expect("failed to build SASL config")
+}
+
+fn client_config(kdc: &Kdc, layer: SecurityLayer, delegate: bool) -> Arc<SASLConfig> {
+    config(Callback {
+        layer,
+        keytab: None,
+        ccache: Some(kdc.ccache.clone()),
+        delegate,
+    })
+}
+
+fn server_config(kdc: &Kdc, layer: SecurityLayer) -> Arc<SASLConfig> {
+    config(Callback {
+        layer,
+        keytab: Some(format!("FILE:{}", kdc.service_keytab.display())),
+        ccache: None,
+        delegate: false,
+    })
+}
+
+fn mechname() -> &'static Mechname {
+    Mechname::parse(b"GSSAPI").unwrap()
+}
+
+fn has_layer(layer: SecurityLayer) -> bool {
+    !layer.contains(SecurityLayer::NO_SECURITY_LAYER)
+}
+
+fn roundtrip<V: Validation, W: Validation>(from: &mut Session<V>, to: &mut Session<W>) {
+    let mut wrapped = Vec::new();
+    from.encode(b"interop data", &mut wrapped)
+        .expect("failed to encode");
+    let mut unwrapped = Vec::new();
+    to.decode(&wrapped, &mut unwrapped)
+        .expect("failed to decode");
+    assert_eq!(unwrapped, b"interop data");
+}
+
+fn rsasl_rsasl(kdc: &Kdc, layer: SecurityLayer) {
+    let mut client = SASLClient::new(client_config(kdc, layer, true))
+        .start_suggested(&[mechname()])
+        .expect("failed to start client");
+    let mut server = SASLServer::<GssValidation>::new(server_config(kdc, layer))
+        .start_suggested(mechname())
+        .expect("failed to start server");
+
+    let mut client_out = Vec::new();
+    client.step(None, &mut client_out).expect("client failed");
+    loop {
+        let mut server_out = Vec::new();
+        let state = server
+            .step(Some(&client_out), &mut server_out)
+            .expect("server failed");
+        if state.is_finished() {
+            break;
+        }
+        client_out.clear();
+        client
+            .step(Some(&server_out), &mut client_out)
+            .expect("client failed");
+    }
+
+    assert_eq!(server.validation(), Some((CLIENT.to_string(), true)));
+    assert_eq!(client.has_security_layer(), has_layer(layer));
+    assert_eq!(server.has_security_layer(), has_layer(layer));
+    if has_layer(layer) {
+        roundtrip(&mut client, &mut server);
+        roundtrip(&mut server, &mut client);
+    }
+}
+
+/// A `gsasl` process exchanging base64 encoded tokens line by line on stdin and stdout
+struct Gsasl {
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Gsasl {
+    fn spawn(program: &Path, side: &str, layer: SecurityLayer) -> Self {
+        let qop = if layer.contains(SecurityLayer::CONFIDENTIALITY) {
+            "qop-conf"
+        } else if layer.contains(SecurityLayer::INTEGRITY) {
+            "qop-int"
+        } else {
+            "qop-auth"
+        };
+        let mut process = Command::new(program)
+            .args([
+                side,
+                "--quiet",
+                "--mechanism=GSSAPI",
+                &format!("--service={SERVICE}"),
+                &format!("--hostname={HOST}"),
+                &format!("--quality-of-protection={qop}"),
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start gsasl");
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        Self {
+            process,
+            stdin,
+            stdout,
+        }
+    }
+
+    fn send(&mut self, line: &[u8]) {
+        self.stdin
+            .write_all(line)
+            .expect("failed to write to gsasl");
+        self.stdin
+            .write_all(b"\n")
+            .expect("failed to write to gsasl");
+        self.stdin.flush().expect("failed to write to gsasl");
+    }
+
+    fn receive(&mut self) -> String {
+        let mut line = String::new();
+        self.stdout
+            .read_line(&mut line)
+            .expect("failed to read from gsasl");
+        line.trim_end().to_string()
+    }
+
+    fn finish(mut self) {
+        drop(self.stdin);
+        let status = self.process.wait().expect("failed to wait for gsasl");
+        assert!(status.success(), "gsasl exited with {status}");
+    }
+}
+
+fn rsasl_gsasl(kdc: &Kdc, gsasl: &Path, layer: SecurityLayer) {
+    let mut client = SASLClient::new(client_config(kdc, layer, false))
+        .start_suggested(&[mechname()])
+        .expect("failed to start client");
+    let mut server = Gsasl::spawn(gsasl, "--server", layer);
+
+    let mut input = None;
+    loop {
+        let mut out = Vec::new();
+        let state = client
+            .step64(input.as_deref().map(str::as_bytes), &mut out)
+            .expect("client failed");
+        server.send(&out);
+        if !state.is_running() {
+            break;
+        }
+        input = Some(server.receive());
+    }
+    assert_eq!(client.has_security_layer(), has_layer(layer));
+    server.finish();
+}
+
+fn gsasl_rsasl(kdc: &Kdc, gsasl: &Path, layer: SecurityLayer) {
+    let mut server = SASLServer::<GssValidation>::new(server_config(kdc, layer))
+        .start_suggested(mechname())
+        .expect("failed to start server");
+    let mut client = Gsasl::spawn(gsasl, "--client", layer);
+
+    loop {
+        let input = client.receive();
+        let mut out = Vec::new();
+        let state = server
+            .step64(Some(input.as_bytes()), &mut out)
+            .expect("server failed");
+        if !state.is_running() {
+            break;
+        }
+        client.send(&out);
+    }
+    assert_eq!(server.validation(), Some((CLIENT.to_string(), false)));
+    assert_eq!(server.has_security_layer(), has_layer(layer));
+    client.finish();
+}
+
+#[test]
+#[ignore = "requires the MIT krb5 KDC programs"]
+fn gssapi_interop() {
+    let Some(kdc) = Kdc::start() else {
+        panic!(
+            "MIT krb5 KDC programs not found, install krb5-kdc, krb5-admin-server and krb5-user"
+        );
+    };
+    let gsasl = find_program("gsasl");
+    if gsasl.is_none() {
+        eprintln!("gsasl not found, skipping GNU SASL scenarios");
+    }
+
+    for layer in [
+        SecurityLayer::NO_SECURITY_LAYER,
+        SecurityLayer::INTEGRITY,
+        SecurityLayer::CONFIDENTIALITY,
+    ] {
+        rsasl_rsasl(&kdc, layer);
+        if let Some(ref gsasl) = gsasl {
+            rsasl_gsasl(&kdc, gsasl, layer);
+            gsasl_rsasl(&kdc, gsasl, layer);
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use crate::alloc::sync::Arc;
+#[cfg(feature = "async")]
+use crate::callback::AsyncSessionCallback;
 use crate::callback::SessionCallback;
 use crate::config::SASLConfig;
 use crate::error::SASLError;
+#[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+use crate::mechanisms::scram::cache::ScramKeyCache;
 use crate::registry::Registry;
 use core::fmt::{Debug, Formatter};
 
@@ -101,18 +105,18 @@ impl ConfigBuilder {
     /// Use the default configuration for each state and only provide a custom callback
     #[must_use]
     pub fn with_defaults(self) -> ConfigBuilder<WantCallback> {
-        ConfigBuilder {
-            state: WantCallback {
-                mechanisms: Registry::default(),
-            },
-        }
+        self.with_registry(Registry::default())
     }
 
     /// Use a pre-initialized mechanism registry, giving the most control over available mechanisms
     #[must_use]
     pub const fn with_registry(self, mechanisms: Registry) -> ConfigBuilder<WantCallback> {
         ConfigBuilder {
-            state: WantCallback { mechanisms },
+            state: WantCallback {
+                mechanisms,
+                #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+                scram_key_cache: None,
+            },
         }
     }
 
@@ -134,13 +138,50 @@ impl ConfigBuilder {
 #[doc(hidden)]
 pub struct WantCallback {
     mechanisms: Registry,
+    #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+    scram_key_cache: Option<Arc<ScramKeyCache>>,
 }
 impl ConfigBuilder<WantCallback> {
+    #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+    #[must_use]
+    /// Share a cache of derived SCRAM keys between all server sessions using this config
+    ///
+    /// See the [`cache`](crate::mechanisms::scram::cache) module for details.
+    pub fn with_scram_key_cache(mut self, cache: Arc<ScramKeyCache>) -> Self {
+        self.state.scram_key_cache = Some(cache);
+        self
+    }
+
     /// Install a callback for querying properties
     pub fn with_callback<CB: SessionCallback + 'static>(
         self,
         callback: CB,
     ) -> Result<Arc<SASLConfig>, SASLError> {
+        #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+        if let Some(cache) = self.state.scram_key_cache {
+            return SASLConfig::new_with_scram_key_cache(callback, self.state.mechanisms, cache);
+        }
         SASLConfig::new(callback, self.state.mechanisms)
     }
+
+    #[cfg(feature = "async")]
+    /// Install a callback that may answer requests asynchronously
+    ///
+    /// *requires feature `async`*
+    ///
+    /// Sessions using this config answer requests deferred by the callback when stepped with
+    /// [`Session::step_async`](crate::prelude::Session::step_async). Since every
+    /// [`SessionCallback`] is also an [`AsyncSessionCallback`] synchronous callbacks can be
+    /// installed using this method as well.
+    pub fn with_async_callback<CB: AsyncSessionCallback + 'static>(
+        self,
+        callback: CB,
+    ) -> Result<Arc<SASLConfig>, SASLError> {
+        SASLConfig::new_async(
+            callback,
+            self.state.mechanisms,
+            #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+            self.state.scram_key_cache,
+        )
+    }
 }
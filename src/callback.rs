@@ -15,9 +15,15 @@ use crate::typed::{tags, Erased, Tagged};
 use crate::validate::{Validate, ValidationError};
 
 // Re-Exports
+#[cfg(feature = "async")]
+use crate::alloc::boxed::Box;
 pub use crate::context::Context;
+#[cfg(any(feature = "provider", feature = "testutils", test))]
+pub use crate::deferred::{OwnedContext, PropertyRequest, ValidationRequest};
 use crate::registry::Mechanism;
 pub use crate::session::SessionData;
+#[cfg(feature = "async")]
+use core::{future::Future, pin::Pin};
 
 pub trait SessionCallback: Send + Sync {
     /// Answer requests by mechanism implementation for some Properties
@@ -140,6 +146,183 @@ pub trait SessionCallback: Send + Sync {
     }
 }
 
+#[derive(Debug, Copy, Clone, Default)]
+/// A callback deferring every request and validation
+///
+/// With this callback installed [`Session::step_resumable`](crate::prelude::Session::step_resumable)
+/// hands out all requests of a mechanism to the protocol implementation instead of answering them
+/// inline. Actions are not handled.
+#[allow(clippy::exhaustive_structs)]
+pub struct DeferAll;
+impl SessionCallback for DeferAll {
+    fn callback(
+        &self,
+        _session_data: &SessionData,
+        _context: &Context,
+        _request: &mut Request,
+    ) -> Result<(), SessionError> {
+        Err(CallbackError::Deferred.into())
+    }
+
+    fn validate(
+        &self,
+        _session_data: &SessionData,
+        _context: &Context,
+        _validate: &mut Validate<'_>,
+    ) -> Result<(), ValidationError> {
+        Err(ValidationError::Deferred)
+    }
+}
+
+#[cfg(feature = "async")]
+/// Future returned by the asynchronous methods of [`AsyncSessionCallback`]
+pub type CallbackFuture<'a, E = SessionError> =
+    Pin<Box<dyn Future<Output = Result<(), E>> + Send + 'a>>;
+
+#[cfg(feature = "async")]
+/// A callback answering requests asynchronously
+///
+/// *requires feature `async`*
+///
+/// The synchronous methods of this trait are called by the mechanism like the ones of
+/// [`SessionCallback`]. They can answer requests directly, or defer them by returning
+/// [`CallbackError::Deferred`] or [`ValidationError::Deferred`], which is what their default
+/// implementations do. A session stepped with
+/// [`Session::step_async`](crate::prelude::Session::step_async) then suspends the mechanism,
+/// awaits [`callback_async`](Self::callback_async) or [`validate_async`](Self::validate_async)
+/// and resumes the step with the answer. This allows e.g. looking up SCRAM credentials or
+/// introspecting OAuth tokens over the network without blocking.
+///
+/// Once a deferred request was answered the step is run again from its start. Actions and a
+/// validation the synchronous methods already handled during the step are not passed to them a
+/// second time, but requests for property values they answered are issued again. Answering
+/// those should therefore not have side effects.
+///
+/// ```rust
+/// # use rsasl::callback::{AsyncSessionCallback, CallbackFuture, PropertyRequest, SessionData};
+/// # use rsasl::property::{AuthId, Password};
+/// # struct Database;
+/// # impl Database {
+/// # async fn password(&self, authid: &str) -> Vec<u8> { unimplemented!() }
+/// # }
+/// struct Callback {
+///     database: Database,
+/// }
+/// impl AsyncSessionCallback for Callback {
+///     fn callback_async<'a>(
+///         &'a self,
+///         _session_data: &'a SessionData,
+///         request: &'a mut PropertyRequest,
+///     ) -> CallbackFuture<'a> {
+///         Box::pin(async move {
+///             if request.is::<Password>() {
+///                 if let Some(authid) = request.context().get_ref::<AuthId>() {
+///                     let password = self.database.password(authid).await;
+///                     request.satisfy::<Password, _>(password);
+///                 }
+///             }
+///             Ok(())
+///         })
+///     }
+/// }
+/// ```
+///
+/// All [`SessionCallback`]s implement this trait by answering every request synchronously.
+pub trait AsyncSessionCallback: Send + Sync {
+    /// Answer or defer requests by mechanism implementation for some Properties
+    ///
+    /// This behaves like [`SessionCallback::callback`]. The default implementation defers all
+    /// requests. Only requests for a value can be deferred; deferred 'Actionable' requests are
+    /// treated as if the callback did not handle them.
+    fn callback(
+        &self,
+        session_data: &SessionData,
+        context: &Context,
+        request: &mut Request,
+    ) -> Result<(), SessionError> {
+        let _ = (session_data, context, request);
+        Err(CallbackError::Deferred.into())
+    }
+
+    /// Answer a request deferred by [`callback`](Self::callback)
+    ///
+    /// Values are provided using [`PropertyRequest::satisfy`]. If the request is not satisfied
+    /// it is treated as if the callback did not answer it.
+    fn callback_async<'a>(
+        &'a self,
+        session_data: &'a SessionData,
+        request: &'a mut PropertyRequest,
+    ) -> CallbackFuture<'a> {
+        let _ = (session_data, request);
+        Box::pin(core::future::ready(Ok(())))
+    }
+
+    /// See [`SessionCallback::enable_channel_binding`]
+    fn enable_channel_binding(&self) -> bool {
+        false
+    }
+
+    /// See [`SessionCallback::prefer`]
+    fn prefer<'a>(&self, a: Option<&'a Mechanism>, b: &'a Mechanism) -> Ordering {
+        a.map_or(Ordering::Less, |old| old.priority.cmp(&b.priority))
+    }
+
+    /// Validate or defer validating an authentication exchange
+    ///
+    /// This behaves like [`SessionCallback::validate`]. The default implementation defers the
+    /// validation.
+    fn validate(
+        &self,
+        session_data: &SessionData,
+        context: &Context,
+        validate: &mut Validate<'_>,
+    ) -> Result<(), ValidationError> {
+        let _ = (session_data, context, validate);
+        Err(ValidationError::Deferred)
+    }
+
+    /// Validate an authentication exchange deferred by [`validate`](Self::validate)
+    ///
+    /// The outcome is provided using [`ValidationRequest::finalize`].
+    fn validate_async<'a>(
+        &'a self,
+        session_data: &'a SessionData,
+        validate: &'a mut ValidationRequest,
+    ) -> CallbackFuture<'a, ValidationError> {
+        let _ = (session_data, validate);
+        Box::pin(core::future::ready(Ok(())))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: SessionCallback + ?Sized> AsyncSessionCallback for T {
+    fn callback(
+        &self,
+        session_data: &SessionData,
+        context: &Context,
+        request: &mut Request,
+    ) -> Result<(), SessionError> {
+        SessionCallback::callback(self, session_data, context, request)
+    }
+
+    fn enable_channel_binding(&self) -> bool {
+        SessionCallback::enable_channel_binding(self)
+    }
+
+    fn prefer<'a>(&self, a: Option<&'a Mechanism>, b: &'a Mechanism) -> Ordering {
+        SessionCallback::prefer(self, a, b)
+    }
+
+    fn validate(
+        &self,
+        session_data: &SessionData,
+        context: &Context,
+        validate: &mut Validate<'_>,
+    ) -> Result<(), ValidationError> {
+        SessionCallback::validate(self, session_data, context, validate)
+    }
+}
+
 #[doc(hidden)]
 #[derive(Debug)]
 pub struct TOKEN(PhantomData<()>);
@@ -198,6 +381,14 @@ pub enum CallbackError {
     NoValue,
     #[error("callback does not handle property {0}")]
     NoCallback(&'static str),
+    /// The callback defers answering the request
+    ///
+    /// Callbacks that answer requests asynchronously return this error to have the request
+    /// passed to `AsyncSessionCallback::callback_async`, or handed out by `Session::step_resumable`.
+    /// If the session is stepped with `step` the request is treated as if the callback did not
+    /// answer it.
+    #[error("callback deferred answering the request")]
+    Deferred,
 
     #[doc(hidden)]
     #[error("callback issued early return")]
@@ -506,4 +697,6 @@ impl<'a> Request<'a> {
 #[cfg(test)]
 mod test {
     static_assertions::assert_obj_safe!(super::Erased, super::SessionCallback);
+    #[cfg(feature = "async")]
+    static_assertions::assert_obj_safe!(super::AsyncSessionCallback);
 }
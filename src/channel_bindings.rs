@@ -12,6 +12,18 @@ pub trait ChannelBindingCallback {
     fn get_cb_data(&self, cbname: &str) -> Option<&[u8]>;
 }
 
+/// Channel binding types defined for TLS
+#[cfg(any(feature = "provider", test))]
+const TLS_CHANNEL_BINDINGS: [&str; 3] = ["tls-exporter", "tls-unique", "tls-server-end-point"];
+
+/// Returns true if `cb` provides data for any of the channel binding types defined for TLS
+#[cfg(any(feature = "provider", test))]
+pub fn has_channel_bindings(cb: &dyn ChannelBindingCallback) -> bool {
+    TLS_CHANNEL_BINDINGS
+        .iter()
+        .any(|cbname| cb.get_cb_data(cbname).is_some())
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct NoChannelBindings;
@@ -1,6 +1,8 @@
 //! Configuration supplied by the downstream user
 
 use crate::alloc::boxed::Box;
+#[cfg(feature = "async")]
+use crate::callback::AsyncSessionCallback;
 use crate::callback::SessionCallback;
 use crate::error::SASLError;
 use crate::registry::{Mechanism, MechanismIter};
@@ -11,17 +13,25 @@ use core::fmt;
 pub use crate::builder::ConfigBuilder;
 use crate::mechanism::Authentication;
 use crate::mechname::Mechname;
+use crate::policy::SecurityPolicy;
+#[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+use crate::{alloc::sync::Arc, mechanisms::scram::cache::ScramKeyCache};
 
 trait ConfigInstance: fmt::Debug + Send + Sync {
     fn get_mech_iter<'a>(&self) -> MechanismIter<'a>;
     #[allow(dead_code)]
     fn get_callback(&self) -> &dyn SessionCallback;
+    #[cfg(feature = "async")]
+    fn get_async_callback(&self) -> Option<&dyn AsyncSessionCallback>;
     #[allow(dead_code)]
     fn select(
         &self,
         cb: bool,
         offered: &mut dyn Iterator<Item = &Mechname>,
+        policy: SecurityPolicy,
     ) -> Result<(Box<dyn Authentication>, &'static Mechanism), SASLError>;
+    #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+    fn scram_key_cache(&self) -> Option<&Arc<ScramKeyCache>>;
 }
 
 #[repr(transparent)]
@@ -48,26 +58,37 @@ impl fmt::Debug for SASLConfig {
 
 #[cfg(any(test, feature = "provider", feature = "testutils"))]
 mod provider {
-    use super::{Mechanism, SASLConfig, SASLError, SessionCallback};
+    use super::{Mechanism, SASLConfig, SASLError, SecurityPolicy, SessionCallback};
     use crate::alloc::boxed::Box;
+    #[cfg(feature = "async")]
+    use crate::callback::AsyncSessionCallback;
     use crate::mechanism::Authentication;
     use crate::mechname::Mechname;
 
     impl SASLConfig {
         #[inline(always)]
-        /// Select the best mechanism of the offered ones.
+        /// Select the best mechanism of the offered ones allowed by `policy`.
+        ///
+        /// `cb` indicates if channel binding data is available.
         pub(crate) fn select_mechanism<'a>(
             &self,
             offered: impl IntoIterator<Item = &'a Mechname>,
+            cb: bool,
+            policy: SecurityPolicy,
         ) -> Result<(Box<dyn Authentication>, &'static Mechanism), SASLError> {
-            let cb = self.get_callback().enable_channel_binding();
-            self.inner.select(cb, &mut offered.into_iter())
+            self.inner.select(cb, &mut offered.into_iter(), policy)
         }
 
         #[inline(always)]
         pub(crate) fn get_callback(&self) -> &dyn SessionCallback {
             self.inner.get_callback()
         }
+
+        #[cfg(feature = "async")]
+        #[inline(always)]
+        pub(crate) fn get_async_callback(&self) -> Option<&dyn AsyncSessionCallback> {
+            self.inner.get_async_callback()
+        }
     }
 }
 
@@ -77,22 +98,42 @@ impl SASLConfig {
     pub(crate) fn mech_list<'a>(&self) -> impl Iterator<Item = &'a Mechanism> {
         self.inner.get_mech_iter()
     }
+
+    #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+    #[must_use]
+    /// The SCRAM key cache shared by all server sessions using this config, if one was installed
+    ///
+    /// This can be used to invalidate cached keys when the password of an user changes.
+    pub fn scram_key_cache(&self) -> Option<&Arc<ScramKeyCache>> {
+        self.inner.scram_key_cache()
+    }
 }
 
 #[cfg(feature = "config_builder")]
 mod instance {
-    use super::{ConfigInstance, Mechanism, MechanismIter, SASLConfig, SASLError, SessionCallback};
+    use super::{
+        ConfigInstance, Mechanism, MechanismIter, SASLConfig, SASLError, SecurityPolicy,
+        SessionCallback,
+    };
     use crate::alloc::{boxed::Box, string::String, sync::Arc};
     pub use crate::builder::ConfigBuilder;
+    #[cfg(feature = "async")]
+    use crate::callback::AsyncSessionCallback;
     use crate::callback::Request;
     use crate::context::Context;
     use crate::core::fmt;
     use crate::error::SessionError;
     use crate::mechanism::Authentication;
+    #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+    use crate::mechanisms::scram::cache::ScramKeyCache;
     use crate::mechname::Mechname;
     use crate::property::{AuthId, AuthzId, Password};
     use crate::registry::Registry;
     use crate::session::SessionData;
+    #[cfg(feature = "async")]
+    use crate::validate::{Validate, ValidationError};
+    #[cfg(feature = "async")]
+    use core::cmp::Ordering;
 
     impl SASLConfig {
         fn cast(arc: Arc<dyn ConfigInstance>) -> Arc<Self> {
@@ -108,6 +149,37 @@ mod instance {
             Ok(Self::cast(outer))
         }
 
+        #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+        pub(crate) fn new_with_scram_key_cache<CB: SessionCallback + 'static>(
+            callback: CB,
+            mechanisms: Registry,
+            scram_key_cache: Arc<ScramKeyCache>,
+        ) -> Result<Arc<Self>, SASLError> {
+            let mut inner = Inner::new(callback, mechanisms)?;
+            inner.scram_key_cache = Some(scram_key_cache);
+            let outer = Arc::new(inner) as Arc<dyn ConfigInstance>;
+            Ok(Self::cast(outer))
+        }
+
+        #[cfg(feature = "async")]
+        pub(crate) fn new_async<CB: AsyncSessionCallback + 'static>(
+            callback: CB,
+            mechanisms: Registry,
+            #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))] scram_key_cache: Option<
+                Arc<ScramKeyCache>,
+            >,
+        ) -> Result<Arc<Self>, SASLError> {
+            let callback = Arc::new(callback);
+            let mut inner = Inner::new(Synchronous(callback.clone()), mechanisms)?;
+            inner.async_callback = Some(callback);
+            #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+            {
+                inner.scram_key_cache = scram_key_cache;
+            }
+            let outer = Arc::new(inner) as Arc<dyn ConfigInstance>;
+            Ok(Self::cast(outer))
+        }
+
         /// Construct a config from a linker-friendly builder
         #[must_use]
         pub const fn builder() -> ConfigBuilder {
@@ -168,19 +240,57 @@ mod instance {
         }
     }
 
+    #[cfg(feature = "async")]
+    /// The synchronous part of an [`AsyncSessionCallback`], used by mechanisms
+    struct Synchronous<CB>(Arc<CB>);
+    #[cfg(feature = "async")]
+    impl<CB: AsyncSessionCallback> SessionCallback for Synchronous<CB> {
+        fn callback(
+            &self,
+            session_data: &SessionData,
+            context: &Context,
+            request: &mut Request,
+        ) -> Result<(), SessionError> {
+            self.0.callback(session_data, context, request)
+        }
+
+        fn enable_channel_binding(&self) -> bool {
+            self.0.enable_channel_binding()
+        }
+
+        fn prefer<'a>(&self, a: Option<&'a Mechanism>, b: &'a Mechanism) -> Ordering {
+            self.0.prefer(a, b)
+        }
+
+        fn validate(
+            &self,
+            session_data: &SessionData,
+            context: &Context,
+            validate: &mut Validate<'_>,
+        ) -> Result<(), ValidationError> {
+            self.0.validate(session_data, context, validate)
+        }
+    }
+
     struct Inner {
         cb: bool,
         #[allow(dead_code)]
         callback: Box<dyn SessionCallback>,
+        #[cfg(feature = "async")]
+        async_callback: Option<Arc<dyn AsyncSessionCallback>>,
         mechanisms: Registry,
+        #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+        scram_key_cache: Option<Arc<ScramKeyCache>>,
     }
 
     impl fmt::Debug for Inner {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            f.debug_struct("SASLConfig")
-                .field("channel_binding", &self.cb)
-                .field("mechanisms", &self.mechanisms)
-                .finish_non_exhaustive()
+            let mut s = f.debug_struct("SASLConfig");
+            s.field("channel_binding", &self.cb)
+                .field("mechanisms", &self.mechanisms);
+            #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+            s.field("scram_key_cache", &self.scram_key_cache);
+            s.finish_non_exhaustive()
         }
     }
 
@@ -194,7 +304,11 @@ mod instance {
             Ok(Self {
                 cb: false, // FIXME!
                 callback: Box::new(callback),
+                #[cfg(feature = "async")]
+                async_callback: None,
                 mechanisms,
+                #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+                scram_key_cache: None,
             })
         }
     }
@@ -208,15 +322,27 @@ mod instance {
             self.callback.as_ref()
         }
 
+        #[cfg(feature = "async")]
+        fn get_async_callback(&self) -> Option<&dyn AsyncSessionCallback> {
+            self.async_callback.as_deref()
+        }
+
         fn select(
             &self,
             cb: bool,
             offered: &mut dyn Iterator<Item = &Mechname>,
+            policy: SecurityPolicy,
         ) -> Result<(Box<dyn Authentication>, &'static Mechanism), SASLError> {
             let callback = self.get_callback();
-            self.mechanisms.select(cb | self.cb, offered, |acc, mech| {
-                callback.prefer(acc, mech)
-            })
+            self.mechanisms
+                .select(cb | self.cb, offered, policy, |acc, mech| {
+                    callback.prefer(acc, mech)
+                })
+        }
+
+        #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+        fn scram_key_cache(&self) -> Option<&Arc<ScramKeyCache>> {
+            self.scram_key_cache.as_ref()
         }
     }
 }
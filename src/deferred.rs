@@ -0,0 +1,351 @@
+//! Requests deferred by a user callback
+//!
+//! A callback may defer answering a request or validating an exchange by returning
+//! [`CallbackError::Deferred`] or [`ValidationError::Deferred`]. The mechanism step is then
+//! suspended and the request is handed out as a [`PropertyRequest`] or [`ValidationRequest`].
+//! Since the values a mechanism provides to callbacks are borrowed from the step they are copied
+//! into an [`OwnedContext`] beforehand. The step is retried once the request was answered.
+
+use crate::alloc::{borrow::ToOwned, boxed::Box, string::String, vec::Vec};
+use crate::callback::{CallbackError, Request};
+use crate::context::{build_context, Context, Demand, DemandReply, Provider};
+use crate::error::SessionError;
+use crate::property::{
+    AuthId, AuthzId, ChannelBindingName, GssapiDisplayName, Hostname, OAuthBearerToken, Password,
+    Property, Realm, Service,
+};
+use crate::validate::{Validate, Validation};
+use core::any::{type_name, Any, TypeId};
+use core::borrow::Borrow;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+use core::marker::PhantomData;
+
+trait CapturedValue: Send + Sync {
+    fn provide<'a>(&'a self, req: &mut Demand<'a>) -> DemandReply<()>;
+}
+
+struct CapturedStr<P>(String, PhantomData<fn() -> P>);
+impl<P: for<'p> Property<'p, Value = str>> CapturedValue for CapturedStr<P> {
+    fn provide<'a>(&'a self, req: &mut Demand<'a>) -> DemandReply<()> {
+        req.provide_ref::<P>(self.0.as_str())?.done()
+    }
+}
+
+struct CapturedBytes<P>(Vec<u8>, PhantomData<fn() -> P>);
+impl<P: for<'p> Property<'p, Value = [u8]>> CapturedValue for CapturedBytes<P> {
+    fn provide<'a>(&'a self, req: &mut Demand<'a>) -> DemandReply<()> {
+        req.provide_ref::<P>(self.0.as_slice())?.done()
+    }
+}
+
+/// An owned copy of the values a mechanism provided to a deferred request
+///
+/// Only values of commonly used properties are copied; these are [`AuthId`], [`AuthzId`],
+/// [`Password`], [`Realm`], [`Hostname`], [`Service`], [`OAuthBearerToken`],
+/// [`ChannelBindingName`] and [`GssapiDisplayName`], as well as the `AnonymousToken` and
+/// `ScramMandatoryExtension` of the respective mechanisms.
+#[derive(Default)]
+pub struct OwnedContext {
+    values: Vec<Box<dyn CapturedValue>>,
+}
+
+impl OwnedContext {
+    pub(crate) fn capture(context: &Context) -> Self {
+        let mut this = Self::default();
+        this.capture_str::<AuthId>(context);
+        this.capture_str::<AuthzId>(context);
+        this.capture_bytes::<Password>(context);
+        this.capture_str::<Realm>(context);
+        this.capture_str::<Hostname>(context);
+        this.capture_str::<Service>(context);
+        this.capture_str::<OAuthBearerToken>(context);
+        this.capture_str::<ChannelBindingName>(context);
+        this.capture_str::<GssapiDisplayName>(context);
+        #[cfg(feature = "anonymous")]
+        this.capture_str::<crate::mechanisms::anonymous::AnonymousToken>(context);
+        #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+        this.capture_str::<crate::mechanisms::scram::properties::ScramMandatoryExtension>(context);
+        this
+    }
+
+    fn capture_str<P: for<'p> Property<'p, Value = str>>(&mut self, context: &Context) {
+        if let Some(value) = context.get_ref::<P>() {
+            self.values
+                .push(Box::new(CapturedStr::<P>(value.to_owned(), PhantomData)));
+        }
+    }
+
+    fn capture_bytes<P: for<'p> Property<'p, Value = [u8]>>(&mut self, context: &Context) {
+        if let Some(value) = context.get_ref::<P>() {
+            self.values
+                .push(Box::new(CapturedBytes::<P>(value.to_owned(), PhantomData)));
+        }
+    }
+
+    /// Query the value of a given Property
+    ///
+    /// This behaves like [`Context::get_ref`], but returns `None` for properties that were not
+    /// copied.
+    #[must_use]
+    pub fn get_ref<'a, P: Property<'a>>(&'a self) -> Option<&'a P::Value> {
+        build_context(&Captured(self)).get_ref::<P>()
+    }
+}
+
+impl fmt::Debug for OwnedContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OwnedContext").finish_non_exhaustive()
+    }
+}
+
+struct Captured<'a>(&'a OwnedContext);
+impl<'a> Provider<'a> for Captured<'a> {
+    fn provide(&self, req: &mut Demand<'a>) -> DemandReply<()> {
+        for value in &self.0.values {
+            value.provide(req)?;
+        }
+        req.done()
+    }
+}
+
+type Answer = Box<dyn Fn(&mut Request<'_>) -> Result<(), SessionError> + Send + Sync>;
+
+/// A request for a property value deferred by the user callback
+///
+/// The value is provided with [`satisfy`](Self::satisfy) or [`satisfy_with`](Self::satisfy_with)
+/// and will be used when the suspended step is resumed. A request that is not satisfied is
+/// treated as if the callback did not answer it.
+pub struct PropertyRequest {
+    property: TypeId,
+    name: &'static str,
+    context: OwnedContext,
+    answer: Option<Answer>,
+}
+
+impl PropertyRequest {
+    pub(crate) fn new<P: Property<'static>>(context: &Context) -> Self {
+        Self {
+            property: TypeId::of::<P>(),
+            name: type_name::<P>(),
+            context: OwnedContext::capture(context),
+            answer: None,
+        }
+    }
+
+    /// Returns true if this request is for the Property `P`
+    #[must_use]
+    pub fn is<'a, P: Property<'a>>(&self) -> bool {
+        self.property == TypeId::of::<P>()
+    }
+
+    /// The type name of the requested Property, for use in diagnostics
+    #[must_use]
+    pub const fn property_name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns true if a value was provided for this request
+    #[must_use]
+    pub const fn is_satisfied(&self) -> bool {
+        self.answer.is_some()
+    }
+
+    /// The values the mechanism provided alongside the request
+    #[must_use]
+    pub const fn context(&self) -> &OwnedContext {
+        &self.context
+    }
+
+    /// Satisfy the request with an owned value
+    ///
+    /// Like [`Request::satisfy`] this does nothing if the request is not for the property `P` or
+    /// was already satisfied, so calls can be chained.
+    ///
+    /// ```rust
+    /// # use rsasl::callback::PropertyRequest;
+    /// # use rsasl::property::{AuthId, Password};
+    /// # fn example(request: &mut PropertyRequest) {
+    /// request
+    ///     .satisfy::<AuthId, _>(String::from("alice"))
+    ///     .satisfy::<Password, _>(b"secret".to_vec());
+    /// # }
+    /// ```
+    pub fn satisfy<P, O>(&mut self, answer: O) -> &mut Self
+    where
+        P: for<'p> Property<'p>,
+        O: for<'p> Borrow<<P as Property<'p>>::Value> + Send + Sync + 'static,
+    {
+        self.satisfy_with::<P, _>(move |request| {
+            request.satisfy::<P>(answer.borrow())?;
+            Ok(())
+        })
+    }
+
+    /// Satisfy the request using the provided closure
+    ///
+    /// The closure is called with the [`Request`] issued by the mechanism when the step is
+    /// resumed. This allows answering properties with borrowed values that can not be expressed
+    /// as an owned type, e.g. `ScramStoredPassword`. Like [`satisfy`](Self::satisfy) this does
+    /// nothing if the request is not for the property `P` or was already satisfied.
+    pub fn satisfy_with<P, F>(&mut self, answer: F) -> &mut Self
+    where
+        P: for<'p> Property<'p>,
+        F: Fn(&mut Request<'_>) -> Result<(), SessionError> + Send + Sync + 'static,
+    {
+        if self.is::<P>() && self.answer.is_none() {
+            self.answer = Some(Box::new(answer));
+        }
+        self
+    }
+
+    pub(crate) fn apply(&self, request: &mut Request<'_>) -> Result<(), SessionError> {
+        match self.answer.as_ref().map(|answer| answer(request)) {
+            None
+            | Some(Ok(()) | Err(SessionError::CallbackError(CallbackError::EarlyReturn(_)))) => {
+                Ok(())
+            }
+            Some(Err(error)) => Err(error),
+        }
+    }
+}
+
+impl fmt::Debug for PropertyRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropertyRequest")
+            .field("property", &self.name)
+            .field("satisfied", &self.is_satisfied())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A validation of the authentication exchange deferred by the user callback
+///
+/// The outcome is provided with [`finalize`](Self::finalize) and will be returned from
+/// [`Session::validation`](crate::prelude::Session::validation) once the step finished.
+pub struct ValidationRequest {
+    validation: TypeId,
+    context: OwnedContext,
+    outcome: Option<Box<dyn Any + Send>>,
+}
+
+impl ValidationRequest {
+    pub(crate) fn new(validate: &Validate, context: &Context) -> Self {
+        Self {
+            validation: validate.validation_id(),
+            context: OwnedContext::capture(context),
+            outcome: None,
+        }
+    }
+
+    /// Returns true if the protocol implementation expects a value of the [`Validation`] `T`
+    #[must_use]
+    pub fn is<T: Validation>(&self) -> bool {
+        self.validation == TypeId::of::<T>()
+    }
+
+    /// The values the mechanism provided for the validation
+    #[must_use]
+    pub const fn context(&self) -> &OwnedContext {
+        &self.context
+    }
+
+    /// Finalize the authentication exchange by providing a last value to the mechanism
+    ///
+    /// This behaves like [`Validate::finalize`].
+    pub fn finalize<T: Validation>(&mut self, outcome: T::Value)
+    where
+        T::Value: Send,
+    {
+        if self.is::<T>() && self.outcome.is_none() {
+            self.outcome = Some(Box::new(outcome));
+        }
+    }
+
+    pub(crate) fn take_outcome<T: Validation>(&mut self) -> Option<T::Value> {
+        self.outcome
+            .take()
+            .and_then(|outcome| outcome.downcast().ok())
+            .map(|outcome| *outcome)
+    }
+}
+
+impl fmt::Debug for ValidationRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValidationRequest")
+            .field("finalized", &self.outcome.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub enum Pending {
+    Property(PropertyRequest),
+    Validation(ValidationRequest),
+}
+
+/// Answers to deferred requests, kept while a step is retried
+///
+/// Since a retried step runs the mechanism again from the start, the actions already performed
+/// and a validation already done are remembered as well so the callback isn't invoked for them a
+/// second time.
+#[derive(Default)]
+pub struct Resume {
+    answered: Vec<PropertyRequest>,
+    validated: Cell<bool>,
+    /// Number of actions performed by earlier attempts of the step
+    actions: Cell<usize>,
+    /// Number of actions issued by the mechanism during the current attempt
+    replayed: Cell<usize>,
+    pending: RefCell<Option<Pending>>,
+}
+
+impl Resume {
+    pub fn answer<'a, P: Property<'a>>(&self) -> Option<&PropertyRequest> {
+        self.answered.iter().find(|request| request.is::<P>())
+    }
+
+    pub fn is_validated(&self) -> bool {
+        self.validated.get()
+    }
+
+    /// Start another attempt of the step
+    pub fn restart(&self) {
+        self.replayed.set(0);
+    }
+
+    /// Register an action issued by the mechanism
+    ///
+    /// Returns `false` if the action was already performed by an earlier attempt. Mechanisms are
+    /// deterministic, so the n-th action of an attempt is the n-th action of every attempt.
+    pub fn act(&self) -> bool {
+        let n = self.replayed.get();
+        self.replayed.set(n + 1);
+        if n < self.actions.get() {
+            false
+        } else {
+            self.actions.set(n + 1);
+            true
+        }
+    }
+
+    /// Suspend the step, keeping only the first deferred request
+    pub fn suspend(&self, pending: Pending) {
+        let mut slot = self.pending.borrow_mut();
+        if slot.is_none() {
+            *slot = Some(pending);
+        }
+    }
+
+    pub fn take_pending(&mut self) -> Option<Pending> {
+        self.pending.get_mut().take()
+    }
+
+    pub fn answered(&mut self, request: PropertyRequest) {
+        self.answered.push(request);
+    }
+
+    pub fn validated(&self) {
+        self.validated.set(true);
+    }
+}
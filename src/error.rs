@@ -4,6 +4,8 @@ use crate::alloc::{boxed::Box, string::String};
 use thiserror::Error;
 
 use crate::callback::CallbackError;
+use crate::mechname::Mechname;
+use crate::policy::PolicyViolation;
 use crate::validate::ValidationError;
 use core::fmt;
 
@@ -100,6 +102,10 @@ pub enum SessionError {
 
     #[error("channel binding data for '{0}' is required")]
     MissingChannelBindingData(String),
+
+    #[error("mechanism {0} can not suspend a step")]
+    /// The callback deferred a request but the mechanism in use can not be suspended
+    NotResumable(&'static Mechname),
 }
 
 impl SessionError {
@@ -142,6 +148,14 @@ pub enum SASLError {
     /// This error may occur even if the mechanism is implemented by rsasl, as an user may have
     /// filtered the otherwise shared mechanisms.
     NoSharedMechanism,
+
+    #[error("mechanism {mechanism} was rejected by the security policy: {reason}")]
+    /// The selected mechanism is not allowed by the [`SecurityPolicy`](crate::policy::SecurityPolicy)
+    /// in use.
+    PolicyViolation {
+        mechanism: &'static Mechname,
+        reason: PolicyViolation,
+    },
 }
 
 #[cfg(test)]
@@ -0,0 +1,573 @@
+//! Exporting in-flight server sessions
+//!
+//! *requires feature `session_export`*
+//!
+//! Services handling authentication exchanges behind a load balancer can not rely on
+//! consecutive steps of an exchange reaching the same process. A server-side [`Session`] can
+//! instead be exported between steps using [`Session::export`], yielding an opaque blob that is
+//! encrypted and authenticated using an [`ExportKey`] shared by all instances. Any instance
+//! holding the same key and a config with the same mechanisms can continue the exchange by
+//! passing the blob to [`SASLServer::resume`].
+//!
+//! ```
+//! # use std::sync::Arc;
+//! # use rsasl::export::{ExportError, ExportKey};
+//! # use rsasl::prelude::*;
+//! # use rsasl::validate::NoValidation;
+//! # fn example(config: Arc<SASLConfig>, key: &ExportKey, session: Session) -> Result<(), ExportError> {
+//! // On the instance that handled the previous step
+//! let exported = session.export(key)?;
+//!
+//! // On the instance receiving the next message of the client
+//! let mut session = SASLServer::<NoValidation>::new(config).resume(key, &exported)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Not all mechanisms can export their state; `GSSAPI` for example keeps its state inside the
+//! GSS-API library. Exporting such a session returns [`ExportError::NotSupported`].
+//!
+//! The blob only contains the state of the mechanism and the external security strength factor.
+//! It does not contain a validation outcome, which only becomes available once the exchange
+//! finished. Every blob carries the time it was issued at and is rejected with
+//! [`ExportError::Expired`] once it is older than the time-to-live of the key, see
+//! [`ExportKey::with_ttl`]. Within that time a blob can be resumed more than once, so protocol
+//! implementations that need to prevent that must track it themselves.
+//!
+//! [`Session`]: crate::prelude::Session
+//! [`Session::export`]: crate::prelude::Session::export
+//! [`SASLServer::resume`]: crate::prelude::SASLServer::resume
+
+use crate::alloc::vec::Vec;
+use crate::error::SASLError;
+use crate::mechname::Mechname;
+use crate::zeroize;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use core::fmt;
+use core::time::Duration;
+use rand::RngCore;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Version of the blob format, authenticated as associated data
+const VERSION: u8 = 2;
+/// Length of the version and issue time, which are authenticated as associated data
+const HEADER_LEN: usize = 9;
+const NONCE_LEN: usize = 24;
+
+/// Time-to-live of exported sessions used unless [`ExportKey::with_ttl`] is called
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// The current time in seconds since the unix epoch
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+/// Domain separation label for deriving the key of [`ExportKey::tag`]
+#[cfg(all(
+    feature = "http",
+    any(feature = "scram-sha-1", feature = "scram-sha-2")
+))]
+const TAG_LABEL: &[u8] = b"rsasl export tag key\0";
+
+#[derive(Clone)]
+/// Secret key used to encrypt and authenticate exported sessions
+///
+/// All instances that should be able to resume sessions exported by each other must use the same
+/// key. Exports are encrypted using `XChaCha20-Poly1305` with a random nonce. The key material is
+/// overwritten with zeroes when the key is dropped.
+pub struct ExportKey {
+    cipher: XChaCha20Poly1305,
+    #[cfg(all(
+        feature = "http",
+        any(feature = "scram-sha-1", feature = "scram-sha-2")
+    ))]
+    tag_key: [u8; 32],
+    ttl: Duration,
+}
+
+impl ExportKey {
+    /// Construct a key from a 32 byte secret
+    ///
+    /// The secret must be generated by a cryptographically secure random number generator.
+    #[must_use]
+    pub fn new(mut secret: [u8; 32]) -> Self {
+        let mut key = Key::from(secret);
+        let cipher = XChaCha20Poly1305::new(&key);
+        zeroize(&mut key);
+        #[cfg(all(
+            feature = "http",
+            any(feature = "scram-sha-1", feature = "scram-sha-2")
+        ))]
+        let tag_key = hmac_sha256(&secret, TAG_LABEL);
+        zeroize(&mut secret);
+        Self {
+            cipher,
+            #[cfg(all(
+                feature = "http",
+                any(feature = "scram-sha-1", feature = "scram-sha-2")
+            ))]
+            tag_key,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Generate a new random key
+    #[must_use]
+    pub fn generate() -> Self {
+        let mut secret = [0; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self::new(secret)
+    }
+
+    /// Set the time after which exported sessions can no longer be resumed
+    ///
+    /// The default is [`DEFAULT_TTL`]. The time-to-live is checked when resuming, so all instances
+    /// should use the same value.
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// The time after which exported sessions can no longer be resumed
+    #[must_use]
+    pub const fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub(crate) fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        self.seal_at(plaintext, unix_time())
+    }
+
+    pub(crate) fn seal_at(&self, plaintext: &[u8], issued_at: u64) -> Vec<u8> {
+        let mut header = [VERSION; HEADER_LEN];
+        header[1..].copy_from_slice(&issued_at.to_be_bytes());
+        let mut nonce = XNonce::default();
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad: &header,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .expect("encrypting an exported session failed");
+
+        let mut blob = Vec::with_capacity(HEADER_LEN + NONCE_LEN + ciphertext.len());
+        blob.extend_from_slice(&header);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&ciphertext);
+        blob
+    }
+
+    pub(crate) fn open(&self, blob: &[u8]) -> Result<Vec<u8>, ExportError> {
+        self.open_at(blob, unix_time())
+    }
+
+    fn open_at(&self, blob: &[u8], now: u64) -> Result<Vec<u8>, ExportError> {
+        if blob.len() < HEADER_LEN + NONCE_LEN || blob[0] != VERSION {
+            return Err(ExportError::Invalid);
+        }
+        let (header, rest) = blob.split_at(HEADER_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: header,
+        };
+        let plaintext = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| ExportError::Invalid)?;
+
+        let mut issued_at = [0; 8];
+        issued_at.copy_from_slice(&header[1..]);
+        if now.saturating_sub(u64::from_be_bytes(issued_at)) > self.ttl.as_secs() {
+            return Err(ExportError::Expired);
+        }
+        Ok(plaintext)
+    }
+
+    #[cfg(all(
+        feature = "http",
+        any(feature = "scram-sha-1", feature = "scram-sha-2")
+    ))]
+    /// Compute a deterministic tag authenticating `data`
+    ///
+    /// The tag is a HMAC-SHA256 keyed with a key derived from the secret of this key, separate
+    /// from the one used to encrypt exported sessions.
+    pub(crate) fn tag(&self, data: &[u8]) -> [u8; 32] {
+        hmac_sha256(&self.tag_key, data)
+    }
+}
+
+#[cfg(all(
+    feature = "http",
+    any(feature = "scram-sha-1", feature = "scram-sha-2")
+))]
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    use hmac::{Hmac, Mac};
+    let mut mac =
+        <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).expect("HMAC can work with any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(all(
+    feature = "http",
+    any(feature = "scram-sha-1", feature = "scram-sha-2")
+))]
+impl Drop for ExportKey {
+    fn drop(&mut self) {
+        zeroize(&mut self.tag_key);
+    }
+}
+
+impl fmt::Debug for ExportKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExportKey").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+/// Error returned when exporting or resuming a session
+pub enum ExportError {
+    #[error("mechanism {0} does not support exporting sessions")]
+    /// The mechanism in use can not export its state
+    NotSupported(&'static Mechname),
+
+    #[error("the exported session is invalid or was exported using a different key")]
+    /// The blob was modified, exported with a different key or by an incompatible version
+    Invalid,
+
+    #[error("the exported session expired")]
+    /// The blob is older than the time-to-live of the key
+    Expired,
+
+    #[error("the mechanism of the exported session can not be used")]
+    /// The mechanism of the exported session is not available or not allowed by the policy
+    Unavailable(#[source] SASLError),
+}
+
+#[derive(Debug, Default)]
+/// Encoder for exported state, writing fixed-size integers and length-prefixed fields
+pub(crate) struct StateWriter(Vec<u8>);
+
+// Not every field type is used with every selection of mechanisms
+#[allow(dead_code)]
+impl StateWriter {
+    pub(crate) fn u8(&mut self, value: u8) -> &mut Self {
+        self.0.push(value);
+        self
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub(crate) fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        let len = u32::try_from(value.len()).expect("exported field is larger than 4GiB");
+        self.u32(len);
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    pub(crate) fn str(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub(crate) fn opt_str(&mut self, value: Option<&str>) -> &mut Self {
+        match value {
+            Some(value) => self.u8(1).str(value),
+            None => self.u8(0),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+#[derive(Debug)]
+/// Decoder for state written by [`StateWriter`]
+///
+/// All methods return `None` if the state is malformed.
+pub(crate) struct StateReader<'a>(&'a [u8]);
+
+#[allow(dead_code)]
+impl<'a> StateReader<'a> {
+    pub(crate) const fn new(state: &'a [u8]) -> Self {
+        Self(state)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(value)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|value| value[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        let value = self.take(4)?;
+        Some(u32::from_be_bytes(value.try_into().ok()?))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    pub(crate) fn str(&mut self) -> Option<&'a str> {
+        core::str::from_utf8(self.bytes()?).ok()
+    }
+
+    #[allow(clippy::option_option)]
+    pub(crate) fn opt_str(&mut self) -> Option<Option<&'a str>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => self.str().map(Some),
+            _ => None,
+        }
+    }
+
+    /// Returns `Some` if the whole state was consumed
+    pub(crate) fn finish(self) -> Option<()> {
+        self.0.is_empty().then_some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let key = ExportKey::generate();
+        let blob = key.seal(b"state");
+        assert_eq!(key.open(&blob).unwrap(), b"state");
+        // A random nonce is used for every export
+        assert_ne!(blob, key.seal(b"state"));
+    }
+
+    #[test]
+    fn test_tampered() {
+        let key = ExportKey::new([7; 32]);
+        let blob = key.seal(b"state");
+        for i in 0..blob.len() {
+            let mut tampered = blob.clone();
+            tampered[i] ^= 1;
+            assert!(matches!(key.open(&tampered), Err(ExportError::Invalid)));
+        }
+        assert!(matches!(key.open(&blob[..20]), Err(ExportError::Invalid)));
+        assert!(matches!(
+            ExportKey::new([8; 32]).open(&blob),
+            Err(ExportError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn test_expired() {
+        let key = ExportKey::generate().with_ttl(Duration::from_secs(60));
+        let blob = key.seal_at(b"state", 1000);
+        assert_eq!(key.open_at(&blob, 1060).unwrap(), b"state");
+        assert!(matches!(
+            key.open_at(&blob, 1061),
+            Err(ExportError::Expired)
+        ));
+        // The issue time is authenticated
+        let mut tampered = blob;
+        tampered[HEADER_LEN - 1] ^= 0x80;
+        assert!(matches!(
+            key.open_at(&tampered, 1000),
+            Err(ExportError::Invalid)
+        ));
+    }
+
+    #[cfg(feature = "config_builder")]
+    mod sessions {
+        use super::*;
+        use crate::config::SASLConfig;
+        use crate::prelude::{SASLServer, Session, State};
+        use crate::test::fixture::{Callback, TestValidation};
+        use std::sync::Arc;
+
+        /// A config as used by each of the instances exporting and resuming sessions
+        fn config() -> Arc<SASLConfig> {
+            SASLConfig::builder()
+                .with_defaults()
+                .with_callback(Callback)
+                .unwrap()
+        }
+
+        fn start(mechanism: &str) -> Session<TestValidation> {
+            SASLServer::new(config())
+                .start_suggested(Mechname::parse(mechanism.as_bytes()).unwrap())
+                .unwrap()
+        }
+
+        fn resume(key: &ExportKey, exported: &[u8]) -> Session<TestValidation> {
+            SASLServer::new(config()).resume(key, exported).unwrap()
+        }
+
+        #[cfg(feature = "scram-sha-2")]
+        #[test]
+        fn test_scram() {
+            use crate::mechanisms::scram::SCRAM_SHA256;
+
+            let key = ExportKey::generate();
+            let client_config =
+                SASLConfig::with_credentials(None, "alice".to_string(), "secret".to_string())
+                    .unwrap();
+            let mut client = crate::test::client_session(client_config, &SCRAM_SHA256);
+
+            let mut client_msg = Vec::new();
+            client.step(None, &mut client_msg).unwrap();
+            let mut server = start("SCRAM-SHA-256");
+            server.set_external_ssf(256);
+            let mut server_msg = Vec::new();
+            assert!(server
+                .step(Some(&client_msg), &mut server_msg)
+                .unwrap()
+                .is_running());
+            let exported = server.export(&key).unwrap();
+            drop(server);
+
+            client_msg.clear();
+            client.step(Some(&server_msg), &mut client_msg).unwrap();
+            let mut server = resume(&key, &exported);
+            assert_eq!(server.get_mechname().as_str(), "SCRAM-SHA-256");
+            assert_eq!(server.external_ssf(), 256);
+            server_msg.clear();
+            assert!(server
+                .step(Some(&client_msg), &mut server_msg)
+                .unwrap()
+                .is_finished());
+            assert!(client
+                .step(Some(&server_msg), &mut Vec::new())
+                .unwrap()
+                .is_finished());
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+        }
+
+        #[cfg(feature = "login")]
+        #[test]
+        fn test_login() {
+            let key = ExportKey::generate();
+            let mut server = start("LOGIN");
+            let mut out = Vec::new();
+            server.step(None, &mut out).unwrap();
+            let mut server = resume(&key, &server.export(&key).unwrap());
+            server.step(Some(b"alice"), &mut out).unwrap();
+            let mut server = resume(&key, &server.export(&key).unwrap());
+            let state = server.step(Some(b"secret"), &mut out).unwrap();
+            assert!(state.is_finished());
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+        }
+
+        #[cfg(feature = "plain")]
+        #[test]
+        fn test_plain() {
+            let key = ExportKey::generate();
+            let exported = start("PLAIN").export(&key).unwrap();
+            let mut server = resume(&key, &exported);
+            let state = server
+                .step(Some(b"\0alice\0secret"), &mut Vec::new())
+                .unwrap();
+            assert_eq!(state, State::Finished(crate::prelude::MessageSent::No));
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+        }
+
+        #[cfg(feature = "plain")]
+        #[test]
+        fn test_invalid() {
+            let key = ExportKey::generate();
+            let exported = start("PLAIN").export(&key).unwrap();
+            let error = SASLServer::<TestValidation>::new(config())
+                .resume(&ExportKey::generate(), &exported)
+                .err()
+                .unwrap();
+            assert!(matches!(error, ExportError::Invalid));
+
+            let policy = crate::policy::SecurityPolicy::new().no_plaintext();
+            let error = SASLServer::<TestValidation>::new(config())
+                .with_policy(policy)
+                .resume(&key, &exported)
+                .err()
+                .unwrap();
+            assert!(matches!(
+                error,
+                ExportError::Unavailable(SASLError::PolicyViolation { .. })
+            ));
+        }
+
+        #[cfg(feature = "plain")]
+        #[test]
+        fn test_expired() {
+            let key = ExportKey::generate();
+            let exported = start("PLAIN").export(&key).unwrap();
+            // Reissue the state as if it was exported an hour ago
+            let state = key.open(&exported).unwrap();
+            let exported = key.seal_at(&state, unix_time() - 3600);
+            let error = SASLServer::<TestValidation>::new(config())
+                .resume(&key, &exported)
+                .err()
+                .unwrap();
+            assert!(matches!(error, ExportError::Expired));
+
+            let key = key.with_ttl(Duration::from_secs(7200));
+            assert!(SASLServer::<TestValidation>::new(config())
+                .resume(&key, &exported)
+                .is_ok());
+        }
+
+        #[cfg(feature = "gssapi_backend")]
+        #[test]
+        fn test_gssapi_not_supported() {
+            let key = ExportKey::generate();
+            let session = crate::test::server_session(config(), &crate::mechanisms::gssapi::GSSAPI);
+            let error = session.export(&key).unwrap_err();
+            assert!(matches!(error, ExportError::NotSupported(name) if name.as_str() == "GSSAPI"));
+        }
+    }
+
+    #[test]
+    fn test_codec() {
+        let mut writer = StateWriter::default();
+        writer
+            .u8(3)
+            .u32(4096)
+            .bytes(b"\0\x01")
+            .str("alice")
+            .opt_str(None)
+            .opt_str(Some("bob"));
+        let state = writer.finish();
+
+        let mut reader = StateReader::new(&state);
+        assert_eq!(reader.u8(), Some(3));
+        assert_eq!(reader.u32(), Some(4096));
+        assert_eq!(reader.bytes(), Some(&b"\0\x01"[..]));
+        assert_eq!(reader.str(), Some("alice"));
+        assert_eq!(reader.opt_str(), Some(None));
+        assert_eq!(reader.opt_str(), Some(Some("bob")));
+        assert_eq!(reader.finish(), Some(()));
+
+        let mut reader = StateReader::new(&state[..state.len() - 1]);
+        reader.u8();
+        reader.u32();
+        reader.bytes();
+        reader.str();
+        reader.opt_str();
+        assert_eq!(reader.opt_str(), None);
+    }
+}
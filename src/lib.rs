@@ -266,6 +266,21 @@ extern crate core;
 #[cfg(any(feature = "std", test))]
 extern crate std as alloc;
 
+#[cfg(any(
+    feature = "scram-sha-1",
+    feature = "scram-sha-2",
+    feature = "session_export"
+))]
+/// Overwrite secret key material with zeroes
+pub(crate) fn zeroize(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        // SAFETY: `byte` is a valid, aligned and exclusive reference. The volatile write prevents
+        // the compiler from optimizing the wipe of memory that is about to be freed away.
+        unsafe { core::ptr::write_volatile(byte, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
 // none of these should be necessary for a provider to compile
 #[cfg(feature = "config_builder")]
 mod builder;
@@ -285,6 +300,7 @@ pub mod validate;
 
 mod error;
 pub mod mechname;
+pub mod policy;
 
 #[cfg(not(any(doc, feature = "unstable_custom_mechanism")))]
 mod mechanism;
@@ -298,9 +314,26 @@ pub mod registry;
 
 mod channel_bindings;
 mod context;
+#[cfg(any(feature = "provider", feature = "testutils", test))]
+mod deferred;
 
 mod vectored_io;
 
+#[cfg(feature = "provider")]
+pub mod stream;
+
+#[cfg(feature = "session_export")]
+pub mod export;
+
+#[cfg(any(
+    feature = "http",
+    feature = "line_protocols",
+    feature = "irc",
+    feature = "xmpp",
+    feature = "ldap"
+))]
+pub mod protocols;
+
 pub mod prelude {
     //! prelude exporting the most commonly used types
     pub use crate::error::{SASLError, SessionError};
@@ -317,7 +350,7 @@ pub mod prelude {
     #[cfg(feature = "provider")]
     pub use crate::sasl::{SASLClient, SASLServer};
     #[cfg(feature = "provider")]
-    pub use crate::session::Session;
+    pub use crate::session::{Session, Step, Suspended};
 }
 
 #[cfg(any(test, feature = "testutils"))]
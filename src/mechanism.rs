@@ -1,6 +1,9 @@
 //! Mechanism traits *only available with feature `unstable_custom_mechanism`*
 //!
 //!
+use crate::alloc::boxed::Box;
+#[cfg(feature = "session_export")]
+use crate::alloc::vec::Vec;
 use crate::error::SessionError::NoSecurityLayer;
 use core2::io::Write;
 
@@ -10,6 +13,7 @@ pub use crate::context::{Demand, DemandReply, EmptyProvider, Provider, ProviderE
 pub use crate::error::MechanismErrorKind;
 #[allow(unused_imports)]
 pub use crate::error::{MechanismError, SessionError};
+pub use crate::policy::Protection;
 pub use crate::session::{MechanismData, State};
 
 /// Trait implemented to be one party in an authentication exchange
@@ -111,6 +115,69 @@ pub trait Authentication: Send + Sync {
     fn has_security_layer(&self) -> bool {
         false
     }
+
+    /// Returns the maximum size of a single protected message the other party accepts
+    ///
+    /// Mechanisms that negotiate a maximum buffer size as part of installing a security layer
+    /// SHOULD return it here, and MUST NOT produce larger frames in `encode`. Returns `None` if
+    /// no security layer is installed or no limit was negotiated.
+    fn max_buffer_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the maximum size of a single protected message this side accepts
+    ///
+    /// Mechanisms that announce a maximum buffer size to the other party SHOULD return it here.
+    /// Returns `None` if no security layer is installed or no limit was announced.
+    fn max_receive_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the protection provided by the installed security layer
+    ///
+    /// Mechanisms installing a security layer SHOULD report the negotiated protection here. The
+    /// default assumes integrity protection if [`has_security_layer`](Self::has_security_layer)
+    /// returns `true`.
+    fn protection(&self) -> Protection {
+        if self.has_security_layer() {
+            Protection::Integrity
+        } else {
+            Protection::None
+        }
+    }
+
+    /// Returns a copy of the current state of the mechanism
+    ///
+    /// A session uses this to suspend a step in which the user callback deferred a request, and
+    /// to retry the step from the copy once the request was answered. Mechanisms whose state can
+    /// not be copied return `None`, which is the default.
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        None
+    }
+
+    /// Serialize the current state of the mechanism
+    ///
+    /// *requires feature `session_export`*
+    ///
+    /// This is used by [`Session::export`](crate::prelude::Session::export) to continue an
+    /// authentication exchange in another process. The returned state is encrypted and
+    /// authenticated before it is handed out. Mechanisms that can not export their state return
+    /// `None`, which is the default.
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restore a state returned by [`export_state`](Self::export_state)
+    ///
+    /// *requires feature `session_export`*
+    ///
+    /// This is called on a freshly constructed instance of the same mechanism. Returns `false` if
+    /// the state could not be restored, which is the default.
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, _state: &[u8]) -> bool {
+        false
+    }
 }
 
 // TODO(?): Proper generic version of the Authentication trait with defined Error types?
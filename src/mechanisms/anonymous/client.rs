@@ -1,4 +1,5 @@
 use super::AnonymousToken;
+use crate::alloc::boxed::Box;
 use crate::context::EmptyProvider;
 use crate::error::SessionError;
 use crate::mechanism::Authentication;
@@ -20,6 +21,10 @@ impl Authentication for Anonymous {
         })?;
         Ok(State::Finished(MessageSent::Yes))
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(*self))
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
 use crate::alloc::boxed::Box;
 use crate::mechanisms::anonymous::{client, server};
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::property::Property;
 use crate::registry::{Matches, Mechanism, Named};
 use crate::session::Side;
@@ -36,6 +37,10 @@ pub static ANONYMOUS: Mechanism = Mechanism {
     first: Side::Client,
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(
+        SecurityFlags::ANONYMOUS.union(SecurityFlags::DICTIONARY_RESISTANT),
+    )
+    .with_properties(&["AnonymousToken"], &[]),
 };
 
 struct Select;
@@ -1,4 +1,7 @@
 use super::AnonymousToken;
+use crate::alloc::boxed::Box;
+#[cfg(feature = "session_export")]
+use crate::alloc::vec::Vec;
 use crate::context::ThisProvider;
 use crate::error::{MechanismError, MechanismErrorKind, SessionError};
 use crate::mechanism::Authentication;
@@ -32,6 +35,21 @@ impl Authentication for Anonymous {
         session.validate(&ThisProvider::<AnonymousToken>::with(input))?;
         Ok(State::Finished(MessageSent::No))
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(*self))
+    }
+
+    // The mechanism has no state besides being constructed
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        Some(Vec::new())
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, state: &[u8]) -> bool {
+        state.is_empty()
+    }
 }
 
 #[cfg(test)]
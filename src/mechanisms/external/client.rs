@@ -1,3 +1,4 @@
+use crate::alloc::boxed::Box;
 use crate::context::EmptyProvider;
 use crate::error::SessionError;
 use crate::mechanism::Authentication;
@@ -22,6 +23,10 @@ impl Authentication for External {
 
         Ok(State::Finished(MessageSent::Yes))
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(*self))
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
 use crate::alloc::boxed::Box;
 use crate::mechanisms::external::{client, server};
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named};
 use crate::session::Side;
 
@@ -17,6 +18,9 @@ pub static EXTERNAL: Mechanism = Mechanism {
 
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(SecurityFlags::DICTIONARY_RESISTANT)
+        .with_authzid()
+        .with_properties(&["AuthzId"], &[]),
 };
 
 struct Select;
@@ -0,0 +1,144 @@
+//! Mapping of Kerberos principals to local identities
+//!
+//! Servers usually don't want to use the full Kerberos principal (e.g. `alice@EXAMPLE.COM`) as
+//! the identity of an authenticated user but a local name (e.g. `alice`). [`AuthToLocal`] is an
+//! ordered list of rules similar to the `auth_to_local` setting of MIT Kerberos. A GSSAPI server
+//! requests it using the [`GssAuthToLocal`](super::properties::GssAuthToLocal) property and
+//! provides the mapped name as [`AuthId`](crate::property::AuthId) to the `validate` callback.
+//!
+//! ```
+//! # use rsasl::mechanisms::gssapi::auth_to_local::AuthToLocal;
+//! let rules = AuthToLocal::new()
+//!     .strip_realm("EXAMPLE.COM")
+//!     .regex(r"([^/@]+)/admin@EXAMPLE\.COM", "$1-admin")
+//!     .unwrap();
+//!
+//! assert_eq!(rules.map("alice@EXAMPLE.COM").as_deref(), Some("alice"));
+//! assert_eq!(rules.map("bob/admin@EXAMPLE.COM").as_deref(), Some("bob-admin"));
+//! assert_eq!(rules.map("eve@EVIL.EXAMPLE").as_deref(), None);
+//! assert_eq!(rules.map("bob/admin@EXAMPLE.COM.EVIL.EXAMPLE").as_deref(), None);
+//! ```
+
+use core::fmt;
+use regex::Regex;
+
+#[derive(Clone)]
+enum Rule {
+    StripRealm(String),
+    Regex(Regex, String),
+}
+
+/// Ordered list of rules mapping principals to local identities
+///
+/// Rules are tried in the order they were added and the result of the first matching rule is
+/// used. If no rule matches a principal has no local identity.
+#[derive(Clone, Default)]
+pub struct AuthToLocal {
+    rules: Vec<Rule>,
+}
+
+impl AuthToLocal {
+    /// Construct an empty rule set that does not map any principal
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Add a rule mapping single-component principals of `realm` to their name
+    ///
+    /// E.g. with a realm of `EXAMPLE.COM`, `alice@EXAMPLE.COM` is mapped to `alice` while
+    /// `alice/admin@EXAMPLE.COM` and `alice@OTHER.EXAMPLE` are not matched.
+    #[must_use]
+    pub fn strip_realm(mut self, realm: impl Into<String>) -> Self {
+        self.rules.push(Rule::StripRealm(realm.into()));
+        self
+    }
+
+    /// Add a rule replacing principals matching `pattern` with `replacement`
+    ///
+    /// `replacement` may refer to capture groups of `pattern` using the syntax of
+    /// [`Regex::replace`]. The pattern is anchored at both ends, so it has to match the full
+    /// principal; e.g. `alice@EXAMPLE\.COM` does not match `alice@EXAMPLE.COM.EVIL.EXAMPLE`.
+    ///
+    /// # Errors
+    /// Returns an error if `pattern` is not a valid regular expression.
+    pub fn regex(
+        mut self,
+        pattern: &str,
+        replacement: impl Into<String>,
+    ) -> Result<Self, regex::Error> {
+        let pattern = Regex::new(&format!("^(?:{pattern})$"))?;
+        self.rules.push(Rule::Regex(pattern, replacement.into()));
+        Ok(self)
+    }
+
+    /// Map `principal` to a local identity using the first matching rule
+    #[must_use]
+    pub fn map(&self, principal: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| match rule {
+            Rule::StripRealm(realm) => {
+                let (name, principal_realm) = principal.rsplit_once('@')?;
+                (principal_realm == realm && !name.is_empty() && !name.contains('/'))
+                    .then(|| name.to_string())
+            }
+            Rule::Regex(pattern, replacement) => pattern.is_match(principal).then(|| {
+                pattern
+                    .replace(principal, replacement.as_str())
+                    .into_owned()
+            }),
+        })
+    }
+}
+
+impl fmt::Debug for AuthToLocal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        for rule in &self.rules {
+            match rule {
+                Rule::StripRealm(realm) => list.entry(&format_args!("strip_realm({realm})")),
+                Rule::Regex(pattern, replacement) => {
+                    list.entry(&format_args!("regex({}, {replacement})", pattern.as_str()))
+                }
+            };
+        }
+        list.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_order() {
+        let rules = AuthToLocal::new()
+            .regex(r"^root@EXAMPLE\.COM$", "nobody")
+            .unwrap()
+            .strip_realm("EXAMPLE.COM")
+            .strip_realm("OTHER.EXAMPLE");
+
+        assert_eq!(rules.map("root@EXAMPLE.COM").as_deref(), Some("nobody"));
+        assert_eq!(rules.map("alice@EXAMPLE.COM").as_deref(), Some("alice"));
+        assert_eq!(rules.map("bob@OTHER.EXAMPLE").as_deref(), Some("bob"));
+        assert_eq!(rules.map("alice/admin@EXAMPLE.COM"), None);
+        assert_eq!(rules.map("@EXAMPLE.COM"), None);
+        assert_eq!(rules.map("alice"), None);
+        assert_eq!(AuthToLocal::new().map("alice@EXAMPLE.COM"), None);
+    }
+
+    #[test]
+    fn test_regex_matches_full_principal() {
+        let rules = AuthToLocal::new()
+            .regex(r"([^@]+)@EXAMPLE\.COM", "$1")
+            .unwrap()
+            .regex(r"a|b@OTHER\.EXAMPLE", "ab")
+            .unwrap();
+
+        assert_eq!(rules.map("alice@EXAMPLE.COM").as_deref(), Some("alice"));
+        assert_eq!(rules.map("alice@EXAMPLE.COM.evil.org"), None);
+        assert_eq!(rules.map("b@OTHER.EXAMPLE").as_deref(), Some("ab"));
+        assert_eq!(rules.map("a").as_deref(), Some("ab"));
+        assert_eq!(rules.map("a@OTHER.EXAMPLE"), None);
+        assert_eq!(rules.map("xb@OTHER.EXAMPLE"), None);
+    }
+}
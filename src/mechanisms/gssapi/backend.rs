@@ -0,0 +1,192 @@
+//! Pluggable GSS-API implementations
+//!
+//! The `GSSAPI` mechanism performs all GSS-API operations through the [`Backend`] trait. With
+//! feature `gssapi` the system GSS-API library is used via the `libgssapi` crate unless the
+//! [`GssBackend`](super::properties::GssBackend) property is satisfied, in which case the provided
+//! backend is used for the session instead. With only feature `gssapi_backend` no library is linked
+//! and the property must be satisfied.
+//!
+//! A deterministic in-process implementation for tests is available in the
+//! [`mock`](super::mock) module with feature `testutils`.
+
+use crate::context::EmptyProvider;
+use crate::error::SessionError;
+use crate::mechanism::MechanismData;
+use crate::mechanisms::gssapi::properties::{Error, GssBackend};
+use core::any::Any;
+use core::fmt;
+
+bitflags::bitflags! {
+    /// Services requested for or provided by a security context
+    #[repr(transparent)]
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    pub struct ContextFlags: u32 {
+        /// Delegate the initiator's credentials to the acceptor
+        const DELEGATE = 0b000_0001;
+        /// Authenticate the acceptor to the initiator
+        const MUTUAL = 0b000_0010;
+        /// Detect replayed messages
+        const REPLAY = 0b000_0100;
+        /// Detect out-of-sequence messages
+        const SEQUENCE = 0b000_1000;
+        /// Messages can be encrypted
+        const CONFIDENTIALITY = 0b001_0000;
+        /// Messages can be integrity protected
+        const INTEGRITY = 0b010_0000;
+        /// Do not reveal the initiator's identity to the acceptor
+        const ANONYMOUS = 0b100_0000;
+    }
+}
+
+/// Credentials to use for a security context as selected by the user callback
+///
+/// Fields that are `None` should be substituted with the backend's defaults.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Credentials {
+    /// Principal to acquire credentials for
+    pub principal: Option<String>,
+    /// Location of the keytab to read keys from
+    pub keytab: Option<String>,
+    /// Location of the credential cache to use
+    pub ccache: Option<String>,
+}
+
+/// An established or in-progress security context
+pub trait SecurityContext: Send + Sync {
+    /// Process a token received from the other party
+    ///
+    /// Returns the token to send to the other party, if any. Initiators are called with `None` to
+    /// produce their first token.
+    ///
+    /// # Errors
+    /// Returns an error if the token is invalid or the context could not be established.
+    fn step(&mut self, input: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Returns true once the context is fully established
+    fn is_complete(&self) -> bool;
+
+    /// Services provided by the established context
+    ///
+    /// # Errors
+    /// Returns an error if the flags can not be queried.
+    fn flags(&mut self) -> Result<ContextFlags, Error>;
+
+    /// Integrity protect and, if `encrypt` is set, encrypt a message
+    ///
+    /// # Errors
+    /// Returns an error if the message could not be wrapped.
+    fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Verify and, if necessary, decrypt a message wrapped by the other party
+    ///
+    /// # Errors
+    /// Returns an error if the message was tampered with or is otherwise invalid.
+    fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// The number of bytes wrapping adds to a message of `len` bytes
+    ///
+    /// # Errors
+    /// Returns an error if the overhead can not be determined.
+    fn wrap_overhead(&mut self, encrypt: bool, len: usize) -> Result<usize, Error>;
+
+    /// Strength in bits of the key used for confidentiality protection, if known
+    ///
+    /// If `None` the strength is assumed to be 56 bits, as done by Cyrus SASL.
+    fn key_bits(&mut self) -> Option<u32> {
+        None
+    }
+}
+
+/// The acceptor side of a security context
+pub trait Acceptor: SecurityContext {
+    /// Display name of the authenticated initiator, e.g. `alice@EXAMPLE.COM`
+    ///
+    /// # Errors
+    /// Returns an error if the context is not established or the name is not valid UTF-8.
+    fn source_name(&mut self) -> Result<String, Error>;
+
+    /// Take the credentials delegated by the initiator, if any
+    fn take_delegated(&mut self) -> Option<DelegatedCredential> {
+        None
+    }
+}
+
+/// A GSS-API implementation
+pub trait Backend: Send + Sync {
+    /// Start a new initiator context to the host-based service `target`, e.g. `imap@mail.example.com`
+    ///
+    /// # Errors
+    /// Returns an error if credentials could not be acquired or the target is invalid.
+    fn initiate(
+        &self,
+        credentials: &Credentials,
+        target: &str,
+        flags: ContextFlags,
+    ) -> Result<Box<dyn SecurityContext>, Error>;
+
+    /// Start a new acceptor context
+    ///
+    /// # Errors
+    /// Returns an error if credentials could not be acquired.
+    fn accept(&self, credentials: &Credentials) -> Result<Box<dyn Acceptor>, Error>;
+}
+
+/// Credentials delegated by the initiator
+///
+/// The concrete type depends on the backend; for the `libgssapi` backend it is a
+/// `libgssapi::credential::Cred`, or a `gssapi::StoreCred` if the acceptor credentials were
+/// read from a [`GssKeytab`](super::properties::GssKeytab) or
+/// [`GssCredentialCache`](super::properties::GssCredentialCache).
+pub struct DelegatedCredential(Box<dyn Any + Send + Sync>);
+
+impl DelegatedCredential {
+    /// Wrap backend specific credentials
+    pub fn new<T: Any + Send + Sync>(credential: T) -> Self {
+        Self(Box::new(credential))
+    }
+
+    /// Returns a reference to the credentials if they are of type `T`
+    #[must_use]
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Take the credentials if they are of type `T`
+    ///
+    /// # Errors
+    /// Returns `self` unchanged if the credentials are of a different type.
+    pub fn downcast<T: Any>(self) -> Result<T, Self> {
+        self.0
+            .downcast()
+            .map(|credential| *credential)
+            .map_err(Self)
+    }
+}
+
+impl fmt::Debug for DelegatedCredential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DelegatedCredential")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Call `f` with the backend selected for this session
+pub(crate) fn with_backend<T>(
+    session: &MechanismData,
+    f: impl Fn(&dyn Backend) -> Result<T, Error>,
+) -> Result<T, SessionError> {
+    #[cfg(feature = "gssapi")]
+    {
+        let custom = session
+            .maybe_need_with::<GssBackend, _, _>(&EmptyProvider, |backend| Ok(f(backend)?))?;
+        match custom {
+            Some(value) => Ok(value),
+            None => Ok(f(&super::Libgssapi)?),
+        }
+    }
+    #[cfg(not(feature = "gssapi"))]
+    {
+        session.need_with::<GssBackend, _, _>(&EmptyProvider, |backend| Ok(f(backend)?))
+    }
+}
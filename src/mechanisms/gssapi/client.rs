@@ -1,15 +1,16 @@
 use crate::context::EmptyProvider;
 use crate::error::SessionError;
-use crate::mechanism::{Authentication, MechanismData, State};
-use crate::mechanisms::gssapi::properties::{Error, GssSecurityLayer, GssService, SecurityLayer};
-use crate::property::Hostname;
+use crate::mechanism::{Authentication, MechanismData, Protection, State};
+use crate::mechanisms::gssapi::backend::{self, ContextFlags, SecurityContext};
+use crate::mechanisms::gssapi::cred;
+use crate::mechanisms::gssapi::layer::{self, Layer};
+use crate::mechanisms::gssapi::properties::{
+    Error, GssDelegateCredentials, GssSecurityLayer, GssService, SecurityLayer,
+};
+use crate::property::{AuthzId, Hostname};
 use crate::session::MessageSent;
 use core::fmt;
 use core2::io::Write;
-use libgssapi::context::{ClientCtx, CtxFlags, SecurityContext};
-use libgssapi::credential::{Cred, CredUsage};
-use libgssapi::name::Name;
-use libgssapi::oid::{OidSet, GSS_MECH_KRB5, GSS_NT_HOSTBASED_SERVICE};
 
 #[derive(Debug, Default)]
 pub struct Gssapi {
@@ -20,9 +21,9 @@ pub struct Gssapi {
 enum GssapiState {
     #[default]
     Initial,
-    Pending(ClientCtx),
-    Last(ClientCtx, SecurityLayer),
-    Completed(Option<(ClientCtx, bool)>),
+    Pending(Box<dyn SecurityContext>),
+    Last(Box<dyn SecurityContext>, SecurityLayer),
+    Completed(Option<Layer<dyn SecurityContext>>),
     Errored,
 }
 
@@ -56,28 +57,29 @@ impl Authentication for Gssapi {
                     targ_name.push_str(hostname);
                     Ok(())
                 })?;
-                let target = Name::new(targ_name.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))
-                    .map_err(Error::Gss)?;
 
-                let mut krb5 = OidSet::new().map_err(Error::Gss)?;
-                krb5.add(&GSS_MECH_KRB5).map_err(Error::Gss)?;
+                let credentials = cred::credentials(session, true)?;
 
-                let cred = Cred::acquire(None, None, CredUsage::Initiate, Some(&krb5))
-                    .map_err(Error::Gss)?;
+                // Request all flags except anonymity and delegation, which is only requested if
+                // the user asks for it. Setting them does not mean the final context will provide
+                // them, so this should not be an issue.
+                let mut flags = ContextFlags::all() - ContextFlags::ANONYMOUS;
+                let delegate = session
+                    .maybe_need_with::<GssDelegateCredentials, _, _>(&EmptyProvider, |delegate| {
+                        Ok(*delegate)
+                    })?
+                    .unwrap_or(false);
+                flags.set(ContextFlags::DELEGATE, delegate);
 
-                self.state = GssapiState::Pending(ClientCtx::new(
-                    Some(cred),
-                    target,
-                    // Allow all flags. Setting them does not mean the final context will provide
-                    // them, so this should not be an issue.
-                    CtxFlags::all(),
-                    Some(&GSS_MECH_KRB5),
-                ));
+                let ctx = backend::with_backend(session, |backend| {
+                    backend.initiate(&credentials, &targ_name, flags)
+                })?;
+                self.state = GssapiState::Pending(ctx);
 
                 self.step(session, input, writer)
             }
             GssapiState::Pending(mut ctx) => {
-                if let Some(token) = ctx.step(input, None).map_err(Error::Gss)? {
+                if let Some(token) = ctx.step(input)? {
                     if !token.is_empty() {
                         writer.write_all(&token)?;
                     }
@@ -90,7 +92,7 @@ impl Authentication for Gssapi {
                 // Request acceptable security layers from the client.
                 let acceptable = session
                     .maybe_need_with::<GssSecurityLayer, _, _>(&EmptyProvider, |acceptable| {
-                        let flags = ctx.flags().map_err(Error::Gss)?;
+                        let flags = ctx.flags()?;
                         // If acceptable contains `NO_SECURITY_LAYER` or is empty, which we treat as
                         // the same, our context is always secure enough.
                         if acceptable.is_empty()
@@ -102,14 +104,14 @@ impl Authentication for Gssapi {
                         // Else, we check if the least required flag is set.
 
                         if acceptable.contains(SecurityLayer::INTEGRITY)
-                            && flags.contains(CtxFlags::GSS_C_INTEG_FLAG)
+                            && flags.contains(ContextFlags::INTEGRITY)
                         {
                             return Ok(*acceptable);
                         }
 
-                        let required = CtxFlags::GSS_C_INTEG_FLAG
-                            | CtxFlags::GSS_C_MUTUAL_FLAG
-                            | CtxFlags::GSS_C_CONF_FLAG;
+                        let required = ContextFlags::INTEGRITY
+                            | ContextFlags::MUTUAL
+                            | ContextFlags::CONFIDENTIALITY;
 
                         if flags.contains(required) {
                             Ok(*acceptable)
@@ -124,40 +126,44 @@ impl Authentication for Gssapi {
             }
             GssapiState::Last(mut ctx, acceptable) => {
                 let input = input.ok_or(SessionError::InputDataRequired)?;
-                let unwrapped = ctx.unwrap(input).map_err(Error::Gss)?;
-                if unwrapped.len() != 4 {
-                    Err(Error::BadFinalToken)?;
+                let unwrapped = ctx.unwrap(input)?;
+                let (supported_sec, server_max, rest) = layer::parse_layer_token(&unwrapped)?;
+                if !rest.is_empty() {
+                    return Err(Error::BadFinalToken.into());
                 }
 
-                let supported_sec =
-                    SecurityLayer::from_bits(unwrapped[0]).ok_or(Error::BadFinalToken)?;
-
                 // This contains all layers that are supported by the server and acceptable to
                 // the user.
                 let shared_layers = supported_sec & acceptable;
 
-                let (response, wrap) = if shared_layers.contains(SecurityLayer::CONFIDENTIALITY) {
-                    (
-                        [SecurityLayer::CONFIDENTIALITY.bits(), 0xFF, 0xFF, 0xFF],
-                        Some(true),
-                    )
+                let (selected, wrap) = if shared_layers.contains(SecurityLayer::CONFIDENTIALITY) {
+                    (SecurityLayer::CONFIDENTIALITY, Some(true))
                 } else if shared_layers.contains(SecurityLayer::INTEGRITY) {
-                    (
-                        [SecurityLayer::INTEGRITY.bits(), 0xFF, 0xFF, 0xFF],
-                        Some(false),
-                    )
+                    (SecurityLayer::INTEGRITY, Some(false))
                 } else if shared_layers.contains(SecurityLayer::NO_SECURITY_LAYER) {
-                    (
-                        [SecurityLayer::NO_SECURITY_LAYER.bits(), 0x00, 0x00, 0x00],
-                        None,
-                    )
+                    (SecurityLayer::NO_SECURITY_LAYER, None)
                 } else {
                     return Err(Error::BadContext.into());
                 };
 
-                let wrapped = ctx.wrap(false, &response).map_err(Error::Gss)?;
+                // Without a security layer the buffer size must be zero.
+                let recv_max = if wrap.is_some() {
+                    layer::receive_max(session)?
+                } else {
+                    0
+                };
+                let mut response = layer::layer_token(selected, recv_max).to_vec();
+                session.maybe_need_with::<AuthzId, _, _>(&EmptyProvider, |authzid| {
+                    response.extend_from_slice(authzid.as_bytes());
+                    Ok(())
+                })?;
+                let wrapped = ctx.wrap(false, &response)?;
                 writer.write_all(&wrapped)?;
-                self.state = GssapiState::Completed(wrap.map(|e| (ctx, e)));
+
+                let layer = wrap
+                    .map(|encrypt| Layer::new(ctx, encrypt, server_max, recv_max))
+                    .transpose()?;
+                self.state = GssapiState::Completed(layer);
                 Ok(State::Finished(MessageSent::Yes))
             }
             GssapiState::Completed(..) | GssapiState::Errored => Err(SessionError::MechanismDone),
@@ -166,22 +172,14 @@ impl Authentication for Gssapi {
 
     fn encode(&mut self, input: &[u8], writer: &mut dyn Write) -> Result<usize, SessionError> {
         match self.state {
-            GssapiState::Completed(Some((ref mut ctx, encrypt))) => {
-                let wrapped = ctx.wrap(encrypt, input).map_err(Error::Gss)?;
-                writer.write_all(&wrapped)?;
-                Ok(input.len())
-            }
+            GssapiState::Completed(Some(ref mut layer)) => layer.encode(input, writer),
             _ => Err(SessionError::NoSecurityLayer),
         }
     }
 
     fn decode(&mut self, input: &[u8], writer: &mut dyn Write) -> Result<usize, SessionError> {
         match self.state {
-            GssapiState::Completed(Some((ref mut ctx, _))) => {
-                let unwrapped = ctx.unwrap(input).map_err(Error::Gss)?;
-                writer.write_all(&unwrapped)?;
-                Ok(input.len())
-            }
+            GssapiState::Completed(Some(ref mut layer)) => layer.decode(input, writer),
             _ => Err(SessionError::NoSecurityLayer),
         }
     }
@@ -189,4 +187,25 @@ impl Authentication for Gssapi {
     fn has_security_layer(&self) -> bool {
         matches!(self.state, GssapiState::Completed(Some(_)))
     }
+
+    fn max_buffer_size(&self) -> Option<usize> {
+        match self.state {
+            GssapiState::Completed(Some(ref layer)) => Some(layer.max_buffer_size()),
+            _ => None,
+        }
+    }
+
+    fn max_receive_size(&self) -> Option<usize> {
+        match self.state {
+            GssapiState::Completed(Some(ref layer)) => Some(layer.max_receive_size()),
+            _ => None,
+        }
+    }
+
+    fn protection(&self) -> Protection {
+        match self.state {
+            GssapiState::Completed(Some(ref layer)) => layer.protection(),
+            _ => Protection::None,
+        }
+    }
 }
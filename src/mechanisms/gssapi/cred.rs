@@ -0,0 +1,32 @@
+//! Selection of initiator and acceptor credentials
+
+use crate::context::EmptyProvider;
+use crate::error::SessionError;
+use crate::mechanism::MechanismData;
+use crate::mechanisms::gssapi::backend::Credentials;
+use crate::mechanisms::gssapi::properties::{
+    GssAcceptorPrincipal, GssCredentialCache, GssInitiatorPrincipal, GssKeytab,
+};
+
+/// Query the principal, keytab and credential cache to use from the user callback
+pub fn credentials(session: &MechanismData, initiator: bool) -> Result<Credentials, SessionError> {
+    let principal = if initiator {
+        session.maybe_need_with::<GssInitiatorPrincipal, _, _>(&EmptyProvider, |p| {
+            Ok(String::from(p))
+        })?
+    } else {
+        session.maybe_need_with::<GssAcceptorPrincipal, _, _>(&EmptyProvider, |p| {
+            Ok(String::from(p))
+        })?
+    };
+    let keytab =
+        session.maybe_need_with::<GssKeytab, _, _>(&EmptyProvider, |p| Ok(String::from(p)))?;
+    let ccache = session
+        .maybe_need_with::<GssCredentialCache, _, _>(&EmptyProvider, |p| Ok(String::from(p)))?;
+
+    Ok(Credentials {
+        principal,
+        keytab,
+        ccache,
+    })
+}
@@ -0,0 +1,153 @@
+//! Security layer negotiation and message protection shared by client and server
+
+use crate::context::EmptyProvider;
+use crate::error::SessionError;
+use crate::mechanism::MechanismData;
+use crate::mechanisms::gssapi::backend::SecurityContext;
+use crate::mechanisms::gssapi::properties::{Error, GssMaxBufferSize, SecurityLayer};
+use crate::policy::Protection;
+use core2::io::Write;
+
+/// The largest buffer size that can be expressed in the security layer negotiation
+pub const MAX_BUFFER_SIZE: u32 = 0x00FF_FFFF;
+
+/// Additional space reserved for padding when calculating the largest plaintext that fits into
+/// a wrapped message, as the padding depends on the exact length of the plaintext.
+const PADDING_RESERVE: usize = 16;
+
+/// Length of the probe message used to calculate the wrapping overhead
+const PROBE_LEN: usize = 64;
+
+/// Key strength assumed for confidentiality protection if the backend can not report it
+const DEFAULT_KEY_BITS: u32 = 56;
+
+/// Query the maximum size of a wrapped message we are willing to receive
+pub fn receive_max(session: &MechanismData) -> Result<u32, SessionError> {
+    Ok(session
+        .maybe_need_with::<GssMaxBufferSize, _, _>(&EmptyProvider, |max| Ok(*max))?
+        .map_or(MAX_BUFFER_SIZE, |max| max.min(MAX_BUFFER_SIZE)))
+}
+
+/// Build the four byte security layer token containing the layer bitmask and a buffer size
+pub fn layer_token(layers: SecurityLayer, max_buffer_size: u32) -> [u8; 4] {
+    let [_, a, b, c] = max_buffer_size.min(MAX_BUFFER_SIZE).to_be_bytes();
+    [layers.bits(), a, b, c]
+}
+
+/// Parse a security layer token into the layer bitmask, buffer size and the remaining bytes
+///
+/// The remaining bytes contain the authorization identity in the final message of the client.
+pub fn parse_layer_token(token: &[u8]) -> Result<(SecurityLayer, u32, &[u8]), Error> {
+    let [layers, a, b, c, rest @ ..] = token else {
+        return Err(Error::BadFinalToken);
+    };
+    let layers = SecurityLayer::from_bits(*layers).ok_or(Error::BadFinalToken)?;
+    Ok((layers, u32::from_be_bytes([0, *a, *b, *c]), rest))
+}
+
+/// An installed integrity or confidentiality protection layer
+pub struct Layer<C: ?Sized> {
+    ctx: Box<C>,
+    encrypt: bool,
+    key_bits: u32,
+    /// Maximum size of a wrapped message the other party accepts
+    send_max: usize,
+    /// Maximum size of a wrapped message we accept
+    recv_max: usize,
+    /// Largest plaintext that is guaranteed to wrap into at most `send_max` bytes
+    max_plaintext: usize,
+}
+
+impl<C: ?Sized + SecurityContext> Layer<C> {
+    pub fn new(
+        mut ctx: Box<C>,
+        encrypt: bool,
+        send_max: u32,
+        recv_max: u32,
+    ) -> Result<Self, Error> {
+        let send_max = send_max as usize;
+        // Calculate the overhead of wrapping using a small probe message.
+        let overhead = ctx.wrap_overhead(encrypt, PROBE_LEN)?;
+        let max_plaintext = send_max.saturating_sub(overhead + PADDING_RESERVE);
+        let key_bits = ctx.key_bits().unwrap_or(DEFAULT_KEY_BITS);
+
+        Ok(Self {
+            ctx,
+            encrypt,
+            key_bits,
+            send_max,
+            recv_max: recv_max as usize,
+            max_plaintext,
+        })
+    }
+
+    pub const fn is_encrypted(&self) -> bool {
+        self.encrypt
+    }
+
+    pub const fn protection(&self) -> Protection {
+        if self.encrypt {
+            Protection::Confidentiality {
+                key_bits: self.key_bits,
+            }
+        } else {
+            Protection::Integrity
+        }
+    }
+
+    pub const fn max_buffer_size(&self) -> usize {
+        self.send_max
+    }
+
+    pub const fn max_receive_size(&self) -> usize {
+        self.recv_max
+    }
+
+    /// Wrap at most as much of `input` as fits into a single message the other party accepts
+    pub fn encode(&mut self, input: &[u8], writer: &mut dyn Write) -> Result<usize, SessionError> {
+        if self.max_plaintext == 0 {
+            return Err(Error::MessageTooLarge.into());
+        }
+        let len = input.len().min(self.max_plaintext);
+        let wrapped = self.ctx.wrap(self.encrypt, &input[..len])?;
+        if wrapped.len() > self.send_max {
+            return Err(Error::MessageTooLarge.into());
+        }
+        writer.write_all(&wrapped)?;
+        Ok(len)
+    }
+
+    pub fn decode(&mut self, input: &[u8], writer: &mut dyn Write) -> Result<usize, SessionError> {
+        if input.len() > self.recv_max {
+            return Err(Error::MessageTooLarge.into());
+        }
+        let unwrapped = self.ctx.unwrap(input)?;
+        writer.write_all(&unwrapped)?;
+        Ok(input.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_token() {
+        let token = layer_token(SecurityLayer::INTEGRITY, 0x0001_0000);
+        assert_eq!(token, [0b010, 0x01, 0x00, 0x00]);
+        let (layers, max, rest) = parse_layer_token(&token).unwrap();
+        assert_eq!(layers.bits(), SecurityLayer::INTEGRITY.bits());
+        assert_eq!(max, 0x0001_0000);
+        assert!(rest.is_empty());
+
+        let (_, _, authzid) = parse_layer_token(b"\x01\x00\x00\x00alice").unwrap();
+        assert_eq!(authzid, b"alice");
+
+        // Sizes that can't be represented are clamped
+        let token = layer_token(SecurityLayer::CONFIDENTIALITY, u32::MAX);
+        assert_eq!(token, [0b100, 0xFF, 0xFF, 0xFF]);
+
+        assert!(parse_layer_token(&[0b001, 0, 0]).is_err());
+        assert!(parse_layer_token(&[0b1000, 0, 0, 0]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use super::{client, server};
 use crate::alloc::boxed::Box;
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named, Side};
 
 #[cfg_attr(
@@ -19,6 +20,31 @@ pub static GSSAPI: Mechanism = Mechanism {
 
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(
+        SecurityFlags::MUTUAL_AUTH.union(SecurityFlags::DICTIONARY_RESISTANT),
+    )
+    .with_authzid()
+    .with_security_layer()
+    .with_properties(
+        &[
+            "Hostname",
+            "GssService",
+            "AuthzId",
+            "GssSecurityLayer",
+            "GssDelegateCredentials",
+            "GssInitiatorPrincipal",
+            "GssCredentialCache",
+            "GssBackend",
+        ],
+        &[
+            "GssSecurityLayer",
+            "GssMaxBufferSize",
+            "GssAuthToLocal",
+            "GssAcceptorPrincipal",
+            "GssKeytab",
+            "GssBackend",
+        ],
+    ),
 };
 
 struct Select;
@@ -0,0 +1,460 @@
+//! Deterministic in-process GSS-API backend *requires feature `testutils`*
+//!
+//! [`MockBackend`] establishes security contexts without a KDC or any system library, making it
+//! possible to exercise the `GSSAPI` mechanism in ordinary unit tests. Select it for a session
+//! by satisfying the [`GssBackend`](super::properties::GssBackend) property:
+//!
+//! ```
+//! # use rsasl::callback::{Context, Request, SessionCallback, SessionData};
+//! # use rsasl::prelude::SessionError;
+//! use rsasl::mechanisms::gssapi::mock::MockBackend;
+//! use rsasl::mechanisms::gssapi::properties::GssBackend;
+//!
+//! struct Callback(MockBackend);
+//! impl SessionCallback for Callback {
+//!     fn callback(
+//!         &self,
+//!         _session_data: &SessionData,
+//!         _context: &Context,
+//!         request: &mut Request,
+//!     ) -> Result<(), SessionError> {
+//!         request.satisfy::<GssBackend>(&self.0)?;
+//!         Ok(())
+//!     }
+//! }
+//! # let _ = Callback(MockBackend::new("alice@EXAMPLE.COM"));
+//! ```
+//!
+//! **The mock provides no security whatsoever.** Tokens and wrapped messages are merely
+//! obfuscated and checksummed so that tampering and mismatched contexts are detected in tests.
+
+use crate::mechanisms::gssapi::backend::{
+    Acceptor, Backend, ContextFlags, Credentials, DelegatedCredential, SecurityContext,
+};
+use crate::mechanisms::gssapi::properties::Error;
+use thiserror::Error;
+
+const INIT_TOKEN: &[u8] = b"MOCK-INIT\0";
+const ACCEPT_TOKEN: &[u8] = b"MOCK-ACCEPT\0";
+/// One marker byte and a four byte checksum
+const WRAP_OVERHEAD: usize = 5;
+const CONFIDENTIALITY_KEY: u8 = 0xA5;
+
+#[derive(Debug, Error)]
+#[error("mock GSS-API: {0}")]
+struct MockError(&'static str);
+
+fn mock_error(reason: &'static str) -> Error {
+    Error::Backend(Box::new(MockError(reason)))
+}
+
+/// A deterministic GSS-API backend for tests
+///
+/// Initiators authenticate as the principal the backend was constructed with unless
+/// [`GssInitiatorPrincipal`](super::properties::GssInitiatorPrincipal) is satisfied. Acceptors
+/// accept any target unless [`GssAcceptorPrincipal`](super::properties::GssAcceptorPrincipal) is
+/// satisfied, in which case it must match the host-based service name of the target, e.g.
+/// `imap@mail.example.com`.
+///
+/// Established contexts provide the requested flags intersected with the flags of the backend.
+#[derive(Clone, Debug)]
+pub struct MockBackend {
+    principal: String,
+    flags: ContextFlags,
+}
+
+impl MockBackend {
+    /// Construct a backend authenticating initiators as `principal`, supporting all flags
+    #[must_use]
+    pub fn new(principal: impl Into<String>) -> Self {
+        Self {
+            principal: principal.into(),
+            flags: ContextFlags::all() - ContextFlags::ANONYMOUS,
+        }
+    }
+
+    /// Restrict the flags established contexts can provide
+    #[must_use]
+    pub const fn with_flags(mut self, flags: ContextFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+impl Backend for MockBackend {
+    fn initiate(
+        &self,
+        credentials: &Credentials,
+        target: &str,
+        flags: ContextFlags,
+    ) -> Result<Box<dyn SecurityContext>, Error> {
+        let principal = credentials
+            .principal
+            .clone()
+            .unwrap_or_else(|| self.principal.clone());
+        Ok(Box::new(MockContext {
+            initiator: true,
+            step: Step::Initial,
+            principal,
+            target: Some(target.to_string()),
+            flags: flags & self.flags,
+        }))
+    }
+
+    fn accept(&self, credentials: &Credentials) -> Result<Box<dyn Acceptor>, Error> {
+        Ok(Box::new(MockContext {
+            initiator: false,
+            step: Step::Initial,
+            principal: String::new(),
+            target: credentials.principal.clone(),
+            flags: self.flags,
+        }))
+    }
+}
+
+/// Credentials delegated by a [`MockBackend`] initiator
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct MockCredential {
+    /// Principal of the initiator that delegated the credentials
+    pub principal: String,
+}
+
+#[derive(Debug)]
+enum Step {
+    Initial,
+    Sent,
+    Complete,
+}
+
+#[derive(Debug)]
+struct MockContext {
+    initiator: bool,
+    step: Step,
+    /// The initiator principal, only known to acceptors once the context is established
+    principal: String,
+    /// The target requested by an initiator or the name an acceptor accepts
+    target: Option<String>,
+    flags: ContextFlags,
+}
+
+impl MockContext {
+    fn checksum(msg: &[u8]) -> [u8; 4] {
+        msg.iter()
+            .fold(0x811C_9DC5u32, |hash, byte| {
+                (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+            })
+            .to_be_bytes()
+    }
+
+    fn accept(&mut self, token: &[u8]) -> Result<Vec<u8>, Error> {
+        let bad_token = || mock_error("invalid initiator token");
+        let token = token.strip_prefix(INIT_TOKEN).ok_or_else(bad_token)?;
+        let [a, b, c, d, rest @ ..] = token else {
+            return Err(bad_token());
+        };
+        let requested =
+            ContextFlags::from_bits(u32::from_be_bytes([*a, *b, *c, *d])).ok_or_else(bad_token)?;
+        let rest = core::str::from_utf8(rest).map_err(|_| bad_token())?;
+        let (principal, target) = rest.split_once('\0').ok_or_else(bad_token)?;
+        if self.target.as_deref().map_or(false, |name| name != target) {
+            return Err(mock_error("initiator requested a different target"));
+        }
+
+        self.principal = principal.to_string();
+        self.flags &= requested;
+        self.step = Step::Complete;
+
+        let mut reply = ACCEPT_TOKEN.to_vec();
+        reply.extend_from_slice(&self.flags.bits().to_be_bytes());
+        Ok(reply)
+    }
+}
+
+impl SecurityContext for MockContext {
+    fn step(&mut self, input: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        match (&self.step, self.initiator, input) {
+            (Step::Initial, false, Some(token)) => self.accept(token).map(Some),
+            (Step::Initial, true, None) => {
+                let mut token = INIT_TOKEN.to_vec();
+                token.extend_from_slice(&self.flags.bits().to_be_bytes());
+                token.extend_from_slice(self.principal.as_bytes());
+                token.push(0);
+                token.extend_from_slice(self.target.as_deref().unwrap_or_default().as_bytes());
+                self.step = Step::Sent;
+                Ok(Some(token))
+            }
+            (Step::Sent, true, Some(token)) => {
+                let flags = token
+                    .strip_prefix(ACCEPT_TOKEN)
+                    .and_then(|flags| <[u8; 4]>::try_from(flags).ok())
+                    .and_then(|flags| ContextFlags::from_bits(u32::from_be_bytes(flags)))
+                    .ok_or_else(|| mock_error("invalid acceptor token"))?;
+                self.flags = flags;
+                self.step = Step::Complete;
+                Ok(None)
+            }
+            _ => Err(mock_error("unexpected context step")),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        matches!(self.step, Step::Complete)
+    }
+
+    fn flags(&mut self) -> Result<ContextFlags, Error> {
+        Ok(self.flags)
+    }
+
+    fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        if !self.is_complete() {
+            return Err(mock_error("context is not established"));
+        }
+        if encrypt && !self.flags.contains(ContextFlags::CONFIDENTIALITY) {
+            return Err(mock_error("confidentiality is not available"));
+        }
+        let mut wrapped = Vec::with_capacity(msg.len() + WRAP_OVERHEAD);
+        wrapped.push(u8::from(encrypt));
+        if encrypt {
+            wrapped.extend(msg.iter().map(|byte| byte ^ CONFIDENTIALITY_KEY));
+        } else {
+            wrapped.extend_from_slice(msg);
+        }
+        wrapped.extend_from_slice(&Self::checksum(msg));
+        Ok(wrapped)
+    }
+
+    fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        if !self.is_complete() {
+            return Err(mock_error("context is not established"));
+        }
+        let bad_message = || mock_error("invalid wrapped message");
+        let [marker, rest @ ..] = msg else {
+            return Err(bad_message());
+        };
+        let split = rest.len().checked_sub(4).ok_or_else(bad_message)?;
+        let (payload, checksum) = rest.split_at(split);
+        let unwrapped: Vec<u8> = match marker {
+            0 => payload.to_vec(),
+            1 => payload
+                .iter()
+                .map(|byte| byte ^ CONFIDENTIALITY_KEY)
+                .collect(),
+            _ => return Err(bad_message()),
+        };
+        if checksum != Self::checksum(&unwrapped) {
+            return Err(bad_message());
+        }
+        Ok(unwrapped)
+    }
+
+    fn wrap_overhead(&mut self, _encrypt: bool, _len: usize) -> Result<usize, Error> {
+        Ok(WRAP_OVERHEAD)
+    }
+}
+
+impl Acceptor for MockContext {
+    fn source_name(&mut self) -> Result<String, Error> {
+        if self.is_complete() {
+            Ok(self.principal.clone())
+        } else {
+            Err(mock_error("context is not established"))
+        }
+    }
+
+    fn take_delegated(&mut self) -> Option<DelegatedCredential> {
+        if self.is_complete() && self.flags.contains(ContextFlags::DELEGATE) {
+            self.flags.remove(ContextFlags::DELEGATE);
+            Some(DelegatedCredential::new(MockCredential {
+                principal: self.principal.clone(),
+            }))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback::{Context, Request, SessionCallback};
+    use crate::error::SessionError;
+    use crate::mechanisms::gssapi::auth_to_local::AuthToLocal;
+    use crate::mechanisms::gssapi::mechinfo::GSSAPI;
+    use crate::mechanisms::gssapi::properties::{
+        GssAcceptorPrincipal, GssAuthToLocal, GssBackend, GssDelegateCredentials,
+        GssDelegatedCredential, GssSecurityLayer, GssService, SecurityLayer,
+    };
+    use crate::policy::Protection;
+    use crate::property::{AuthId, AuthzId, GssapiDisplayName, Hostname};
+    use crate::session::{Session, SessionData};
+    use crate::test;
+    use crate::validate::{Validate, ValidationError};
+    use core::cell::Cell;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct C {
+        backend: MockBackend,
+        acceptor: &'static str,
+        layer: SecurityLayer,
+        delegate: bool,
+        validated: Arc<AtomicBool>,
+    }
+    impl SessionCallback for C {
+        fn callback(
+            &self,
+            _session_data: &SessionData,
+            _context: &Context,
+            request: &mut Request,
+        ) -> Result<(), SessionError> {
+            request
+                .satisfy::<GssBackend>(&self.backend)?
+                .satisfy::<GssService>("imap")?
+                .satisfy::<Hostname>("mail.example.com")?
+                .satisfy::<GssAcceptorPrincipal>(self.acceptor)?
+                .satisfy::<GssDelegateCredentials>(&self.delegate)?
+                .satisfy::<GssSecurityLayer>(&self.layer)?
+                .satisfy::<AuthzId>("shared")?
+                .satisfy::<GssAuthToLocal>(&AuthToLocal::new().strip_realm("EXAMPLE.COM"))?;
+            Ok(())
+        }
+
+        fn validate(
+            &self,
+            _session_data: &SessionData,
+            context: &Context,
+            _validate: &mut Validate<'_>,
+        ) -> Result<(), ValidationError> {
+            assert_eq!(context.get_ref::<AuthId>(), Some("alice"));
+            assert_eq!(context.get_ref::<AuthzId>(), Some("shared"));
+            assert_eq!(
+                context.get_ref::<GssapiDisplayName>(),
+                Some("alice@EXAMPLE.COM")
+            );
+            let delegated = context
+                .get_ref::<GssDelegatedCredential>()
+                .and_then(Cell::take)
+                .map(|cred| cred.downcast::<MockCredential>().unwrap());
+            assert_eq!(
+                delegated.map(|cred| cred.principal).as_deref(),
+                self.delegate.then_some("alice@EXAMPLE.COM")
+            );
+            self.validated.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn callback() -> C {
+        C {
+            backend: MockBackend::new("alice@EXAMPLE.COM"),
+            acceptor: "imap@mail.example.com",
+            layer: SecurityLayer::all(),
+            delegate: false,
+            validated: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Run a full exchange, returning the client and server sessions
+    fn exchange(callback: C) -> Result<(Session, Session), SessionError> {
+        let validated = callback.validated.clone();
+        let config = test::server_config(callback);
+        let mut client = test::client_session(config.clone(), &GSSAPI);
+        let mut server = test::server_session(config, &GSSAPI);
+
+        let mut client_out = Vec::new();
+        client.step(None, &mut client_out)?;
+        loop {
+            let mut server_out = Vec::new();
+            let state = server.step(Some(&client_out), &mut server_out)?;
+            if state.is_finished() {
+                break;
+            }
+            client_out.clear();
+            client.step(Some(&server_out), &mut client_out)?;
+        }
+        assert!(validated.load(Ordering::Relaxed));
+        Ok((client, server))
+    }
+
+    fn roundtrip(from: &mut Session, to: &mut Session, msg: &[u8]) -> Vec<u8> {
+        let mut wrapped = Vec::new();
+        assert_eq!(from.encode(msg, &mut wrapped).unwrap(), msg.len());
+        let mut unwrapped = Vec::new();
+        to.decode(&wrapped, &mut unwrapped).unwrap();
+        unwrapped
+    }
+
+    #[test]
+    fn test_confidentiality() {
+        let (mut client, mut server) = exchange(callback()).unwrap();
+        assert!(client.has_security_layer());
+        assert!(server.has_security_layer());
+        let protection = Protection::Confidentiality { key_bits: 56 };
+        assert_eq!(client.protection(), protection);
+        assert_eq!(server.protection(), protection);
+        server.set_external_ssf(256);
+        assert_eq!(server.ssf(), 256);
+        server.set_external_ssf(0);
+        assert_eq!(server.ssf(), 56);
+        assert_eq!(roundtrip(&mut client, &mut server, b"ping"), b"ping");
+        assert_eq!(roundtrip(&mut server, &mut client, b"pong"), b"pong");
+
+        let mut wrapped = Vec::new();
+        client.encode(b"secret", &mut wrapped).unwrap();
+        assert!(!wrapped.windows(6).any(|w| w == b"secret"));
+        let last = wrapped.len() - 1;
+        wrapped[last] ^= 1;
+        assert!(server.decode(&wrapped, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_integrity_only() {
+        let mut cb = callback();
+        cb.backend = cb
+            .backend
+            .with_flags(ContextFlags::MUTUAL | ContextFlags::INTEGRITY);
+        let (mut client, mut server) = exchange(cb).unwrap();
+        assert_eq!(client.protection(), Protection::Integrity);
+        assert_eq!(server.ssf(), 1);
+        let mut wrapped = Vec::new();
+        client.encode(b"visible", &mut wrapped).unwrap();
+        assert_eq!(&wrapped[1..8], b"visible");
+        assert_eq!(roundtrip(&mut client, &mut server, b"ping"), b"ping");
+    }
+
+    #[test]
+    fn test_no_security_layer() {
+        let mut cb = callback();
+        cb.layer = SecurityLayer::NO_SECURITY_LAYER;
+        let (client, mut server) = exchange(cb).unwrap();
+        assert!(!client.has_security_layer());
+        assert!(!server.has_security_layer());
+        assert_eq!(client.protection(), Protection::None);
+        server.set_external_ssf(128);
+        assert_eq!(server.ssf(), 128);
+    }
+
+    #[test]
+    fn test_delegation() {
+        let mut cb = callback();
+        cb.delegate = true;
+        exchange(cb).unwrap();
+    }
+
+    #[test]
+    fn test_wrong_target() {
+        let mut cb = callback();
+        cb.acceptor = "smtp@mail.example.com";
+        let validated = cb.validated.clone();
+        assert!(exchange(cb).is_err());
+        assert!(!validated.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_no_local_identity() {
+        let mut cb = callback();
+        cb.backend = MockBackend::new("eve@EVIL.EXAMPLE");
+        assert!(exchange(cb).is_err());
+    }
+}
@@ -1,21 +1,35 @@
 use crate::error::{MechanismError, MechanismErrorKind};
+use crate::mechanisms::gssapi::auth_to_local::AuthToLocal;
+use crate::mechanisms::gssapi::backend::{Backend, DelegatedCredential};
 use crate::prelude::Property;
 use crate::property::SizedProperty;
+use core::cell::Cell;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
+    #[cfg(feature = "gssapi")]
     #[error("GSS-API error: {0}")]
     Gss(
         #[source]
         #[from]
         libgssapi::error::Error,
     ),
+    #[error("GSS-API backend error: {0}")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
     #[error("final token is invalid")]
     BadFinalToken,
     #[error("produced context is not secure enough")]
     BadContext,
+    #[error("message exceeds the negotiated maximum buffer size")]
+    MessageTooLarge,
+    #[error("principal name is not valid UTF-8")]
+    BadName,
+    #[error("no local identity for principal")]
+    NoLocalIdentity,
+    #[error("selecting a keytab or credential cache requires feature `gssapi_cred_store`")]
+    CredentialStoreUnsupported,
 }
 
 impl MechanismError for Error {
@@ -30,6 +44,90 @@ impl Property<'_> for GssService {
     type Value = str;
 }
 
+/// Kerberos principal to acquire initiator credentials for, e.g. `alice@EXAMPLE.COM`
+///
+/// If not satisfied the default principal of the credential cache is used.
+#[non_exhaustive]
+pub struct GssInitiatorPrincipal;
+impl Property<'_> for GssInitiatorPrincipal {
+    type Value = str;
+}
+
+/// Kerberos principal to acquire acceptor credentials for, e.g. `imap/mail.example.com@EXAMPLE.COM`
+///
+/// If not satisfied any principal in the keytab is accepted.
+#[non_exhaustive]
+pub struct GssAcceptorPrincipal;
+impl Property<'_> for GssAcceptorPrincipal {
+    type Value = str;
+}
+
+/// Location of the keytab to read keys from, e.g. `FILE:/etc/krb5.keytab`
+///
+/// Acceptors use this as their keytab, initiators as client keytab to obtain initial tickets. If
+/// not satisfied the default keytab is used. With the default backend selecting a keytab requires
+/// feature `gssapi_cred_store`, otherwise authentication fails.
+#[non_exhaustive]
+pub struct GssKeytab;
+impl Property<'_> for GssKeytab {
+    type Value = str;
+}
+
+/// Location of the credential cache to use, e.g. `FILE:/tmp/krb5cc_1000` or `KCM:`
+///
+/// If not satisfied the default credential cache is used. With the default backend selecting a
+/// credential cache requires feature `gssapi_cred_store`, otherwise authentication fails.
+#[non_exhaustive]
+pub struct GssCredentialCache;
+impl Property<'_> for GssCredentialCache {
+    type Value = str;
+}
+
+/// Request delegation of the client's credentials to the server
+///
+/// Requested by the client using a 'satisfiable' callback. If not satisfied or `false`, no
+/// credentials are delegated.
+#[non_exhaustive]
+pub struct GssDelegateCredentials;
+impl SizedProperty<'_> for GssDelegateCredentials {
+    type Value = bool;
+}
+
+/// Credentials delegated by the client
+///
+/// Provided by the server to the `validate` callback, containing `None` if the client did not
+/// delegate its credentials. The credentials can be moved out of the cell to e.g. authenticate onward to another service;
+/// if they are not taken they are released after validation. With the default backend they can be
+/// downcast to a `libgssapi::credential::Cred`, or to a `gssapi::StoreCred` if the acceptor
+/// credentials were read from a [`GssKeytab`] or [`GssCredentialCache`].
+#[non_exhaustive]
+pub struct GssDelegatedCredential;
+impl SizedProperty<'_> for GssDelegatedCredential {
+    type Value = Cell<Option<DelegatedCredential>>;
+}
+
+/// GSS-API implementation to use for a session
+///
+/// Requested by both client and server using a 'satisfiable' callback before a security context
+/// is started. If not satisfied the system library is used via `libgssapi`, which requires feature
+/// `gssapi`. See the [`backend`](super::backend) module.
+#[non_exhaustive]
+pub struct GssBackend;
+impl Property<'_> for GssBackend {
+    type Value = dyn Backend;
+}
+
+/// Rules mapping the authenticated principal to a local identity
+///
+/// Requested by the server using a 'satisfiable' callback before calling `validate`. The mapped
+/// identity is provided as [`AuthId`](crate::property::AuthId); if no rule matches the
+/// authentication fails. If not satisfied the principal is provided as `AuthId` unchanged.
+#[non_exhaustive]
+pub struct GssAuthToLocal;
+impl SizedProperty<'_> for GssAuthToLocal {
+    type Value = AuthToLocal;
+}
+
 /// Acceptable security layers
 #[non_exhaustive]
 pub struct GssSecurityLayer;
@@ -37,6 +135,18 @@ impl SizedProperty<'_> for GssSecurityLayer {
     type Value = SecurityLayer;
 }
 
+/// Maximum size of a single wrapped message this side is willing to receive
+///
+/// Requested using a 'satisfiable' callback once the security context has been established, and
+/// sent to the other party during security layer negotiation. Values larger than `0xFFFFFF`,
+/// the largest size that can be expressed in the negotiation, are clamped. If not satisfied
+/// `0xFFFFFF` is used.
+#[non_exhaustive]
+pub struct GssMaxBufferSize;
+impl SizedProperty<'_> for GssMaxBufferSize {
+    type Value = u32;
+}
+
 bitflags::bitflags! {
     #[repr(transparent)]
     #[derive(Copy, Clone)]
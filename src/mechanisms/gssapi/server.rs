@@ -1,15 +1,66 @@
-use crate::context::EmptyProvider;
+use crate::context::{Demand, DemandReply, EmptyProvider, Provider};
 use crate::error::SessionError;
-use crate::mechanism::{Authentication, MechanismData, State};
-use crate::mechanisms::gssapi::properties::{Error, GssSecurityLayer, SecurityLayer};
+use crate::mechanism::{Authentication, MechanismData, Protection, State};
+use crate::mechanisms::gssapi::backend::{self, Acceptor, ContextFlags, DelegatedCredential};
+use crate::mechanisms::gssapi::cred;
+use crate::mechanisms::gssapi::layer::{self, Layer};
+use crate::mechanisms::gssapi::properties::{
+    Error, GssAuthToLocal, GssDelegatedCredential, GssSecurityLayer, SecurityLayer,
+};
 use crate::prelude::State::Finished;
+use crate::property::{AuthId, AuthzId, GssapiDisplayName};
 use crate::session::MessageSent;
 use alloc::mem;
+use core::cell::Cell;
 use core::fmt;
 use core2::io::Write;
-use libgssapi::context::{CtxFlags, SecurityContext, ServerCtx};
-use libgssapi::credential::{Cred, CredUsage};
-use libgssapi::oid::{OidSet, GSS_MECH_KRB5};
+
+/// Information about the established context provided to the `validate` callback
+struct GssapiProvider<'a> {
+    authid: &'a str,
+    authzid: &'a str,
+    display_name: &'a str,
+    delegated: &'a Cell<Option<DelegatedCredential>>,
+}
+impl<'a> Provider<'a> for GssapiProvider<'a> {
+    fn provide(&self, req: &mut Demand<'a>) -> DemandReply<()> {
+        req.provide_ref::<AuthId>(self.authid)?
+            .provide_ref::<GssapiDisplayName>(self.display_name)?
+            .provide_ref::<GssDelegatedCredential>(self.delegated)?;
+        if !self.authzid.is_empty() {
+            req.provide_ref::<AuthzId>(self.authzid)?;
+        }
+        req.done()
+    }
+}
+
+/// Map the authenticated principal to a local identity and call the `validate` callback
+fn validate(
+    session: &mut MechanismData,
+    ctx: &mut dyn Acceptor,
+    authzid: &[u8],
+) -> Result<(), SessionError> {
+    let authzid = core::str::from_utf8(authzid).map_err(|_| Error::BadFinalToken)?;
+    let display_name = ctx.source_name()?;
+    let display_name = display_name.as_str();
+
+    let local_name = session
+        .maybe_need_with::<GssAuthToLocal, _, _>(&EmptyProvider, |rules| {
+            rules
+                .map(display_name)
+                .ok_or_else(|| Error::NoLocalIdentity.into())
+        })?
+        .unwrap_or_else(|| String::from(display_name));
+
+    let delegated = Cell::new(ctx.take_delegated());
+    session.validate(&GssapiProvider {
+        authid: &local_name,
+        authzid,
+        display_name,
+        delegated: &delegated,
+    })?;
+    Ok(())
+}
 
 #[derive(Debug, Default)]
 pub struct Gssapi {
@@ -20,10 +71,10 @@ pub struct Gssapi {
 enum GssapiState {
     #[default]
     Initial,
-    Pending(ServerCtx),
-    Installed(ServerCtx, SecurityLayer),
-    Final(ServerCtx, SecurityLayer),
-    Done(Option<(ServerCtx, bool)>),
+    Pending(Box<dyn Acceptor>),
+    Installed(Box<dyn Acceptor>, SecurityLayer, u32),
+    Final(Box<dyn Acceptor>, SecurityLayer, u32),
+    Done(Option<Layer<dyn Acceptor>>),
     Errored,
 }
 
@@ -34,8 +85,8 @@ impl fmt::Debug for GssapiState {
             Self::Pending(..) => f.write_str("Pending"),
             Self::Installed(..) => f.write_str("Installed"),
             Self::Final(..) => f.write_str("Final"),
-            Self::Done(Some((_, true))) => f.write_str("Done<Confidentiality>"),
-            Self::Done(Some((_, false))) => f.write_str("Done<Integrity>"),
+            Self::Done(Some(layer)) if layer.is_encrypted() => f.write_str("Done<Confidentiality>"),
+            Self::Done(Some(_)) => f.write_str("Done<Integrity>"),
             Self::Done(None) => f.write_str("Done<NoSecurity>"),
             Self::Errored => f.write_str("Errored"),
         }
@@ -51,17 +102,14 @@ impl Authentication for Gssapi {
     ) -> Result<State, SessionError> {
         match mem::replace(&mut self.state, GssapiState::Errored) {
             GssapiState::Initial => {
-                let mut krb5 = OidSet::new().map_err(Error::Gss)?;
-                krb5.add(&GSS_MECH_KRB5).map_err(Error::Gss)?;
-                let cred = Cred::acquire(None, None, CredUsage::Accept, Some(&krb5))
-                    .map_err(Error::Gss)?;
-                let ctx = ServerCtx::new(cred);
+                let credentials = cred::credentials(session, false)?;
+                let ctx = backend::with_backend(session, |backend| backend.accept(&credentials))?;
                 self.state = GssapiState::Pending(ctx);
                 self.step(session, input, writer)
             }
             GssapiState::Pending(mut ctx) => {
                 let input = input.ok_or(SessionError::InputDataRequired)?;
-                let token = ctx.step(input).map_err(Error::Gss)?;
+                let token = ctx.step(Some(input))?;
                 if ctx.is_complete() {
                     // Query the user for acceptable security layers
                     let mut acceptable = session
@@ -70,13 +118,12 @@ impl Authentication for Gssapi {
                         })?
                         .unwrap_or_default();
 
-                    let ctx_flags = ctx.flags().map_err(Error::Gss)?;
+                    let ctx_flags = ctx.flags()?;
 
-                    if !ctx_flags.contains(CtxFlags::GSS_C_MUTUAL_FLAG | CtxFlags::GSS_C_CONF_FLAG)
-                    {
+                    if !ctx_flags.contains(ContextFlags::MUTUAL | ContextFlags::CONFIDENTIALITY) {
                         acceptable.set(SecurityLayer::CONFIDENTIALITY, false);
                     }
-                    if !ctx_flags.contains(CtxFlags::GSS_C_INTEG_FLAG) {
+                    if !ctx_flags.contains(ContextFlags::INTEGRITY) {
                         acceptable.set(SecurityLayer::INTEGRITY, false);
                     }
 
@@ -86,7 +133,8 @@ impl Authentication for Gssapi {
                         return Err(Error::BadContext.into());
                     }
 
-                    self.state = GssapiState::Installed(ctx, acceptable);
+                    let recv_max = layer::receive_max(session)?;
+                    self.state = GssapiState::Installed(ctx, acceptable, recv_max);
                 }
                 // If an auth exchange token was produced we need to do another loop, otherwise we
                 // immediately produce the supported security layer token.
@@ -97,39 +145,36 @@ impl Authentication for Gssapi {
                     self.step(session, None, writer)
                 }
             }
-            GssapiState::Installed(mut ctx, supported) => {
-                let out_bytes = if supported
+            GssapiState::Installed(mut ctx, supported, recv_max) => {
+                let recv_max = if supported
                     .intersects(SecurityLayer::CONFIDENTIALITY | SecurityLayer::INTEGRITY)
                 {
-                    // TODO: This should come from a call to `GSS_Wrap_size_limit` instead of
-                    //       being a static 2^25 - 1
-                    [supported.bits(), 0xFF, 0xFF, 0xFF]
+                    recv_max
                 } else {
-                    [supported.bits(), 0x00, 0x00, 0x00]
+                    0
                 };
-                let wrapped = ctx.wrap(false, &out_bytes).map_err(Error::Gss)?;
+                let out_bytes = layer::layer_token(supported, recv_max);
+                let wrapped = ctx.wrap(false, &out_bytes)?;
                 writer.write_all(&wrapped)?;
-                self.state = GssapiState::Final(ctx, supported);
+                self.state = GssapiState::Final(ctx, supported, recv_max);
                 Ok(State::Running)
             }
-            GssapiState::Final(mut ctx, supported) => {
+            GssapiState::Final(mut ctx, supported, recv_max) => {
                 let input = input.ok_or(SessionError::InputDataRequired)?;
-                let unwrapped = ctx.unwrap(input).map_err(Error::Gss)?;
-                if unwrapped.len() != 4 {
-                    Err(Error::BadFinalToken)?;
-                }
-                let selected =
-                    SecurityLayer::from_bits(unwrapped[0]).ok_or(Error::BadFinalToken)?;
+                let unwrapped = ctx.unwrap(input)?;
+                let (selected, client_max, authzid) = layer::parse_layer_token(&unwrapped)?;
 
                 // If the client selected a layer we don't support or accept, error.
                 if !selected.intersects(supported) {
                     Err(Error::BadFinalToken)?;
                 }
 
+                validate(session, ctx.as_mut(), authzid)?;
+
                 let wrap_state = if selected.contains(SecurityLayer::CONFIDENTIALITY) {
-                    Some((ctx, true))
+                    Some(Layer::new(ctx, true, client_max, recv_max)?)
                 } else if selected.contains(SecurityLayer::INTEGRITY) {
-                    Some((ctx, false))
+                    Some(Layer::new(ctx, false, client_max, recv_max)?)
                 } else {
                     None
                 };
@@ -147,24 +192,37 @@ impl Authentication for Gssapi {
         matches!(self.state, GssapiState::Done(Some(_)))
     }
 
+    fn max_buffer_size(&self) -> Option<usize> {
+        match self.state {
+            GssapiState::Done(Some(ref layer)) => Some(layer.max_buffer_size()),
+            _ => None,
+        }
+    }
+
+    fn max_receive_size(&self) -> Option<usize> {
+        match self.state {
+            GssapiState::Done(Some(ref layer)) => Some(layer.max_receive_size()),
+            _ => None,
+        }
+    }
+
+    fn protection(&self) -> Protection {
+        match self.state {
+            GssapiState::Done(Some(ref layer)) => layer.protection(),
+            _ => Protection::None,
+        }
+    }
+
     fn encode(&mut self, input: &[u8], writer: &mut dyn Write) -> Result<usize, SessionError> {
         match self.state {
-            GssapiState::Done(Some((ref mut ctx, encrypt))) => {
-                let wrapped = ctx.wrap(encrypt, input).map_err(Error::Gss)?;
-                writer.write_all(&wrapped)?;
-                Ok(input.len())
-            }
+            GssapiState::Done(Some(ref mut layer)) => layer.encode(input, writer),
             _ => Err(SessionError::NoSecurityLayer),
         }
     }
 
     fn decode(&mut self, input: &[u8], writer: &mut dyn Write) -> Result<usize, SessionError> {
         match self.state {
-            GssapiState::Done(Some((ref mut ctx, _))) => {
-                let unwrapped = ctx.unwrap(input).map_err(Error::Gss)?;
-                writer.write_all(&unwrapped)?;
-                Ok(input.len())
-            }
+            GssapiState::Done(Some(ref mut layer)) => layer.decode(input, writer),
             _ => Err(SessionError::NoSecurityLayer),
         }
     }
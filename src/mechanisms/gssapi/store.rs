@@ -0,0 +1,521 @@
+//! Security contexts using credentials acquired from an explicit credential store
+//!
+//! *requires feature `gssapi_cred_store`*
+//!
+//! `libgssapi` does not expose `gss_acquire_cred_from`, and its contexts can only be constructed
+//! from its own credential type. Credentials acquired from a keytab or credential cache selected
+//! by the user callback are therefore kept as raw handles owned by the types in this module, and
+//! used by a security context that calls into the C library directly.
+
+use crate::mechanisms::gssapi::backend::{
+    Acceptor, ContextFlags, Credentials, DelegatedCredential, SecurityContext,
+};
+use crate::mechanisms::gssapi::properties::Error;
+use crate::mechanisms::gssapi::system::{from_gss, wrap_overhead};
+use core::ffi::{c_int, c_void};
+use core::{fmt, ptr};
+use libgssapi::context::CtxFlags;
+use libgssapi::credential::CredUsage;
+use libgssapi::error::MajorFlags;
+use libgssapi::oid::{GSS_MECH_KRB5, GSS_NT_HOSTBASED_SERVICE, GSS_NT_KRB5_PRINCIPAL};
+use libgssapi_sys::{
+    gss_OID, gss_OID_desc, gss_OID_set_desc, gss_accept_sec_context, gss_acquire_cred_from,
+    gss_buffer_desc, gss_cred_id_t, gss_cred_usage_t, gss_ctx_id_t, gss_delete_sec_context,
+    gss_display_name, gss_import_name, gss_init_sec_context, gss_inquire_context,
+    gss_key_value_element_desc, gss_key_value_set_desc, gss_name_t, gss_release_buffer,
+    gss_release_cred, gss_release_name, gss_unwrap, gss_wrap, OM_uint32, _GSS_C_INDEFINITE,
+    _GSS_S_CONTINUE_NEEDED, GSS_C_ACCEPT, GSS_C_INITIATE, GSS_C_QOP_DEFAULT, GSS_S_COMPLETE,
+};
+use std::ffi::CString;
+
+/// The calling and routine error bits of a major status code (RFC 2744, section 3.9.1)
+const GSS_ERROR_MASK: OM_uint32 = 0xFFFF_0000;
+
+fn gss_error(major: OM_uint32, minor: OM_uint32) -> Error {
+    Error::Gss(libgssapi::error::Error {
+        major: MajorFlags::from_bits_retain(major),
+        minor,
+    })
+}
+
+fn check(major: OM_uint32, minor: OM_uint32) -> Result<(), Error> {
+    if major & GSS_ERROR_MASK == 0 {
+        Ok(())
+    } else {
+        Err(gss_error(major, minor))
+    }
+}
+
+/// Build an `gss_OID_desc` borrowing the bytes of `oid`
+// OIDs are only a few bytes long
+#[allow(clippy::cast_possible_truncation)]
+fn oid_desc(oid: &[u8]) -> gss_OID_desc {
+    gss_OID_desc {
+        length: oid.len() as OM_uint32,
+        elements: oid.as_ptr().cast_mut().cast::<c_void>(),
+    }
+}
+
+/// Build a `gss_buffer_desc` borrowing `data`
+///
+/// The buffer must only be passed as an input parameter, which the library does not modify.
+fn input_buffer(data: &[u8]) -> gss_buffer_desc {
+    gss_buffer_desc {
+        length: data.len(),
+        value: data.as_ptr().cast_mut().cast::<c_void>(),
+    }
+}
+
+/// A buffer allocated by the library, released on drop
+struct OwnedBuffer(gss_buffer_desc);
+
+impl OwnedBuffer {
+    const fn empty() -> Self {
+        Self(gss_buffer_desc {
+            length: 0,
+            value: ptr::null_mut(),
+        })
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        if self.0.value.is_null() {
+            return Vec::new();
+        }
+        // SAFETY: The library returned `length` initialized bytes at `value`, which stay valid
+        // until the buffer is released.
+        unsafe { core::slice::from_raw_parts(self.0.value.cast::<u8>(), self.0.length) }.to_vec()
+    }
+}
+
+impl Drop for OwnedBuffer {
+    fn drop(&mut self) {
+        if !self.0.value.is_null() {
+            let mut minor = GSS_S_COMPLETE;
+            // SAFETY: The buffer was allocated by the library and is released exactly once.
+            unsafe { gss_release_buffer(&mut minor, &mut self.0) };
+        }
+    }
+}
+
+/// An owned name handle, released on drop
+struct OwnedName(gss_name_t);
+
+// SAFETY: Name handles are not bound to the thread that imported them and are not modified after
+// the import.
+unsafe impl Send for OwnedName {}
+unsafe impl Sync for OwnedName {}
+
+impl OwnedName {
+    /// Import `name` as a name of type `name_type`
+    fn import(name: &[u8], name_type: &[u8]) -> Result<Self, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut name_type = oid_desc(name_type);
+        let mut buffer = input_buffer(name);
+        let mut handle: gss_name_t = ptr::null_mut();
+        // SAFETY: All pointers are valid for the duration of the call; the input buffer and OID
+        // are only read from.
+        let major =
+            unsafe { gss_import_name(&mut minor, &mut buffer, &mut name_type, &mut handle) };
+        check(major, minor)?;
+        Ok(Self(handle))
+    }
+
+    const fn as_raw(&self) -> gss_name_t {
+        self.0
+    }
+
+    fn display(&self) -> Result<Vec<u8>, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut out = OwnedBuffer::empty();
+        // SAFETY: The name is a valid handle, the output buffer is released by `OwnedBuffer`.
+        let major =
+            unsafe { gss_display_name(&mut minor, self.0, &mut out.0, ptr::null_mut::<gss_OID>()) };
+        check(major, minor)?;
+        Ok(out.to_vec())
+    }
+}
+
+impl Drop for OwnedName {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            let mut minor = GSS_S_COMPLETE;
+            // SAFETY: The handle is owned by this value and released exactly once.
+            unsafe { gss_release_name(&mut minor, &mut self.0) };
+        }
+    }
+}
+
+/// Acquire credentials for the given usage from the keytab and credential cache requested by the
+/// user callback
+pub fn acquire(credentials: &Credentials, usage: CredUsage) -> Result<StoreCred, Error> {
+    let Credentials {
+        principal,
+        keytab,
+        ccache,
+    } = credentials;
+
+    // An initiator reads its keys from the 'client keytab', the 'keytab' is used by acceptors.
+    // Keys are nul-terminated as they are passed to C as-is.
+    let keytab_key: &[u8] = match usage {
+        CredUsage::Initiate => b"client_keytab\0",
+        _ => b"keytab\0",
+    };
+    let store = keytab
+        .iter()
+        .map(|keytab| (keytab_key, keytab))
+        .chain(ccache.iter().map(|ccache| (&b"ccache\0"[..], ccache)))
+        .map(|(key, value)| Ok((key, CString::new(value.as_str()).map_err(|_| bad_name())?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    acquire_from(principal.as_deref(), usage, &store)
+}
+
+fn bad_name() -> Error {
+    Error::Gss(libgssapi::error::Error {
+        major: MajorFlags::GSS_S_BAD_NAME,
+        minor: 0,
+    })
+}
+
+/// Call `gss_acquire_cred_from` with the given credential store
+///
+/// `libgssapi` does not expose credential stores, so this calls into the C library directly.
+// The store holds at most a keytab and a credential cache, and the usage constants are small
+// positive values whose type differs between MIT Kerberos and Heimdal.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::unnecessary_cast
+)]
+fn acquire_from(
+    principal: Option<&str>,
+    usage: CredUsage,
+    store: &[(&[u8], CString)],
+) -> Result<StoreCred, Error> {
+    let mut minor: OM_uint32 = GSS_S_COMPLETE;
+
+    let name = principal
+        .map(|principal| OwnedName::import(principal.as_bytes(), &GSS_NT_KRB5_PRINCIPAL))
+        .transpose()?;
+
+    let mut krb5 = oid_desc(&GSS_MECH_KRB5);
+    let mut mechs = gss_OID_set_desc {
+        count: 1,
+        elements: &mut krb5,
+    };
+    let mut elements: Vec<gss_key_value_element_desc> = store
+        .iter()
+        .map(|(key, value)| gss_key_value_element_desc {
+            key: key.as_ptr().cast(),
+            value: value.as_ptr(),
+        })
+        .collect();
+    let cred_store = gss_key_value_set_desc {
+        count: elements.len() as OM_uint32,
+        elements: elements.as_mut_ptr(),
+    };
+    let usage = match usage {
+        CredUsage::Initiate => GSS_C_INITIATE,
+        _ => GSS_C_ACCEPT,
+    };
+
+    let mut cred: gss_cred_id_t = ptr::null_mut();
+    // SAFETY: All input structures outlive the call and are not modified by it.
+    let major = unsafe {
+        gss_acquire_cred_from(
+            &mut minor,
+            name.as_ref().map_or(ptr::null_mut(), OwnedName::as_raw),
+            _GSS_C_INDEFINITE,
+            &mut mechs,
+            usage as gss_cred_usage_t,
+            &cred_store,
+            &mut cred,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    if major != GSS_S_COMPLETE {
+        return Err(gss_error(major, minor));
+    }
+
+    // SAFETY: `cred` was just acquired and is owned by nothing else.
+    Ok(unsafe { StoreCred::from_raw(cred) })
+}
+
+/// Credentials acquired from a [`GssKeytab`](super::properties::GssKeytab) or
+/// [`GssCredentialCache`](super::properties::GssCredentialCache)
+///
+/// This is also the type of the [`DelegatedCredential`] collected by acceptors using such
+/// credentials. The handle is owned by this value and released on drop.
+pub struct StoreCred(gss_cred_id_t);
+
+// SAFETY: Credential handles are not bound to the thread that acquired them, and this type only
+// hands out the handle to library calls that do not modify it.
+unsafe impl Send for StoreCred {}
+unsafe impl Sync for StoreCred {}
+
+impl StoreCred {
+    /// Take ownership of a credential handle
+    ///
+    /// # Safety
+    /// `cred` must be a credential handle returned by the library that is not released elsewhere.
+    pub const unsafe fn from_raw(cred: gss_cred_id_t) -> Self {
+        Self(cred)
+    }
+
+    /// The raw credential handle, e.g. to pass to `gss_store_cred_into`
+    ///
+    /// The handle stays owned by this value and must not be released.
+    #[must_use]
+    pub const fn as_raw(&self) -> gss_cred_id_t {
+        self.0
+    }
+}
+
+impl fmt::Debug for StoreCred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreCred").finish_non_exhaustive()
+    }
+}
+
+impl Drop for StoreCred {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            let mut minor = GSS_S_COMPLETE;
+            // SAFETY: The handle is owned by this value and released exactly once.
+            unsafe { gss_release_cred(&mut minor, &mut self.0) };
+        }
+    }
+}
+
+enum Role {
+    Initiator { target: OwnedName, flags: CtxFlags },
+    Acceptor,
+}
+
+/// A security context using credentials from [`StoreCred`]
+pub struct StoreCtx {
+    ctx: gss_ctx_id_t,
+    cred: StoreCred,
+    role: Role,
+    complete: bool,
+    delegated: Option<StoreCred>,
+}
+
+// SAFETY: The context handle is owned by this value and only used through `&mut self`.
+unsafe impl Send for StoreCtx {}
+unsafe impl Sync for StoreCtx {}
+
+impl StoreCtx {
+    /// Start an initiator context to the host-based service `target`
+    pub fn initiator(cred: StoreCred, target: &str, flags: CtxFlags) -> Result<Self, Error> {
+        let target = OwnedName::import(target.as_bytes(), &GSS_NT_HOSTBASED_SERVICE)?;
+        Ok(Self {
+            ctx: ptr::null_mut(),
+            cred,
+            role: Role::Initiator { target, flags },
+            complete: false,
+            delegated: None,
+        })
+    }
+
+    /// Start an acceptor context
+    ///
+    /// Credentials delegated by the initiator are handed out as [`StoreCred`].
+    pub const fn acceptor(cred: StoreCred) -> Self {
+        Self {
+            ctx: ptr::null_mut(),
+            cred,
+            role: Role::Acceptor,
+            complete: false,
+            delegated: None,
+        }
+    }
+
+    fn inquire_flags(&self) -> Result<OM_uint32, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut flags: OM_uint32 = 0;
+        // SAFETY: The context is a valid handle; all outputs but the flags are not requested.
+        let major = unsafe {
+            gss_inquire_context(
+                &mut minor,
+                self.ctx,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut flags,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        check(major, minor)?;
+        Ok(flags)
+    }
+}
+
+impl Drop for StoreCtx {
+    fn drop(&mut self) {
+        if !self.ctx.is_null() {
+            let mut minor = GSS_S_COMPLETE;
+            // SAFETY: The handle is owned by this value and deleted exactly once.
+            unsafe { gss_delete_sec_context(&mut minor, &mut self.ctx, ptr::null_mut()) };
+        }
+    }
+}
+
+impl SecurityContext for StoreCtx {
+    fn step(&mut self, input: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        if self.complete {
+            return Ok(None);
+        }
+        let mut minor = GSS_S_COMPLETE;
+        let mut input = input.map(input_buffer);
+        let mut output = OwnedBuffer::empty();
+        let major = match self.role {
+            Role::Initiator {
+                ref target,
+                ref flags,
+            } => {
+                let mut krb5 = oid_desc(&GSS_MECH_KRB5);
+                let input = input
+                    .as_mut()
+                    .map_or(ptr::null_mut(), |input| input as *mut gss_buffer_desc);
+                // SAFETY: All handles are valid and owned by `self`, the input token is only read
+                // from and the output token is released by `OwnedBuffer`.
+                unsafe {
+                    gss_init_sec_context(
+                        &mut minor,
+                        self.cred.0,
+                        &mut self.ctx,
+                        target.as_raw(),
+                        &mut krb5,
+                        flags.bits(),
+                        _GSS_C_INDEFINITE,
+                        ptr::null_mut(),
+                        input,
+                        ptr::null_mut(),
+                        &mut output.0,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                    )
+                }
+            }
+            Role::Acceptor => {
+                let input = input.get_or_insert_with(|| input_buffer(&[]));
+                let mut delegated: gss_cred_id_t = ptr::null_mut();
+                // SAFETY: As above; the source name is not requested and delegated credentials
+                // are owned by `StoreCred`.
+                let major = unsafe {
+                    gss_accept_sec_context(
+                        &mut minor,
+                        &mut self.ctx,
+                        self.cred.0,
+                        input,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        &mut output.0,
+                        ptr::null_mut(),
+                        ptr::null_mut(),
+                        &mut delegated,
+                    )
+                };
+                if !delegated.is_null() {
+                    // SAFETY: The library handed ownership of the delegated credentials to us.
+                    self.delegated = Some(unsafe { StoreCred::from_raw(delegated) });
+                }
+                major
+            }
+        };
+        check(major, minor)?;
+        self.complete = major & _GSS_S_CONTINUE_NEEDED == 0;
+        let token = output.to_vec();
+        if self.complete && token.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(token))
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    fn flags(&mut self) -> Result<ContextFlags, Error> {
+        let flags = self.inquire_flags()?;
+        Ok(from_gss(CtxFlags::from_bits_retain(flags)))
+    }
+
+    fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut input = input_buffer(msg);
+        let mut output = OwnedBuffer::empty();
+        // SAFETY: The context is a valid handle, the input is only read from and the output is
+        // released by `OwnedBuffer`.
+        let major = unsafe {
+            gss_wrap(
+                &mut minor,
+                self.ctx,
+                c_int::from(encrypt),
+                GSS_C_QOP_DEFAULT,
+                &mut input,
+                ptr::null_mut(),
+                &mut output.0,
+            )
+        };
+        check(major, minor)?;
+        Ok(output.to_vec())
+    }
+
+    fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut input = input_buffer(msg);
+        let mut output = OwnedBuffer::empty();
+        // SAFETY: As for `wrap`.
+        let major = unsafe {
+            gss_unwrap(
+                &mut minor,
+                self.ctx,
+                &mut input,
+                &mut output.0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        check(major, minor)?;
+        Ok(output.to_vec())
+    }
+
+    fn wrap_overhead(&mut self, encrypt: bool, len: usize) -> Result<usize, Error> {
+        wrap_overhead(self, encrypt, len)
+    }
+}
+
+impl Acceptor for StoreCtx {
+    fn source_name(&mut self) -> Result<String, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut name: gss_name_t = ptr::null_mut();
+        // SAFETY: The context is a valid handle; the returned name is released by `OwnedName`.
+        let major = unsafe {
+            gss_inquire_context(
+                &mut minor,
+                self.ctx,
+                &mut name,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        let name = OwnedName(name);
+        check(major, minor)?;
+        String::from_utf8(name.display()?).map_err(|_| Error::BadName)
+    }
+
+    fn take_delegated(&mut self) -> Option<DelegatedCredential> {
+        self.delegated.take().map(DelegatedCredential::new)
+    }
+}
@@ -0,0 +1,188 @@
+//! GSS-API backend using the system library via `libgssapi`
+
+use crate::mechanisms::gssapi::backend::{
+    Acceptor, Backend, ContextFlags, Credentials, DelegatedCredential, SecurityContext,
+};
+use crate::mechanisms::gssapi::properties::Error;
+#[cfg(feature = "gssapi_cred_store")]
+use crate::mechanisms::gssapi::store::{self, StoreCred};
+use libgssapi::context::{ClientCtx, CtxFlags, SecurityContext as GssContext, ServerCtx};
+use libgssapi::credential::{Cred, CredUsage};
+use libgssapi::name::Name;
+use libgssapi::oid::{OidSet, GSS_MECH_KRB5, GSS_NT_HOSTBASED_SERVICE, GSS_NT_KRB5_PRINCIPAL};
+
+/// The system GSS-API library, restricted to the KerberosV5 mechanism
+///
+/// This is the backend used if [`GssBackend`](super::properties::GssBackend) is not satisfied.
+#[derive(Clone, Copy, Debug, Default)]
+#[non_exhaustive]
+pub struct Libgssapi;
+
+impl Backend for Libgssapi {
+    fn initiate(
+        &self,
+        credentials: &Credentials,
+        target: &str,
+        flags: ContextFlags,
+    ) -> Result<Box<dyn SecurityContext>, Error> {
+        match acquire(credentials, CredUsage::Initiate)? {
+            Acquired::Default(cred) => {
+                let target = Name::new(target.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))?;
+                let ctx = ClientCtx::new(Some(cred), target, to_gss(flags), Some(&GSS_MECH_KRB5));
+                Ok(Box::new(ctx))
+            }
+            #[cfg(feature = "gssapi_cred_store")]
+            Acquired::Store(cred) => Ok(Box::new(store::StoreCtx::initiator(
+                cred,
+                target,
+                to_gss(flags),
+            )?)),
+        }
+    }
+
+    fn accept(&self, credentials: &Credentials) -> Result<Box<dyn Acceptor>, Error> {
+        match acquire(credentials, CredUsage::Accept)? {
+            Acquired::Default(cred) => Ok(Box::new(ServerCtx::new(cred))),
+            #[cfg(feature = "gssapi_cred_store")]
+            Acquired::Store(cred) => Ok(Box::new(store::StoreCtx::acceptor(cred))),
+        }
+    }
+}
+
+const FLAG_MAP: [(ContextFlags, CtxFlags); 7] = [
+    (ContextFlags::DELEGATE, CtxFlags::GSS_C_DELEG_FLAG),
+    (ContextFlags::MUTUAL, CtxFlags::GSS_C_MUTUAL_FLAG),
+    (ContextFlags::REPLAY, CtxFlags::GSS_C_REPLAY_FLAG),
+    (ContextFlags::SEQUENCE, CtxFlags::GSS_C_SEQUENCE_FLAG),
+    (ContextFlags::CONFIDENTIALITY, CtxFlags::GSS_C_CONF_FLAG),
+    (ContextFlags::INTEGRITY, CtxFlags::GSS_C_INTEG_FLAG),
+    (ContextFlags::ANONYMOUS, CtxFlags::GSS_C_ANON_FLAG),
+];
+
+fn to_gss(flags: ContextFlags) -> CtxFlags {
+    FLAG_MAP
+        .iter()
+        .filter(|(flag, _)| flags.contains(*flag))
+        .fold(CtxFlags::empty(), |acc, (_, gss)| acc | *gss)
+}
+
+pub(super) fn from_gss(flags: CtxFlags) -> ContextFlags {
+    FLAG_MAP
+        .iter()
+        .filter(|(_, gss)| flags.contains(*gss))
+        .fold(ContextFlags::empty(), |acc, (flag, _)| acc | *flag)
+}
+
+/// Calculate the wrapping overhead of a message of `len` bytes by wrapping a probe message
+///
+/// `gss_wrap_iov_length` would avoid the work, but is not available with every GSS-API library.
+pub(super) fn wrap_overhead<C: SecurityContext + ?Sized>(
+    ctx: &mut C,
+    encrypt: bool,
+    len: usize,
+) -> Result<usize, Error> {
+    let wrapped = ctx.wrap(encrypt, &vec![0u8; len])?;
+    Ok(wrapped.len().saturating_sub(len))
+}
+
+impl SecurityContext for ClientCtx {
+    fn step(&mut self, input: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        let token = Self::step(self, input, None)?;
+        Ok(token.map(|token| token.to_vec()))
+    }
+
+    fn is_complete(&self) -> bool {
+        GssContext::is_complete(self)
+    }
+
+    fn flags(&mut self) -> Result<ContextFlags, Error> {
+        Ok(from_gss(GssContext::flags(self)?))
+    }
+
+    fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(GssContext::wrap(self, encrypt, msg)?.to_vec())
+    }
+
+    fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(GssContext::unwrap(self, msg)?.to_vec())
+    }
+
+    fn wrap_overhead(&mut self, encrypt: bool, len: usize) -> Result<usize, Error> {
+        wrap_overhead(self, encrypt, len)
+    }
+}
+
+impl SecurityContext for ServerCtx {
+    fn step(&mut self, input: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        let token = Self::step(self, input.unwrap_or_default())?;
+        Ok(token.map(|token| token.to_vec()))
+    }
+
+    fn is_complete(&self) -> bool {
+        GssContext::is_complete(self)
+    }
+
+    fn flags(&mut self) -> Result<ContextFlags, Error> {
+        Ok(from_gss(GssContext::flags(self)?))
+    }
+
+    fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(GssContext::wrap(self, encrypt, msg)?.to_vec())
+    }
+
+    fn unwrap(&mut self, msg: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(GssContext::unwrap(self, msg)?.to_vec())
+    }
+
+    fn wrap_overhead(&mut self, encrypt: bool, len: usize) -> Result<usize, Error> {
+        wrap_overhead(self, encrypt, len)
+    }
+}
+
+impl Acceptor for ServerCtx {
+    fn source_name(&mut self) -> Result<String, Error> {
+        let name = GssContext::source_name(self)?.display_name()?;
+        String::from_utf8(name.to_vec()).map_err(|_| Error::BadName)
+    }
+
+    fn take_delegated(&mut self) -> Option<DelegatedCredential> {
+        self.take_delegated_cred().map(DelegatedCredential::new)
+    }
+}
+
+/// Credentials acquired by [`acquire`]
+enum Acquired {
+    /// Credentials acquired by `libgssapi` from the default locations
+    Default(Cred),
+    /// Credentials acquired from an explicit keytab or credential cache
+    #[cfg(feature = "gssapi_cred_store")]
+    Store(StoreCred),
+}
+
+/// Acquire credentials for the given usage from the principal, keytab and credential cache
+/// requested by the user callback.
+///
+/// Selecting a keytab or credential cache requires `gss_acquire_cred_from`, which is only used
+/// with feature `gssapi_cred_store`.
+fn acquire(credentials: &Credentials, usage: CredUsage) -> Result<Acquired, Error> {
+    if credentials.keytab.is_none() && credentials.ccache.is_none() {
+        let name = credentials
+            .principal
+            .as_ref()
+            .map(|p| Name::new(p.as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL)))
+            .transpose()?;
+        let mut krb5 = OidSet::new()?;
+        krb5.add(&GSS_MECH_KRB5)?;
+        let cred = Cred::acquire(name.as_ref(), None, usage, Some(&krb5))?;
+        return Ok(Acquired::Default(cred));
+    }
+
+    #[cfg(feature = "gssapi_cred_store")]
+    {
+        Ok(Acquired::Store(store::acquire(credentials, usage)?))
+    }
+    #[cfg(not(feature = "gssapi_cred_store"))]
+    {
+        Err(Error::CredentialStoreUnsupported)
+    }
+}
@@ -1,3 +1,4 @@
+use crate::alloc::boxed::Box;
 use crate::context::EmptyProvider;
 use crate::error::SessionError;
 use crate::mechanism::{Authentication, MechanismData};
@@ -49,6 +50,10 @@ impl Authentication for Login {
             LoginState::Done => Err(SessionError::MechanismDone),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(*self))
+    }
 }
 
 #[cfg(test)]
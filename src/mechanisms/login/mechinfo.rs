@@ -1,6 +1,7 @@
 use super::{client, server};
 use crate::alloc::boxed::Box;
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named};
 use crate::session::Side;
 
@@ -17,6 +18,8 @@ pub static LOGIN: Mechanism = Mechanism {
 
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(SecurityFlags::PLAINTEXT)
+        .with_properties(&["AuthId", "Password"], &[]),
 };
 
 struct Select;
@@ -1,7 +1,13 @@
-use crate::alloc::string::String;
+#[cfg(feature = "session_export")]
+use crate::alloc::vec::Vec;
+use crate::alloc::{boxed::Box, string::String};
 use crate::context::{Demand, DemandReply, Provider};
 use crate::error::MechanismErrorKind;
+#[cfg(feature = "session_export")]
+use crate::export::{StateReader, StateWriter};
 use crate::mechanism::{Authentication, MechanismError};
+#[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+use crate::mechanisms::scram::credentials::upgrade_credentials;
 use crate::prelude::SessionError;
 use crate::property::{AuthId, Password};
 use crate::session::{MechanismData, MessageSent, State};
@@ -21,11 +27,11 @@ impl MechanismError for LoginError {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Login {
     state: LoginState,
 }
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 enum LoginState {
     New,
     WaitingForUsername,
@@ -85,6 +91,8 @@ impl Authentication for Login {
                         password: input,
                     };
                     session.validate(&prov)?;
+                    #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+                    upgrade_credentials(session, &prov, input)?;
                     self.state = LoginState::Done;
                     Ok(State::Finished(MessageSent::No))
                 } else {
@@ -94,4 +102,40 @@ impl Authentication for Login {
             LoginState::Done => Err(SessionError::MechanismDone),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        let mut writer = StateWriter::default();
+        match self.state {
+            LoginState::New => writer.u8(0),
+            LoginState::WaitingForUsername => writer.u8(1),
+            LoginState::WaitingForPassword(ref username) => writer.u8(2).str(username),
+            LoginState::Done => writer.u8(3),
+        };
+        Some(writer.finish())
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, state: &[u8]) -> bool {
+        let mut reader = StateReader::new(state);
+        let state = match reader.u8() {
+            Some(0) => LoginState::New,
+            Some(1) => LoginState::WaitingForUsername,
+            Some(2) => match reader.str() {
+                Some(username) => LoginState::WaitingForPassword(username.to_string()),
+                None => return false,
+            },
+            Some(3) => LoginState::Done,
+            _ => return false,
+        };
+        if reader.finish().is_none() {
+            return false;
+        }
+        self.state = state;
+        true
+    }
 }
@@ -64,6 +64,19 @@ pub mod login {
     //! The `LOGIN` mechanism sends authentication data in the plain without any form of hashing
     //! or encryption being applied. It should thus only be used over an encrypted channel such
     //! as TLS.
+    //!
+    //! # Server
+    //! The provider passed to `validate` will allow access to [`AuthId`] and [`Password`].
+    //!
+    //! If a `SCRAM-*` mechanism is enabled, [`ScramCredentialUpgrade`] is requested after
+    //! `validate` finalized the validation, allowing the callback to opt into generating SCRAM
+    //! credentials from the password that was used. The outcome of the validation is provided
+    //! with the request as `ScramValidationOutcome`.
+
+    #[cfg(all(doc, any(feature = "scram-sha-1", feature = "scram-sha-2")))]
+    use crate::mechanisms::scram::properties::ScramCredentialUpgrade;
+    #[cfg(doc)]
+    use crate::property::*;
 
     mod client;
     mod mechinfo;
@@ -101,7 +114,14 @@ pub mod plain {
     //! - `Password` may or may not be UTF-8. If it is UTF-8 saslprep will have been applied to it.
     //!   If it is not UTF-8 the input bytes are provided verbatim with no modification or
     //!   preparation algorithm applied.
+    //!
+    //! If a `SCRAM-*` mechanism is enabled, [`ScramCredentialUpgrade`] is requested after
+    //! `validate` finalized the validation, allowing the callback to opt into generating SCRAM
+    //! credentials from the password that was used. The outcome of the validation is provided
+    //! with the request as `ScramValidationOutcome`.
 
+    #[cfg(all(doc, any(feature = "scram-sha-1", feature = "scram-sha-2")))]
+    use crate::mechanisms::scram::properties::ScramCredentialUpgrade;
     #[cfg(doc)]
     use crate::property::*;
 
@@ -133,15 +153,26 @@ pub mod scram {
     //!
     //! Afterwards [`ChannelBindings`] is queried, with the name of channel bindings to be
     //! supplied available from the provider as [`ChannelBindingName`].
+    //!
+    //! # Server
+    //!
+    //! The server queries [`ScramStoredPassword`](properties::ScramStoredPassword) for the
+    //! credentials of the user. If that request is not satisfied the plaintext [`Password`] is
+    //! queried instead and the required keys are derived on the fly using a random salt.
+    //! [`ScramCredentials`](credentials::ScramCredentials) can be used to generate stored
+    //! credentials from a plaintext password ahead of time.
 
     #[cfg(doc)]
     use crate::property::*;
 
+    pub mod cache;
     mod client;
+    pub mod credentials;
     mod mechinfo;
     mod parser;
     pub mod properties;
-    mod server;
+    pub(crate) mod server;
+    pub mod storage;
     pub mod tools;
     pub use mechinfo::*;
 }
@@ -197,23 +228,67 @@ pub mod oauthbearer {
     pub use mechinfo::*;
 }
 
-#[cfg(feature = "gssapi")]
+#[cfg(feature = "gssapi_backend")]
 pub mod gssapi {
-    //! `GSSAPI` *mechanism. Requires feature `gssapi`*
+    //! `GSSAPI` *mechanism. Requires feature `gssapi` or `gssapi_backend`*
+    //!
+    //! GSS-API operations are performed by a [`Backend`](backend::Backend), by default the system
+    //! library via `libgssapi`. See the [`backend`] module for using a different implementation.
     //!
     //! # Client
     //!
-    //! Requests the properties [`GssService`](properties::GssService), [`Hostname`] and [`GssSecurityLayer`](properties::GssSecurityLayer) using 'satisfiable' callbacks.
+    //! Requests the properties [`GssService`](properties::GssService), [`Hostname`], [`GssSecurityLayer`](properties::GssSecurityLayer) and [`AuthzId`] using 'satisfiable' callbacks.
+    //! If a security layer is selected [`GssMaxBufferSize`](properties::GssMaxBufferSize) is also requested.
+    //!
+    //! Before acquiring credentials [`GssBackend`](properties::GssBackend),
+    //! [`GssInitiatorPrincipal`](properties::GssInitiatorPrincipal),
+    //! [`GssDelegateCredentials`](properties::GssDelegateCredentials),
+    //! [`GssKeytab`](properties::GssKeytab) and [`GssCredentialCache`](properties::GssCredentialCache)
+    //! are requested using 'satisfiable' callbacks.
     //!
     //! # Server
     //!
-    //! Requests the property [`GssSecurityLayer`](properties::GssSecurityLayer) using a 'satisfiable' callback.
+    //! Requests the properties [`GssSecurityLayer`](properties::GssSecurityLayer) and [`GssMaxBufferSize`](properties::GssMaxBufferSize) using 'satisfiable' callbacks.
+    //!
+    //! Before acquiring credentials [`GssBackend`](properties::GssBackend),
+    //! [`GssAcceptorPrincipal`](properties::GssAcceptorPrincipal),
+    //! [`GssKeytab`](properties::GssKeytab) and [`GssCredentialCache`](properties::GssCredentialCache)
+    //! are requested using 'satisfiable' callbacks.
+    //!
+    //! Before calling `validate` [`GssAuthToLocal`](properties::GssAuthToLocal) is requested using
+    //! a 'satisfiable' callback to map the authenticated principal to a local identity.
+    //!
+    //! Calls `validate` once the security layer has been negotiated, providing [`AuthId`],
+    //! [`AuthzId`] if the client sent one, [`GssapiDisplayName`] and
+    //! [`GssDelegatedCredential`](properties::GssDelegatedCredential), which contains the
+    //! credentials delegated by the client, if any.
+    //!
+    //! # Security layer
+    //!
+    //! Both sides advertise the maximum size of a wrapped message they accept. Once a layer is
+    //! installed [`Session::max_buffer_size`](crate::prelude::Session::max_buffer_size) returns
+    //! the limit of the other party, and each call to `encode` protects only as much input as fits
+    //! into a single message of that size.
     #[cfg(doc)]
     use crate::property::*;
 
+    pub mod auth_to_local;
+    pub mod backend;
     mod client;
+    mod cred;
+    mod layer;
     mod mechinfo;
+    #[cfg(any(test, feature = "testutils"))]
+    pub mod mock;
     pub mod properties;
     mod server;
+    #[cfg(feature = "gssapi_cred_store")]
+    mod store;
+    #[cfg(feature = "gssapi")]
+    mod system;
     pub use mechinfo::*;
+    #[cfg(feature = "gssapi_cred_store")]
+    pub use store::StoreCred;
+    #[cfg(feature = "gssapi")]
+    pub use system::Libgssapi;
 }
@@ -1,3 +1,4 @@
+use crate::alloc::boxed::Box;
 use crate::context::EmptyProvider;
 use crate::error::SessionError;
 use crate::mechanism::{Authentication, MechanismData, State};
@@ -80,4 +81,8 @@ impl Authentication for OAuthBearer {
             OAuthBearerState::Done => Err(SessionError::MechanismDone),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
 }
@@ -1,6 +1,7 @@
 use super::{client, server};
 use crate::alloc::boxed::Box;
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named, Side};
 
 #[cfg_attr(
@@ -19,6 +20,14 @@ pub static OAUTHBEARER: Mechanism = Mechanism {
 
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(
+        SecurityFlags::PLAINTEXT.union(SecurityFlags::DICTIONARY_RESISTANT),
+    )
+    .with_authzid()
+    .with_properties(
+        &["AuthzId", "OAuthBearerKV", "OAuthBearerToken"],
+        &["OAuthBearerValidate"],
+    ),
 };
 
 struct Select;
@@ -1,3 +1,6 @@
+use crate::alloc::boxed::Box;
+#[cfg(feature = "session_export")]
+use crate::alloc::vec::Vec;
 use crate::context::{Demand, DemandReply, Provider};
 use crate::error::SessionError;
 use crate::mechanism::{Authentication, MechanismData, State};
@@ -77,6 +80,26 @@ impl Authentication for OAuthBearer {
             OAuthBearerState::Errored => Ok(State::Finished(MessageSent::No)),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        let errored = self.state == OAuthBearerState::Errored;
+        Some(vec![u8::from(errored)])
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, state: &[u8]) -> bool {
+        self.state = match state {
+            [0] => OAuthBearerState::Initial,
+            [1] => OAuthBearerState::Errored,
+            _ => return false,
+        };
+        true
+    }
 }
 
 #[cfg(test)]
@@ -59,6 +59,10 @@ impl Authentication for Plain {
 
         Ok(State::Finished(MessageSent::Yes))
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(*self))
+    }
 }
 
 #[cfg(test)]
@@ -2,6 +2,7 @@ use crate::alloc::boxed::Box;
 use crate::error::{MechanismError, MechanismErrorKind};
 use crate::mechanisms::plain::{client, server};
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named};
 use crate::session::Side;
 use core::str::Utf8Error;
@@ -24,6 +25,9 @@ pub static PLAIN: Mechanism = Mechanism {
     first: Side::Client,
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(SecurityFlags::PLAINTEXT)
+        .with_authzid()
+        .with_properties(&["AuthId", "AuthzId", "Password"], &[]),
 };
 
 struct Select;
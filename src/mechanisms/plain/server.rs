@@ -1,7 +1,12 @@
 use super::mechinfo::PlainError;
+use crate::alloc::boxed::Box;
+#[cfg(feature = "session_export")]
+use crate::alloc::vec::Vec;
 use crate::context::{Demand, DemandReply, Provider};
 use crate::error::SessionError;
 use crate::mechanism::Authentication;
+#[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+use crate::mechanisms::scram::credentials::upgrade_credentials;
 use crate::property::{AuthId, AuthzId, Password};
 use crate::session::{MechanismData, MessageSent, State};
 use core2::io::Write;
@@ -22,6 +27,7 @@ impl<'a> Provider<'a> for PlainProvider<'a> {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
 pub struct Plain;
 impl Authentication for Plain {
     #[allow(clippy::similar_names)]
@@ -70,6 +76,8 @@ impl Authentication for Plain {
             };
 
             session.validate(&provider)?;
+            #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+            upgrade_credentials(session, &provider, provider.password)?;
         } else {
             if password.is_empty() {
                 return Err(PlainError::Empty.into());
@@ -82,8 +90,141 @@ impl Authentication for Plain {
             };
 
             session.validate(&provider)?;
+            #[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+            upgrade_credentials(session, &provider, provider.password)?;
         }
 
         Ok(State::Finished(MessageSent::No))
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(*self))
+    }
+
+    // The mechanism has no state besides being constructed
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        Some(Vec::new())
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, state: &[u8]) -> bool {
+        state.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "scram-sha-2"))]
+mod tests {
+    use crate::callback::{Context, Request, SessionCallback, SessionData};
+    use crate::channel_bindings::NoChannelBindings;
+    use crate::error::SessionError;
+    use crate::mechanisms::scram::credentials::{ScramCredentials, ScramDigest};
+    use crate::mechanisms::scram::properties::{
+        ScramCredentialUpgrade, ScramUpgradedCredentials, ScramValidationOutcome,
+    };
+    use crate::property::Password;
+    use crate::sasl::Sasl;
+    use crate::session::{Session, Side};
+    use crate::test;
+    use crate::typed::Tagged;
+    use crate::validate::{Validate, Validation, ValidationError};
+    use core::any::Any;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A validation that is finalized for rejected logins as well
+    struct Accepted;
+    impl Validation for Accepted {
+        type Value = Result<(), ()>;
+    }
+
+    struct C {
+        upgrade: bool,
+        handle_action: bool,
+        upgraded: Arc<AtomicUsize>,
+    }
+    impl SessionCallback for C {
+        fn callback(
+            &self,
+            _session_data: &SessionData,
+            context: &Context,
+            request: &mut Request,
+        ) -> Result<(), SessionError> {
+            let accepted = context
+                .get_ref::<ScramValidationOutcome>()
+                .and_then(<dyn Any>::downcast_ref::<Result<(), ()>>)
+                .map_or(false, Result::is_ok);
+            if self.upgrade && accepted {
+                request.satisfy::<ScramCredentialUpgrade>(&4096)?;
+            }
+            if !self.handle_action {
+                return Ok(());
+            }
+            if let Some(credentials) = request.get_action::<ScramUpgradedCredentials>() {
+                assert_eq!(credentials.len(), ScramDigest::ALL.len());
+                for creds in credentials {
+                    let expected =
+                        ScramCredentials::derive(creds.digest(), b"secret", creds.salt(), 4096);
+                    assert_eq!(creds, &expected);
+                }
+                self.upgraded.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(())
+        }
+
+        fn validate(
+            &self,
+            _session_data: &SessionData,
+            context: &Context,
+            validate: &mut Validate<'_>,
+        ) -> Result<(), ValidationError> {
+            let outcome = if context.get_ref::<Password>() == Some(b"secret".as_slice()) {
+                Ok(())
+            } else {
+                Err(())
+            };
+            validate.finalize::<Accepted>(outcome);
+            Ok(())
+        }
+    }
+
+    fn authenticate(input: &[u8], upgrade: bool, handle_action: bool) -> usize {
+        let upgraded = Arc::new(AtomicUsize::new(0));
+        let config = test::server_config(C {
+            upgrade,
+            handle_action,
+            upgraded: upgraded.clone(),
+        });
+        let mechanism = &super::super::mechinfo::PLAIN;
+        let mech = mechanism.server(&config).unwrap().unwrap();
+        let sasl = Sasl {
+            config,
+            cb: NoChannelBindings,
+            validation: Tagged::<Accepted>(None),
+        };
+        let mut session = Session::new(sasl, Side::Server, mech, *mechanism);
+        let state = session
+            .step(Some(input), &mut Cursor::new(Vec::new()))
+            .unwrap();
+        assert!(state.is_finished());
+        upgraded.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_credential_upgrade() {
+        assert_eq!(authenticate(b"\0user\0secret", true, true), 1);
+        assert_eq!(authenticate(b"\0user\0secret", false, true), 0);
+    }
+
+    #[test]
+    fn test_no_upgrade_on_failed_login() {
+        assert_eq!(authenticate(b"\0user\0wrong", true, true), 0);
+    }
+
+    #[test]
+    fn test_unhandled_upgrade_action() {
+        // The login succeeds even though nobody took the upgraded credentials
+        assert_eq!(authenticate(b"\0user\0secret", true, false), 0);
+    }
 }
@@ -0,0 +1,308 @@
+//! Caching of SCRAM keys derived from plaintext passwords
+//!
+//! If the callback of a SCRAM server answers [`Password`](crate::property::Password) instead of
+//! [`ScramStoredPassword`](super::properties::ScramStoredPassword) the server has to run PBKDF2
+//! for every authentication exchange. A [`ScramKeyCache`] installed using
+//! [`ConfigBuilder::with_scram_key_cache`](crate::config::ConfigBuilder::with_scram_key_cache)
+//! stores the derived keys instead, so that following exchanges of the same user can skip the
+//! key derivation.
+//!
+//! Every entry is bound to the password it was derived from using a MAC keyed with a secret of
+//! the cache. An entry is only used if the password the callback currently answers matches; if
+//! it doesn't the entry is dropped and the keys are derived again. [`ScramKeyCache::invalidate`]
+//! can still be used to evict the entries of an user right away.
+
+use crate::alloc::{string::String, vec::Vec};
+use crate::mechanisms::scram::credentials::{ScramCredentials, ScramDigest};
+use crate::zeroize;
+use core::fmt;
+use digest::crypto_common::BlockSizeUser;
+use digest::{Digest, Mac};
+use hmac::SimpleHmac;
+use rand::{thread_rng, RngCore};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Domain separation labels for the MACs computed with the secret of a cache
+const SALT_LABEL: &[u8] = b"rsasl scram cache salt\0";
+const PASSWORD_LABEL: &[u8] = b"rsasl scram cache password\0";
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct CacheKey {
+    authid: String,
+    salt: Vec<u8>,
+    iterations: u32,
+    digest: ScramDigest,
+}
+
+impl CacheKey {
+    fn new(authid: &str, credentials: &ScramCredentials) -> Self {
+        Self {
+            authid: String::from(authid),
+            salt: credentials.salt().to_vec(),
+            iterations: credentials.iterations(),
+            digest: credentials.digest(),
+        }
+    }
+}
+
+struct Entry {
+    credentials: ScramCredentials,
+    password_mac: Vec<u8>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    clock: u64,
+    map: HashMap<CacheKey, Entry>,
+}
+
+/// Bounded cache of SCRAM keys, shared between sessions
+///
+/// Entries are identified by the authentication id, the salt, the iteration count and the digest
+/// used. The salt of an user is derived from the authentication id and a random secret of the
+/// cache, see [`salt`](Self::salt). If the cache is full the least recently used entry is
+/// evicted. The keys of evicted or invalidated entries are overwritten with zeroes.
+pub struct ScramKeyCache {
+    capacity: usize,
+    secret: [u8; 32],
+    entries: Mutex<Entries>,
+}
+
+fn keyed_mac<D: Digest + BlockSizeUser>(secret: &[u8], label: &[u8], data: &[u8]) -> SimpleHmac<D> {
+    let mut mac = <SimpleHmac<D>>::new_from_slice(secret).expect("HMAC can work with any key size");
+    Mac::update(&mut mac, label);
+    Mac::update(&mut mac, data);
+    mac
+}
+
+impl ScramKeyCache {
+    /// Construct an empty cache holding at most `capacity` entries
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        let mut secret = [0u8; 32];
+        thread_rng().fill_bytes(&mut secret);
+        Self {
+            capacity,
+            secret,
+            entries: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        // The entries are always left in a consistent state, so a poisoned lock can be reused.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[allow(clippy::missing_panics_doc)]
+    fn mac(&self, digest: ScramDigest, label: &[u8], data: &[u8]) -> Vec<u8> {
+        match digest {
+            #[cfg(feature = "scram-sha-1")]
+            ScramDigest::Sha1 => keyed_mac::<sha1::Sha1>(&self.secret, label, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            #[cfg(feature = "scram-sha-2")]
+            ScramDigest::Sha256 => keyed_mac::<sha2::Sha256>(&self.secret, label, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+            #[cfg(feature = "scram-sha-2")]
+            ScramDigest::Sha512 => keyed_mac::<sha2::Sha512>(&self.secret, label, data)
+                .finalize()
+                .into_bytes()
+                .to_vec(),
+        }
+    }
+
+    fn verify_password(&self, digest: ScramDigest, password: &[u8], password_mac: &[u8]) -> bool {
+        let result = match digest {
+            #[cfg(feature = "scram-sha-1")]
+            ScramDigest::Sha1 => keyed_mac::<sha1::Sha1>(&self.secret, PASSWORD_LABEL, password)
+                .verify_slice(password_mac),
+            #[cfg(feature = "scram-sha-2")]
+            ScramDigest::Sha256 => {
+                keyed_mac::<sha2::Sha256>(&self.secret, PASSWORD_LABEL, password)
+                    .verify_slice(password_mac)
+            }
+            #[cfg(feature = "scram-sha-2")]
+            ScramDigest::Sha512 => {
+                keyed_mac::<sha2::Sha512>(&self.secret, PASSWORD_LABEL, password)
+                    .verify_slice(password_mac)
+            }
+        };
+        result.is_ok()
+    }
+
+    /// The salt used to derive the keys of `authid` for the given digest
+    ///
+    /// The salt stays the same for the lifetime of the cache, so that entries can be looked up
+    /// by it, but differs between caches.
+    #[must_use]
+    pub fn salt(&self, authid: &str, digest: ScramDigest) -> Vec<u8> {
+        self.mac(digest, SALT_LABEL, authid.as_bytes())
+    }
+
+    /// Look up the credentials of `authid` for the given digest and iteration count
+    ///
+    /// Returns `None` if there is no entry, or if the entry was derived from a different
+    /// password than `password`. In the latter case the entry is removed.
+    #[must_use]
+    pub fn get(
+        &self,
+        authid: &str,
+        password: &[u8],
+        digest: ScramDigest,
+        iterations: u32,
+    ) -> Option<ScramCredentials> {
+        let key = CacheKey {
+            authid: String::from(authid),
+            salt: self.salt(authid, digest),
+            iterations,
+            digest,
+        };
+        let mut entries = self.lock();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.map.get_mut(&key)?;
+        if !self.verify_password(digest, password, &entry.password_mac) {
+            entries.map.remove(&key);
+            return None;
+        }
+        entry.last_used = clock;
+        let credentials = entry.credentials.clone();
+        drop(entries);
+        Some(credentials)
+    }
+
+    /// Insert credentials derived from `password` for `authid`, replacing any previous entry
+    ///
+    /// The credentials are only found by [`get`](Self::get) if they were derived using the salt
+    /// returned by [`salt`](Self::salt).
+    pub fn insert(&self, authid: &str, password: &[u8], credentials: ScramCredentials) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = CacheKey::new(authid, &credentials);
+        let password_mac = self.mac(credentials.digest(), PASSWORD_LABEL, password);
+        let mut entries = self.lock();
+        entries.clock += 1;
+        let last_used = entries.clock;
+        if !entries.map.contains_key(&key) && entries.map.len() >= self.capacity {
+            let oldest = entries
+                .map
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.map.remove(&oldest);
+            }
+        }
+        entries.map.insert(
+            key,
+            Entry {
+                credentials,
+                password_mac,
+                last_used,
+            },
+        );
+    }
+
+    /// Remove all entries of `authid`, e.g. because their password was changed
+    pub fn invalidate(&self, authid: &str) {
+        self.lock().map.retain(|key, _| key.authid != authid);
+    }
+
+    /// Remove all entries
+    pub fn clear(&self) {
+        self.lock().map.clear();
+    }
+
+    /// The number of entries currently cached
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().map.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of entries this cache holds
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Drop for ScramKeyCache {
+    fn drop(&mut self) {
+        zeroize(&mut self.secret);
+    }
+}
+
+impl fmt::Debug for ScramKeyCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramKeyCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(cache: &ScramKeyCache, authid: &str, password: &[u8], iterations: u32) {
+        let digest = ScramDigest::ALL[0];
+        let salt = cache.salt(authid, digest);
+        let credentials = ScramCredentials::derive(digest, password, &salt, iterations);
+        cache.insert(authid, password, credentials);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let digest = ScramDigest::ALL[0];
+        let cache = ScramKeyCache::new(2);
+        insert(&cache, "a", b"a", 1);
+        insert(&cache, "b", b"b", 1);
+        // Use "a" so "b" becomes the least recently used entry
+        assert!(cache.get("a", b"a", digest, 1).is_some());
+        insert(&cache, "c", b"c", 1);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b", b"b", digest, 1).is_none());
+        assert!(cache.get("c", b"c", digest, 1).is_some());
+        assert!(cache.get("a", b"a", digest, 2).is_none());
+
+        cache.invalidate("a");
+        assert!(cache.get("a", b"a", digest, 1).is_none());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_password_change() {
+        let digest = ScramDigest::ALL[0];
+        let cache = ScramKeyCache::new(2);
+        insert(&cache, "a", b"old", 1);
+        assert!(cache.get("a", b"new", digest, 1).is_none());
+        // The stale entry was dropped
+        assert!(cache.is_empty());
+        assert!(cache.get("a", b"old", digest, 1).is_none());
+    }
+
+    #[test]
+    fn test_salt() {
+        let digest = ScramDigest::ALL[0];
+        let cache = ScramKeyCache::new(2);
+        assert_eq!(cache.salt("a", digest), cache.salt("a", digest));
+        assert_ne!(cache.salt("a", digest), cache.salt("b", digest));
+        assert_ne!(
+            cache.salt("a", digest),
+            ScramKeyCache::new(2).salt("a", digest)
+        );
+    }
+}
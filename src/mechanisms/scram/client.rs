@@ -1,14 +1,18 @@
-use crate::alloc::{string::String, vec::Vec};
+use crate::alloc::{boxed::Box, string::String, vec::Vec};
 use crate::callback::CallbackError;
 use crate::context::{Demand, DemandReply, EmptyProvider, Provider};
 use crate::error::{MechanismError, MechanismErrorKind, SessionError};
 use crate::mechanism::Authentication;
 use crate::mechanisms::scram::parser::{
-    ClientFinal, SaslName, ServerErrorValue, ServerFinal, ServerFirst,
+    ClientFinal, Extensions, ParseError, SaslName, ServerErrorValue, ServerFinal, ServerFirst,
+};
+use crate::mechanisms::scram::properties::{
+    Iterations, Salt, SaltedPassword, ScramCachedPassword, ScramClientExtensions,
+    ScramMandatoryExtension, ScramServerExtensions,
 };
-use crate::mechanisms::scram::properties::{Iterations, Salt, SaltedPassword, ScramCachedPassword};
 use crate::mechanisms::scram::tools::{
-    compute_signatures, derive_keys, generate_nonce, hash_password, DOutput,
+    accepts_mandatory_extension, derive_keys, generate_nonce, hash_password, sign_auth_message,
+    DOutput,
 };
 use crate::property::{AuthId, AuthzId, OverrideCBType, Password};
 use crate::session::{MechanismData, MessageSent, State};
@@ -29,11 +33,13 @@ pub type ScramSha512Client<const N: usize> = ScramClient<sha2::Sha512, N>;
 #[cfg(feature = "scram-sha-1")]
 pub type ScramSha1Client<const N: usize> = ScramClient<sha1::Sha1, N>;
 
+#[derive(Clone)]
 enum CbSupport {
     ClientNoSupport,
     ServerNoSupport,
     Supported,
 }
+#[derive(Clone)]
 pub struct ScramClient<D: Digest + BlockSizeUser + FixedOutputReset, const N: usize> {
     state: Option<ScramClientState<D, N>>,
 }
@@ -59,12 +65,14 @@ impl<D: Digest + BlockSizeUser + FixedOutputReset, const N: usize> ScramClient<D
     }
 }
 
+#[derive(Clone)]
 enum ScramClientState<D: Digest + BlockSizeUser + FixedOutputReset, const N: usize> {
     Initial(ScramState<StateClientFirst<N>>),
     ClientFirst(ScramState<WaitingServerFirst<D, N>>),
     ServerFirst(ScramState<WaitingServerFinal<D>>),
 }
 
+#[derive(Clone)]
 struct ScramState<S> {
     state: S,
 }
@@ -124,6 +132,7 @@ impl<D: Digest + BlockSizeUser> ScramState<WaitingServerFinal<D>> {
     }
 }
 
+#[derive(Clone)]
 struct StateClientFirst<const N: usize> {
     plus: CbSupport,
     nonce: PhantomData<&'static [u8; N]>,
@@ -213,14 +222,26 @@ impl<const N: usize> StateClientFirst<N> {
 
         let client_nonce: [u8; N] = generate_nonce(rng);
 
-        let iovecs = [
-            &gs2_header[..],
-            b"n=",
-            username.as_bytes(),
-            b",r=",
-            &client_nonce,
-        ];
-        let mut vecw = VectoredWriter::new(iovecs);
+        let mut client_first_bare = Vec::new();
+        session.maybe_need_with::<ScramMandatoryExtension, _, _>(&EmptyProvider, |mext| {
+            if mext.is_empty() || mext.contains(',') {
+                return Err(SCRAMError::ParseError(ParseError::BadExtensionValue).into());
+            }
+            client_first_bare.extend_from_slice(b"m=");
+            client_first_bare.extend_from_slice(mext.as_bytes());
+            client_first_bare.push(b',');
+            Ok(())
+        })?;
+        client_first_bare.extend_from_slice(b"n=");
+        client_first_bare.extend_from_slice(username.as_bytes());
+        client_first_bare.extend_from_slice(b",r=");
+        client_first_bare.extend_from_slice(&client_nonce);
+        session.maybe_need_with::<ScramClientExtensions, _, _>(&EmptyProvider, |extensions| {
+            Extensions::encode_into(extensions, &mut client_first_bare)
+                .map_err(|e| SCRAMError::ParseError(e).into())
+        })?;
+
+        let mut vecw = VectoredWriter::new([&gs2_header[..], &client_first_bare[..]]);
         (*written) = vecw.write_all_vectored(writer)?;
 
         if let Some(cbdata) = cbdata {
@@ -231,19 +252,33 @@ impl<const N: usize> StateClientFirst<N> {
         Ok(WaitingServerFirst::new(
             channel_bindings,
             client_nonce,
-            username,
+            client_first_bare,
         ))
     }
 }
 
+/// Parse the iteration count sent by the server, rejecting a count of zero
+fn parse_iterations(iteration_count: &[u8]) -> Result<u32, SCRAMError> {
+    let iterations: u32 = core::str::from_utf8(iteration_count)
+        .map_err(|e| SCRAMError::ParseError(ParseError::BadUtf8(e)))?
+        .parse()
+        .map_err(|_| SCRAMError::Protocol(ProtocolError::IterationCountFormat))?;
+
+    if iterations == 0 {
+        return Err(SCRAMError::Protocol(ProtocolError::IterationCountZero));
+    }
+    Ok(iterations)
+}
+
 // Waiting for first server msg
+#[derive(Clone)]
 struct WaitingServerFirst<D, const N: usize> {
     // base64-encoded channel bindings, i.e. the attribute to send with 'c=' in client final.
     channel_bindings: String,
     // The generated client nonce
     client_nonce: [u8; N],
-    // Authid
-    username: String,
+    // The client-first-message-bare as sent, required for the AuthMessage
+    client_first_bare: Vec<u8>,
 
     // Marker for the digest in use
     digest: PhantomData<D>,
@@ -253,11 +288,15 @@ impl<D, const N: usize> WaitingServerFirst<D, N>
 where
     D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync,
 {
-    pub const fn new(channel_bindings: String, client_nonce: [u8; N], username: String) -> Self {
+    pub const fn new(
+        channel_bindings: String,
+        client_nonce: [u8; N],
+        client_first_bare: Vec<u8>,
+    ) -> Self {
         Self {
             channel_bindings,
             client_nonce,
-            username,
+            client_first_bare,
             digest: PhantomData,
         }
     }
@@ -269,11 +308,13 @@ where
         writer: impl Write,
         written: &mut usize,
     ) -> Result<WaitingServerFinal<D>, SessionError> {
-        let _server_first @ ServerFirst {
+        let ServerFirst {
+            mext,
             nonce,
             server_nonce: _,
             salt: salt64,
             iteration_count,
+            extensions,
         } = ServerFirst::parse(input).map_err(SCRAMError::ParseError)?;
 
         let server_nonce = nonce
@@ -283,24 +324,26 @@ where
             return Err(SCRAMError::Protocol(ProtocolError::InvalidNonce).into());
         }
 
-        let iterations: u32 = core::str::from_utf8(iteration_count)
-            .map_err(|e| SCRAMError::ParseError(super::parser::ParseError::BadUtf8(e)))?
-            .parse()
-            .map_err(|_| SCRAMError::Protocol(ProtocolError::IterationCountFormat))?;
-
-        if iterations == 0 {
-            return Err(SCRAMError::Protocol(ProtocolError::IterationCountZero).into());
-        }
+        let iterations = parse_iterations(iteration_count)?;
 
         let salt = base64::engine::general_purpose::STANDARD
             .decode(salt64)
             .map_err(|_| SCRAMError::Protocol(ProtocolError::Base64Decode))?;
 
+        let extensions: Vec<(char, &str)> = extensions.iter().collect();
         let prov = ScramClientProvider {
             iterations: &iterations,
             salt: &salt[..],
+            mext,
+            extensions: &extensions,
         };
 
+        // The server requires an extension we don't implement; unless the user explicitly handles
+        // it we must abort the exchange.
+        if mext.is_some() && !accepts_mandatory_extension(session_data, &prov)? {
+            return Err(SCRAMError::ParseError(ParseError::UnknownMandatoryExtensions).into());
+        }
+
         // first, see if the user has cached the keys directly. This is the best case with the
         // least work to be done.
         let mut keys: Option<(DOutput<D>, DOutput<D>)>;
@@ -352,15 +395,18 @@ where
         let mut client_signature = DOutput::<D>::default();
         let mut server_signature = DOutput::<D>::default();
 
-        compute_signatures::<D>(
+        sign_auth_message::<D>(
             &stored_key,
             &server_key,
-            &self.username,
-            &self.client_nonce,
-            server_nonce,
-            salt64,
-            iteration_count,
-            self.channel_bindings.as_bytes(),
+            &[
+                &self.client_first_bare,
+                b",",
+                input,
+                b",c=",
+                self.channel_bindings.as_bytes(),
+                b",r=",
+                nonce,
+            ],
             &mut client_signature,
             &mut server_signature,
         );
@@ -384,17 +430,23 @@ where
             server_key,
             salt,
             iterations,
+            extensions
+                .iter()
+                .map(|(name, value)| (*name, (*value).to_string()))
+                .collect(),
         ))
     }
 }
 
 // Waiting for final server msg
+#[derive(Clone)]
 struct WaitingServerFinal<D: Digest + BlockSizeUser> {
     verifier: DOutput<D>,
     client_key: DOutput<D>,
     server_key: DOutput<D>,
     salt: Vec<u8>,
     iterations: u32,
+    extensions: Vec<(char, String)>,
 }
 
 impl<D: Digest + BlockSizeUser> WaitingServerFinal<D> {
@@ -404,6 +456,7 @@ impl<D: Digest + BlockSizeUser> WaitingServerFinal<D> {
         server_key: DOutput<D>,
         salt: Vec<u8>,
         iterations: u32,
+        extensions: Vec<(char, String)>,
     ) -> Self {
         Self {
             verifier,
@@ -411,6 +464,7 @@ impl<D: Digest + BlockSizeUser> WaitingServerFinal<D> {
             server_key,
             salt,
             iterations,
+            extensions,
         }
     }
 
@@ -426,9 +480,16 @@ impl<D: Digest + BlockSizeUser> WaitingServerFinal<D> {
                     .map_err(|_| SCRAMError::Protocol(ProtocolError::Base64Decode))?;
 
                 if self.verifier.as_slice() == &v[..] {
+                    let extensions: Vec<(char, &str)> = self
+                        .extensions
+                        .iter()
+                        .map(|(name, value)| (*name, value.as_str()))
+                        .collect();
                     let prov = ScramClientProvider {
                         salt: &self.salt[..],
                         iterations: &self.iterations,
+                        mext: None,
+                        extensions: &extensions,
                     };
 
                     // `let _` because the client doesn't have to save the generated keys
@@ -453,7 +514,7 @@ struct StateServerFinal {}
 
 impl<D, const N: usize> Authentication for ScramClient<D, N>
 where
-    D: Digest + BlockSizeUser + FixedOutputReset + Clone + Send + Sync,
+    D: Digest + BlockSizeUser + FixedOutputReset + Clone + Send + Sync + 'static,
 {
     fn step(
         &mut self,
@@ -488,6 +549,10 @@ where
             None => panic!("State machine in invalid state"),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Error)]
@@ -529,11 +594,17 @@ impl MechanismError for SCRAMError {
 struct ScramClientProvider<'a> {
     iterations: &'a u32,
     salt: &'a [u8],
+    mext: Option<&'a str>,
+    extensions: &'a [(char, &'a str)],
 }
 impl<'a> Provider<'a> for ScramClientProvider<'a> {
     fn provide(&self, req: &mut Demand<'a>) -> DemandReply<()> {
+        if let Some(mext) = self.mext {
+            req.provide_ref::<ScramMandatoryExtension>(mext)?;
+        }
         req.provide_ref::<Salt>(self.salt)?
             .provide_ref::<Iterations>(self.iterations)?
+            .provide_ref::<ScramServerExtensions>(self.extensions)?
             .done()
     }
 }
@@ -0,0 +1,420 @@
+//! Generating SCRAM credentials from plaintext passwords
+//!
+//! A SCRAM server does not need to know the plaintext password of an user; it only requires the
+//! salt, iteration count, `StoredKey` and `ServerKey`. [`ScramCredentials`] derives these values
+//! for any supported digest, e.g. when an user registers or changes their password.
+
+use crate::alloc::vec::Vec;
+use crate::mechanisms::scram::properties::ScramStoredPassword;
+use crate::mechanisms::scram::tools::derive_stored_keys;
+use crate::zeroize;
+use core::fmt;
+use rand::{thread_rng, RngCore};
+#[cfg(any(feature = "plain", feature = "login"))]
+use {
+    crate::callback::CallbackError,
+    crate::context::{Demand, DemandReply, Provider},
+    crate::error::SessionError,
+    crate::mechanisms::scram::properties::{
+        ScramCredentialUpgrade, ScramUpgradedCredentials, ScramValidationOutcome,
+    },
+    crate::session::MechanismData,
+    core::any::Any,
+    stringprep::saslprep,
+};
+
+/// Length of the salt generated by [`ScramCredentials::generate`]
+pub const DEFAULT_SALT_LEN: usize = 32;
+
+/// Iteration count used when none was specified explicitly
+pub const DEFAULT_ITERATIONS: u32 = 16384;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+/// Digest algorithms SCRAM credentials can be derived for
+pub enum ScramDigest {
+    #[cfg(feature = "scram-sha-1")]
+    /// SHA-1, as used by `SCRAM-SHA-1` and `SCRAM-SHA-1-PLUS`
+    Sha1,
+    #[cfg(feature = "scram-sha-2")]
+    /// SHA-256, as used by `SCRAM-SHA-256` and `SCRAM-SHA-256-PLUS`
+    Sha256,
+    #[cfg(feature = "scram-sha-2")]
+    /// SHA-512, as used by `SCRAM-SHA-512` and `SCRAM-SHA-512-PLUS`
+    Sha512,
+}
+
+impl ScramDigest {
+    /// All digests supported with the enabled features
+    pub const ALL: &'static [Self] = &[
+        #[cfg(feature = "scram-sha-1")]
+        Self::Sha1,
+        #[cfg(feature = "scram-sha-2")]
+        Self::Sha256,
+        #[cfg(feature = "scram-sha-2")]
+        Self::Sha512,
+    ];
+
+    /// The name of the hash function as registered with IANA, e.g. `"SHA-256"`
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "scram-sha-1")]
+            Self::Sha1 => "SHA-1",
+            #[cfg(feature = "scram-sha-2")]
+            Self::Sha256 => "SHA-256",
+            #[cfg(feature = "scram-sha-2")]
+            Self::Sha512 => "SHA-512",
+        }
+    }
+
+    /// The name of the (non-PLUS) SCRAM mechanism using this digest, e.g. `"SCRAM-SHA-256"`
+    #[must_use]
+    pub const fn mechanism_name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "scram-sha-1")]
+            Self::Sha1 => "SCRAM-SHA-1",
+            #[cfg(feature = "scram-sha-2")]
+            Self::Sha256 => "SCRAM-SHA-256",
+            #[cfg(feature = "scram-sha-2")]
+            Self::Sha512 => "SCRAM-SHA-512",
+        }
+    }
+
+    /// Look up the digest used by the SCRAM mechanism `name`, ignoring a `-PLUS` suffix
+    #[must_use]
+    pub fn from_mechanism_name(name: &str) -> Option<Self> {
+        let name = name.strip_suffix("-PLUS").unwrap_or(name);
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|digest| digest.mechanism_name().eq_ignore_ascii_case(name))
+    }
+
+    /// Length of the digest output in bytes, which is also the length of the derived keys
+    #[must_use]
+    pub const fn output_len(self) -> usize {
+        match self {
+            #[cfg(feature = "scram-sha-1")]
+            Self::Sha1 => 20,
+            #[cfg(feature = "scram-sha-2")]
+            Self::Sha256 => 32,
+            #[cfg(feature = "scram-sha-2")]
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for ScramDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Eq, PartialEq)]
+/// The secrets a SCRAM server stores for an user
+///
+/// The keys are overwritten with zeroes when a value of this type is dropped.
+///
+/// Use [`as_stored_password`](Self::as_stored_password) to answer a [`ScramStoredPassword`]
+/// request with these credentials.
+pub struct ScramCredentials {
+    digest: ScramDigest,
+    iterations: u32,
+    salt: Vec<u8>,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl ScramCredentials {
+    /// Derive credentials from a password using the given salt and iteration count
+    ///
+    /// The password is used as-is; no normalization like `SASLprep` is applied to it.
+    #[must_use]
+    pub fn derive(digest: ScramDigest, password: &[u8], salt: &[u8], iterations: u32) -> Self {
+        let (stored_key, server_key) = match digest {
+            #[cfg(feature = "scram-sha-1")]
+            ScramDigest::Sha1 => {
+                let (stored, server) = derive_stored_keys::<sha1::Sha1>(password, iterations, salt);
+                (stored.to_vec(), server.to_vec())
+            }
+            #[cfg(feature = "scram-sha-2")]
+            ScramDigest::Sha256 => {
+                let (stored, server) =
+                    derive_stored_keys::<sha2::Sha256>(password, iterations, salt);
+                (stored.to_vec(), server.to_vec())
+            }
+            #[cfg(feature = "scram-sha-2")]
+            ScramDigest::Sha512 => {
+                let (stored, server) =
+                    derive_stored_keys::<sha2::Sha512>(password, iterations, salt);
+                (stored.to_vec(), server.to_vec())
+            }
+        };
+        Self {
+            digest,
+            iterations,
+            salt: salt.to_vec(),
+            stored_key,
+            server_key,
+        }
+    }
+
+    /// Derive credentials from a password with a freshly generated random salt
+    #[must_use]
+    pub fn generate(digest: ScramDigest, password: &[u8], iterations: u32) -> Self {
+        let mut salt = [0u8; DEFAULT_SALT_LEN];
+        thread_rng().fill_bytes(&mut salt);
+        Self::derive(digest, password, &salt, iterations)
+    }
+
+    /// Derive credentials for every digest in [`ScramDigest::ALL`] sharing the same salt
+    #[must_use]
+    pub fn derive_all(password: &[u8], salt: &[u8], iterations: u32) -> Vec<Self> {
+        ScramDigest::ALL
+            .iter()
+            .map(|digest| Self::derive(*digest, password, salt, iterations))
+            .collect()
+    }
+
+    /// Construct credentials from previously stored values
+    ///
+    /// Returns `None` if the key lengths don't match the output length of `digest`.
+    #[must_use]
+    pub fn from_parts(
+        digest: ScramDigest,
+        iterations: u32,
+        salt: &[u8],
+        stored_key: &[u8],
+        server_key: &[u8],
+    ) -> Option<Self> {
+        if stored_key.len() != digest.output_len() || server_key.len() != digest.output_len() {
+            return None;
+        }
+        Some(Self {
+            digest,
+            iterations,
+            salt: salt.to_vec(),
+            stored_key: stored_key.to_vec(),
+            server_key: server_key.to_vec(),
+        })
+    }
+
+    #[must_use]
+    pub const fn digest(&self) -> ScramDigest {
+        self.digest
+    }
+
+    #[must_use]
+    pub const fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    #[must_use]
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    #[must_use]
+    pub fn stored_key(&self) -> &[u8] {
+        &self.stored_key
+    }
+
+    #[must_use]
+    pub fn server_key(&self) -> &[u8] {
+        &self.server_key
+    }
+
+    /// Borrow these credentials as a value for [`ScramStoredPassword`]
+    #[must_use]
+    pub fn as_stored_password(&self) -> ScramStoredPassword<'_> {
+        ScramStoredPassword::new(
+            self.iterations,
+            &self.salt,
+            &self.stored_key,
+            &self.server_key,
+        )
+    }
+}
+
+impl Drop for ScramCredentials {
+    fn drop(&mut self) {
+        zeroize(&mut self.stored_key);
+        zeroize(&mut self.server_key);
+    }
+}
+
+impl fmt::Debug for ScramCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramCredentials")
+            .field("digest", &self.digest)
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Provides the outcome of the validation alongside the values of the mechanism
+#[cfg(any(feature = "plain", feature = "login"))]
+struct UpgradeProvider<'a> {
+    outcome: &'a dyn Any,
+    provider: &'a dyn Provider<'a>,
+}
+
+#[cfg(any(feature = "plain", feature = "login"))]
+impl<'a> Provider<'a> for UpgradeProvider<'a> {
+    fn provide(&self, req: &mut Demand<'a>) -> DemandReply<()> {
+        req.provide_ref::<ScramValidationOutcome>(self.outcome)?;
+        self.provider.provide(req)
+    }
+}
+
+/// Derive SCRAM credentials from a plaintext password if the user callback opts in
+///
+/// Used by mechanisms that see the plaintext password (i.e. `PLAIN` and `LOGIN`) after calling
+/// `validate`. Nothing is requested unless the validation was finalized with an outcome, which is
+/// provided as [`ScramValidationOutcome`]; see [`ScramCredentialUpgrade`].
+///
+/// Only deferring the request is reported as an error. The exchange was already validated at
+/// this point, so a failure to migrate the credentials must not fail the authentication.
+#[cfg(any(feature = "plain", feature = "login"))]
+pub(crate) fn upgrade_credentials<'a>(
+    session: &'a MechanismData,
+    provider: &'a dyn Provider<'a>,
+    password: &[u8],
+) -> Result<(), SessionError> {
+    let Some(outcome) = session.validation_outcome() else {
+        return Ok(());
+    };
+    let provider = UpgradeProvider { outcome, provider };
+    let iterations =
+        match session.maybe_need_with::<ScramCredentialUpgrade, _, _>(&provider, |i| Ok(*i)) {
+            Ok(Some(iterations)) => iterations,
+            Err(error @ SessionError::CallbackError(CallbackError::Deferred)) => return Err(error),
+            Ok(None) | Err(_) => return Ok(()),
+        };
+    let iterations = if iterations == 0 {
+        DEFAULT_ITERATIONS
+    } else {
+        iterations
+    };
+
+    // SCRAM hashes the password after normalizing it with SASLprep, which PLAIN and LOGIN may
+    // not have done.
+    let prepared = core::str::from_utf8(password)
+        .ok()
+        .and_then(|p| saslprep(p).ok());
+    let password = prepared.as_deref().map_or(password, str::as_bytes);
+
+    let credentials: Vec<ScramCredentials> = ScramDigest::ALL
+        .iter()
+        .map(|digest| ScramCredentials::generate(*digest, password, iterations))
+        .collect();
+    let _ = session.action::<ScramUpgradedCredentials>(&provider, &credentials);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "scram-sha-1")]
+    fn test_rfc5802_sha1() {
+        use crate::mechanisms::scram::tools::{sign_auth_message, DOutput};
+        use base64::Engine;
+        use digest::generic_array::GenericArray;
+
+        // The example exchange given in RFC 5802 section 5
+        let salt64 = "QSXCR+Q6sek8bf92";
+        let salt = base64::engine::general_purpose::STANDARD
+            .decode(salt64)
+            .unwrap();
+        let creds = ScramCredentials::derive(ScramDigest::Sha1, b"pencil", &salt, 4096);
+
+        let mut client_signature = DOutput::<sha1::Sha1>::default();
+        let mut server_signature = DOutput::<sha1::Sha1>::default();
+        sign_auth_message::<sha1::Sha1>(
+            GenericArray::from_slice(creds.stored_key()),
+            GenericArray::from_slice(creds.server_key()),
+            &[
+                b"n=user,r=fyko+d2lbbFgONRv9qkxdawL,",
+                b"r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096,",
+                b"c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j",
+            ],
+            &mut client_signature,
+            &mut server_signature,
+        );
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD.encode(server_signature),
+            "rmF9pqV8S7suAoZWja4dJRkFsKQ="
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "scram-sha-1")]
+    #[allow(deprecated)]
+    fn test_compute_signatures() {
+        // The deprecated helper still produces the signatures of RFC 5802 section 5
+        use crate::mechanisms::scram::tools::{
+            compute_signatures, derive_keys, hash_password, DOutput,
+        };
+        use base64::Engine;
+        use digest::generic_array::GenericArray;
+
+        let salt64 = "QSXCR+Q6sek8bf92";
+        let salt = base64::engine::general_purpose::STANDARD
+            .decode(salt64)
+            .unwrap();
+        let creds = ScramCredentials::derive(ScramDigest::Sha1, b"pencil", &salt, 4096);
+
+        let mut client_signature = DOutput::<sha1::Sha1>::default();
+        let mut server_signature = DOutput::<sha1::Sha1>::default();
+        compute_signatures::<sha1::Sha1>(
+            GenericArray::from_slice(creds.stored_key()),
+            GenericArray::from_slice(creds.server_key()),
+            "user",
+            b"fyko+d2lbbFgONRv9qkxdawL",
+            b"3rfcNHYJY1ZVvWVs7j",
+            salt64.as_bytes(),
+            b"4096",
+            b"biws",
+            &mut client_signature,
+            &mut server_signature,
+        );
+        // ClientProof is ClientKey XOR ClientSignature
+        let mut salted_password = DOutput::<sha1::Sha1>::default();
+        hash_password::<sha1::Sha1>(b"pencil", 4096, &salt, &mut salted_password);
+        let (client_key, _) = derive_keys::<sha1::Sha1>(&salted_password);
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(key, signature)| key ^ signature)
+            .collect();
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD.encode(proof),
+            "v0X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+        );
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD.encode(server_signature),
+            "rmF9pqV8S7suAoZWja4dJRkFsKQ="
+        );
+    }
+
+    #[test]
+    fn test_derive_all() {
+        let all = ScramCredentials::derive_all(b"secret", b"salt", 4096);
+        assert_eq!(all.len(), ScramDigest::ALL.len());
+        for creds in &all {
+            assert_eq!(creds.stored_key().len(), creds.digest().output_len());
+            assert_eq!(creds.server_key().len(), creds.digest().output_len());
+            let parts = ScramCredentials::from_parts(
+                creds.digest(),
+                creds.iterations(),
+                creds.salt(),
+                creds.stored_key(),
+                creds.server_key(),
+            );
+            assert_eq!(parts.as_ref(), Some(creds));
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use crate::alloc::boxed::Box;
 use crate::error::SASLError;
 use crate::mechanism::Authentication;
+use crate::mechanisms::scram::credentials::ScramDigest;
 use crate::mechanisms::scram::{client, server};
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named, Selection, Selector};
 use crate::session::Side;
 
@@ -11,8 +13,8 @@ const NONCE_LEN: usize = 24;
 #[cfg(feature = "scram-sha-1")]
 mod scram_sha1 {
     use super::{
-        client, server, Authentication, Box, Matches, Mechanism, Mechname, Named, SASLError,
-        Selection, Selector, Side, NONCE_LEN,
+        client, server, Authentication, Box, Capabilities, Matches, Mechanism, Mechname, Named,
+        SASLError, ScramDigest, SecurityFlags, Selection, Selector, Side, NONCE_LEN,
     };
 
     #[cfg_attr(
@@ -28,7 +30,10 @@ mod scram_sha1 {
             let can_cb = sasl
                 .mech_list()
                 .any(|m| m.mechanism.as_str() == "SCRAM-SHA-1-PLUS");
-            Ok(Box::new(server::ScramSha1Server::<NONCE_LEN>::new(can_cb)))
+            Ok(Box::new(
+                server::ScramSha1Server::<NONCE_LEN>::new(can_cb)
+                    .with_key_cache(sasl, ScramDigest::Sha1),
+            ))
         }),
         first: Side::Client,
         select: |cb| {
@@ -39,6 +44,24 @@ mod scram_sha1 {
             })
         },
         offer: |_| true,
+        capabilities: Capabilities::new(SecurityFlags::MUTUAL_AUTH)
+            .with_authzid()
+            .with_properties(
+                &[
+                    "AuthId",
+                    "AuthzId",
+                    "Password",
+                    "SaltedPassword",
+                    "ScramCachedPassword",
+                    "ScramClientExtensions",
+                ],
+                &[
+                    "ScramStoredPassword",
+                    "Password",
+                    "ScramServerExtensions",
+                    "ScramAcceptMandatory",
+                ],
+            ),
     };
 
     struct Select1;
@@ -90,7 +113,12 @@ mod scram_sha1 {
         mechanism: Mechname::const_new(b"SCRAM-SHA-1-PLUS"),
         priority: 500,
         client: Some(|| Ok(Box::new(client::ScramSha1Client::<NONCE_LEN>::new_plus()))),
-        server: Some(|_sasl| Ok(Box::new(server::ScramSha1Server::<NONCE_LEN>::new_plus()))),
+        server: Some(|sasl| {
+            Ok(Box::new(
+                server::ScramSha1Server::<NONCE_LEN>::new_plus()
+                    .with_key_cache(sasl, ScramDigest::Sha1),
+            ))
+        }),
         first: Side::Client,
         select: |cb| {
             if cb {
@@ -99,7 +127,29 @@ mod scram_sha1 {
                 None
             }
         },
-        offer: |_| true,
+        offer: |cb| cb,
+        capabilities: Capabilities::new(
+            SecurityFlags::MUTUAL_AUTH.union(SecurityFlags::CHANNEL_BINDING),
+        )
+        .with_authzid()
+        .with_properties(
+            &[
+                "AuthId",
+                "AuthzId",
+                "Password",
+                "SaltedPassword",
+                "ScramCachedPassword",
+                "ScramClientExtensions",
+                "ChannelBindings",
+            ],
+            &[
+                "ScramStoredPassword",
+                "Password",
+                "ScramServerExtensions",
+                "ScramAcceptMandatory",
+                "ChannelBindings",
+            ],
+        ),
     };
 
     struct Select1Plus;
@@ -115,8 +165,8 @@ pub use scram_sha1::*;
 #[cfg(feature = "scram-sha-2")]
 mod scram_sha256 {
     use super::{
-        client, server, Authentication, Box, Matches, Mechanism, Mechname, Named, SASLError,
-        Selection, Selector, Side, NONCE_LEN,
+        client, server, Authentication, Box, Capabilities, Matches, Mechanism, Mechname, Named,
+        SASLError, ScramDigest, SecurityFlags, Selection, Selector, Side, NONCE_LEN,
     };
 
     #[cfg_attr(
@@ -131,9 +181,10 @@ mod scram_sha256 {
             let can_cb = sasl
                 .mech_list()
                 .any(|m| m.mechanism.as_str() == "SCRAM-SHA-256-PLUS");
-            Ok(Box::new(server::ScramSha256Server::<NONCE_LEN>::new(
-                can_cb,
-            )))
+            Ok(Box::new(
+                server::ScramSha256Server::<NONCE_LEN>::new(can_cb)
+                    .with_key_cache(sasl, ScramDigest::Sha256),
+            ))
         }),
         first: Side::Client,
         select: |cb| {
@@ -144,6 +195,24 @@ mod scram_sha256 {
             })
         },
         offer: |_| true,
+        capabilities: Capabilities::new(SecurityFlags::MUTUAL_AUTH)
+            .with_authzid()
+            .with_properties(
+                &[
+                    "AuthId",
+                    "AuthzId",
+                    "Password",
+                    "SaltedPassword",
+                    "ScramCachedPassword",
+                    "ScramClientExtensions",
+                ],
+                &[
+                    "ScramStoredPassword",
+                    "Password",
+                    "ScramServerExtensions",
+                    "ScramAcceptMandatory",
+                ],
+            ),
     };
 
     struct Select256;
@@ -195,7 +264,12 @@ mod scram_sha256 {
         mechanism: Mechname::const_new(b"SCRAM-SHA-256-PLUS"),
         priority: 700,
         client: Some(|| Ok(Box::new(client::ScramSha256Client::<NONCE_LEN>::new_plus()))),
-        server: Some(|_sasl| Ok(Box::new(server::ScramSha256Server::<NONCE_LEN>::new_plus()))),
+        server: Some(|sasl| {
+            Ok(Box::new(
+                server::ScramSha256Server::<NONCE_LEN>::new_plus()
+                    .with_key_cache(sasl, ScramDigest::Sha256),
+            ))
+        }),
         first: Side::Client,
         select: |cb| {
             if cb {
@@ -204,7 +278,29 @@ mod scram_sha256 {
                 None
             }
         },
-        offer: |_| true,
+        offer: |cb| cb,
+        capabilities: Capabilities::new(
+            SecurityFlags::MUTUAL_AUTH.union(SecurityFlags::CHANNEL_BINDING),
+        )
+        .with_authzid()
+        .with_properties(
+            &[
+                "AuthId",
+                "AuthzId",
+                "Password",
+                "SaltedPassword",
+                "ScramCachedPassword",
+                "ScramClientExtensions",
+                "ChannelBindings",
+            ],
+            &[
+                "ScramStoredPassword",
+                "Password",
+                "ScramServerExtensions",
+                "ScramAcceptMandatory",
+                "ChannelBindings",
+            ],
+        ),
     };
 
     struct Select256Plus;
@@ -220,8 +316,8 @@ pub use scram_sha256::*;
 #[cfg(feature = "scram-sha-2")]
 mod scram_sha512 {
     use super::{
-        client, server, Authentication, Box, Matches, Mechanism, Mechname, Named, SASLError,
-        Selection, Selector, Side, NONCE_LEN,
+        client, server, Authentication, Box, Capabilities, Matches, Mechanism, Mechname, Named,
+        SASLError, ScramDigest, SecurityFlags, Selection, Selector, Side, NONCE_LEN,
     };
 
     #[cfg_attr(
@@ -236,9 +332,10 @@ mod scram_sha512 {
             let can_cb = sasl
                 .mech_list()
                 .any(|m| m.mechanism.as_str() == "SCRAM-SHA-512-PLUS");
-            Ok(Box::new(server::ScramSha512Server::<NONCE_LEN>::new(
-                can_cb,
-            )))
+            Ok(Box::new(
+                server::ScramSha512Server::<NONCE_LEN>::new(can_cb)
+                    .with_key_cache(sasl, ScramDigest::Sha512),
+            ))
         }),
         first: Side::Client,
         select: |cb| {
@@ -249,6 +346,24 @@ mod scram_sha512 {
             })
         },
         offer: |_| true,
+        capabilities: Capabilities::new(SecurityFlags::MUTUAL_AUTH)
+            .with_authzid()
+            .with_properties(
+                &[
+                    "AuthId",
+                    "AuthzId",
+                    "Password",
+                    "SaltedPassword",
+                    "ScramCachedPassword",
+                    "ScramClientExtensions",
+                ],
+                &[
+                    "ScramStoredPassword",
+                    "Password",
+                    "ScramServerExtensions",
+                    "ScramAcceptMandatory",
+                ],
+            ),
     };
 
     struct Select512;
@@ -300,7 +415,12 @@ mod scram_sha512 {
         mechanism: Mechname::const_new(b"SCRAM-SHA-512-PLUS"),
         priority: 700,
         client: Some(|| Ok(Box::new(client::ScramSha512Client::<NONCE_LEN>::new_plus()))),
-        server: Some(|_sasl| Ok(Box::new(server::ScramSha512Server::<NONCE_LEN>::new_plus()))),
+        server: Some(|sasl| {
+            Ok(Box::new(
+                server::ScramSha512Server::<NONCE_LEN>::new_plus()
+                    .with_key_cache(sasl, ScramDigest::Sha512),
+            ))
+        }),
         first: Side::Client,
         select: |cb| {
             if cb {
@@ -309,7 +429,29 @@ mod scram_sha512 {
                 None
             }
         },
-        offer: |_| true,
+        offer: |cb| cb,
+        capabilities: Capabilities::new(
+            SecurityFlags::MUTUAL_AUTH.union(SecurityFlags::CHANNEL_BINDING),
+        )
+        .with_authzid()
+        .with_properties(
+            &[
+                "AuthId",
+                "AuthzId",
+                "Password",
+                "SaltedPassword",
+                "ScramCachedPassword",
+                "ScramClientExtensions",
+                "ChannelBindings",
+            ],
+            &[
+                "ScramStoredPassword",
+                "Password",
+                "ScramServerExtensions",
+                "ScramAcceptMandatory",
+                "ChannelBindings",
+            ],
+        ),
     };
 
     struct Select512Plus;
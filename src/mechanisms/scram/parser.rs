@@ -169,6 +169,8 @@ pub enum ParseError {
     MissingAttributes,
     #[error("an extension is unknown but marked mandatory")]
     UnknownMandatoryExtensions,
+    #[error("extension attribute has an invalid value")]
+    BadExtensionValue,
     #[error("invalid UTF-8: {0}")]
     BadUtf8(
         #[from]
@@ -224,12 +226,102 @@ impl<'scram> GS2CBindFlag<'scram> {
     }
 }
 
+/// Split off the next `attr "=" value` pair from a comma-separated list of attributes
+fn next_attribute<'scram>(input: &mut &'scram [u8]) -> Result<(u8, &'scram [u8]), ParseError> {
+    if input.is_empty() {
+        return Err(ParseError::MissingAttributes);
+    }
+    let (attribute, rest) = input
+        .iter()
+        .position(|b| *b == b',')
+        .map_or((*input, &[][..]), |idx| (&input[..idx], &input[idx + 1..]));
+    *input = rest;
+
+    match attribute {
+        [name, b'=', value @ ..] if name.is_ascii_alphabetic() => Ok((*name, value)),
+        [bad, ..] => Err(ParseError::InvalidAttribute(*bad)),
+        [] => Err(ParseError::MissingAttributes),
+    }
+}
+
+/// Split a client-first-message into its GS2 header and the client-first-message-bare
+///
+/// The returned GS2 header includes the trailing comma, so that it can be used verbatim as
+/// input for the channel binding data sent by the client in the client-final-message.
+pub fn split_client_first(input: &[u8]) -> Result<(&[u8], &[u8]), ParseError> {
+    let cbflag_end = input
+        .iter()
+        .position(|b| *b == b',')
+        .ok_or(ParseError::BadCBFlag)?;
+    let authzid_end = input[cbflag_end + 1..]
+        .iter()
+        .position(|b| *b == b',')
+        .ok_or(ParseError::BadGS2Header)?
+        + cbflag_end
+        + 1;
+    Ok(input.split_at(authzid_end + 1))
+}
+
+/// Extension attributes of a SCRAM message
+///
+/// RFC 5802 allows both the client-first-message and the server-first-message to carry
+/// additional `attr "=" value` pairs after the mandatory attributes. This type wraps the raw,
+/// already validated list of them.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Default)]
+pub struct Extensions<'scram>(&'scram str);
+impl<'scram> Extensions<'scram> {
+    pub fn parse(input: &'scram [u8]) -> Result<Self, ParseError> {
+        let mut rest = input;
+        while !rest.is_empty() {
+            let (_, value) = next_attribute(&mut rest)?;
+            if value.is_empty() {
+                return Err(ParseError::BadExtensionValue);
+            }
+        }
+        Ok(Self(core::str::from_utf8(input)?))
+    }
+
+    /// Encode the given extension attributes, prepending each with a comma
+    pub fn encode_into(extensions: &[(char, &str)], out: &mut Vec<u8>) -> Result<(), ParseError> {
+        for (name, value) in extensions {
+            if !name.is_ascii_alphabetic() {
+                return Err(ParseError::InvalidAttribute(
+                    u8::try_from(u32::from(*name)).unwrap_or(0),
+                ));
+            }
+            if value.is_empty() || value.contains(',') {
+                return Err(ParseError::BadExtensionValue);
+            }
+            out.push(b',');
+            out.push(*name as u8);
+            out.push(b'=');
+            out.extend_from_slice(value.as_bytes());
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (char, &'scram str)> {
+        self.0
+            .split(',')
+            .filter(|attribute| !attribute.is_empty())
+            .map(|attribute| {
+                // Validated when parsing: `ALPHA "=" 1*value-char`, so splitting at 1 and 2 is
+                // always on a char boundary.
+                let (name, value) = attribute.split_at(1);
+                (char::from(name.as_bytes()[0]), &value[1..])
+            })
+    }
+}
+
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub struct ClientFirstMessage<'scram> {
     pub cbflag: GS2CBindFlag<'scram>,
     pub authzid: Option<&'scram str>,
+    /// Value of the reserved mandatory extension attribute `m`
+    pub mext: Option<&'scram str>,
     pub username: &'scram str,
     pub nonce: &'scram [u8],
+    pub extensions: Extensions<'scram>,
 }
 impl<'scram> ClientFirstMessage<'scram> {
     #[allow(unused)]
@@ -242,50 +334,61 @@ impl<'scram> ClientFirstMessage<'scram> {
         Self {
             cbflag,
             authzid,
+            mext: None,
             username,
             nonce,
+            extensions: Extensions(""),
         }
     }
 
     pub fn parse(input: &'scram [u8]) -> Result<Self, ParseError> {
-        let mut partiter = input.split(|b| matches!(b, b','));
+        let (gs2_header, mut bare) = split_client_first(input)?;
+        let mut partiter = gs2_header.split(|b| matches!(b, b','));
 
         let first = partiter.next().ok_or(ParseError::BadCBFlag)?;
         let cbflag = GS2CBindFlag::parse(first)?;
 
         let authzid = partiter.next().ok_or(ParseError::BadGS2Header)?;
-        let authzid = if authzid.is_empty() {
-            None
-        } else {
-            Some(core::str::from_utf8(&authzid[2..]).map_err(ParseError::BadUtf8)?)
+        let authzid = match authzid {
+            [] => None,
+            [b'a', b'=', authzid @ ..] => {
+                Some(core::str::from_utf8(authzid).map_err(ParseError::BadUtf8)?)
+            }
+            _ => return Err(ParseError::BadGS2Header),
         };
 
-        let next = partiter.next().ok_or(ParseError::MissingAttributes)?;
-        if &next[0..2] == b"m=" {
-            return Err(ParseError::UnknownMandatoryExtensions);
-        }
-
-        let username = if &next[0..2] == b"n=" {
-            core::str::from_utf8(&next[2..]).map_err(ParseError::BadUtf8)?
+        let (mut name, mut value) = next_attribute(&mut bare)?;
+        let mext = if name == b'm' {
+            let mext = core::str::from_utf8(value).map_err(ParseError::BadUtf8)?;
+            (name, value) = next_attribute(&mut bare)?;
+            Some(mext)
         } else {
-            return Err(ParseError::InvalidAttribute(next[0]));
+            None
         };
 
-        let next = partiter.next().ok_or(ParseError::MissingAttributes)?;
-        let nonce = if &next[0..2] == b"r=" {
-            &next[2..]
+        let username = if name == b'n' {
+            core::str::from_utf8(value).map_err(ParseError::BadUtf8)?
         } else {
-            return Err(ParseError::InvalidAttribute(next[0]));
+            return Err(ParseError::InvalidAttribute(name));
         };
+
+        let (name, nonce) = next_attribute(&mut bare)?;
+        if name != b'r' {
+            return Err(ParseError::InvalidAttribute(name));
+        }
         if !nonce.iter().all(|b| matches!(b, 0x21..=0x2B | 0x2D..=0x7E)) {
             return Err(ParseError::BadNonce);
         }
 
+        let extensions = Extensions::parse(bare)?;
+
         Ok(Self {
             cbflag,
             authzid,
+            mext,
             username,
             nonce,
+            extensions,
         })
     }
 
@@ -316,31 +419,12 @@ impl<'scram> ClientFirstMessage<'scram> {
             self.nonce,
         ]
     }
-
-    #[allow(clippy::similar_names)]
-    pub(super) fn build_gs2_header_vec(&self) -> Vec<u8> {
-        let [cba, cbb, prefix, authzid] = self.gs2_header_parts();
-
-        let gs2_header_len = cba.len() + cbb.len() + prefix.len() + authzid.len() + 1;
-        let mut gs2_header = Vec::with_capacity(gs2_header_len);
-
-        // y | n | p=
-        gs2_header.extend_from_slice(cba);
-        // &[] | cbname
-        gs2_header.extend_from_slice(cbb);
-        // b","
-        gs2_header.extend_from_slice(prefix);
-        // authzid
-        gs2_header.extend_from_slice(authzid);
-        // b","
-        gs2_header.extend_from_slice(b",");
-
-        gs2_header
-    }
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub struct ServerFirst<'scram> {
+    /// Value of the reserved mandatory extension attribute `m`
+    pub mext: Option<&'scram str>,
     /// Client or Client+Server Nonce
     ///
     /// If the field `server_nonce` is None this contains both client and server nonce
@@ -349,6 +433,7 @@ pub struct ServerFirst<'scram> {
     pub server_nonce: Option<&'scram [u8]>,
     pub salt: &'scram [u8],
     pub iteration_count: &'scram [u8],
+    pub extensions: Extensions<'scram>,
 }
 
 impl<'scram> ServerFirst<'scram> {
@@ -359,53 +444,50 @@ impl<'scram> ServerFirst<'scram> {
         iteration_count: &'scram [u8],
     ) -> Self {
         Self {
+            mext: None,
             nonce: client_nonce,
             server_nonce: Some(server_nonce),
             salt,
             iteration_count,
+            extensions: Extensions(""),
         }
     }
 
-    pub fn parse(input: &'scram [u8]) -> Result<Self, ParseError> {
-        let mut partiter = input.split(|b| matches!(b, b','));
-
-        let next = partiter.next().ok_or(ParseError::MissingAttributes)?;
-        if next.len() < 2 {
-            return Err(ParseError::MissingAttributes);
-        }
-        if &next[0..2] == b"m=" {
-            return Err(ParseError::UnknownMandatoryExtensions);
-        }
-
-        let nonce = if &next[0..2] == b"r=" {
-            &next[2..]
+    pub fn parse(mut input: &'scram [u8]) -> Result<Self, ParseError> {
+        let (mut name, mut value) = next_attribute(&mut input)?;
+        let mext = if name == b'm' {
+            let mext = core::str::from_utf8(value).map_err(ParseError::BadUtf8)?;
+            (name, value) = next_attribute(&mut input)?;
+            Some(mext)
         } else {
-            return Err(ParseError::InvalidAttribute(next[0]));
+            None
         };
 
-        let next = partiter.next().ok_or(ParseError::MissingAttributes)?;
-        let salt = if &next[0..2] == b"s=" {
-            &next[2..]
+        let nonce = if name == b'r' {
+            value
         } else {
-            return Err(ParseError::InvalidAttribute(next[0]));
+            return Err(ParseError::InvalidAttribute(name));
         };
 
-        let next = partiter.next().ok_or(ParseError::MissingAttributes)?;
-        let iteration_count = if &next[0..2] == b"i=" {
-            &next[2..]
-        } else {
-            return Err(ParseError::InvalidAttribute(next[0]));
-        };
+        let (name, salt) = next_attribute(&mut input)?;
+        if name != b's' {
+            return Err(ParseError::InvalidAttribute(name));
+        }
 
-        if let Some(next) = partiter.next() {
-            return Err(ParseError::InvalidAttribute(next[0]));
+        let (name, iteration_count) = next_attribute(&mut input)?;
+        if name != b'i' {
+            return Err(ParseError::InvalidAttribute(name));
         }
 
+        let extensions = Extensions::parse(input)?;
+
         Ok(Self {
+            mext,
             nonce,
             server_nonce: None,
             salt,
             iteration_count,
+            extensions,
         })
     }
 
@@ -610,4 +692,64 @@ mod tests {
             assert_eq!(GS2CBindFlag::parse(input), Ok(*output));
         }
     }
+
+    #[test]
+    fn test_parse_client_first_extensions() {
+        let msg = ClientFirstMessage::parse(b"n,,m=ext,n=user,r=abcdef,x=1,y=a=b").unwrap();
+        assert_eq!(msg.mext, Some("ext"));
+        assert_eq!(msg.username, "user");
+        assert_eq!(msg.nonce, b"abcdef");
+        let extensions: Vec<_> = msg.extensions.iter().collect();
+        assert_eq!(extensions, [('x', "1"), ('y', "a=b")]);
+
+        let msg = ClientFirstMessage::parse(b"y,a=admin,n=user,r=abcdef").unwrap();
+        assert_eq!(msg.authzid, Some("admin"));
+        assert_eq!(msg.mext, None);
+        assert_eq!(msg.extensions.iter().count(), 0);
+
+        assert_eq!(
+            ClientFirstMessage::parse(b"n,,n=user,r=abcdef,1=x"),
+            Err(ParseError::InvalidAttribute(b'1'))
+        );
+        assert_eq!(
+            ClientFirstMessage::parse(b"n,,n=user,r=abcdef,x="),
+            Err(ParseError::BadExtensionValue)
+        );
+        assert_eq!(
+            ClientFirstMessage::parse(b"n,,n=user"),
+            Err(ParseError::MissingAttributes)
+        );
+    }
+
+    #[test]
+    fn test_parse_server_first_extensions() {
+        let msg = ServerFirst::parse(b"m=ext,r=abcdef,s=c2FsdA==,i=4096,x=foo").unwrap();
+        assert_eq!(msg.mext, Some("ext"));
+        assert_eq!(msg.nonce, b"abcdef");
+        assert_eq!(msg.salt, b"c2FsdA==");
+        assert_eq!(msg.iteration_count, b"4096");
+        let extensions: Vec<_> = msg.extensions.iter().collect();
+        assert_eq!(extensions, [('x', "foo")]);
+
+        assert_eq!(
+            ServerFirst::parse(b"r=abcdef,i=4096"),
+            Err(ParseError::InvalidAttribute(b'i'))
+        );
+    }
+
+    #[test]
+    fn test_encode_extensions() {
+        let mut out = Vec::new();
+        Extensions::encode_into(&[('x', "1"), ('y', "a=b")], &mut out).unwrap();
+        assert_eq!(out, b",x=1,y=a=b");
+
+        assert_eq!(
+            Extensions::encode_into(&[('x', "a,b")], &mut out),
+            Err(ParseError::BadExtensionValue)
+        );
+        assert_eq!(
+            Extensions::encode_into(&[('1', "a")], &mut out),
+            Err(ParseError::InvalidAttribute(b'1'))
+        );
+    }
 }
@@ -1,4 +1,6 @@
+use crate::mechanisms::scram::credentials::ScramCredentials;
 use crate::property::{Property, SizedProperty};
+use core::any::Any;
 
 /// Iterations of a hash or key derivation algorithm used
 #[non_exhaustive]
@@ -74,3 +76,112 @@ pub struct SaltedPassword;
 impl Property<'_> for SaltedPassword {
     type Value = [u8];
 }
+
+/// Extension attributes sent by the client in the client-first-message
+///
+/// On the client side this property is requested using a 'satisfiable' callback, allowing the
+/// callback to add custom extensions to the client-first-message. Extension names must be a
+/// single ASCII letter and values must not be empty or contain a comma.
+///
+/// On the server side the received extensions are made available to all callbacks and to
+/// `validate` through the provided `Context`.
+#[non_exhaustive]
+pub struct ScramClientExtensions;
+impl<'a> Property<'a> for ScramClientExtensions {
+    type Value = [(char, &'a str)];
+}
+
+/// Extension attributes sent by the server in the server-first-message
+///
+/// On the server side this property is requested using a 'satisfiable' callback, allowing the
+/// callback to add custom extensions to the server-first-message.
+///
+/// On the client side the received extensions are made available to the callbacks querying
+/// credentials through the provided `Context`.
+#[non_exhaustive]
+pub struct ScramServerExtensions;
+impl<'a> Property<'a> for ScramServerExtensions {
+    type Value = [(char, &'a str)];
+}
+
+/// Value of the reserved mandatory extension attribute `m`
+///
+/// A client may satisfy this property to send a mandatory extension in its client-first-message.
+/// If the other party sent a mandatory extension its value is provided in the `Context` of all
+/// callbacks issued afterwards.
+#[non_exhaustive]
+pub struct ScramMandatoryExtension;
+impl Property<'_> for ScramMandatoryExtension {
+    type Value = str;
+}
+
+/// Accept a mandatory extension sent by the other party
+///
+/// If a `m=` attribute is received this property is requested using a 'satisfiable' callback,
+/// with the value of the attribute available as [`ScramMandatoryExtension`]. If the callback
+/// does not answer with `true` the authentication is aborted; a server will send the error
+/// `e=extensions-not-supported` in that case.
+#[non_exhaustive]
+pub struct ScramAcceptMandatory;
+impl SizedProperty<'_> for ScramAcceptMandatory {
+    type Value = bool;
+}
+
+/// Opt into generating SCRAM credentials after a `PLAIN` or `LOGIN` authentication
+///
+/// The `PLAIN` and `LOGIN` servers request this property using a 'satisfiable' callback once the
+/// `validate` callback has finalized the validation. It is not requested if `validate` returned
+/// an error or did not finalize the validation. If a finalized validation can still indicate a
+/// rejected authentication, e.g. with a `Result` value, the callback should check the
+/// [`ScramValidationOutcome`] provided with the request and only satisfy this property for
+/// accepted ones.
+///
+/// Satisfying this property with an iteration count makes the mechanism derive credentials for
+/// every enabled SCRAM digest from the plaintext password, which are then passed to an action
+/// callback for [`ScramUpgradedCredentials`]. An iteration count of `0` selects
+/// [`DEFAULT_ITERATIONS`](super::credentials::DEFAULT_ITERATIONS).
+#[non_exhaustive]
+pub struct ScramCredentialUpgrade;
+impl SizedProperty<'_> for ScramCredentialUpgrade {
+    type Value = u32;
+}
+
+/// Outcome of the validation of a `PLAIN` or `LOGIN` authentication
+///
+/// This is provided in the `Context` of the [`ScramCredentialUpgrade`] request as the value the
+/// validation was finalized with. It can be downcast to the `Value` of the
+/// [`Validation`](crate::validate::Validation) used:
+///
+/// ```rust
+/// # use core::any::Any;
+/// # use rsasl::callback::{Context, Request};
+/// # use rsasl::mechanisms::scram::properties::{ScramCredentialUpgrade, ScramValidationOutcome};
+/// # use rsasl::prelude::SessionError;
+/// # struct User;
+/// fn callback(context: &Context, request: &mut Request) -> Result<(), SessionError> {
+///     let accepted = context
+///         .get_ref::<ScramValidationOutcome>()
+///         .and_then(<dyn Any>::downcast_ref::<Result<User, ()>>)
+///         .map_or(false, Result::is_ok);
+///     if accepted {
+///         request.satisfy::<ScramCredentialUpgrade>(&4096)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+#[non_exhaustive]
+pub struct ScramValidationOutcome;
+impl Property<'_> for ScramValidationOutcome {
+    type Value = dyn Any;
+}
+
+/// SCRAM credentials derived from the password used in a `PLAIN` or `LOGIN` authentication
+///
+/// This property is issued as an action if the callback opted in using
+/// [`ScramCredentialUpgrade`], with one set of credentials per enabled digest. Each set uses its
+/// own random salt.
+#[non_exhaustive]
+pub struct ScramUpgradedCredentials;
+impl Property<'_> for ScramUpgradedCredentials {
+    type Value = [ScramCredentials];
+}
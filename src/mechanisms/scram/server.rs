@@ -1,23 +1,35 @@
 use crate::alloc::format;
+use crate::alloc::sync::Arc;
 use crate::alloc::{boxed::Box, string::String, vec::Vec};
+use crate::config::SASLConfig;
 use crate::context::{Demand, DemandReply, Provider};
 use crate::error::{MechanismError, MechanismErrorKind, SessionError};
+#[cfg(feature = "session_export")]
+use crate::export::{StateReader, StateWriter};
 use crate::mechanism::Authentication;
+use crate::mechanisms::scram::cache::ScramKeyCache;
 use crate::mechanisms::scram::client::{ProtocolError, SCRAMError};
+use crate::mechanisms::scram::credentials::{
+    ScramCredentials, ScramDigest, DEFAULT_ITERATIONS, DEFAULT_SALT_LEN,
+};
 use crate::mechanisms::scram::parser::{
-    ClientFinal, ClientFirstMessage, GS2CBindFlag, ServerErrorValue, ServerFinal, ServerFirst,
+    split_client_first, ClientFinal, ClientFirstMessage, Extensions, GS2CBindFlag, ParseError,
+    ServerErrorValue, ServerFinal, ServerFirst,
+};
+use crate::mechanisms::scram::properties::{
+    ScramClientExtensions, ScramMandatoryExtension, ScramServerExtensions, ScramStoredPassword,
+};
+use crate::mechanisms::scram::tools::{
+    accepts_mandatory_extension, derive_stored_keys, generate_nonce, sign_auth_message, DOutput,
 };
-use crate::mechanisms::scram::properties::ScramStoredPassword;
-use crate::mechanisms::scram::tools::{compute_signatures, generate_nonce, DOutput};
-use crate::property::{AuthId, AuthzId};
+use crate::property::{AuthId, AuthzId, Password};
 use crate::session::{MechanismData, MessageSent, State};
 use crate::vectored_io::VectoredWriter;
 use base64::Engine;
-use core::marker::PhantomData;
 use core2::io::Write;
 use digest::crypto_common::BlockSizeUser;
 use digest::generic_array::GenericArray;
-use digest::{Digest, FixedOutput, OutputSizeUser};
+use digest::{Digest, FixedOutputReset, OutputSizeUser};
 use hmac::SimpleHmac;
 use rand::{thread_rng, Rng, RngCore};
 use thiserror::Error;
@@ -35,9 +47,6 @@ trait ScramConfig {
     const ABORT_IMMEDIATELY: bool = false;
 }
 
-const DEFAULT_ITERATIONS: &[u8] = b"16384"; // 2u32.pow(14) TODO check if still reasonable
-const DEFAULT_SALT_LEN: usize = 32;
-
 #[cfg(feature = "scram-sha-1")]
 pub type ScramSha1Server<const N: usize> = ScramServer<sha1::Sha1, N>;
 #[cfg(feature = "scram-sha-2")]
@@ -59,7 +68,7 @@ impl MechanismError for ScramServerError {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum CBSupport {
     /// Server doesn't support CB
     No,
@@ -67,10 +76,14 @@ enum CBSupport {
     Yes,
 }
 
-pub struct ScramServer<D: Digest + BlockSizeUser + FixedOutput, const N: usize> {
+#[derive(Clone)]
+pub struct ScramServer<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync, const N: usize>
+{
     state: Option<ScramServerState<D, N>>,
 }
-impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> ScramServer<D, N> {
+impl<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync, const N: usize>
+    ScramServer<D, N>
+{
     pub const fn new(can_cb: bool) -> Self {
         let plus = if can_cb {
             CBSupport::Yes
@@ -88,12 +101,24 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> ScramServer<D, N>
             state: Some(ScramServerState::WaitingClientFirst(ScramState::new(plus))),
         }
     }
+
+    /// Use the SCRAM key cache installed in `config`, if any
+    pub fn with_key_cache(mut self, config: &SASLConfig, digest: ScramDigest) -> Self {
+        if let Some(ScramServerState::WaitingClientFirst(ref mut state)) = self.state {
+            state.state.key_cache = config
+                .scram_key_cache()
+                .map(|cache| (cache.clone(), digest));
+        }
+        self
+    }
 }
 
 #[derive(Copy, Clone)]
 struct Prov<'a> {
     authid: &'a str,
     authzid: Option<&'a str>,
+    mext: Option<&'a str>,
+    extensions: &'a [(char, &'a str)],
 }
 impl<'a> Provider<'a> for Prov<'a> {
     fn provide(&self, req: &mut Demand<'a>) -> DemandReply<()> {
@@ -101,48 +126,205 @@ impl<'a> Provider<'a> for Prov<'a> {
         if let Some(authzid) = self.authzid {
             req.provide_ref::<AuthzId>(authzid)?;
         }
-        req.done()
+        if let Some(mext) = self.mext {
+            req.provide_ref::<ScramMandatoryExtension>(mext)?;
+        }
+        req.provide_ref::<ScramClientExtensions>(self.extensions)?
+            .done()
     }
 }
 
+#[derive(Clone)]
 pub struct WaitingClientFirst<const N: usize> {
     plus: CBSupport,
-    nonce: PhantomData<&'static [u8; N]>,
+    key_cache: Option<(Arc<ScramKeyCache>, ScramDigest)>,
+    /// Server nonce handed out ahead of time instead of generating a fresh one
+    server_nonce: Option<[u8; N]>,
+}
+
+/// Credentials of the user: iteration count and base64-encoded salt as sent in the
+/// server-first-message, `StoredKey` and `ServerKey`
+type Credentials<D> = (
+    String,
+    String,
+    GenericArray<u8, <D as OutputSizeUser>::OutputSize>,
+    DOutput<D>,
+);
+
+/// Query the user callback for the credentials of the user
+///
+/// Stored credentials are preferred; if only the plaintext password is available the keys are
+/// taken from the key cache if possible or otherwise derived from it. Without a key cache a
+/// random salt is used, otherwise the salt the cache assigns to the user.
+fn lookup_credentials<D>(
+    session_data: &MechanismData,
+    provider: &Prov,
+    key_cache: Option<(&ScramKeyCache, ScramDigest)>,
+) -> Result<Option<Credentials<D>>, SessionError>
+where
+    D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync,
+{
+    let stored = session_data.maybe_need_with::<ScramStoredPassword, _, _>(
+        provider,
+        |ScramStoredPassword {
+             iterations,
+             salt,
+             stored_key,
+             server_key,
+         }| {
+            // First, check if the given values are even possible; we know the digest in
+            // use, we exactly know its output size
+            let hmac_len = <SimpleHmac<D> as OutputSizeUser>::output_size();
+            let hash_len = <D as Digest>::output_size();
+            if stored_key.len() != hash_len || server_key.len() != hmac_len {
+                return Err(SessionError::MechanismError(Box::new(
+                    ScramServerError::PasswordHashInvalid,
+                )));
+            }
+
+            Ok((
+                format!("{iterations}"),
+                base64::engine::general_purpose::STANDARD.encode(salt),
+                GenericArray::clone_from_slice(stored_key),
+                GenericArray::clone_from_slice(server_key),
+            ))
+        },
+    )?;
+
+    if stored.is_some() {
+        return Ok(stored);
+    }
+
+    session_data.maybe_need_with::<Password, _, _>(provider, |password| {
+        let Some((cache, digest)) = key_cache else {
+            let mut salt = [0u8; DEFAULT_SALT_LEN];
+            thread_rng().fill_bytes(&mut salt);
+            let (stored_key, server_key) =
+                derive_stored_keys::<D>(password, DEFAULT_ITERATIONS, &salt);
+            return Ok((
+                format!("{DEFAULT_ITERATIONS}"),
+                base64::engine::general_purpose::STANDARD.encode(salt),
+                stored_key,
+                server_key,
+            ));
+        };
+        let cached = cache.get(provider.authid, password, digest, DEFAULT_ITERATIONS);
+        let credentials = cached.unwrap_or_else(|| {
+            let salt = cache.salt(provider.authid, digest);
+            let credentials = ScramCredentials::derive(digest, password, &salt, DEFAULT_ITERATIONS);
+            cache.insert(provider.authid, password, credentials.clone());
+            credentials
+        });
+        if credentials.stored_key().len() != <D as Digest>::output_size() {
+            return Err(ScramServerError::PasswordHashInvalid.into());
+        }
+        Ok((
+            format!("{}", credentials.iterations()),
+            base64::engine::general_purpose::STANDARD.encode(credentials.salt()),
+            GenericArray::clone_from_slice(credentials.stored_key()),
+            GenericArray::clone_from_slice(credentials.server_key()),
+        ))
+    })
+}
+
+/// Generate a random base64-encoded salt, used to not reveal that a user doesn't exist
+fn random_salt() -> String {
+    let mut salt = [0u8; DEFAULT_SALT_LEN];
+    thread_rng().fill_bytes(&mut salt);
+    base64::engine::general_purpose::STANDARD.encode(salt)
+}
+
+#[cfg(feature = "session_export")]
+/// Returns `true` if `nonce` only consists of printable characters other than `,`
+fn is_valid_nonce(nonce: &[u8]) -> bool {
+    nonce
+        .iter()
+        .all(|c| (0x21..=0x7E).contains(c) && *c != b',')
+}
+
+#[cfg(feature = "http")]
+/// Exported state of a fresh server session using `server_nonce` instead of a random nonce
+///
+/// Importing this state allows a server to hand out its nonce ahead of the exchange, as done for
+/// reauthentication in HTTP (RFC 7804). The nonce must have the length of nonces generated by
+/// the mechanism and only consist of printable characters other than `,`.
+pub fn fixed_nonce_state(server_nonce: &[u8]) -> Vec<u8> {
+    let mut writer = StateWriter::default();
+    writer.u8(3).bytes(server_nonce);
+    writer.finish()
+}
+
+/// Encode a server-first-message, appending any extensions provided by the user callback
+fn build_server_first(
+    session_data: &mut MechanismData,
+    provider: &Prov,
+    msg: &ServerFirst,
+) -> Result<Vec<u8>, SessionError> {
+    let mut server_first = Vec::new();
+    VectoredWriter::new(msg.as_ioslices()).write_all_vectored(&mut server_first)?;
+    session_data.maybe_need_with::<ScramServerExtensions, _, _>(provider, |extensions| {
+        Extensions::encode_into(extensions, &mut server_first)
+            .map_err(|e| SCRAMError::ParseError(e).into())
+    })?;
+    Ok(server_first)
 }
 
 impl<const N: usize> WaitingClientFirst<N> {
     const fn new(plus: CBSupport) -> Self {
         Self {
             plus,
-            nonce: PhantomData,
+            key_cache: None,
+            server_nonce: None,
         }
     }
 
-    fn handle_client_first<D: Digest + BlockSizeUser + FixedOutput>(
+    /// Handle the client-first-message
+    ///
+    /// Returns `None` if the authentication was aborted and a server-final-message containing
+    /// an error was written instead of a server-first-message.
+    fn handle_client_first<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync>(
         self,
         rng: &mut impl Rng,
         session_data: &mut MechanismData,
         client_first: &[u8],
-        writer: impl Write,
+        mut writer: impl Write,
         written: &mut usize,
-    ) -> Result<WaitingClientFinal<D, N>, SessionError> {
+    ) -> Result<Option<WaitingClientFinal<D, N>>, SessionError> {
         // Step 1: (try to) parse the client message received.
-        let client_first @ ClientFirstMessage {
+        let (gs2_header, client_first_bare) =
+            split_client_first(client_first).map_err(SCRAMError::ParseError)?;
+        let ClientFirstMessage {
             cbflag,
-            authzid, // FIXME: Save authzid
+            authzid,
+            mext,
             username: authid,
             nonce: client_nonce,
+            extensions,
         } = ClientFirstMessage::parse(client_first).map_err(SCRAMError::ParseError)?;
 
         // AuthMessage we need to validate the user:
         // client-first-message-bare + "," + server-first-message + "," + client-final-message-without-proof
 
         // TODO: Only store this if we're a -PLUS
-        let mut gs2_header = client_first.build_gs2_header_vec();
+        let mut gs2_header = gs2_header.to_vec();
+
+        let extensions: Vec<(char, &str)> = extensions.iter().collect();
 
         // FIXME: Escape Username from SCRAM format to whatever
-        // TODO: This must at this stage provide so much more info <.<
-        let provider = Prov { authid, authzid };
+        let provider = Prov {
+            authid,
+            authzid,
+            mext,
+            extensions: &extensions,
+        };
+
+        // We don't know of any mandatory extensions, so unless the user explicitly accepts the
+        // one sent we have to abort the exchange.
+        if mext.is_some() && !accepts_mandatory_extension(session_data, &provider)? {
+            let msg = ServerFinal::Error(ServerErrorValue::ExtensionsNotSupported);
+            *written = VectoredWriter::new(msg.to_ioslices()).write_all_vectored(writer)?;
+            return Ok(None);
+        }
 
         match cbflag {
             // TODO: check if this is a protocol downgrade
@@ -160,90 +342,78 @@ impl<const N: usize> WaitingClientFirst<N> {
             })?,
         }
 
-        let params = session_data.maybe_need_with::<ScramStoredPassword, _, _>(
-            &provider,
-            |ScramStoredPassword {
-                 iterations,
-                 salt,
-                 stored_key,
-                 server_key,
-             }| {
-                // First, check if the given values are even possible; we know the digest in
-                // use, we exactly know its output size
-                let hmac_len = <SimpleHmac<D> as OutputSizeUser>::output_size();
-                let hash_len = <D as Digest>::output_size();
-                if stored_key.len() != hash_len || server_key.len() != hmac_len {
-                    return Err(SessionError::MechanismError(Box::new(
-                        ScramServerError::PasswordHashInvalid,
-                    )));
-                }
+        let key_cache = self
+            .key_cache
+            .as_ref()
+            .map(|(cache, digest)| (&**cache, *digest));
+        let params = lookup_credentials::<D>(session_data, &provider, key_cache)?;
 
-                Ok((
-                    format!("{iterations}"),
-                    base64::engine::general_purpose::STANDARD.encode(salt),
-                    GenericArray::clone_from_slice(stored_key),
-                    GenericArray::clone_from_slice(server_key),
-                ))
-            },
-        )?;
+        let server_nonce: [u8; N] = self.server_nonce.unwrap_or_else(|| generate_nonce(rng));
 
-        let server_nonce: [u8; N] = generate_nonce(rng);
+        let (iterations, salt, keys) =
+            if let Some((iterations, salt, stored_key, server_key)) = params {
+                (iterations, salt, Some((stored_key, server_key)))
+            } else {
+                (format!("{DEFAULT_ITERATIONS}"), random_salt(), None)
+            };
 
-        if let Some((iterations, salt, stored_key, server_key)) = params {
-            let msg = ServerFirst::new(
-                client_nonce,
-                &server_nonce,
-                salt.as_bytes(),
-                iterations.as_bytes(),
-            );
-            let mut vecw = VectoredWriter::new(msg.as_ioslices());
-            *written = vecw.write_all_vectored(writer)?;
-
-            Ok(WaitingClientFinal::new(
-                client_nonce.into(),
-                server_nonce,
-                gs2_header,
-                authid.to_string(),
-                authzid.map(ToString::to_string),
-                salt,
-                iterations,
-                stored_key,
-                server_key,
-            ))
-        } else {
-            let mut salt = [0u8; DEFAULT_SALT_LEN];
-            thread_rng().fill_bytes(&mut salt);
-            let salt = base64::engine::general_purpose::STANDARD.encode(salt);
+        let msg = ServerFirst::new(
+            client_nonce,
+            &server_nonce,
+            salt.as_bytes(),
+            iterations.as_bytes(),
+        );
+        let server_first = build_server_first(session_data, &provider, &msg)?;
+        writer.write_all(&server_first)?;
+        *written = server_first.len();
+
+        let Some((stored_key, server_key)) = keys else {
+            return Ok(Some(WaitingClientFinal::bad_user()));
+        };
 
-            let msg = ServerFirst::new(
-                client_nonce,
-                &server_nonce,
-                salt.as_bytes(),
-                DEFAULT_ITERATIONS,
-            );
-            let mut vecw = VectoredWriter::new(msg.as_ioslices());
-            *written = vecw.write_all_vectored(writer)?;
-
-            Ok(WaitingClientFinal::bad_user())
-        }
+        Ok(Some(WaitingClientFinal::new(
+            client_nonce.into(),
+            server_nonce,
+            gs2_header,
+            client_first_bare.to_vec(),
+            server_first,
+            authid.to_string(),
+            authzid.map(ToString::to_string),
+            mext.map(ToString::to_string),
+            extensions
+                .iter()
+                .map(|(name, value)| (*name, (*value).to_string()))
+                .collect(),
+            stored_key,
+            server_key,
+        )))
     }
 }
 
-pub struct WaitingClientFinal<D: Digest + BlockSizeUser + FixedOutput, const N: usize> {
+#[derive(Clone)]
+pub struct WaitingClientFinal<
+    D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync,
+    const N: usize,
+> {
     data: Option<FinalInner<D, N>>,
 }
-struct FinalInner<D: Digest + BlockSizeUser + FixedOutput, const N: usize> {
+#[derive(Clone)]
+struct FinalInner<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync, const N: usize> {
     client_nonce: Vec<u8>,
     server_nonce: [u8; N],
     gs2_header: Vec<u8>,
+    client_first_bare: Vec<u8>,
+    server_first: Vec<u8>,
     username: String,
     authzid: Option<String>,
-    salt: String,
-    iterations: String,
+    mext: Option<String>,
+    extensions: Vec<(char, String)>,
     stored_key: GenericArray<u8, D::OutputSize>,
     server_key: DOutput<D>,
 }
-impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal<D, N> {
+impl<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync, const N: usize>
+    WaitingClientFinal<D, N>
+{
     // There really isn't a good way of cutting down on the number of args and they are *pretty*
     // self-explanatory.
     #[allow(clippy::too_many_arguments)]
@@ -251,10 +421,12 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
         client_nonce: Vec<u8>,
         server_nonce: [u8; N],
         gs2_header: Vec<u8>,
+        client_first_bare: Vec<u8>,
+        server_first: Vec<u8>,
         username: String,
         authzid: Option<String>,
-        salt: String,
-        iterations: String,
+        mext: Option<String>,
+        extensions: Vec<(char, String)>,
         stored_key: GenericArray<u8, D::OutputSize>,
         server_key: DOutput<D>,
     ) -> Self {
@@ -263,10 +435,12 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
                 client_nonce,
                 server_nonce,
                 gs2_header,
+                client_first_bare,
+                server_first,
                 username,
                 authzid,
-                salt,
-                iterations,
+                mext,
+                extensions,
                 stored_key,
                 server_key,
             }),
@@ -277,6 +451,65 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
         Self { data: None }
     }
 
+    #[cfg(feature = "session_export")]
+    fn export(&self, writer: &mut StateWriter) {
+        let Some(ref data) = self.data else {
+            writer.u8(0);
+            return;
+        };
+        writer
+            .u8(1)
+            .bytes(&data.client_nonce)
+            .bytes(&data.server_nonce)
+            .bytes(&data.gs2_header)
+            .bytes(&data.client_first_bare)
+            .bytes(&data.server_first)
+            .str(&data.username)
+            .opt_str(data.authzid.as_deref())
+            .opt_str(data.mext.as_deref())
+            .u32(u32::try_from(data.extensions.len()).unwrap_or(u32::MAX));
+        for (name, value) in &data.extensions {
+            writer.u32(u32::from(*name)).str(value);
+        }
+        writer.bytes(&data.stored_key).bytes(&data.server_key);
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import(reader: &mut StateReader) -> Option<Self> {
+        if reader.u8()? == 0 {
+            return Some(Self::bad_user());
+        }
+        let client_nonce = reader.bytes()?.to_vec();
+        let server_nonce = reader.bytes()?.try_into().ok()?;
+        let gs2_header = reader.bytes()?.to_vec();
+        let client_first_bare = reader.bytes()?.to_vec();
+        let server_first = reader.bytes()?.to_vec();
+        let username = reader.str()?.to_string();
+        let authzid = reader.opt_str()?.map(ToString::to_string);
+        let mext = reader.opt_str()?.map(ToString::to_string);
+        let count = reader.u32()?;
+        let mut extensions = Vec::new();
+        for _ in 0..count {
+            let name = char::from_u32(reader.u32()?)?;
+            extensions.push((name, reader.str()?.to_string()));
+        }
+        let stored_key = GenericArray::from_exact_iter(reader.bytes()?.iter().copied())?;
+        let server_key = DOutput::<D>::from_exact_iter(reader.bytes()?.iter().copied())?;
+        Some(Self::new(
+            client_nonce,
+            server_nonce,
+            gs2_header,
+            client_first_bare,
+            server_first,
+            username,
+            authzid,
+            mext,
+            extensions,
+            stored_key,
+            server_key,
+        ))
+    }
+
     fn handle_client_final(
         self,
         client_final: &[u8],
@@ -290,14 +523,23 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
             proof,
         } = ClientFinal::parse(client_final).map_err(SCRAMError::ParseError)?;
 
+        // Everything before ",p=" is the client-final-message-without-proof
+        let proof_start = client_final
+            .windows(3)
+            .rposition(|window| window == b",p=")
+            .ok_or(SCRAMError::ParseError(ParseError::MissingAttributes))?;
+        let client_final_without_proof = &client_final[..proof_start];
+
         let msg = if let Some(FinalInner {
             client_nonce,
             server_nonce,
             gs2_header,
+            client_first_bare,
+            server_first,
             username,
             authzid,
-            salt,
-            iterations,
+            mext,
+            extensions,
             stored_key,
             server_key,
         }) = self.data
@@ -320,15 +562,16 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
                     let mut client_signature = DOutput::<D>::default();
                     let mut server_signature = DOutput::<D>::default();
 
-                    compute_signatures::<D>(
+                    sign_auth_message::<D>(
                         &stored_key,
                         &server_key,
-                        &username,
-                        &client_nonce,
-                        &server_nonce,
-                        salt.as_bytes(),
-                        iterations.as_bytes(),
-                        channel_binding,
+                        &[
+                            &client_first_bare,
+                            b",",
+                            &server_first,
+                            b",",
+                            client_final_without_proof,
+                        ],
                         &mut client_signature,
                         &mut server_signature,
                     );
@@ -352,9 +595,15 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
                         let mut vecw = VectoredWriter::new(msg.to_ioslices());
                         *written = vecw.write_all_vectored(writer)?;
 
+                        let extensions: Vec<(char, &str)> = extensions
+                            .iter()
+                            .map(|(name, value)| (*name, value.as_str()))
+                            .collect();
                         let prov = Prov {
                             authid: username.as_str(),
                             authzid: authzid.as_deref(),
+                            mext: mext.as_deref(),
+                            extensions: &extensions,
                         };
                         session_data.validate(&prov)?;
 
@@ -378,6 +627,7 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> WaitingClientFinal
     }
 }
 
+#[derive(Clone)]
 struct ScramState<S> {
     state: S,
 }
@@ -388,21 +638,23 @@ impl<const N: usize> ScramState<WaitingClientFirst<N>> {
         }
     }
 
-    fn step<D: Digest + BlockSizeUser + FixedOutput>(
+    fn step<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync>(
         self,
         rng: &mut impl Rng,
         session_data: &mut MechanismData,
         input: &[u8],
         writer: impl Write,
         written: &mut usize,
-    ) -> Result<ScramState<WaitingClientFinal<D, N>>, SessionError> {
+    ) -> Result<Option<ScramState<WaitingClientFinal<D, N>>>, SessionError> {
         let state = self
             .state
             .handle_client_first(rng, session_data, input, writer, written)?;
-        Ok(ScramState { state })
+        Ok(state.map(|state| ScramState { state }))
     }
 }
-impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> ScramState<WaitingClientFinal<D, N>> {
+impl<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync, const N: usize>
+    ScramState<WaitingClientFinal<D, N>>
+{
     fn step(
         self,
         input: &[u8],
@@ -416,13 +668,17 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> ScramState<Waiting
     }
 }
 
-enum ScramServerState<D: Digest + BlockSizeUser + FixedOutput, const N: usize> {
+#[derive(Clone)]
+enum ScramServerState<D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync, const N: usize> {
     WaitingClientFirst(ScramState<WaitingClientFirst<N>>),
     WaitingClientFinal(ScramState<WaitingClientFinal<D, N>>),
     Finished(ScramState<()>),
 }
 
-impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> Authentication for ScramServer<D, N> {
+impl<D, const N: usize> Authentication for ScramServer<D, N>
+where
+    D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync + 'static,
+{
     fn step(
         &mut self,
         session: &mut MechanismData,
@@ -436,10 +692,15 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> Authentication for
 
                 let mut rng = rand::thread_rng();
                 let mut written = 0;
-                let new_state =
-                    state.step(&mut rng, session, client_first, writer, &mut written)?;
-                self.state = Some(WaitingClientFinal(new_state));
-                Ok(State::Running)
+                if let Some(new_state) =
+                    state.step(&mut rng, session, client_first, writer, &mut written)?
+                {
+                    self.state = Some(WaitingClientFinal(new_state));
+                    Ok(State::Running)
+                } else {
+                    self.state = Some(Finished(ScramState { state: () }));
+                    Ok(State::Finished(MessageSent::Yes))
+                }
             }
             Some(WaitingClientFinal(state)) => {
                 let client_final = input.ok_or(SessionError::InputDataRequired)?;
@@ -453,4 +714,291 @@ impl<D: Digest + BlockSizeUser + FixedOutput, const N: usize> Authentication for
             None => panic!("SCRAM server state machine in invalid state"),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        use ScramServerState::{Finished, WaitingClientFinal, WaitingClientFirst};
+        let mut writer = StateWriter::default();
+        match self.state.as_ref()? {
+            WaitingClientFirst(state) => {
+                if let Some(server_nonce) = state.state.server_nonce {
+                    writer.u8(3).bytes(&server_nonce);
+                } else {
+                    writer.u8(0);
+                }
+            }
+            WaitingClientFinal(state) => state.state.export(writer.u8(1)),
+            Finished(_) => {
+                writer.u8(2);
+            }
+        }
+        Some(writer.finish())
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, state: &[u8]) -> bool {
+        let mut reader = StateReader::new(state);
+        let state = match reader.u8() {
+            // A fresh instance is already waiting for the client-first-message and has the key
+            // cache of the config installed
+            Some(0) => return reader.finish().is_some(),
+            Some(3) => {
+                let Some(ScramServerState::WaitingClientFirst(state)) = self.state.as_mut() else {
+                    return false;
+                };
+                let Some(server_nonce) = reader
+                    .bytes()
+                    .and_then(|nonce| <[u8; N]>::try_from(nonce).ok())
+                else {
+                    return false;
+                };
+                if !is_valid_nonce(&server_nonce) || reader.finish().is_none() {
+                    return false;
+                }
+                state.state.server_nonce = Some(server_nonce);
+                return true;
+            }
+            Some(1) => match self::WaitingClientFinal::import(&mut reader) {
+                Some(state) => ScramServerState::WaitingClientFinal(ScramState { state }),
+                None => return false,
+            },
+            Some(2) => ScramServerState::Finished(ScramState { state: () }),
+            _ => return false,
+        };
+        if reader.finish().is_none() {
+            return false;
+        }
+        self.state = Some(state);
+        true
+    }
+}
+
+#[cfg(all(test, feature = "scram-sha-2"))]
+mod tests {
+    use super::*;
+    use crate::callback::{Context, Request, SessionCallback};
+    use crate::mechanisms::scram::mechinfo::SCRAM_SHA256;
+    use crate::mechanisms::scram::properties::ScramAcceptMandatory;
+    use crate::property::Password;
+    use crate::session::{SessionData, Side};
+    use crate::test;
+    use crate::validate::{Validate, ValidationError};
+    use std::io::Cursor;
+
+    struct C {
+        mext: Option<&'static str>,
+        accept: bool,
+        plaintext: bool,
+    }
+    impl SessionCallback for C {
+        fn callback(
+            &self,
+            session_data: &SessionData,
+            context: &Context,
+            request: &mut Request,
+        ) -> Result<(), SessionError> {
+            if session_data.side() == Side::Client {
+                if let Some(mext) = self.mext {
+                    request.satisfy::<ScramMandatoryExtension>(mext)?;
+                }
+                request
+                    .satisfy::<ScramClientExtensions>(&[('x', "client")])?
+                    .satisfy::<AuthId>("user")?
+                    .satisfy::<Password>(b"secret")?;
+            } else {
+                if request.is::<ScramAcceptMandatory>() {
+                    assert_eq!(context.get_ref::<ScramMandatoryExtension>(), self.mext);
+                    request.satisfy::<ScramAcceptMandatory>(&self.accept)?;
+                }
+                request.satisfy::<ScramServerExtensions>(&[('y', "server")])?;
+
+                if self.plaintext {
+                    request.satisfy::<Password>(b"secret")?;
+                } else {
+                    let (stored_key, server_key) =
+                        derive_stored_keys::<sha2::Sha256>(b"secret", 4096, b"salt");
+                    request.satisfy::<ScramStoredPassword>(&ScramStoredPassword::new(
+                        4096,
+                        b"salt",
+                        &stored_key,
+                        &server_key,
+                    ))?;
+                }
+            }
+            Ok(())
+        }
+
+        fn validate(
+            &self,
+            _session_data: &SessionData,
+            context: &Context,
+            _validate: &mut Validate<'_>,
+        ) -> Result<(), ValidationError> {
+            let extensions = context.get_ref::<ScramClientExtensions>().unwrap();
+            assert_eq!(extensions, [('x', "client")]);
+            Ok(())
+        }
+    }
+
+    /// Run a full exchange, returning the final server message and the resulting client state
+    fn exchange(callback: C) -> (Vec<u8>, Result<State, SessionError>) {
+        exchange_with(test::server_config(callback))
+    }
+
+    fn exchange_with(config: Arc<SASLConfig>) -> (Vec<u8>, Result<State, SessionError>) {
+        let mut server = test::server_session(config.clone(), &SCRAM_SHA256);
+        let mut client = test::client_session(config, &SCRAM_SHA256);
+
+        let mut client_out = Vec::new();
+        client.step(None, &mut client_out).unwrap();
+        loop {
+            let mut server_out = Cursor::new(Vec::new());
+            let state = server.step(Some(&client_out), &mut server_out);
+            let server_out = server_out.into_inner();
+            if !matches!(state, Ok(State::Running)) {
+                let client_state = client.step(Some(&server_out), &mut Vec::new());
+                return (server_out, client_state);
+            }
+            client_out.clear();
+            client.step(Some(&server_out), &mut client_out).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_extensions_roundtrip() {
+        let (server_final, client_state) = exchange(C {
+            mext: None,
+            accept: false,
+            plaintext: false,
+        });
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+    }
+
+    #[test]
+    fn test_mandatory_extension_rejected() {
+        let (server_final, client_state) = exchange(C {
+            mext: Some("unknown"),
+            accept: false,
+            plaintext: false,
+        });
+        assert_eq!(server_final, b"e=extensions-not-supported");
+        assert!(client_state.is_err());
+    }
+
+    #[test]
+    fn test_mandatory_extension_accepted() {
+        let (server_final, client_state) = exchange(C {
+            mext: Some("known"),
+            accept: true,
+            plaintext: false,
+        });
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+    }
+
+    #[test]
+    fn test_plaintext_password() {
+        let (server_final, client_state) = exchange(C {
+            mext: None,
+            accept: false,
+            plaintext: true,
+        });
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+    }
+
+    #[test]
+    #[cfg(feature = "config_builder")]
+    fn test_key_cache() {
+        use crate::registry::Registry;
+
+        let cache = Arc::new(ScramKeyCache::new(8));
+        let callback = C {
+            mext: None,
+            accept: false,
+            plaintext: true,
+        };
+        let config = SASLConfig::new_with_scram_key_cache(
+            callback,
+            Registry::with_mechanisms(&[]),
+            cache.clone(),
+        )
+        .unwrap();
+
+        let (server_final, client_state) = exchange_with(config.clone());
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+        let cached = cache
+            .get("user", b"secret", ScramDigest::Sha256, DEFAULT_ITERATIONS)
+            .unwrap();
+
+        // The second exchange must reuse the cached keys and thus the salt
+        let (server_final, client_state) = exchange_with(config);
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(
+            cache.get("user", b"secret", ScramDigest::Sha256, DEFAULT_ITERATIONS),
+            Some(cached)
+        );
+    }
+
+    /// Answers `Password` with different passwords for the client and the server
+    struct Passwords {
+        client: &'static [u8],
+        server: &'static [u8],
+    }
+    impl SessionCallback for Passwords {
+        fn callback(
+            &self,
+            session_data: &SessionData,
+            _context: &Context,
+            request: &mut Request,
+        ) -> Result<(), SessionError> {
+            if session_data.side() == Side::Client {
+                request
+                    .satisfy::<AuthId>("user")?
+                    .satisfy::<Password>(self.client)?;
+            } else {
+                request.satisfy::<Password>(self.server)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "config_builder")]
+    fn test_key_cache_password_change() {
+        use crate::registry::Registry;
+
+        let cache = Arc::new(ScramKeyCache::new(8));
+        let exchange = |client, server| {
+            let config = SASLConfig::new_with_scram_key_cache(
+                Passwords { client, server },
+                Registry::with_mechanisms(&[]),
+                cache.clone(),
+            )
+            .unwrap();
+            exchange_with(config)
+        };
+
+        let (server_final, client_state) = exchange(b"old", b"old");
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+
+        // The password was changed without invalidating the cache
+        let (server_final, client_state) = exchange(b"old", b"new");
+        assert_eq!(server_final, b"e=invalid-proof");
+        assert!(client_state.is_err());
+
+        let (server_final, client_state) = exchange(b"new", b"new");
+        assert!(server_final.starts_with(b"v="));
+        assert!(client_state.unwrap().is_finished());
+        assert_eq!(cache.len(), 1);
+    }
 }
@@ -0,0 +1,277 @@
+//! Parsing and serializing SCRAM verifiers in commonly used storage formats
+//!
+//! Different systems store the SCRAM credentials of an user in different text formats. This
+//! module converts between these formats and [`ScramCredentials`], allowing verifiers to be
+//! migrated between systems or directly used to answer a
+//! [`ScramStoredPassword`](super::properties::ScramStoredPassword) request.
+//!
+//! ```
+//! # #[cfg(feature = "scram-sha-2")] {
+//! # use rsasl::mechanisms::scram::storage::VerifierFormat;
+//! # use rsasl::mechanisms::scram::credentials::ScramCredentials;
+//! let verifier = "SCRAM-SHA-256$4096:MDEyMzQ1Njc4OWFiY2RlZg==$\
+//!     bpSY5Ze9NUH+I35LC3gVq+DpBfK46iXBxvhAKqVu9pE=:VpYlBuxyzeCI1KnctrefdljpB1mk3Gp7sBI/t11+NkQ=";
+//! let credentials = VerifierFormat::PostgreSql.parse(verifier).unwrap();
+//! assert_eq!(credentials.iterations(), 4096);
+//!
+//! let dovecot = VerifierFormat::Dovecot.serialize(&credentials);
+//! assert!(dovecot.starts_with("{SCRAM-SHA-256}4096,"));
+//! # }
+//! ```
+
+use crate::alloc::{format, string::String};
+use crate::mechanisms::scram::credentials::{ScramCredentials, ScramDigest};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use thiserror::Error;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+/// Storage formats for SCRAM verifiers
+pub enum VerifierFormat {
+    /// The format used by PostgreSQL in `pg_authid.rolpassword`:
+    ///
+    /// `SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>`
+    PostgreSql,
+
+    /// The LDAP `authPassword` attribute value as specified in
+    /// [RFC 5803](https://www.rfc-editor.org/rfc/rfc5803):
+    ///
+    /// `SCRAM-SHA-1$<iterations>:<salt>$<StoredKey>:<ServerKey>`
+    ///
+    /// Unlike [`PostgreSql`](Self::PostgreSql) whitespace around the `$` separators is allowed.
+    AuthPassword,
+
+    /// The format used by Dovecot password schemes:
+    ///
+    /// `{SCRAM-SHA-256}<iterations>,<salt>,<StoredKey>,<ServerKey>`
+    Dovecot,
+
+    /// The `cmusaslsecretSCRAM-*` secret stored in a Cyrus SASL `sasldb`:
+    ///
+    /// `<iterations>$<salt>$<StoredKey>:<ServerKey>`
+    ///
+    /// This format does not contain the digest used, it is instead given by the name of the
+    /// property the secret is stored under.
+    Sasldb(ScramDigest),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+/// Error returned when parsing a stored verifier failed
+pub enum VerifierError {
+    #[error("verifier is malformed")]
+    Malformed,
+
+    #[error("unsupported SCRAM scheme '{0}'")]
+    UnknownScheme(String),
+
+    #[error("iteration count is invalid")]
+    InvalidIterations,
+
+    #[error("invalid base64 encoding: {0}")]
+    Base64(
+        #[from]
+        #[source]
+        base64::DecodeError,
+    ),
+
+    #[error("key length does not match the digest in use")]
+    KeyLength,
+}
+
+impl VerifierFormat {
+    /// Parse a verifier stored in this format
+    ///
+    /// # Errors
+    /// Returns an error if the verifier is not well-formed, uses an unsupported digest or the
+    /// stored keys do not match the output length of the digest.
+    pub fn parse(self, verifier: &str) -> Result<ScramCredentials, VerifierError> {
+        let verifier = verifier.trim();
+        let (digest, iterations, salt, stored_key, server_key) = match self {
+            Self::PostgreSql | Self::AuthPassword => {
+                let mut parts = verifier.splitn(3, '$');
+                let (Some(scheme), Some(info), Some(value)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(VerifierError::Malformed);
+                };
+                let (scheme, info, value) = if self == Self::AuthPassword {
+                    (scheme.trim_end(), info.trim(), value.trim_start())
+                } else {
+                    (scheme, info, value)
+                };
+                let (iterations, salt) = info.split_once(':').ok_or(VerifierError::Malformed)?;
+                let (stored_key, server_key) =
+                    value.split_once(':').ok_or(VerifierError::Malformed)?;
+                (
+                    parse_scheme(scheme)?,
+                    iterations,
+                    salt,
+                    stored_key,
+                    server_key,
+                )
+            }
+            Self::Dovecot => {
+                let (scheme, rest) = verifier
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .ok_or(VerifierError::Malformed)?;
+                let mut parts = rest.split(',');
+                let (Some(iterations), Some(salt), Some(stored_key), Some(server_key), None) = (
+                    parts.next(),
+                    parts.next(),
+                    parts.next(),
+                    parts.next(),
+                    parts.next(),
+                ) else {
+                    return Err(VerifierError::Malformed);
+                };
+                (
+                    parse_scheme(scheme)?,
+                    iterations,
+                    salt,
+                    stored_key,
+                    server_key,
+                )
+            }
+            Self::Sasldb(digest) => {
+                let mut parts = verifier.splitn(3, '$');
+                let (Some(iterations), Some(salt), Some(value)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(VerifierError::Malformed);
+                };
+                let (stored_key, server_key) =
+                    value.split_once(':').ok_or(VerifierError::Malformed)?;
+                (digest, iterations, salt, stored_key, server_key)
+            }
+        };
+
+        let iterations = iterations
+            .parse::<u32>()
+            .ok()
+            .filter(|i| *i > 0)
+            .ok_or(VerifierError::InvalidIterations)?;
+        ScramCredentials::from_parts(
+            digest,
+            iterations,
+            &STANDARD.decode(salt)?,
+            &STANDARD.decode(stored_key)?,
+            &STANDARD.decode(server_key)?,
+        )
+        .ok_or(VerifierError::KeyLength)
+    }
+
+    /// Serialize credentials into this format
+    ///
+    /// For [`Sasldb`](Self::Sasldb) the digest given in the format is ignored in favour of the
+    /// one the credentials were generated with.
+    #[must_use]
+    pub fn serialize(self, credentials: &ScramCredentials) -> String {
+        let scheme = credentials.digest().mechanism_name();
+        let iterations = credentials.iterations();
+        let salt = STANDARD.encode(credentials.salt());
+        let stored_key = STANDARD.encode(credentials.stored_key());
+        let server_key = STANDARD.encode(credentials.server_key());
+        match self {
+            Self::PostgreSql | Self::AuthPassword => {
+                format!("{scheme}${iterations}:{salt}${stored_key}:{server_key}")
+            }
+            Self::Dovecot => format!("{{{scheme}}}{iterations},{salt},{stored_key},{server_key}"),
+            Self::Sasldb(_) => format!("{iterations}${salt}${stored_key}:{server_key}"),
+        }
+    }
+}
+
+fn parse_scheme(scheme: &str) -> Result<ScramDigest, VerifierError> {
+    ScramDigest::from_mechanism_name(scheme)
+        .filter(|digest| digest.mechanism_name() == scheme)
+        .ok_or_else(|| VerifierError::UnknownScheme(String::from(scheme)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "scram-sha-2")]
+    const POSTGRES: &str = "SCRAM-SHA-256$4096:MDEyMzQ1Njc4OWFiY2RlZg==$\
+        bpSY5Ze9NUH+I35LC3gVq+DpBfK46iXBxvhAKqVu9pE=:VpYlBuxyzeCI1KnctrefdljpB1mk3Gp7sBI/t11+NkQ=";
+
+    #[test]
+    #[cfg(feature = "scram-sha-2")]
+    fn test_postgres() {
+        let credentials = VerifierFormat::PostgreSql.parse(POSTGRES).unwrap();
+        assert_eq!(
+            credentials,
+            ScramCredentials::derive(ScramDigest::Sha256, b"secret", b"0123456789abcdef", 4096)
+        );
+        assert_eq!(VerifierFormat::PostgreSql.serialize(&credentials), POSTGRES);
+    }
+
+    #[test]
+    #[cfg(feature = "scram-sha-1")]
+    fn test_auth_password() {
+        // Credentials of the example exchange in RFC 5802
+        let verifier = "SCRAM-SHA-1 $ 4096:QSXCR+Q6sek8bf92 $ \
+            6dlGYMOdZcOPutkcNY8U2g7vK9Y=:D+CSWLOshSulAsxiupA+qs2/fTE=";
+        let credentials = VerifierFormat::AuthPassword.parse(verifier).unwrap();
+        assert_eq!(credentials.digest(), ScramDigest::Sha1);
+        assert_eq!(
+            VerifierFormat::AuthPassword.serialize(&credentials),
+            verifier.replace(' ', "")
+        );
+        assert!(matches!(
+            VerifierFormat::PostgreSql.parse(verifier),
+            Err(VerifierError::UnknownScheme(_))
+        ));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for credentials in ScramCredentials::derive_all(b"pencil", b"salt", 4096) {
+            let formats = [
+                VerifierFormat::PostgreSql,
+                VerifierFormat::AuthPassword,
+                VerifierFormat::Dovecot,
+                VerifierFormat::Sasldb(credentials.digest()),
+            ];
+            for format in formats {
+                let serialized = format.serialize(&credentials);
+                assert_eq!(format.parse(&serialized).unwrap(), credentials);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let digest = ScramDigest::ALL[0];
+        let invalid = [
+            (VerifierFormat::PostgreSql, "SCRAM-SHA-256$4096:c2FsdA=="),
+            (
+                VerifierFormat::Dovecot,
+                "SCRAM-SHA-256}4096,c2FsdA==,AA==,AA==",
+            ),
+            (VerifierFormat::Dovecot, "{SCRAM-SHA-256}4096,c2FsdA==,AA=="),
+            (VerifierFormat::Sasldb(digest), "4096$c2FsdA==$AA=="),
+        ];
+        for (format, verifier) in invalid {
+            assert!(
+                matches!(format.parse(verifier), Err(VerifierError::Malformed)),
+                "{verifier}"
+            );
+        }
+        assert!(matches!(
+            VerifierFormat::Sasldb(digest).parse("0$c2FsdA==$AA==:AA=="),
+            Err(VerifierError::InvalidIterations)
+        ));
+        assert!(matches!(
+            VerifierFormat::Sasldb(digest).parse("4096$c2FsdA==$AA==:AA=="),
+            Err(VerifierError::KeyLength)
+        ));
+        assert!(matches!(
+            VerifierFormat::Dovecot.parse("{SCRAM-MD5}4096,c2FsdA==,AA==,AA=="),
+            Err(VerifierError::UnknownScheme(_))
+        ));
+    }
+}
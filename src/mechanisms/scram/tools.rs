@@ -1,10 +1,15 @@
 use digest::crypto_common::BlockSizeUser;
 use digest::generic_array::GenericArray;
-use digest::{Digest, FixedOutput, FixedOutputReset, Mac, OutputSizeUser, Update};
+use digest::{Digest, FixedOutput, FixedOutputReset, Mac, OutputSizeUser};
 use hmac::SimpleHmac;
 use rand::distributions::{Distribution, Slice};
 use rand::Rng;
 
+use super::properties::ScramAcceptMandatory;
+use crate::context::Provider;
+use crate::error::SessionError;
+use crate::session::MechanismData;
+
 /// All the characters that are valid chars for a nonce
 pub(super) const PRINTABLE: &[u8] =
     b"!\"#$%&'()*+-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxy";
@@ -24,7 +29,7 @@ where
 }
 
 #[allow(clippy::too_many_arguments)]
-#[allow(clippy::missing_panics_doc)]
+#[deprecated(note = "use `sign_auth_message`, which includes any exchanged extension attributes")]
 pub fn compute_signatures<D: Digest + BlockSizeUser + FixedOutput>(
     stored_key: &GenericArray<u8, D::OutputSize>,
     server_key: &DOutput<D>,
@@ -37,45 +42,56 @@ pub fn compute_signatures<D: Digest + BlockSizeUser + FixedOutput>(
     client_signature: &mut DOutput<D>,
     server_signature: &mut DOutput<D>,
 ) {
-    <SimpleHmac<D>>::new_from_slice(stored_key.as_slice())
-        .expect("HMAC can work with any key size")
-        .chain(b"n=")
-        .chain(username.as_bytes())
-        .chain(b",r=")
-        .chain(client_nonce)
-        .chain(b",r=")
-        .chain(client_nonce)
-        .chain(server_nonce)
-        .chain(",s=")
-        .chain(salt)
-        .chain(",i=")
-        .chain(iterations)
-        .chain(b",c=")
-        .chain(channel_binding)
-        .chain(b",r=")
-        .chain(client_nonce)
-        .chain(server_nonce)
-        .finalize_into(client_signature);
+    let auth_message: [&[u8]; 16] = [
+        b"n=",
+        username.as_bytes(),
+        b",r=",
+        client_nonce,
+        b",r=",
+        client_nonce,
+        server_nonce,
+        b",s=",
+        salt,
+        b",i=",
+        iterations,
+        b",c=",
+        channel_binding,
+        b",r=",
+        client_nonce,
+        server_nonce,
+    ];
+    sign_auth_message::<D>(
+        stored_key,
+        server_key,
+        &auth_message,
+        client_signature,
+        server_signature,
+    );
+}
 
-    <SimpleHmac<D>>::new_from_slice(server_key.as_slice())
-        .expect("HMAC can work with any key size")
-        .chain(b"n=")
-        .chain(username.as_bytes())
-        .chain(b",r=")
-        .chain(client_nonce)
-        .chain(b",r=")
-        .chain(client_nonce)
-        .chain(server_nonce)
-        .chain(",s=")
-        .chain(salt)
-        .chain(",i=")
-        .chain(iterations)
-        .chain(b",c=")
-        .chain(channel_binding)
-        .chain(b",r=")
-        .chain(client_nonce)
-        .chain(server_nonce)
-        .finalize_into(server_signature);
+/// Compute the client and server signature over an `AuthMessage`
+///
+/// The `AuthMessage` is given as a list of parts that are concatenated in order, i.e.
+/// `client-first-message-bare "," server-first-message "," client-final-message-without-proof`,
+/// including any extension attributes that were exchanged.
+#[allow(clippy::missing_panics_doc)]
+pub fn sign_auth_message<D: Digest + BlockSizeUser + FixedOutput>(
+    stored_key: &GenericArray<u8, D::OutputSize>,
+    server_key: &DOutput<D>,
+    auth_message: &[&[u8]],
+    client_signature: &mut DOutput<D>,
+    server_signature: &mut DOutput<D>,
+) {
+    let mut client_hmac = <SimpleHmac<D>>::new_from_slice(stored_key.as_slice())
+        .expect("HMAC can work with any key size");
+    let mut server_hmac = <SimpleHmac<D>>::new_from_slice(server_key.as_slice())
+        .expect("HMAC can work with any key size");
+    for part in auth_message {
+        Mac::update(&mut client_hmac, part);
+        Mac::update(&mut server_hmac, part);
+    }
+    client_hmac.finalize_into(client_signature);
+    server_hmac.finalize_into(server_signature);
 }
 
 #[allow(clippy::missing_panics_doc)]
@@ -97,3 +113,33 @@ where
 
     (client_key, server_key)
 }
+
+/// Derive the `StoredKey` and `ServerKey` a SCRAM server needs from a plaintext password
+///
+/// This runs PBKDF2 with the given salt and iteration count, which is the expensive part of SCRAM.
+#[must_use]
+pub fn derive_stored_keys<D>(
+    password: &[u8],
+    iterations: u32,
+    salt: &[u8],
+) -> (GenericArray<u8, D::OutputSize>, DOutput<D>)
+where
+    D: Digest + BlockSizeUser + FixedOutputReset + Clone + Sync,
+{
+    let mut salted_password = DOutput::<D>::default();
+    hash_password::<D>(password, iterations, salt, &mut salted_password);
+    let (client_key, server_key) = derive_keys::<D>(&salted_password);
+    (D::digest(client_key), server_key)
+}
+
+/// Ask the user callback whether the mandatory extension sent by the other party is understood.
+///
+/// Since we don't implement any mandatory extensions ourselves this defaults to `false`.
+pub(super) fn accepts_mandatory_extension(
+    session_data: &MechanismData,
+    provider: &dyn Provider,
+) -> Result<bool, SessionError> {
+    Ok(session_data
+        .maybe_need_with::<ScramAcceptMandatory, _, _>(provider, |accept| Ok(*accept))?
+        .unwrap_or(false))
+}
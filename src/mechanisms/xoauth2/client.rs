@@ -9,12 +9,12 @@ use crate::vectored_io::VectoredWriter;
 use core2::io::Write;
 use thiserror::Error;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct XOAuth2 {
     state: XOAuth2State,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 enum XOAuth2State {
     #[default]
     Initial,
@@ -89,6 +89,10 @@ impl Authentication for XOAuth2 {
             XOAuth2State::Done => Err(SessionError::MechanismDone),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,7 @@
 use super::{client, server};
 use crate::alloc::boxed::Box;
 use crate::mechname::Mechname;
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::registry::{Matches, Mechanism, Named, Side};
 
 #[cfg_attr(
@@ -19,6 +20,10 @@ pub static XOAUTH2: Mechanism = Mechanism {
 
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(
+        SecurityFlags::PLAINTEXT.union(SecurityFlags::DICTIONARY_RESISTANT),
+    )
+    .with_properties(&["AuthId", "OAuthBearerToken"], &["XOAuth2Validate"]),
 };
 
 struct Select;
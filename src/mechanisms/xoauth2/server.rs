@@ -1,4 +1,6 @@
 use crate::alloc::boxed::Box;
+#[cfg(feature = "session_export")]
+use crate::alloc::vec::Vec;
 use crate::context::{Demand, DemandReply, Provider};
 use crate::error::{MechanismError, MechanismErrorKind, SessionError};
 use crate::mechanism::{Authentication, MechanismData, State};
@@ -112,6 +114,26 @@ impl Authentication for XOAuth2 {
             XOAuth2State::Errored => Ok(State::Finished(MessageSent::No)),
         }
     }
+
+    fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[cfg(feature = "session_export")]
+    fn export_state(&self) -> Option<Vec<u8>> {
+        let errored = self.state == XOAuth2State::Errored;
+        Some(vec![u8::from(errored)])
+    }
+
+    #[cfg(feature = "session_export")]
+    fn import_state(&mut self, state: &[u8]) -> bool {
+        self.state = match state {
+            [0] => XOAuth2State::Initial,
+            [1] => XOAuth2State::Errored,
+            _ => return false,
+        };
+        true
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,463 @@
+//! Mechanism capabilities and security policies restricting the mechanisms used for authentication
+//!
+//! Not every mechanism is appropriate in every situation. Sending a password in the clear using
+//! `PLAIN` is fine over a TLS-protected connection but should never be done over an unprotected
+//! one, and a server may not want to accept `ANONYMOUS` authentication at all. A
+//! [`SecurityPolicy`] describes which properties a mechanism must or must not have to be used,
+//! similar to the `SASL_SEC_*` flags of Cyrus SASL.
+//!
+//! Each mechanism describes itself using [`Capabilities`], available via
+//! [`Mechanism::capabilities`]. The [`SecurityFlags`] of a mechanism are what the policy is
+//! checked against. A server installs a policy using
+//! [`SASLServer::with_policy`](crate::prelude::SASLServer::with_policy), which will then only
+//! advertise and accept mechanisms allowed by it. A client installs a policy using
+//! [`SASLClient::with_policy`](crate::prelude::SASLClient::with_policy) to skip offered
+//! mechanisms not allowed by it; if none remain starting the session fails with
+//! [`SASLError::PolicyViolation`](crate::prelude::SASLError::PolicyViolation) naming the rejected
+//! mechanism and the reason.
+//!
+//! ```
+//! # use rsasl::policy::SecurityPolicy;
+//! // Refuse anonymous logins, and plaintext logins unless the connection is protected by TLS
+//! # let tls_established = false;
+//! let policy = SecurityPolicy::new()
+//!     .no_anonymous()
+//!     .no_plaintext()
+//!     .with_tls(tls_established)
+//!     .plus_only_with_channel_bindings();
+//!
+//! // Require the server to authenticate itself, using channel binding whenever it is offered
+//! let policy = SecurityPolicy::new()
+//!     .require_mutual_auth()
+//!     .refuse_downgrade();
+//! ```
+
+use crate::registry::Mechanism;
+use core::fmt;
+use core::ops::BitOr;
+
+#[cfg(feature = "std")]
+use thiserror::Error;
+
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+/// Security relevant properties of a mechanism
+pub struct SecurityFlags(u8);
+
+impl SecurityFlags {
+    /// The mechanism has none of the listed properties
+    pub const NONE: Self = Self(0);
+    /// Credentials are sent in a form that allows an eavesdropper to reuse them, e.g. a password
+    /// or bearer token sent in the clear
+    pub const PLAINTEXT: Self = Self(0b0000_0001);
+    /// The mechanism does not authenticate the client
+    pub const ANONYMOUS: Self = Self(0b0000_0010);
+    /// The server proves its identity to the client as part of the exchange
+    pub const MUTUAL_AUTH: Self = Self(0b0000_0100);
+    /// Compromise of long-term secrets does not compromise past sessions
+    pub const FORWARD_SECRECY: Self = Self(0b0000_1000);
+    /// The authentication is bound to the underlying channel, e.g. the TLS connection
+    pub const CHANNEL_BINDING: Self = Self(0b0001_0000);
+    /// An eavesdropper can not mount an offline dictionary attack against the credentials
+    pub const DICTIONARY_RESISTANT: Self = Self(0b0010_0000);
+
+    const NAMES: [(Self, &'static str); 6] = [
+        (Self::PLAINTEXT, "PLAINTEXT"),
+        (Self::ANONYMOUS, "ANONYMOUS"),
+        (Self::MUTUAL_AUTH, "MUTUAL_AUTH"),
+        (Self::FORWARD_SECRECY, "FORWARD_SECRECY"),
+        (Self::CHANNEL_BINDING, "CHANNEL_BINDING"),
+        (Self::DICTIONARY_RESISTANT, "DICTIONARY_RESISTANT"),
+    ];
+
+    #[must_use]
+    /// Returns the union of `self` and `other`
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    #[must_use]
+    /// Returns true if all flags set in `other` are also set in `self`
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[must_use]
+    /// Returns true if any flag set in `other` is also set in `self`
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for SecurityFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl fmt::Debug for SecurityFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for (flag, name) in Self::NAMES {
+            if self.contains(flag) {
+                set.entry(&format_args!("{name}"));
+            }
+        }
+        set.finish()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+/// Structured description of what a mechanism provides and requires
+///
+/// ```
+/// # #[cfg(feature = "plain")] {
+/// # use rsasl::mechanisms::plain::PLAIN;
+/// # use rsasl::policy::SecurityFlags;
+/// let capabilities = PLAIN.capabilities();
+/// assert!(capabilities.security.contains(SecurityFlags::PLAINTEXT));
+/// assert!(capabilities.authzid);
+/// assert!(!capabilities.security_layer);
+/// assert!(capabilities.client_properties.contains(&"Password"));
+/// # }
+/// ```
+pub struct Capabilities {
+    /// Security relevant properties of the mechanism
+    pub security: SecurityFlags,
+    /// The client can request an authorization identity different from the authentication
+    /// identity
+    pub authzid: bool,
+    /// The mechanism can negotiate a security layer protecting data after the authentication
+    pub security_layer: bool,
+    /// Names of the properties a client may request from its callback, e.g. `"Password"`
+    ///
+    /// Properties used only by optional features of the mechanism are not listed.
+    pub client_properties: &'static [&'static str],
+    /// Names of the properties a server may request from its callback
+    ///
+    /// Properties provided to [`validate`](crate::callback::SessionCallback::validate) are not
+    /// listed.
+    pub server_properties: &'static [&'static str],
+}
+
+impl Capabilities {
+    #[must_use]
+    /// Construct capabilities with the given security flags and nothing else
+    pub const fn new(security: SecurityFlags) -> Self {
+        Self {
+            security,
+            authzid: false,
+            security_layer: false,
+            client_properties: &[],
+            server_properties: &[],
+        }
+    }
+
+    #[must_use]
+    /// The client can request an authorization identity
+    pub const fn with_authzid(mut self) -> Self {
+        self.authzid = true;
+        self
+    }
+
+    #[must_use]
+    /// The mechanism can negotiate a security layer
+    pub const fn with_security_layer(mut self) -> Self {
+        self.security_layer = true;
+        self
+    }
+
+    #[must_use]
+    /// Set the names of the properties requested by clients and servers respectively
+    pub const fn with_properties(
+        mut self,
+        client: &'static [&'static str],
+        server: &'static [&'static str],
+    ) -> Self {
+        self.client_properties = client;
+        self.server_properties = server;
+        self
+    }
+}
+
+#[allow(clippy::exhaustive_enums)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+/// Protection provided by a security layer
+///
+/// The strength of a protection is usually expressed as a *security strength factor* (SSF), see
+/// [`ssf`](Self::ssf).
+pub enum Protection {
+    #[default]
+    /// Data is not protected
+    None,
+    /// Data is protected against modification but not encrypted
+    Integrity,
+    /// Data is protected against modification and encrypted with a key of `key_bits` strength
+    Confidentiality { key_bits: u32 },
+}
+
+impl Protection {
+    #[must_use]
+    /// The security strength factor of this protection
+    ///
+    /// Like with Cyrus SASL this is `0` for no protection, `1` for integrity protection and the
+    /// key strength in bits for confidentiality protection.
+    pub const fn ssf(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Integrity => 1,
+            Self::Confidentiality { key_bits } => key_bits,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Error)]
+#[non_exhaustive]
+/// The reason a mechanism was rejected by a [`SecurityPolicy`]
+pub enum PolicyViolation {
+    #[error("credentials would be sent in the clear over an unprotected connection")]
+    Plaintext,
+    #[error("anonymous authentication is not allowed")]
+    Anonymous,
+    #[error("the mechanism does not provide mutual authentication")]
+    NoMutualAuth,
+    #[error("the mechanism does not provide forward secrecy")]
+    NoForwardSecrecy,
+    #[error("no channel binding data is available")]
+    NoChannelBindings,
+    #[error("the mechanism does not bind the authentication to the channel")]
+    NotChannelBound,
+    #[error("the mechanism is susceptible to offline dictionary attacks")]
+    NoDictionaryResistance,
+    #[error("a mechanism using channel binding was offered as well")]
+    Downgrade,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// A set of requirements a mechanism has to fulfill to be used
+///
+/// The default policy allows all mechanisms.
+pub struct SecurityPolicy {
+    forbidden: SecurityFlags,
+    required: SecurityFlags,
+    channel_bindings: bool,
+    refuse_downgrade: bool,
+    tls: bool,
+}
+
+impl SecurityPolicy {
+    #[must_use]
+    /// Construct a policy allowing all mechanisms
+    pub const fn new() -> Self {
+        Self {
+            forbidden: SecurityFlags::NONE,
+            required: SecurityFlags::NONE,
+            channel_bindings: false,
+            refuse_downgrade: false,
+            tls: false,
+        }
+    }
+
+    #[must_use]
+    /// Forbid mechanisms sending credentials in the clear unless [`with_tls`](Self::with_tls) is
+    /// set
+    pub const fn no_plaintext(mut self) -> Self {
+        self.forbidden = self.forbidden.union(SecurityFlags::PLAINTEXT);
+        self
+    }
+
+    #[must_use]
+    /// Forbid mechanisms that do not authenticate the client
+    pub const fn no_anonymous(mut self) -> Self {
+        self.forbidden = self.forbidden.union(SecurityFlags::ANONYMOUS);
+        self
+    }
+
+    #[must_use]
+    /// Require mechanisms to authenticate the server to the client
+    pub const fn require_mutual_auth(mut self) -> Self {
+        self.required = self.required.union(SecurityFlags::MUTUAL_AUTH);
+        self
+    }
+
+    #[must_use]
+    /// Require mechanisms to provide forward secrecy
+    pub const fn require_forward_secrecy(mut self) -> Self {
+        self.required = self.required.union(SecurityFlags::FORWARD_SECRECY);
+        self
+    }
+
+    #[must_use]
+    /// Require mechanisms to bind the authentication to the underlying channel, i.e. only allow
+    /// `-PLUS` variants
+    pub const fn require_channel_binding(mut self) -> Self {
+        self.required = self.required.union(SecurityFlags::CHANNEL_BINDING);
+        self
+    }
+
+    #[must_use]
+    /// Require mechanisms to be resistant against offline dictionary attacks by an eavesdropper
+    pub const fn require_dictionary_resistance(mut self) -> Self {
+        self.required = self.required.union(SecurityFlags::DICTIONARY_RESISTANT);
+        self
+    }
+
+    #[must_use]
+    /// Refuse mechanisms without channel binding if the other party offered one with it
+    ///
+    /// This only applies to clients with channel binding data available. A server offering e.g.
+    /// `SCRAM-SHA-256-PLUS` that the client can use will then never be authenticated to using
+    /// `SCRAM-SHA-256` or `PLAIN` instead, even if those are preferred.
+    pub const fn refuse_downgrade(mut self) -> Self {
+        self.refuse_downgrade = true;
+        self
+    }
+
+    #[must_use]
+    /// Only allow channel binding mechanisms (e.g. `SCRAM-SHA-256-PLUS`) if channel binding data
+    /// is available
+    pub const fn plus_only_with_channel_bindings(mut self) -> Self {
+        self.channel_bindings = true;
+        self
+    }
+
+    #[must_use]
+    /// Set if the connection is protected by TLS
+    ///
+    /// Plaintext mechanisms are allowed over TLS-protected connections even if
+    /// [`no_plaintext`](Self::no_plaintext) is set.
+    pub const fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Check if `mechanism` is allowed by this policy
+    ///
+    /// `channel_bindings` indicates if channel binding data is available for the connection.
+    ///
+    /// # Errors
+    /// Returns the first requirement of the policy the mechanism fails.
+    pub fn check(
+        self,
+        mechanism: &Mechanism,
+        channel_bindings: bool,
+    ) -> Result<(), PolicyViolation> {
+        let flags = mechanism.capabilities.security;
+        if !self.tls
+            && self.forbidden.contains(SecurityFlags::PLAINTEXT)
+            && flags.contains(SecurityFlags::PLAINTEXT)
+        {
+            Err(PolicyViolation::Plaintext)
+        } else if self.forbidden.contains(SecurityFlags::ANONYMOUS)
+            && flags.contains(SecurityFlags::ANONYMOUS)
+        {
+            Err(PolicyViolation::Anonymous)
+        } else if self.required.contains(SecurityFlags::MUTUAL_AUTH)
+            && !flags.contains(SecurityFlags::MUTUAL_AUTH)
+        {
+            Err(PolicyViolation::NoMutualAuth)
+        } else if self.required.contains(SecurityFlags::FORWARD_SECRECY)
+            && !flags.contains(SecurityFlags::FORWARD_SECRECY)
+        {
+            Err(PolicyViolation::NoForwardSecrecy)
+        } else if self.required.contains(SecurityFlags::CHANNEL_BINDING)
+            && !flags.contains(SecurityFlags::CHANNEL_BINDING)
+        {
+            Err(PolicyViolation::NotChannelBound)
+        } else if self.required.contains(SecurityFlags::DICTIONARY_RESISTANT)
+            && !flags.contains(SecurityFlags::DICTIONARY_RESISTANT)
+        {
+            Err(PolicyViolation::NoDictionaryResistance)
+        } else if self.channel_bindings && !(mechanism.offer)(channel_bindings) {
+            Err(PolicyViolation::NoChannelBindings)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check if a client may select `mechanism`
+    ///
+    /// `binding_offered` indicates if the server offered a mechanism using channel binding that
+    /// the client can use.
+    pub(crate) fn check_selection(
+        self,
+        mechanism: &Mechanism,
+        channel_bindings: bool,
+        binding_offered: bool,
+    ) -> Result<(), PolicyViolation> {
+        if self.refuse_downgrade
+            && channel_bindings
+            && binding_offered
+            && !mechanism
+                .capabilities
+                .security
+                .contains(SecurityFlags::CHANNEL_BINDING)
+        {
+            Err(PolicyViolation::Downgrade)
+        } else {
+            self.check(mechanism, channel_bindings)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "plain", feature = "scram-sha-2"))]
+    fn test_refuse_downgrade() {
+        use crate::mechanisms::{plain::PLAIN, scram::SCRAM_SHA256_PLUS};
+
+        let policy = SecurityPolicy::new().refuse_downgrade();
+        assert_eq!(
+            policy.check_selection(&PLAIN, true, true),
+            Err(PolicyViolation::Downgrade)
+        );
+        assert_eq!(
+            policy.check_selection(&SCRAM_SHA256_PLUS, true, true),
+            Ok(())
+        );
+        // Without channel binding data the client could not have used the offered mechanism
+        assert_eq!(policy.check_selection(&PLAIN, false, true), Ok(()));
+        assert_eq!(policy.check_selection(&PLAIN, true, false), Ok(()));
+        assert_eq!(
+            SecurityPolicy::new().check_selection(&PLAIN, true, true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "scram-sha-2", feature = "login", feature = "anonymous"))]
+    fn test_builtin_capabilities() {
+        use crate::mechanisms::{anonymous::ANONYMOUS, login::LOGIN, scram};
+
+        let plus = scram::SCRAM_SHA256_PLUS.capabilities();
+        assert!(plus
+            .security
+            .contains(SecurityFlags::MUTUAL_AUTH | SecurityFlags::CHANNEL_BINDING));
+        assert!(plus.authzid);
+        assert!(plus.server_properties.contains(&"ScramStoredPassword"));
+
+        let bare = scram::SCRAM_SHA256.capabilities();
+        assert!(!bare.security.contains(SecurityFlags::CHANNEL_BINDING));
+
+        assert!(!LOGIN.capabilities().authzid);
+        assert!(!LOGIN.capabilities().security_layer);
+        assert_eq!(
+            ANONYMOUS.capabilities().client_properties,
+            ["AnonymousToken"]
+        );
+    }
+
+    #[test]
+    fn test_flags_debug() {
+        let flags = SecurityFlags::MUTUAL_AUTH | SecurityFlags::CHANNEL_BINDING;
+        assert_eq!(format!("{flags:?}"), "{MUTUAL_AUTH, CHANNEL_BINDING}");
+        assert!(flags.contains(SecurityFlags::MUTUAL_AUTH));
+        assert!(!flags.contains(SecurityFlags::MUTUAL_AUTH | SecurityFlags::PLAINTEXT));
+        assert!(flags.intersects(SecurityFlags::MUTUAL_AUTH | SecurityFlags::PLAINTEXT));
+    }
+}
@@ -131,8 +131,12 @@ mod properties {
     pub struct Passcode;
 
     #[derive(Debug)]
+    /// The display name of an authenticated GSS-API principal, e.g. `alice@EXAMPLE.COM`
     #[non_exhaustive]
     pub struct GssapiDisplayName;
+    impl Property<'_> for GssapiDisplayName {
+        type Value = str;
+    }
 
     #[derive(Debug)]
     #[non_exhaustive]
@@ -0,0 +1,427 @@
+//! HTTP authentication using SASL
+//!
+//! *requires feature `http`*
+//!
+//! HTTP carries authentication exchanges in the `WWW-Authenticate`, `Authorization` and
+//! `Authentication-Info` header fields ([RFC 9110, section 11](https://www.rfc-editor.org/rfc/rfc9110#section-11)).
+//! The value of these fields is parsed into and generated from [`AuthParams`].
+//!
+//! Two ways of authenticating are supported:
+//! - the [`sasl`] scheme running any SASL mechanism
+//! - the `SCRAM-*` schemes of RFC 7804 in [`scram`], requiring a SCRAM mechanism to be enabled
+//!
+//! HTTP itself is stateless, and consecutive requests of an exchange may reach different
+//! instances of a service. The servers of both thus hand their state to the client, which
+//! returns it with its next request, using [exported sessions](crate::export).
+
+use crate::alloc::string::String;
+use crate::alloc::vec::Vec;
+use crate::error::{SASLError, SessionError};
+use crate::export::ExportError;
+use core::fmt;
+use thiserror::Error;
+
+pub mod sasl;
+#[cfg(any(feature = "scram-sha-1", feature = "scram-sha-2"))]
+pub mod scram;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+/// Error returned by the HTTP authentication helpers
+pub enum HttpError {
+    #[error("malformed authentication header")]
+    /// A header value could not be parsed
+    Malformed,
+    #[error("expected authentication scheme {0}")]
+    /// The header value uses a different authentication scheme
+    WrongScheme(&'static str),
+    #[error("required parameter {0} is missing")]
+    /// A parameter required at this point of the exchange is missing
+    MissingParameter(&'static str),
+    #[error("the realm does not match the realm of the server")]
+    /// The realm echoed by the client differs from the one the server is configured with
+    WrongRealm,
+    #[error("the server nonce is invalid or expired")]
+    /// A nonce handed out by the server for reauthentication is invalid or has expired
+    StaleNonce,
+    #[error("parameter {0} is not valid base64")]
+    /// A parameter carrying binary data is not valid base64
+    Base64(&'static str),
+    #[error(transparent)]
+    SASL(#[from] SASLError),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error(transparent)]
+    Export(#[from] ExportError),
+}
+
+#[derive(Debug)]
+#[allow(clippy::exhaustive_enums)]
+/// Outcome of handling the credentials of a request on the server side
+pub enum ServerStep<T> {
+    /// The exchange continues
+    ///
+    /// The request must be answered with a `401` response carrying the contained challenge in a
+    /// `WWW-Authenticate` header.
+    Challenge(AuthParams),
+    /// The exchange has finished
+    ///
+    /// `validation` is the value the callback validated the exchange with; if it is `None` the
+    /// request must be rejected, e.g. with a `401` response carrying fresh challenges. Otherwise
+    /// the request succeeded and the response must carry `info` in an `Authentication-Info`
+    /// header, if set.
+    Finished {
+        validation: Option<T>,
+        info: Option<AuthParams>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A challenge or credentials of an HTTP authentication scheme
+///
+/// This is the value of a `WWW-Authenticate`, `Authorization` or `Authentication-Info` header,
+/// consisting of a scheme name followed by either a list of `name=value` parameters or a single
+/// `token68`. Parameter names are case-insensitive. When formatted all parameter values are sent
+/// as quoted strings.
+///
+/// Some schemes send an `Authentication-Info` header, which only consists of parameters. These
+/// are constructed using [`info`](Self::info) and parsed using [`parse_info`](Self::parse_info),
+/// with an empty scheme name.
+pub struct AuthParams {
+    scheme: String,
+    token68: Option<String>,
+    params: Vec<(String, String)>,
+}
+
+impl AuthParams {
+    #[must_use]
+    /// Construct parameters for `scheme` without any parameters
+    pub fn new(scheme: impl Into<String>) -> Self {
+        Self {
+            scheme: scheme.into(),
+            token68: None,
+            params: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    /// Construct parameters of an `Authentication-Info` header, which names no scheme
+    pub fn info() -> Self {
+        Self::new(String::new())
+    }
+
+    /// Parse the value of an `Authentication-Info` header naming no scheme
+    ///
+    /// # Errors
+    /// Returns [`HttpError::Malformed`] if `value` is not a list of parameters.
+    pub fn parse_info(value: &str) -> Result<Self, HttpError> {
+        let mut all = Self::parse_list(value, Vec::from([Self::info()]))?;
+        if all.len() == 1 {
+            Ok(all.remove(0))
+        } else {
+            Err(HttpError::Malformed)
+        }
+    }
+
+    /// Parse a header value containing a single challenge or credentials
+    ///
+    /// # Errors
+    /// Returns [`HttpError::Malformed`] if `value` is not a single challenge or credentials.
+    pub fn parse(value: &str) -> Result<Self, HttpError> {
+        let mut all = Self::parse_all(value)?;
+        if all.len() == 1 {
+            Ok(all.remove(0))
+        } else {
+            Err(HttpError::Malformed)
+        }
+    }
+
+    /// Parse a header value containing a comma-separated list of challenges
+    ///
+    /// A `WWW-Authenticate` header may offer several schemes at once, e.g.
+    /// `Basic realm="x", SASL mech="PLAIN"`.
+    ///
+    /// # Errors
+    /// Returns [`HttpError::Malformed`] if `value` does not follow the `challenge` grammar.
+    pub fn parse_all(value: &str) -> Result<Vec<Self>, HttpError> {
+        Self::parse_list(value, Vec::new())
+    }
+
+    fn parse_list(value: &str, mut all: Vec<Self>) -> Result<Vec<Self>, HttpError> {
+        let mut rest = value;
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ',']);
+            if rest.is_empty() {
+                return Ok(all);
+            }
+            let (name, after) = split_token(rest);
+            if name.is_empty() {
+                return Err(HttpError::Malformed);
+            }
+            let after_ws = skip_ws(after);
+            match (after_ws.strip_prefix('='), all.last_mut()) {
+                (Some(value), Some(current)) => {
+                    let (value, after) = parse_value(skip_ws(value))?;
+                    current.params.push((String::from(name), value));
+                    // Parameters are separated by commas
+                    rest = skip_ws(after);
+                    if !(rest.is_empty() || rest.starts_with(',')) {
+                        return Err(HttpError::Malformed);
+                    }
+                }
+                (Some(_), None) => return Err(HttpError::Malformed),
+                (None, _) => {
+                    let mut current = Self::new(name);
+                    rest = after_ws;
+                    if let Some((token68, after)) = split_token68(rest) {
+                        current.token68 = Some(String::from(token68));
+                        rest = after;
+                    }
+                    all.push(current);
+                }
+            }
+        }
+    }
+
+    #[must_use]
+    /// The name of the authentication scheme
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    #[must_use]
+    /// Returns `true` if the scheme is `scheme`, ignoring ASCII case
+    pub fn is_scheme(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+
+    #[must_use]
+    /// The `token68` sent instead of parameters, if any
+    pub fn token68(&self) -> Option<&str> {
+        self.token68.as_deref()
+    }
+
+    #[must_use]
+    /// The value of the first parameter called `name`, ignoring ASCII case
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Iterate over all parameters in the order they were given
+    pub fn params(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Set the parameter `name` to `value`, replacing a previous value
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        if let Some(param) = self
+            .params
+            .iter_mut()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+        {
+            param.1 = value;
+        } else {
+            self.params.push((String::from(name), value));
+        }
+    }
+
+    #[must_use]
+    /// Set the parameter `name` to `value`, replacing a previous value
+    pub fn with(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    /// Decode the base64 value of the parameter `name`
+    pub(crate) fn get_base64(&self, name: &'static str) -> Result<Option<Vec<u8>>, HttpError> {
+        use base64::Engine;
+        self.get(name)
+            .map(|value| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(value)
+                    .map_err(|_| HttpError::Base64(name))
+            })
+            .transpose()
+    }
+
+    /// Set the parameter `name` to the base64 encoding of `data`
+    pub(crate) fn set_base64(&mut self, name: &str, data: &[u8]) {
+        use base64::Engine;
+        self.set(name, base64::engine::general_purpose::STANDARD.encode(data));
+    }
+}
+
+impl fmt::Display for AuthParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.scheme)?;
+        if let Some(token68) = &self.token68 {
+            write!(f, " {token68}")?;
+        }
+        for (i, (name, value)) in self.params.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            } else if !self.scheme.is_empty() {
+                f.write_str(" ")?;
+            }
+            write!(f, "{name}=\"")?;
+            for c in value.chars() {
+                if c == '"' || c == '\\' {
+                    f.write_str("\\")?;
+                }
+                write!(f, "{c}")?;
+            }
+            f.write_str("\"")?;
+        }
+        Ok(())
+    }
+}
+
+const fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(
+            c,
+            '!' | '#'
+                | '$'
+                | '%'
+                | '&'
+                | '\''
+                | '*'
+                | '+'
+                | '-'
+                | '.'
+                | '^'
+                | '_'
+                | '`'
+                | '|'
+                | '~'
+        )
+}
+
+fn skip_ws(s: &str) -> &str {
+    s.trim_start_matches([' ', '\t'])
+}
+
+fn split_token(s: &str) -> (&str, &str) {
+    let end = s.find(|c| !is_tchar(c)).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// Split off a `token68` if `s` starts with one that is not the name of a parameter
+fn split_token68(s: &str) -> Option<(&str, &str)> {
+    let chars = s
+        .find(|c: char| {
+            !(c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~' | '+' | '/'))
+        })
+        .unwrap_or(s.len());
+    if chars == 0 {
+        return None;
+    }
+    let end = s[chars..]
+        .find(|c| c != '=')
+        .map_or(s.len(), |padding| chars + padding);
+    let trailing = skip_ws(&s[end..]);
+    (trailing.is_empty() || trailing.starts_with(',')).then(|| s.split_at(end))
+}
+
+/// Parse a parameter value given either as token or as quoted string
+fn parse_value(s: &str) -> Result<(String, &str), HttpError> {
+    if let Some(quoted) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((value, &quoted[i + 1..])),
+                '\\' => value.push(chars.next().ok_or(HttpError::Malformed)?.1),
+                c => value.push(c),
+            }
+        }
+        Err(HttpError::Malformed)
+    } else {
+        // Be lenient and accept base64 data given as token without quoting
+        let end = s.find([',', ' ', '\t']).unwrap_or(s.len());
+        let (value, rest) = s.split_at(end);
+        if value.is_empty() {
+            return Err(HttpError::Malformed);
+        }
+        Ok((String::from(value), rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let params =
+            AuthParams::parse(r#"SASL mech="SCRAM-SHA-256 PLAIN", realm="a \"b\" c",c2s=AAEC=="#)
+                .unwrap();
+        assert!(params.is_scheme("sasl"));
+        assert_eq!(params.get("MECH"), Some("SCRAM-SHA-256 PLAIN"));
+        assert_eq!(params.get("realm"), Some(r#"a "b" c"#));
+        assert_eq!(params.get("c2s"), Some("AAEC=="));
+        assert_eq!(params.get("s2s"), None);
+        assert_eq!(params.token68(), None);
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let all = AuthParams::parse_all(
+            r#"Basic realm="x", Negotiate, Bearer dGVzdA==, SASL mech="PLAIN", realm=y"#,
+        )
+        .unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].scheme(), "Basic");
+        assert_eq!(all[0].get("realm"), Some("x"));
+        assert_eq!(all[1].scheme(), "Negotiate");
+        assert_eq!(all[2].token68(), Some("dGVzdA=="));
+        assert_eq!(all[3].get("mech"), Some("PLAIN"));
+        assert_eq!(all[3].get("realm"), Some("y"));
+
+        assert!(AuthParams::parse("Basic realm=\"x\", Negotiate").is_err());
+    }
+
+    #[test]
+    fn test_malformed() {
+        for value in [
+            "",
+            "realm=\"x\"",
+            "SASL mech=\"PLAIN",
+            "SASL realm=\"x\", mech=",
+            "SASL =\"x\"",
+            "SASL mech=\"PLAIN\" realm=\"x\"",
+        ] {
+            assert!(AuthParams::parse(value).is_err(), "{value} was accepted");
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let params = AuthParams::new("SASL")
+            .with("realm", r#"a "quoted" \ realm"#)
+            .with("mech", "PLAIN")
+            .with("Mech", "LOGIN");
+        let value = params.to_string();
+        assert_eq!(value, r#"SASL realm="a \"quoted\" \\ realm", mech="LOGIN""#);
+        assert_eq!(AuthParams::parse(&value).unwrap(), params);
+    }
+
+    #[test]
+    fn test_info() {
+        let info = AuthParams::info().with("sid", "AAAA").with("data", "dj1=");
+        let value = info.to_string();
+        assert_eq!(value, r#"sid="AAAA", data="dj1=""#);
+        assert_eq!(AuthParams::parse_info(&value).unwrap(), info);
+        assert_eq!(
+            AuthParams::parse_info("sid=AAAA").unwrap().get("sid"),
+            Some("AAAA")
+        );
+        assert!(AuthParams::parse_info("SASL s2c=\"\"").is_err());
+    }
+}
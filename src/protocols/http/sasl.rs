@@ -0,0 +1,465 @@
+//! The `SASL` HTTP authentication scheme
+//!
+//! This implements the scheme of [draft-vanrein-httpauth-sasl](https://datatracker.ietf.org/doc/draft-vanrein-httpauth-sasl/),
+//! running any SASL mechanism over HTTP. Each round-trip of the exchange performs a single step
+//! of a [`Session`], using the following parameters:
+//!
+//! - `mech`: the space-separated list of mechanisms offered by the server, or the mechanism
+//!   selected by the client
+//! - `realm`: the realm authenticated against, echoed back by the client
+//! - `c2s` and `s2c`: the base64-encoded SASL messages of the client and server
+//! - `s2s`: the state of the server, returned unmodified by the client with its next request
+//!
+//! A server answers a request without credentials with a `401` response carrying the
+//! [`challenge`](SaslServer::challenge) in a `WWW-Authenticate` header. Credentials received in
+//! the `Authorization` header of a request are passed to [`SaslServer::authenticate`], which
+//! either returns a further challenge to be sent with another `401` response, or finishes the
+//! exchange. A successful exchange is answered with the requested resource, adding an
+//! `Authentication-Info` header if the mechanism produced additional data.
+//!
+//! The server keeps no state in between requests. Instead the in-flight session is
+//! [exported](crate::export) into the `s2s` parameter, so every instance sharing the same
+//! [`ExportKey`] can handle the next request of an exchange. An `s2s` parameter older than the
+//! [time-to-live](ExportKey::with_ttl) of the key is rejected, which limits how long a captured
+//! request can be replayed.
+//!
+//! A client selects a mechanism from the challenge using [`SaslClient::start`], then sends
+//! the [`authorization`](SaslClient::authorization) for each request, passing the challenge of
+//! the previous `401` response. Once a different response was received the exchange is
+//! completed using [`SaslClient::finish`].
+
+use super::{AuthParams, HttpError, ServerStep};
+use crate::alloc::string::String;
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::config::SASLConfig;
+use crate::export::ExportKey;
+use crate::mechname::Mechname;
+use crate::policy::SecurityPolicy;
+use crate::prelude::{SASLClient, SASLServer, Session};
+use crate::session::{MessageSent, State};
+use crate::validate::Validation;
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Name of the authentication scheme
+pub const SCHEME: &str = "SASL";
+
+fn check_scheme(params: &AuthParams) -> Result<(), HttpError> {
+    if params.is_scheme(SCHEME) {
+        Ok(())
+    } else {
+        Err(HttpError::WrongScheme(SCHEME))
+    }
+}
+
+/// Server side of the `SASL` authentication scheme
+///
+/// A `SaslServer` holds no state of individual exchanges and can be shared by all requests.
+pub struct SaslServer<V: Validation> {
+    config: Arc<SASLConfig>,
+    key: ExportKey,
+    policy: SecurityPolicy,
+    realm: Option<String>,
+    validation: PhantomData<fn() -> V>,
+}
+
+impl<V: Validation> fmt::Debug for SaslServer<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SaslServer")
+            .field("config", &self.config)
+            .field("policy", &self.policy)
+            .field("realm", &self.realm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V: Validation> SaslServer<V> {
+    #[must_use]
+    /// Construct a server using `config`, exporting sessions using `key`
+    ///
+    /// All instances of a service handling requests must use the same key and a config
+    /// offering the same mechanisms.
+    pub fn new(config: Arc<SASLConfig>, key: ExportKey) -> Self {
+        Self {
+            config,
+            key,
+            policy: SecurityPolicy::new(),
+            realm: None,
+            validation: PhantomData,
+        }
+    }
+
+    #[must_use]
+    /// Only offer and accept mechanisms allowed by `policy`
+    pub const fn with_policy(mut self, policy: SecurityPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[must_use]
+    /// Send `realm` with every challenge
+    ///
+    /// Credentials must then echo the same realm.
+    pub fn with_realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    fn sasl_server(&self) -> SASLServer<V> {
+        SASLServer::new(self.config.clone()).with_policy(self.policy)
+    }
+
+    fn params(&self) -> AuthParams {
+        let mut params = AuthParams::new(SCHEME);
+        if let Some(realm) = &self.realm {
+            params.set("realm", realm.as_str());
+        }
+        params
+    }
+
+    #[must_use]
+    /// The challenge starting an exchange, offering all available mechanisms
+    pub fn challenge(&self) -> AuthParams {
+        let mut mechs = String::new();
+        for mechanism in self.sasl_server().get_available() {
+            if !mechs.is_empty() {
+                mechs.push(' ');
+            }
+            mechs.push_str(mechanism.mechanism.as_str());
+        }
+        self.params().with("mech", mechs)
+    }
+
+    /// Handle the credentials sent in the `Authorization` header of a request
+    ///
+    /// This performs a single step of the exchange, starting a new one if the credentials
+    /// carry no `s2s` parameter.
+    ///
+    /// # Errors
+    /// Returns an error if the credentials are malformed, carry a different realm, the selected
+    /// mechanism is not available, `s2s` was not produced by an instance sharing the same key or
+    /// has expired, or the mechanism failed. The request should then be rejected with a fresh
+    /// [`challenge`](Self::challenge).
+    pub fn authenticate(
+        &self,
+        credentials: &AuthParams,
+    ) -> Result<ServerStep<V::Value>, HttpError> {
+        check_scheme(credentials)?;
+        if let Some(realm) = &self.realm {
+            let echoed = credentials
+                .get("realm")
+                .ok_or(HttpError::MissingParameter("realm"))?;
+            if echoed != realm {
+                return Err(HttpError::WrongRealm);
+            }
+        }
+        let c2s = credentials.get_base64("c2s")?;
+
+        let mut session = if let Some(s2s) = credentials.get_base64("s2s")? {
+            self.sasl_server().resume(&self.key, &s2s)?
+        } else {
+            let mech = credentials
+                .get("mech")
+                .ok_or(HttpError::MissingParameter("mech"))?;
+            let mech = Mechname::parse(mech.as_bytes()).map_err(|_| HttpError::Malformed)?;
+            let session = self.sasl_server().start_suggested(mech)?;
+            if c2s.is_none() && !session.are_we_first() {
+                // The client did not send an initial response, ask for it with an empty
+                // challenge.
+                return self.challenge_with(&session, &[]);
+            }
+            session
+        };
+
+        let mut s2c = Vec::new();
+        match session.step(c2s.as_deref(), &mut s2c)? {
+            State::Running => self.challenge_with(&session, &s2c),
+            State::Finished(sent) => {
+                let info = (sent == MessageSent::Yes).then(|| {
+                    let mut info = AuthParams::new(SCHEME);
+                    info.set_base64("s2c", &s2c);
+                    info
+                });
+                Ok(ServerStep::Finished {
+                    validation: session.validation(),
+                    info,
+                })
+            }
+        }
+    }
+
+    fn challenge_with(
+        &self,
+        session: &Session<V>,
+        s2c: &[u8],
+    ) -> Result<ServerStep<V::Value>, HttpError> {
+        let mut challenge = self.params();
+        challenge.set_base64("s2c", s2c);
+        challenge.set_base64("s2s", &session.export(&self.key)?);
+        Ok(ServerStep::Challenge(challenge))
+    }
+}
+
+/// Client side of the `SASL` authentication scheme
+pub struct SaslClient {
+    session: Session,
+    realm: Option<String>,
+    finished: bool,
+}
+
+impl fmt::Debug for SaslClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SaslClient")
+            .field("mechanism", &self.session.get_mechname())
+            .field("realm", &self.realm)
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SaslClient {
+    /// Start an exchange using one of the mechanisms offered by `challenge`
+    ///
+    /// # Errors
+    /// Returns an error if `challenge` is not a `SASL` challenge offering mechanisms, or if no
+    /// offered mechanism is available.
+    pub fn start(client: SASLClient, challenge: &AuthParams) -> Result<Self, HttpError> {
+        check_scheme(challenge)?;
+        let mechs = challenge
+            .get("mech")
+            .ok_or(HttpError::MissingParameter("mech"))?;
+        let offered = mechs
+            .split(' ')
+            .filter_map(|mech| Mechname::parse(mech.as_bytes()).ok());
+        let session = client.start_suggested_iter(offered)?;
+        Ok(Self {
+            session,
+            realm: challenge.get("realm").map(String::from),
+            finished: false,
+        })
+    }
+
+    #[must_use]
+    /// The mechanism selected for this exchange
+    pub const fn mechname(&self) -> &Mechname {
+        self.session.get_mechname()
+    }
+
+    /// The credentials to send in the `Authorization` header of the next request
+    ///
+    /// `challenge` is the challenge of the last `401` response, or `None` for the first request
+    /// of the exchange.
+    ///
+    /// # Errors
+    /// Returns an error if `challenge` is malformed or the mechanism failed.
+    pub fn authorization(
+        &mut self,
+        challenge: Option<&AuthParams>,
+    ) -> Result<AuthParams, HttpError> {
+        let mut credentials = AuthParams::new(SCHEME);
+        if let Some(realm) = &self.realm {
+            credentials.set("realm", realm.as_str());
+        }
+
+        let mut c2s = Vec::new();
+        let state = if let Some(challenge) = challenge {
+            check_scheme(challenge)?;
+            let s2s = challenge
+                .get("s2s")
+                .ok_or(HttpError::MissingParameter("s2s"))?;
+            credentials.set("s2s", s2s);
+            let s2c = challenge.get_base64("s2c")?.unwrap_or_default();
+            self.session.step(Some(&s2c), &mut c2s)?
+        } else {
+            credentials.set("mech", self.session.get_mechname().as_str());
+            if !self.session.are_we_first() {
+                return Ok(credentials);
+            }
+            self.session.step(None, &mut c2s)?
+        };
+
+        self.finished = state.is_finished();
+        if state.has_sent_message() {
+            credentials.set_base64("c2s", &c2s);
+        }
+        Ok(credentials)
+    }
+
+    /// Complete the exchange after receiving a response other than `401`
+    ///
+    /// `info` is the value of the `Authentication-Info` header of the response, if present.
+    /// This must be called even if no such header was sent, as the mechanism may require
+    /// additional data from the server, e.g. to authenticate the server.
+    ///
+    /// # Errors
+    /// Returns an error if `info` is malformed or the mechanism failed, e.g. because the server
+    /// could not be authenticated.
+    pub fn finish(&mut self, info: Option<&AuthParams>) -> Result<State, HttpError> {
+        let s2c = if let Some(info) = info {
+            check_scheme(info)?;
+            info.get_base64("s2c")?
+        } else {
+            None
+        };
+        if self.finished && s2c.is_none() {
+            return Ok(State::Finished(MessageSent::No));
+        }
+        let state = self.session.step(s2c.as_deref(), &mut Vec::new())?;
+        self.finished = state.is_finished();
+        Ok(state)
+    }
+}
+
+#[cfg(all(test, feature = "config_builder"))]
+mod tests {
+    use super::*;
+    use crate::test::fixture::{Callback, TestValidation};
+    use std::string::ToString;
+
+    fn server(key: &ExportKey) -> SaslServer<TestValidation> {
+        let config = SASLConfig::builder()
+            .with_defaults()
+            .with_callback(Callback)
+            .unwrap();
+        SaslServer::new(config, key.clone()).with_realm("example.com")
+    }
+
+    fn client(challenge: &str, password: &str) -> SaslClient {
+        let config =
+            SASLConfig::with_credentials(None, "alice".to_string(), password.to_string()).unwrap();
+        SaslClient::start(
+            SASLClient::new(config),
+            &AuthParams::parse(challenge).unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Run an exchange passing all headers through their textual representation
+    fn run(
+        key: &ExportKey,
+        mechanisms: &str,
+        password: &str,
+    ) -> Result<(Option<String>, State), HttpError> {
+        let challenge = server(key).challenge().to_string();
+        assert!(challenge.starts_with("SASL realm=\"example.com\", mech=\""));
+        let challenge = AuthParams::parse(&challenge)?
+            .with("mech", mechanisms)
+            .to_string();
+        let mut client = client(&challenge, password);
+
+        let mut last = None;
+        for _ in 0..5 {
+            let authorization = client.authorization(last.as_ref())?.to_string();
+            assert!(authorization.contains("realm=\"example.com\""));
+            match server(key).authenticate(&AuthParams::parse(&authorization)?)? {
+                ServerStep::Challenge(challenge) => {
+                    last = Some(AuthParams::parse(&challenge.to_string())?);
+                }
+                ServerStep::Finished { validation, info } => {
+                    let info = info.map(|info| AuthParams::parse(&info.to_string()).unwrap());
+                    return Ok((validation, client.finish(info.as_ref())?));
+                }
+            }
+        }
+        panic!("exchange did not finish");
+    }
+
+    #[test]
+    fn test_plain() {
+        let key = ExportKey::generate();
+        let (validation, _) = run(&key, "PLAIN", "secret").unwrap();
+        assert_eq!(validation.as_deref(), Some("alice"));
+        let (validation, _) = run(&key, "PLAIN", "wrong").unwrap();
+        assert_eq!(validation, None);
+    }
+
+    #[cfg(feature = "scram-sha-2")]
+    #[test]
+    fn test_scram() {
+        let key = ExportKey::generate();
+        let (validation, state) = run(&key, "SCRAM-SHA-256", "secret").unwrap();
+        assert_eq!(validation.as_deref(), Some("alice"));
+        assert!(state.is_finished());
+
+        // The server reports the failure, which the client can not verify
+        assert!(run(&key, "SCRAM-SHA-256", "wrong").is_err());
+    }
+
+    #[cfg(feature = "login")]
+    #[test]
+    fn test_server_first() {
+        let key = ExportKey::generate();
+        let (validation, _) = run(&key, "LOGIN", "secret").unwrap();
+        assert_eq!(validation.as_deref(), Some("alice"));
+    }
+
+    #[cfg(feature = "login")]
+    #[test]
+    fn test_expired_state() {
+        use crate::export::{unix_time, ExportError};
+
+        let key = ExportKey::generate();
+        let config =
+            SASLConfig::with_credentials(None, "alice".to_string(), "secret".to_string()).unwrap();
+        let challenge = server(&key).challenge().with("mech", "LOGIN");
+        let mut client = SaslClient::start(SASLClient::new(config), &challenge).unwrap();
+        let authorization = client.authorization(None).unwrap();
+        let ServerStep::Challenge(challenge) = server(&key).authenticate(&authorization).unwrap()
+        else {
+            panic!("LOGIN did not ask for the username");
+        };
+        let authorization = client.authorization(Some(&challenge)).unwrap();
+        assert!(server(&key).authenticate(&authorization).is_ok());
+
+        // Replay the request with the state reissued as if it was exported an hour ago
+        let s2s = authorization.get_base64("s2s").unwrap().unwrap();
+        let state = key.open(&s2s).unwrap();
+        let mut replayed = authorization;
+        replayed.set_base64("s2s", &key.seal_at(&state, unix_time() - 3600));
+        assert!(matches!(
+            server(&key).authenticate(&replayed),
+            Err(HttpError::Export(ExportError::Expired))
+        ));
+    }
+
+    #[test]
+    fn test_wrong_realm() {
+        let key = ExportKey::generate();
+        let credentials = AuthParams::new(SCHEME)
+            .with("realm", "example.org")
+            .with("mech", "PLAIN")
+            .with("c2s", "AGFsaWNlAHNlY3JldA==");
+        assert!(matches!(
+            server(&key).authenticate(&credentials),
+            Err(HttpError::WrongRealm)
+        ));
+        let credentials = AuthParams::new(SCHEME).with("mech", "PLAIN");
+        assert!(matches!(
+            server(&key).authenticate(&credentials),
+            Err(HttpError::MissingParameter("realm"))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_state() {
+        let key = ExportKey::generate();
+        let credentials = AuthParams::new(SCHEME)
+            .with("realm", "example.com")
+            .with("mech", "PLAIN")
+            .with("s2s", "AAAA")
+            .with("c2s", "");
+        assert!(matches!(
+            server(&key).authenticate(&credentials),
+            Err(HttpError::Export(_))
+        ));
+        assert!(matches!(
+            server(&key).authenticate(&AuthParams::new("Basic")),
+            Err(HttpError::WrongScheme(SCHEME))
+        ));
+        assert!(matches!(
+            server(&key).authenticate(&AuthParams::new(SCHEME).with("realm", "example.com")),
+            Err(HttpError::MissingParameter("mech"))
+        ));
+    }
+}
@@ -0,0 +1,645 @@
+//! SCRAM HTTP authentication ([RFC 7804](https://www.rfc-editor.org/rfc/rfc7804))
+//!
+//! *requires feature `http` and one of `scram-sha-1` or `scram-sha-2`*
+//!
+//! RFC 7804 runs the `SCRAM-*` mechanisms as HTTP authentication schemes of the same name,
+//! carrying the base64-encoded SCRAM messages in the `data` parameter. An exchange takes two
+//! round-trips:
+//!
+//! ```text
+//! S: WWW-Authenticate: SCRAM-SHA-256 realm="example.com"
+//! C: Authorization: SCRAM-SHA-256 realm="example.com", data=<client-first-message>
+//! S: WWW-Authenticate: SCRAM-SHA-256 sid=<session id>, data=<server-first-message>
+//! C: Authorization: SCRAM-SHA-256 sid=<session id>, data=<client-final-message>
+//! S: Authentication-Info: sid=<session id>, data=<server-final-message>
+//! ```
+//!
+//! [`ScramServer`] keeps no state between requests, the session id `sid` is the in-flight
+//! session [exported](crate::export) using an [`ExportKey`] shared by all instances. A session id
+//! older than the [time-to-live](ExportKey::with_ttl) of the key is rejected.
+//!
+//! # Reauthentication
+//!
+//! A server enabling [reauthentication](ScramServer::with_reauthentication) includes a server
+//! nonce `sr` and its lifetime in seconds `ttl` with its challenges. A client that authenticated
+//! before knows the salt and iteration count of its credentials and can thus authenticate in a
+//! single round-trip, completing the `server-first-message` from `sr` on its own:
+//!
+//! ```text
+//! C: Authorization: SCRAM-SHA-256 sr=<server nonce>,
+//!        data=<client-first-message "," client-final-message>
+//! S: Authentication-Info: data=<server-final-message>
+//! ```
+//!
+//! The server nonce is authenticated with a HMAC keyed by the [`ExportKey`] and bound to its
+//! expiry, so no
+//! server state is needed to check it. An expired nonce is rejected with
+//! [`HttpError::StaleNonce`], and the client should retry using a nonce of the fresh challenges.
+//! A reauthentication only succeeds if the credentials of the user still use the same salt, i.e.
+//! the server must provide stored credentials using
+//! [`ScramStoredPassword`](crate::mechanisms::scram::properties::ScramStoredPassword).
+//!
+//! The same request can be replayed while the nonce is valid. Servers that must prevent this
+//! have to remember the nonces used until they expire.
+//!
+//! `-PLUS` variants are not offered, as HTTP provides no channel binding data.
+
+use super::{AuthParams, HttpError, ServerStep};
+use crate::alloc::format;
+use crate::alloc::string::{String, ToString};
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::config::SASLConfig;
+use crate::export::{unix_time, ExportKey};
+use crate::mechanisms::scram::server::fixed_nonce_state;
+use crate::mechname::Mechname;
+use crate::policy::SecurityPolicy;
+use crate::prelude::{SASLClient, SASLServer, Session};
+use crate::session::State;
+use crate::validate::Validation;
+use base64::Engine;
+use core::fmt;
+use core::marker::PhantomData;
+use core::time::Duration;
+use rand::RngCore;
+
+/// Length of the expiry and random part of a server nonce
+const NONCE_DATA_LEN: usize = 8;
+/// Length of the truncated tag of a server nonce
+///
+/// Together with the data this makes for 24 characters of base64, the length of nonces
+/// generated by the SCRAM mechanisms.
+const NONCE_TAG_LEN: usize = 10;
+
+/// Returns the upper-cased scheme of `params` if it is a SCRAM scheme usable over HTTP
+fn scram_scheme(params: &AuthParams) -> Option<String> {
+    let scheme = params.scheme().to_ascii_uppercase();
+    (scheme.starts_with("SCRAM-") && !scheme.ends_with("-PLUS")).then_some(scheme)
+}
+
+fn mechname(scheme: &str) -> Result<&Mechname, HttpError> {
+    Mechname::parse(scheme.as_bytes()).map_err(|_| HttpError::WrongScheme("SCRAM"))
+}
+
+/// Find the value of the SCRAM attribute `name` in `message`, skipping the first `skip` fields
+fn attribute(message: &[u8], skip: usize, name: u8) -> Option<&str> {
+    message
+        .split(|c| *c == b',')
+        .skip(skip)
+        .find_map(|field| match field {
+            [c, b'=', value @ ..] if *c == name => core::str::from_utf8(value).ok(),
+            _ => None,
+        })
+}
+
+/// Server side of SCRAM HTTP authentication
+///
+/// A `ScramServer` holds no state of individual exchanges and can be shared by all requests.
+pub struct ScramServer<V: Validation> {
+    config: Arc<SASLConfig>,
+    key: ExportKey,
+    policy: SecurityPolicy,
+    realm: Option<String>,
+    reauthentication: Option<u32>,
+    validation: PhantomData<fn() -> V>,
+}
+
+impl<V: Validation> fmt::Debug for ScramServer<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramServer")
+            .field("config", &self.config)
+            .field("policy", &self.policy)
+            .field("realm", &self.realm)
+            .field("reauthentication", &self.reauthentication)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V: Validation> ScramServer<V> {
+    #[must_use]
+    /// Construct a server using `config`, exporting sessions using `key`
+    ///
+    /// All instances of a service handling requests must use the same key and a config
+    /// offering the same mechanisms.
+    pub fn new(config: Arc<SASLConfig>, key: ExportKey) -> Self {
+        Self {
+            config,
+            key,
+            policy: SecurityPolicy::new(),
+            realm: None,
+            reauthentication: None,
+            validation: PhantomData,
+        }
+    }
+
+    #[must_use]
+    /// Only offer and accept mechanisms allowed by `policy`
+    pub const fn with_policy(mut self, policy: SecurityPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[must_use]
+    /// Send `realm` with every challenge
+    pub fn with_realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = Some(realm.into());
+        self
+    }
+
+    #[must_use]
+    /// Offer reauthentication in a single round-trip using server nonces valid for `ttl`
+    pub fn with_reauthentication(mut self, ttl: Duration) -> Self {
+        self.reauthentication = Some(u32::try_from(ttl.as_secs()).unwrap_or(u32::MAX));
+        self
+    }
+
+    fn sasl_server(&self) -> SASLServer<V> {
+        SASLServer::new(self.config.clone()).with_policy(self.policy)
+    }
+
+    #[must_use]
+    /// The challenges starting an exchange, one for each available SCRAM mechanism
+    pub fn challenges(&self) -> Vec<AuthParams> {
+        self.sasl_server()
+            .get_available()
+            .into_iter()
+            .filter_map(|mechanism| {
+                let mut challenge = AuthParams::new(mechanism.mechanism.as_str());
+                scram_scheme(&challenge)?;
+                if let Some(realm) = &self.realm {
+                    challenge.set("realm", realm.as_str());
+                }
+                if let Some(ttl) = self.reauthentication {
+                    let expiry = unix_time().saturating_add(u64::from(ttl));
+                    challenge.set("sr", self.issue_nonce(expiry));
+                    challenge.set("ttl", ttl.to_string());
+                }
+                Some(challenge)
+            })
+            .collect()
+    }
+
+    /// Handle the credentials sent in the `Authorization` header of a request
+    ///
+    /// Credentials carrying neither `sid` nor `sr` start a new exchange.
+    ///
+    /// # Errors
+    /// Returns an error if the credentials are malformed, the mechanism is not available, `sid`
+    /// was not produced by an instance sharing the same key or has expired, or the mechanism
+    /// failed. The request should then be rejected with fresh [`challenges`](Self::challenges).
+    pub fn authenticate(
+        &self,
+        credentials: &AuthParams,
+    ) -> Result<ServerStep<V::Value>, HttpError> {
+        let scheme = scram_scheme(credentials).ok_or(HttpError::WrongScheme("SCRAM"))?;
+        let mechname = mechname(&scheme)?;
+        let data = credentials
+            .get_base64("data")?
+            .ok_or(HttpError::MissingParameter("data"))?;
+
+        if let Some(sid) = credentials.get("sid") {
+            let exported = base64::engine::general_purpose::STANDARD
+                .decode(sid)
+                .map_err(|_| HttpError::Base64("sid"))?;
+            let mut session = self.sasl_server().resume(&self.key, &exported)?;
+            if session.get_mechname() != mechname {
+                return Err(HttpError::WrongScheme("SCRAM"));
+            }
+            let mut server_final = Vec::new();
+            session.step(Some(&data), &mut server_final)?;
+            Ok(finished(&mut session, Some(sid), &server_final))
+        } else if let Some(sr) = credentials.get("sr") {
+            self.reauthenticate(mechname, sr, &data)
+        } else {
+            let mut session = self.sasl_server().start_suggested(mechname)?;
+            let mut server_first = Vec::new();
+            if session.step(Some(&data), &mut server_first)?.is_finished() {
+                // The exchange was aborted, e.g. due to an unsupported mandatory extension
+                return Ok(finished(&mut session, None, &server_first));
+            }
+            let mut challenge = AuthParams::new(scheme);
+            challenge.set_base64("sid", &session.export(&self.key)?);
+            challenge.set_base64("data", &server_first);
+            Ok(ServerStep::Challenge(challenge))
+        }
+    }
+
+    fn reauthenticate(
+        &self,
+        mechname: &Mechname,
+        sr: &str,
+        data: &[u8],
+    ) -> Result<ServerStep<V::Value>, HttpError> {
+        if self.reauthentication.is_none() || !self.check_nonce(sr, unix_time()) {
+            return Err(HttpError::StaleNonce);
+        }
+        // The client-final-message starts with the channel binding attribute, which can not
+        // occur in the client-first-message
+        let split = data
+            .windows(3)
+            .position(|window| window == b",c=")
+            .ok_or(HttpError::Malformed)?;
+        let (client_first, client_final) = (&data[..split], &data[split + 1..]);
+
+        let mut session = self.sasl_server().start_suggested(mechname)?;
+        session.import(0, &fixed_nonce_state(sr.as_bytes()))?;
+        let mut server_first = Vec::new();
+        if session
+            .step(Some(client_first), &mut server_first)?
+            .is_finished()
+        {
+            return Ok(finished(&mut session, None, &server_first));
+        }
+        let mut server_final = Vec::new();
+        session.step(Some(client_final), &mut server_final)?;
+        Ok(finished(&mut session, None, &server_final))
+    }
+
+    fn issue_nonce(&self, expiry: u64) -> String {
+        let mut nonce = [0; NONCE_DATA_LEN + NONCE_TAG_LEN];
+        let expiry = u32::try_from(expiry).unwrap_or(u32::MAX);
+        nonce[..4].copy_from_slice(&expiry.to_be_bytes());
+        rand::thread_rng().fill_bytes(&mut nonce[4..NONCE_DATA_LEN]);
+        let tag = self.key.tag(&nonce[..NONCE_DATA_LEN]);
+        nonce[NONCE_DATA_LEN..].copy_from_slice(&tag[..NONCE_TAG_LEN]);
+        base64::engine::general_purpose::STANDARD.encode(nonce)
+    }
+
+    fn check_nonce(&self, sr: &str, now: u64) -> bool {
+        let Ok(nonce) = base64::engine::general_purpose::STANDARD.decode(sr) else {
+            return false;
+        };
+        let Ok(nonce) = <[u8; NONCE_DATA_LEN + NONCE_TAG_LEN]>::try_from(nonce) else {
+            return false;
+        };
+        let (data, tag) = nonce.split_at(NONCE_DATA_LEN);
+        let expected = self.key.tag(data);
+        let difference = tag
+            .iter()
+            .zip(&expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        let expiry = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        difference == 0 && u64::from(expiry) >= now
+    }
+}
+
+fn finished<V: Validation>(
+    session: &mut Session<V>,
+    sid: Option<&str>,
+    data: &[u8],
+) -> ServerStep<V::Value> {
+    let mut info = AuthParams::info();
+    if let Some(sid) = sid {
+        info.set("sid", sid);
+    }
+    info.set_base64("data", data);
+    ServerStep::Finished {
+        validation: session.validation(),
+        info: Some(info),
+    }
+}
+
+/// Client side of SCRAM HTTP authentication
+pub struct ScramClient {
+    session: Session,
+    realm: Option<String>,
+    /// Salt and iteration count sent by the server
+    credentials: Option<(String, String)>,
+    verified: bool,
+}
+
+impl fmt::Debug for ScramClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramClient")
+            .field("mechanism", &self.session.get_mechname())
+            .field("realm", &self.realm)
+            .field("verified", &self.verified)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScramClient {
+    /// Start an exchange using one of the SCRAM schemes offered by `challenges`
+    ///
+    /// Challenges of other schemes are ignored.
+    ///
+    /// # Errors
+    /// Returns an error if none of the offered SCRAM mechanisms is available.
+    pub fn start(client: SASLClient, challenges: &[AuthParams]) -> Result<Self, HttpError> {
+        let offered: Vec<String> = challenges.iter().filter_map(scram_scheme).collect();
+        let session = client.start_suggested_iter(
+            offered
+                .iter()
+                .filter_map(|scheme| Mechname::parse(scheme.as_bytes()).ok()),
+        )?;
+        let realm = challenges
+            .iter()
+            .find(|challenge| challenge.is_scheme(session.get_mechname().as_str()))
+            .and_then(|challenge| challenge.get("realm"))
+            .map(String::from);
+        Ok(Self {
+            session,
+            realm,
+            credentials: None,
+            verified: false,
+        })
+    }
+
+    #[must_use]
+    /// The mechanism selected for this exchange
+    pub const fn mechname(&self) -> &Mechname {
+        self.session.get_mechname()
+    }
+
+    /// The credentials to send in the `Authorization` header of the next request
+    ///
+    /// `challenge` is the challenge of the last `401` response, or `None` for the first request
+    /// of the exchange.
+    ///
+    /// # Errors
+    /// Returns an error if `challenge` is malformed or the mechanism failed.
+    pub fn authorization(
+        &mut self,
+        challenge: Option<&AuthParams>,
+    ) -> Result<AuthParams, HttpError> {
+        let mut credentials = AuthParams::new(self.session.get_mechname().as_str());
+        let mut data = Vec::new();
+        if let Some(challenge) = challenge {
+            if !challenge.is_scheme(self.session.get_mechname().as_str()) {
+                return Err(HttpError::WrongScheme("SCRAM"));
+            }
+            let sid = challenge
+                .get("sid")
+                .ok_or(HttpError::MissingParameter("sid"))?;
+            let server_first = challenge
+                .get_base64("data")?
+                .ok_or(HttpError::MissingParameter("data"))?;
+            self.credentials = attribute(&server_first, 0, b's')
+                .zip(attribute(&server_first, 0, b'i'))
+                .map(|(salt, iterations)| (salt.to_string(), iterations.to_string()));
+            self.session.step(Some(&server_first), &mut data)?;
+            credentials.set("sid", sid);
+        } else {
+            if let Some(realm) = &self.realm {
+                credentials.set("realm", realm.as_str());
+            }
+            self.session.step(None, &mut data)?;
+        }
+        credentials.set_base64("data", &data);
+        Ok(credentials)
+    }
+
+    /// Complete the exchange after receiving a response other than `401`
+    ///
+    /// `info` is the value of the `Authentication-Info` header of the response, parsed using
+    /// [`AuthParams::parse_info`]. This must be called even if no such header was sent, as the
+    /// server has to be authenticated.
+    ///
+    /// # Errors
+    /// Returns an error if `info` is malformed or the server could not be authenticated.
+    pub fn finish(&mut self, info: Option<&AuthParams>) -> Result<State, HttpError> {
+        let server_final = info.map(|info| info.get_base64("data")).transpose()?;
+        let state = self
+            .session
+            .step(server_final.flatten().as_deref(), &mut Vec::new())?;
+        self.verified = state.is_finished();
+        Ok(state)
+    }
+
+    #[must_use]
+    /// Parameters for reauthenticating in a single round-trip later on
+    ///
+    /// Only available after the exchange was completed successfully using
+    /// [`finish`](Self::finish).
+    pub fn reauthentication(&self) -> Option<Reauthentication> {
+        let (salt, iterations) = self.credentials.clone().filter(|_| self.verified)?;
+        Some(Reauthentication {
+            mechanism: self.session.get_mechname().as_str().to_string(),
+            realm: self.realm.clone(),
+            salt,
+            iterations,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Parameters of a previous exchange allowing to reauthenticate in a single round-trip
+///
+/// These contain no secrets, the password is requested from the config again.
+pub struct Reauthentication {
+    mechanism: String,
+    realm: Option<String>,
+    salt: String,
+    iterations: String,
+}
+
+impl Reauthentication {
+    /// Reauthenticate using the server nonce `sr` of `challenge`
+    ///
+    /// Returns the client, which must be [finished](ScramClient::finish) with the response,
+    /// and the credentials to send in the `Authorization` header.
+    ///
+    /// # Errors
+    /// Returns [`HttpError::MissingParameter`] if the server did not offer reauthentication in
+    /// `challenge`, [`HttpError::Malformed`] if `sr` is not a valid SCRAM nonce, or an error if
+    /// the mechanism failed.
+    pub fn authorization(
+        &self,
+        client: SASLClient,
+        challenge: &AuthParams,
+    ) -> Result<(ScramClient, AuthParams), HttpError> {
+        if !challenge.is_scheme(&self.mechanism) {
+            return Err(HttpError::MissingParameter("sr"));
+        }
+        let sr = challenge
+            .get("sr")
+            .ok_or(HttpError::MissingParameter("sr"))?;
+        // The nonce is spliced into the server-first-message, so it must not be able to add
+        // attributes overriding the salt or iteration count.
+        if sr.is_empty() || !sr.bytes().all(|c| c.is_ascii_graphic() && c != b',') {
+            return Err(HttpError::Malformed);
+        }
+        let mut session = client.start_suggested_iter([mechname(&self.mechanism)?])?;
+
+        let mut data = Vec::new();
+        session.step(None, &mut data)?;
+        // Skip the GS2 header to get at the client nonce
+        let client_nonce = attribute(&data, 2, b'r').ok_or(HttpError::Malformed)?;
+        let server_first = format!("r={client_nonce}{sr},s={},i={}", self.salt, self.iterations);
+        data.push(b',');
+        session.step(Some(server_first.as_bytes()), &mut data)?;
+
+        let mut credentials = AuthParams::new(self.mechanism.as_str());
+        if let Some(realm) = &self.realm {
+            credentials.set("realm", realm.as_str());
+        }
+        credentials.set("sr", sr);
+        credentials.set_base64("data", &data);
+        let client = ScramClient {
+            session,
+            realm: self.realm.clone(),
+            credentials: Some((self.salt.clone(), self.iterations.clone())),
+            verified: false,
+        };
+        Ok((client, credentials))
+    }
+}
+
+#[cfg(all(test, feature = "config_builder", feature = "scram-sha-2"))]
+mod tests {
+    use super::*;
+    use crate::test::fixture::{Callback, TestValidation};
+
+    fn server(key: &ExportKey) -> ScramServer<TestValidation> {
+        let config = SASLConfig::builder()
+            .with_defaults()
+            .with_callback(Callback)
+            .unwrap();
+        ScramServer::new(config, key.clone())
+            .with_realm("example.com")
+            .with_reauthentication(Duration::from_secs(60))
+    }
+
+    fn client(password: &str) -> SASLClient {
+        SASLClient::new(
+            SASLConfig::with_credentials(None, "alice".to_string(), password.to_string()).unwrap(),
+        )
+    }
+
+    fn parse(value: &AuthParams) -> AuthParams {
+        AuthParams::parse(&value.to_string()).unwrap()
+    }
+
+    fn challenge(key: &ExportKey) -> AuthParams {
+        server(key)
+            .challenges()
+            .iter()
+            .map(parse)
+            .find(|challenge| challenge.is_scheme("SCRAM-SHA-256"))
+            .unwrap()
+    }
+
+    /// Run a full exchange, returning the client for reauthentication
+    fn authenticate(key: &ExportKey, password: &str) -> Result<ScramClient, HttpError> {
+        let mut client = ScramClient::start(client(password), &[challenge(key)])?;
+        assert_eq!(client.mechname().as_str(), "SCRAM-SHA-256");
+
+        let credentials = parse(&client.authorization(None)?);
+        assert_eq!(credentials.get("realm"), Some("example.com"));
+        let ServerStep::Challenge(challenge) = server(key).authenticate(&credentials)? else {
+            panic!("exchange finished early");
+        };
+        let credentials = parse(&client.authorization(Some(&parse(&challenge)))?);
+        assert!(credentials.get("sid").is_some());
+        let ServerStep::Finished { validation, info } = server(key).authenticate(&credentials)?
+        else {
+            panic!("exchange did not finish");
+        };
+        let info = AuthParams::parse_info(&info.unwrap().to_string())?;
+        assert_eq!(info.get("sid"), credentials.get("sid"));
+        client.finish(Some(&info))?;
+        assert_eq!(validation.as_deref(), Some("alice"));
+        Ok(client)
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let key = ExportKey::generate();
+        let client = authenticate(&key, "secret").unwrap();
+        assert!(client.reauthentication().is_some());
+        assert!(authenticate(&key, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_reauthenticate() {
+        let key = ExportKey::generate();
+        let reauthentication = authenticate(&key, "secret")
+            .unwrap()
+            .reauthentication()
+            .unwrap();
+
+        let (mut scram, credentials) = reauthentication
+            .authorization(client("secret"), &challenge(&key))
+            .unwrap();
+        let ServerStep::Finished { validation, info } =
+            server(&key).authenticate(&parse(&credentials)).unwrap()
+        else {
+            panic!("reauthentication did not finish");
+        };
+        assert_eq!(validation.as_deref(), Some("alice"));
+        assert!(scram.finish(info.as_ref()).unwrap().is_finished());
+        assert!(scram.reauthentication().is_some());
+
+        let (_, credentials) = reauthentication
+            .authorization(client("wrong"), &challenge(&key))
+            .unwrap();
+        let ServerStep::Finished { validation, .. } =
+            server(&key).authenticate(&credentials).unwrap()
+        else {
+            panic!("reauthentication did not finish");
+        };
+        assert_eq!(validation, None);
+    }
+
+    #[test]
+    fn test_nonce() {
+        let key = ExportKey::generate();
+        let scram = server(&key);
+        let nonce = scram.issue_nonce(1000);
+        assert_eq!(nonce.len(), 24);
+        assert!(scram.check_nonce(&nonce, 1000));
+        assert!(!scram.check_nonce(&nonce, 1001));
+        assert!(!server(&ExportKey::generate()).check_nonce(&nonce, 1000));
+
+        let mut tampered = nonce.into_bytes();
+        tampered[10] = if tampered[10] == b'A' { b'B' } else { b'A' };
+        assert!(!scram.check_nonce(&String::from_utf8(tampered).unwrap(), 0));
+
+        let credentials = AuthParams::new("SCRAM-SHA-256")
+            .with("sr", scram.issue_nonce(0))
+            .with("data", "");
+        assert!(matches!(
+            scram.authenticate(&credentials),
+            Err(HttpError::StaleNonce)
+        ));
+    }
+
+    #[test]
+    fn test_injected_nonce() {
+        let key = ExportKey::generate();
+        let reauthentication = authenticate(&key, "secret")
+            .unwrap()
+            .reauthentication()
+            .unwrap();
+        for sr in ["abc,s=AAAA,i=1", "abc def", "", "nönce"] {
+            let challenge = challenge(&key).with("sr", sr);
+            assert!(matches!(
+                reauthentication.authorization(client("secret"), &challenge),
+                Err(HttpError::Malformed)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_expired_sid() {
+        use crate::export::{unix_time, ExportError};
+
+        let key = ExportKey::generate();
+        let mut client = ScramClient::start(client("secret"), &[challenge(&key)]).unwrap();
+        let credentials = client.authorization(None).unwrap();
+        let ServerStep::Challenge(challenge) = server(&key).authenticate(&credentials).unwrap()
+        else {
+            panic!("exchange finished early");
+        };
+        let mut credentials = client.authorization(Some(&challenge)).unwrap();
+
+        // Reissue the session as if it was exported an hour ago
+        let sid = credentials.get_base64("sid").unwrap().unwrap();
+        let state = key.open(&sid).unwrap();
+        credentials.set_base64("sid", &key.seal_at(&state, unix_time() - 3600));
+        assert!(matches!(
+            server(&key).authenticate(&credentials),
+            Err(HttpError::Export(ExportError::Expired))
+        ));
+    }
+}
@@ -0,0 +1,531 @@
+//! IRCv3 `AUTHENTICATE` framing
+//!
+//! *requires feature `irc`*
+//!
+//! IRCv3 SASL runs the exchange using `AUTHENTICATE` messages. The client selects a mechanism
+//! with `AUTHENTICATE <mechanism>`, to which the server answers with an empty challenge or the
+//! first message of server-first mechanisms. Messages are base64-encoded and split into chunks
+//! of 400 bytes, each sent in its own `AUTHENTICATE` message. A chunk shorter than 400 bytes ends
+//! the message; a message that is empty or ends in a chunk of exactly 400 bytes is terminated by
+//! `AUTHENTICATE +`. The client can abort the exchange with `AUTHENTICATE *`. The server signals
+//! the outcome using the numerics 900 to 908, see [`Reply`].
+//!
+//! As IRC interleaves `AUTHENTICATE` messages with unrelated traffic the helpers in this module
+//! do not perform any I/O. They are given the parameter of each received `AUTHENTICATE` message
+//! and return the lines to send, without line terminators.
+//!
+//! ```
+//! # use rsasl::prelude::*;
+//! # use rsasl::protocols::irc::{parse_mechanisms, IrcClient};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let config = SASLConfig::with_credentials(None, "alice".to_string(), "secret".to_string())?;
+//! // From `CAP LS` or `CAP NEW`: `sasl=EXTERNAL,PLAIN`
+//! let mechanisms = parse_mechanisms("EXTERNAL,PLAIN");
+//! let mut session = SASLClient::new(config).start_suggested(&mechanisms)?;
+//!
+//! let mut client = IrcClient::new();
+//! assert_eq!(client.start(&session), "AUTHENTICATE PLAIN");
+//! // The server answers with `AUTHENTICATE +`
+//! let lines = client.authenticate(&mut session, "+")?;
+//! assert_eq!(lines, ["AUTHENTICATE AGFsaWNlAHNlY3JldA=="]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::SessionError;
+use crate::mechname::Mechname;
+use crate::prelude::{ChannelBindingCallback, Session};
+use crate::session::{MessageSent, State};
+use crate::validate::Validation;
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+use thiserror::Error;
+
+/// Length of the chunks messages are split into
+pub const CHUNK_LENGTH: usize = 400;
+
+/// Parse the value of the `sasl` capability or the list of RPL_SASLMECHS into mechanism names
+///
+/// Invalid names are skipped. The result can be passed to
+/// [`SASLClient::start_suggested`](crate::prelude::SASLClient::start_suggested). An empty
+/// result does not mean no mechanisms are available, as servers are not required to advertise
+/// them; clients may still try a mechanism, learning the available ones from RPL_SASLMECHS if
+/// it is not.
+#[must_use]
+pub fn parse_mechanisms(value: &str) -> Vec<&Mechname> {
+    value
+        .split(',')
+        .filter_map(|name| Mechname::parse(name.as_bytes()).ok())
+        .collect()
+}
+
+/// Split a base64-encoded message into `AUTHENTICATE` lines
+fn chunk(data: &[u8]) -> Vec<String> {
+    let mut lines: Vec<String> = data
+        .chunks(CHUNK_LENGTH)
+        .map(|chunk| format!("AUTHENTICATE {}", String::from_utf8_lossy(chunk)))
+        .collect();
+    if data.len() % CHUNK_LENGTH == 0 {
+        lines.push(String::from("AUTHENTICATE +"));
+    }
+    lines
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+/// Error returned by the IRC helpers
+pub enum IrcError {
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error("chunk is longer than 400 bytes")]
+    ChunkTooLong,
+    #[error("message exceeds the maximum length")]
+    /// A server should answer with [`Reply::TooLong`]
+    TooLong,
+    #[error("AUTHENTICATE received after the exchange finished")]
+    Finished,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Numeric replies concerning SASL
+pub enum Reply {
+    /// RPL_LOGGEDIN (900), the client is now logged in to an account
+    LoggedIn,
+    /// RPL_LOGGEDOUT (901), the client is now logged out
+    LoggedOut,
+    /// ERR_NICKLOCKED (902), the account is unavailable
+    NickLocked,
+    /// RPL_SASLSUCCESS (903), the authentication succeeded
+    Success,
+    /// ERR_SASLFAIL (904), the authentication failed
+    Fail,
+    /// ERR_SASLTOOLONG (905), a message exceeded the maximum length
+    TooLong,
+    /// ERR_SASLABORTED (906), the exchange was aborted
+    Aborted,
+    /// ERR_SASLALREADY (907), the client already authenticated
+    Already,
+    /// RPL_SASLMECHS (908), the list of available mechanisms
+    Mechanisms,
+}
+
+impl Reply {
+    #[must_use]
+    /// Returns the reply for `numeric` if it is one of 900 to 908
+    pub const fn from_numeric(numeric: u16) -> Option<Self> {
+        Some(match numeric {
+            900 => Self::LoggedIn,
+            901 => Self::LoggedOut,
+            902 => Self::NickLocked,
+            903 => Self::Success,
+            904 => Self::Fail,
+            905 => Self::TooLong,
+            906 => Self::Aborted,
+            907 => Self::Already,
+            908 => Self::Mechanisms,
+            _ => return None,
+        })
+    }
+
+    #[must_use]
+    /// Returns the numeric of the reply
+    pub const fn numeric(self) -> u16 {
+        match self {
+            Self::LoggedIn => 900,
+            Self::LoggedOut => 901,
+            Self::NickLocked => 902,
+            Self::Success => 903,
+            Self::Fail => 904,
+            Self::TooLong => 905,
+            Self::Aborted => 906,
+            Self::Already => 907,
+            Self::Mechanisms => 908,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Reassembly of chunked messages
+struct Reassembler {
+    buffer: Vec<u8>,
+    max_length: usize,
+}
+
+impl Reassembler {
+    const fn new(max_length: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_length,
+        }
+    }
+
+    /// Add a chunk, returning the base64-encoded message once it is complete
+    fn push(&mut self, chunk: &str) -> Result<Option<Vec<u8>>, IrcError> {
+        if chunk == "+" {
+            return Ok(Some(core::mem::take(&mut self.buffer)));
+        }
+        if chunk.len() > CHUNK_LENGTH {
+            self.buffer.clear();
+            return Err(IrcError::ChunkTooLong);
+        }
+        if self.buffer.len() + chunk.len() > self.max_length {
+            self.buffer.clear();
+            return Err(IrcError::TooLong);
+        }
+        self.buffer.extend_from_slice(chunk.as_bytes());
+        if chunk.len() == CHUNK_LENGTH {
+            Ok(None)
+        } else {
+            Ok(Some(core::mem::take(&mut self.buffer)))
+        }
+    }
+}
+
+/// Default maximum length of a reassembled base64-encoded message
+const MAX_LENGTH: usize = 65536;
+
+#[derive(Clone, Debug)]
+/// Client side of an IRCv3 SASL exchange
+pub struct IrcClient {
+    incoming: Reassembler,
+    state: Option<State>,
+}
+
+impl Default for IrcClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IrcClient {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            incoming: Reassembler::new(MAX_LENGTH),
+            state: None,
+        }
+    }
+
+    #[must_use]
+    /// Limit the length of reassembled base64-encoded messages from the server
+    pub const fn with_max_length(mut self, max_length: usize) -> Self {
+        self.incoming.max_length = max_length;
+        self
+    }
+
+    #[must_use]
+    /// Returns the line selecting the mechanism of `session`
+    pub fn start<V: Validation, CB: ChannelBindingCallback>(
+        &self,
+        session: &Session<V, CB>,
+    ) -> String {
+        format!("AUTHENTICATE {}", session.get_mechname())
+    }
+
+    #[must_use]
+    /// Returns the line aborting the exchange
+    pub const fn abort(&self) -> &'static str {
+        "AUTHENTICATE *"
+    }
+
+    /// Handle an `AUTHENTICATE` message from the server, returning the lines to send
+    ///
+    /// `param` is the parameter of the message. The returned lines are empty if the message is
+    /// not complete yet.
+    ///
+    /// # Errors
+    /// Returns an error if the message is invalid or the mechanism failed.
+    pub fn authenticate<V: Validation, CB: ChannelBindingCallback>(
+        &mut self,
+        session: &mut Session<V, CB>,
+        param: &str,
+    ) -> Result<Vec<String>, IrcError> {
+        let Some(data) = self.incoming.push(param)? else {
+            return Ok(Vec::new());
+        };
+        let mut out = Vec::new();
+        // The first challenge of client-first mechanisms is always empty and not passed on
+        let input = (self.state.is_some() || !session.are_we_first()).then_some(data);
+        let state = session.step64(input.as_deref(), &mut out)?;
+        self.state = Some(state);
+        Ok(chunk(&out))
+    }
+
+    /// Handle a numeric reply
+    ///
+    /// On [`Reply::Success`] a mechanism still expecting data is given the chance to fail,
+    /// e.g. because the server could not be authenticated.
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism rejected the end of the exchange.
+    pub fn reply<V: Validation, CB: ChannelBindingCallback>(
+        &mut self,
+        session: &mut Session<V, CB>,
+        reply: Reply,
+    ) -> Result<(), IrcError> {
+        if reply == Reply::Success && self.state.as_ref().map_or(true, State::is_running) {
+            self.state = Some(session.step64(None, &mut Vec::new())?);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Result of handling a message on the server side
+pub enum ServerStep {
+    /// Send these lines and wait for the next message
+    ///
+    /// The lines are empty if the message is not complete yet.
+    Continue(Vec<String>),
+    /// The mechanism finished the exchange
+    ///
+    /// The caller must check the validation of the session and send [`Reply::LoggedIn`] and
+    /// [`Reply::Success`], or [`Reply::Fail`].
+    Finished,
+    /// The client aborted the exchange, the caller must send [`Reply::Aborted`]
+    Aborted,
+}
+
+#[derive(Clone, Debug)]
+/// Server side of an IRCv3 SASL exchange
+pub struct IrcServer {
+    incoming: Reassembler,
+    finished: bool,
+}
+
+impl Default for IrcServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IrcServer {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            incoming: Reassembler::new(MAX_LENGTH),
+            finished: false,
+        }
+    }
+
+    #[must_use]
+    /// Limit the length of reassembled base64-encoded messages from the client
+    pub const fn with_max_length(mut self, max_length: usize) -> Self {
+        self.incoming.max_length = max_length;
+        self
+    }
+
+    /// Start the exchange for `session`, returning the lines to send
+    ///
+    /// `session` is started from the mechanism the client selected with
+    /// `AUTHENTICATE <mechanism>`, e.g. using
+    /// [`SASLServer::start_suggested`](crate::prelude::SASLServer::start_suggested).
+    /// If the mechanism is not available the server sends [`Reply::Mechanisms`] and
+    /// [`Reply::Fail`] instead.
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism failed.
+    pub fn start<V: Validation, CB: ChannelBindingCallback>(
+        &mut self,
+        session: &mut Session<V, CB>,
+    ) -> Result<Vec<String>, IrcError> {
+        if session.are_we_first() {
+            let mut out = Vec::new();
+            session.step64(None, &mut out)?;
+            Ok(chunk(&out))
+        } else {
+            Ok(chunk(&[]))
+        }
+    }
+
+    /// Handle an `AUTHENTICATE` message from the client
+    ///
+    /// `param` is the parameter of the message.
+    ///
+    /// # Errors
+    /// Returns an error if the message is invalid or the mechanism failed. For
+    /// [`IrcError::TooLong`] the server should send [`Reply::TooLong`], otherwise
+    /// [`Reply::Fail`].
+    pub fn authenticate<V: Validation, CB: ChannelBindingCallback>(
+        &mut self,
+        session: &mut Session<V, CB>,
+        param: &str,
+    ) -> Result<ServerStep, IrcError> {
+        if param == "*" {
+            return Ok(ServerStep::Aborted);
+        }
+        let Some(data) = self.incoming.push(param)? else {
+            return Ok(ServerStep::Continue(Vec::new()));
+        };
+        if self.finished {
+            // The final challenge carried additional data, acknowledged by an empty response
+            return if data.is_empty() {
+                Ok(ServerStep::Finished)
+            } else {
+                Err(IrcError::Finished)
+            };
+        }
+        let mut out = Vec::new();
+        match session.step64(Some(&data), &mut out)? {
+            State::Running => Ok(ServerStep::Continue(chunk(&out))),
+            State::Finished(MessageSent::Yes) => {
+                self.finished = true;
+                Ok(ServerStep::Continue(chunk(&out)))
+            }
+            State::Finished(MessageSent::No) => Ok(ServerStep::Finished),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mechanisms() {
+        let mechanisms = parse_mechanisms("EXTERNAL,PLAIN,in valid,SCRAM-SHA-256");
+        let names: Vec<&str> = mechanisms.iter().map(|name| name.as_str()).collect();
+        assert_eq!(names, ["EXTERNAL", "PLAIN", "SCRAM-SHA-256"]);
+        assert!(parse_mechanisms("").is_empty());
+    }
+
+    #[test]
+    fn test_reply() {
+        for numeric in 900..=908 {
+            assert_eq!(Reply::from_numeric(numeric).unwrap().numeric(), numeric);
+        }
+        assert_eq!(Reply::from_numeric(899), None);
+    }
+
+    #[test]
+    fn test_chunking() {
+        assert_eq!(chunk(b""), ["AUTHENTICATE +"]);
+        assert_eq!(chunk(b"abc"), ["AUTHENTICATE abc"]);
+
+        let data = "a".repeat(800);
+        let lines = chunk(data.as_bytes());
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "AUTHENTICATE +");
+
+        let mut reassembler = Reassembler::new(MAX_LENGTH);
+        for line in &lines {
+            let param = line.strip_prefix("AUTHENTICATE ").unwrap();
+            if let Some(message) = reassembler.push(param).unwrap() {
+                assert_eq!(message, data.as_bytes());
+                assert_eq!(line, "AUTHENTICATE +");
+            }
+        }
+
+        let data = "a".repeat(401);
+        let lines = chunk(data.as_bytes());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], "AUTHENTICATE a");
+    }
+
+    #[test]
+    fn test_reassembly_limits() {
+        let mut reassembler = Reassembler::new(500);
+        assert!(matches!(
+            reassembler.push(&"a".repeat(401)),
+            Err(IrcError::ChunkTooLong)
+        ));
+        assert_eq!(reassembler.push(&"a".repeat(400)).unwrap(), None);
+        assert!(matches!(
+            reassembler.push(&"a".repeat(400)),
+            Err(IrcError::TooLong)
+        ));
+        assert_eq!(reassembler.push("+").unwrap(), Some(Vec::new()));
+    }
+
+    #[cfg(all(feature = "config_builder", feature = "plain"))]
+    mod exchange {
+        use super::super::*;
+        use crate::config::SASLConfig;
+        use crate::prelude::{SASLClient, SASLServer};
+        use crate::test::fixture::{Callback, TestValidation};
+        use std::string::ToString;
+
+        /// Run an exchange, passing lines between both sides
+        fn run(capability: &str, authid: &str) -> Result<Option<String>, IrcError> {
+            let config =
+                SASLConfig::with_credentials(None, authid.to_string(), "secret".to_string())
+                    .unwrap();
+            let mut client_session = SASLClient::new(config)
+                .start_suggested(&parse_mechanisms(capability))
+                .unwrap();
+            let mut client = IrcClient::new();
+
+            let line = client.start(&client_session);
+            let mechanism = line.strip_prefix("AUTHENTICATE ").unwrap();
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_callback(Callback)
+                .unwrap();
+            let mut server_session = SASLServer::<TestValidation>::new(config)
+                .start_suggested(Mechname::parse(mechanism.as_bytes()).unwrap())
+                .unwrap();
+            let mut server = IrcServer::new();
+
+            let mut to_client = server.start(&mut server_session)?;
+            loop {
+                let mut to_server = Vec::new();
+                for line in to_client {
+                    let param = line.strip_prefix("AUTHENTICATE ").unwrap();
+                    to_server.extend(client.authenticate(&mut client_session, param)?);
+                }
+                to_client = Vec::new();
+                for line in to_server {
+                    let param = line.strip_prefix("AUTHENTICATE ").unwrap();
+                    match server.authenticate(&mut server_session, param)? {
+                        ServerStep::Continue(lines) => to_client.extend(lines),
+                        ServerStep::Finished => {
+                            client.reply(&mut client_session, Reply::Success)?;
+                            return Ok(server_session.validation());
+                        }
+                        ServerStep::Aborted => panic!("client aborted"),
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_plain() {
+            assert_eq!(run("PLAIN", "alice").unwrap().as_deref(), Some("alice"));
+            // Spread the message over two chunks plus a terminating empty chunk
+            let authid = "a".repeat(CHUNK_LENGTH * 6 / 4 - 8);
+            assert_eq!(run("PLAIN", &authid).unwrap(), Some(authid));
+        }
+
+        #[cfg(feature = "scram-sha-2")]
+        #[test]
+        fn test_scram() {
+            assert_eq!(
+                run("SCRAM-SHA-256", "alice").unwrap().as_deref(),
+                Some("alice")
+            );
+        }
+
+        #[test]
+        fn test_abort() {
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_callback(Callback)
+                .unwrap();
+            let mut session = SASLServer::<TestValidation>::new(config)
+                .start_suggested(Mechname::parse(b"PLAIN").unwrap())
+                .unwrap();
+            let mut server = IrcServer::new();
+            assert_eq!(server.start(&mut session).unwrap(), ["AUTHENTICATE +"]);
+            let abort = IrcClient::new().abort();
+            assert_eq!(
+                server
+                    .authenticate(&mut session, abort.strip_prefix("AUTHENTICATE ").unwrap())
+                    .unwrap(),
+                ServerStep::Aborted
+            );
+        }
+    }
+}
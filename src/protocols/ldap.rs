@@ -0,0 +1,1002 @@
+//! LDAP SASL bind (RFC 4511 and RFC 4513)
+//!
+//! *requires feature `ldap`*
+//!
+//! LDAP runs SASL exchanges as a series of bind operations. Each `BindRequest` names the
+//! mechanism and carries the next message of the client as `credentials`; the server answers with
+//! a `BindResponse` carrying its message as `serverSaslCreds` and the result code
+//! `saslBindInProgress` until the exchange is finished.
+//!
+//! This module encodes and decodes complete `LDAPMessage`s containing a [`BindRequest`] or
+//! [`BindResponse`] using the subset of BER permitted by RFC 4511 section 5.1, i.e. definite
+//! lengths only. Absent and empty credentials are kept apart, as mechanisms such as GSSAPI or
+//! SCRAM rely on the difference. [`frame_length`] determines the length of a message when
+//! reading them from a stream, rejecting messages larger than the given maximum before they are
+//! buffered.
+//!
+//! [`LdapClient`] produces the bind requests of a client session and interprets the responses,
+//! while [`LdapServer`] starts and steps server sessions from received bind requests.
+
+use crate::alloc::string::String;
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::callback::CallbackError;
+use crate::channel_bindings::NoChannelBindings;
+use crate::config::SASLConfig;
+use crate::error::{MechanismErrorKind, SASLError, SessionError};
+use crate::mechname::Mechname;
+use crate::policy::SecurityPolicy;
+use crate::prelude::{ChannelBindingCallback, SASLServer, Session};
+use crate::session::{MessageSent, State};
+use crate::validate::{NoValidation, Validation};
+use core::fmt;
+use thiserror::Error;
+
+const SEQUENCE: u8 = 0x30;
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const ENUMERATED: u8 = 0x0A;
+const BIND_REQUEST: u8 = 0x60;
+const BIND_RESPONSE: u8 = 0x61;
+const AUTH_SIMPLE: u8 = 0x80;
+const AUTH_SASL: u8 = 0xA3;
+const REFERRAL: u8 = 0xA3;
+const SERVER_SASL_CREDS: u8 = 0x87;
+
+/// Largest message ID allowed by RFC 4511 (`maxInt`)
+pub const MAX_MESSAGE_ID: u32 = 2_147_483_647;
+
+/// Suggested maximum size of a message passed to [`frame_length`], in bytes
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+/// Error returned by the LDAP helpers
+pub enum LdapError {
+    #[error("malformed message: {0}")]
+    Malformed(&'static str),
+    #[error("bind failed with {code}: {diagnostic_message}")]
+    /// The server rejected the bind
+    Bind {
+        code: ResultCode,
+        diagnostic_message: String,
+    },
+    #[error("message of {0} bytes exceeds the maximum size")]
+    /// The header of a message announced more than the maximum size passed to [`frame_length`]
+    TooLarge(usize),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+/// Result codes relevant to bind operations (RFC 4511 section 4.1.9)
+pub enum ResultCode {
+    Success,
+    OperationsError,
+    ProtocolError,
+    AuthMethodNotSupported,
+    StrongerAuthRequired,
+    ConfidentialityRequired,
+    SaslBindInProgress,
+    InappropriateAuthentication,
+    InvalidCredentials,
+    InsufficientAccessRights,
+    Busy,
+    Unavailable,
+    UnwillingToPerform,
+    /// `other` (80)
+    Other,
+    /// A result code not listed here
+    Unrecognized(u32),
+}
+
+impl ResultCode {
+    #[must_use]
+    pub const fn from_code(code: u32) -> Self {
+        match code {
+            0 => Self::Success,
+            1 => Self::OperationsError,
+            2 => Self::ProtocolError,
+            7 => Self::AuthMethodNotSupported,
+            8 => Self::StrongerAuthRequired,
+            13 => Self::ConfidentialityRequired,
+            14 => Self::SaslBindInProgress,
+            48 => Self::InappropriateAuthentication,
+            49 => Self::InvalidCredentials,
+            50 => Self::InsufficientAccessRights,
+            51 => Self::Busy,
+            52 => Self::Unavailable,
+            53 => Self::UnwillingToPerform,
+            80 => Self::Other,
+            code => Self::Unrecognized(code),
+        }
+    }
+
+    #[must_use]
+    pub const fn code(self) -> u32 {
+        match self {
+            Self::Success => 0,
+            Self::OperationsError => 1,
+            Self::ProtocolError => 2,
+            Self::AuthMethodNotSupported => 7,
+            Self::StrongerAuthRequired => 8,
+            Self::ConfidentialityRequired => 13,
+            Self::SaslBindInProgress => 14,
+            Self::InappropriateAuthentication => 48,
+            Self::InvalidCredentials => 49,
+            Self::InsufficientAccessRights => 50,
+            Self::Busy => 51,
+            Self::Unavailable => 52,
+            Self::UnwillingToPerform => 53,
+            Self::Other => 80,
+            Self::Unrecognized(code) => code,
+        }
+    }
+
+    #[must_use]
+    /// Returns the result code to report for an error of the mechanism
+    ///
+    /// Failed authentications, unknown users and invalid messages are reported as
+    /// `invalidCredentials`, errors of the server itself, e.g. of callbacks, as `other`.
+    pub fn from_session_error(error: &SessionError) -> Self {
+        match error {
+            SessionError::InputDataRequired
+            | SessionError::MechanismDone
+            | SessionError::MutualAuthenticationFailed
+            | SessionError::CallbackError(CallbackError::NoValue) => Self::InvalidCredentials,
+            SessionError::MechanismError(error) => match error.kind() {
+                MechanismErrorKind::Parse
+                | MechanismErrorKind::Protocol
+                | MechanismErrorKind::Outcome => Self::InvalidCredentials,
+            },
+            _ => Self::Other,
+        }
+    }
+
+    #[must_use]
+    /// Returns the result code to report if the mechanism requested can not be used
+    pub const fn from_sasl_error(error: &SASLError) -> Self {
+        match error {
+            SASLError::NoSharedMechanism => Self::AuthMethodNotSupported,
+            SASLError::PolicyViolation { .. } => Self::StrongerAuthRequired,
+        }
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unrecognized(code) => write!(f, "result code {code}"),
+            known => write!(f, "{known:?} ({})", known.code()),
+        }
+    }
+}
+
+/*
+ * BER encoding
+ */
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    match u8::try_from(len) {
+        Ok(short) if short < 0x80 => out.push(short),
+        _ => {
+            let mut octets = 0u8;
+            let mut rest = len;
+            while rest > 0 {
+                octets += 1;
+                rest >>= 8;
+            }
+            out.push(0x80 | octets);
+            let bytes = len.to_be_bytes();
+            out.extend_from_slice(&bytes[bytes.len() - usize::from(octets)..]);
+        }
+    }
+}
+
+fn write_tlv(out: &mut Vec<u8>, tag: u8, content: &[u8]) {
+    out.push(tag);
+    write_length(out, content.len());
+    out.extend_from_slice(content);
+}
+
+/// Write a non-negative INTEGER or ENUMERATED in its minimal two's complement form
+fn write_integer(out: &mut Vec<u8>, tag: u8, value: u32) {
+    let bytes = u64::from(value).to_be_bytes();
+    // Keep a leading zero byte if the highest bit of the next one is set
+    let skip = bytes
+        .windows(2)
+        .take_while(|pair| pair[0] == 0 && pair[1] & 0x80 == 0)
+        .count();
+    write_tlv(out, tag, &bytes[skip..]);
+}
+
+fn write_message(message_id: u32, operation: &[u8]) -> Vec<u8> {
+    let mut content = Vec::new();
+    write_integer(&mut content, INTEGER, message_id);
+    content.extend_from_slice(operation);
+    let mut out = Vec::new();
+    write_tlv(&mut out, SEQUENCE, &content);
+    out
+}
+
+/*
+ * BER decoding
+ */
+
+/// Read a tag and length, returning the tag, the length of the header and of the content
+fn read_header(data: &[u8]) -> Result<Option<(u8, usize, usize)>, LdapError> {
+    let (Some(&tag), Some(&first)) = (data.first(), data.get(1)) else {
+        return Ok(None);
+    };
+    if tag & 0x1F == 0x1F {
+        return Err(LdapError::Malformed("unsupported high tag number"));
+    }
+    if first < 0x80 {
+        return Ok(Some((tag, 2, usize::from(first))));
+    }
+    let octets = usize::from(first & 0x7F);
+    if octets == 0 {
+        return Err(LdapError::Malformed("indefinite length"));
+    }
+    if octets > 4 {
+        return Err(LdapError::Malformed("length too large"));
+    }
+    let Some(bytes) = data.get(2..2 + octets) else {
+        return Ok(None);
+    };
+    let len = bytes
+        .iter()
+        .fold(0usize, |len, byte| (len << 8) | usize::from(*byte));
+    Ok(Some((tag, 2 + octets, len)))
+}
+
+/// Returns the length of the first message in `data`, or `None` if more data is required to
+/// determine it
+///
+/// BER lengths allow messages of up to 4 GiB, so the size of a message including its header is
+/// limited to `max_size` bytes. [`DEFAULT_MAX_MESSAGE_SIZE`] is plenty for bind operations.
+///
+/// # Errors
+/// Returns an error if the data does not start with a valid message header or the message is
+/// larger than `max_size`.
+pub fn frame_length(data: &[u8], max_size: usize) -> Result<Option<usize>, LdapError> {
+    match read_header(data)? {
+        Some((SEQUENCE, header, len)) => match header.checked_add(len) {
+            Some(size) if size <= max_size => Ok(Some(size)),
+            size => Err(LdapError::TooLarge(size.unwrap_or(usize::MAX))),
+        },
+        Some(_) => Err(LdapError::Malformed("not an LDAPMessage")),
+        None => Ok(None),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    const fn peek(&self) -> Option<u8> {
+        match self.data {
+            [tag, ..] => Some(*tag),
+            [] => None,
+        }
+    }
+
+    fn read(&mut self) -> Result<(u8, &'a [u8]), LdapError> {
+        let (tag, header, len) =
+            read_header(self.data)?.ok_or(LdapError::Malformed("truncated"))?;
+        let end = header
+            .checked_add(len)
+            .ok_or(LdapError::Malformed("length too large"))?;
+        let content = self
+            .data
+            .get(header..end)
+            .ok_or(LdapError::Malformed("truncated"))?;
+        self.data = &self.data[end..];
+        Ok((tag, content))
+    }
+
+    fn expect(&mut self, tag: u8, what: &'static str) -> Result<&'a [u8], LdapError> {
+        match self.read()? {
+            (read, content) if read == tag => Ok(content),
+            _ => Err(LdapError::Malformed(what)),
+        }
+    }
+
+    /// Read a non-negative INTEGER or ENUMERATED fitting into an `i32`
+    fn integer(&mut self, tag: u8, what: &'static str) -> Result<u32, LdapError> {
+        let content = self.expect(tag, what)?;
+        if content.is_empty() || content.len() > 4 || content[0] & 0x80 != 0 {
+            return Err(LdapError::Malformed(what));
+        }
+        Ok(content
+            .iter()
+            .fold(0u32, |value, byte| (value << 8) | u32::from(*byte)))
+    }
+
+    fn string(&mut self, what: &'static str) -> Result<String, LdapError> {
+        let content = self.expect(OCTET_STRING, what)?;
+        String::from_utf8(content.to_vec()).map_err(|_| LdapError::Malformed(what))
+    }
+
+    fn finish(&self) -> Result<(), LdapError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(LdapError::Malformed("trailing data"))
+        }
+    }
+}
+
+/// Read an `LDAPMessage`, returning the message ID and the protocol operation
+fn read_message(data: &[u8], operation: u8) -> Result<(u32, &[u8]), LdapError> {
+    let mut outer = Reader::new(data);
+    let mut message = Reader::new(outer.expect(SEQUENCE, "not an LDAPMessage")?);
+    outer.finish()?;
+    let message_id = message.integer(INTEGER, "invalid messageID")?;
+    let content = message.expect(operation, "unexpected protocol operation")?;
+    // Controls are not relevant to the exchange
+    Ok((message_id, content))
+}
+
+/*
+ * Protocol operations
+ */
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Authentication of a bind request
+pub enum Authentication {
+    /// A simple bind with the given password
+    Simple(Vec<u8>),
+    /// A SASL bind (`SaslCredentials`)
+    Sasl {
+        mechanism: String,
+        credentials: Option<Vec<u8>>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+/// A bind request
+pub struct BindRequest {
+    pub message_id: u32,
+    pub version: u32,
+    pub name: String,
+    pub authentication: Authentication,
+}
+
+impl BindRequest {
+    #[must_use]
+    /// Construct a version 3 SASL bind request
+    pub fn sasl(
+        message_id: u32,
+        name: impl Into<String>,
+        mechanism: impl Into<String>,
+        credentials: Option<Vec<u8>>,
+    ) -> Self {
+        Self {
+            message_id,
+            version: 3,
+            name: name.into(),
+            authentication: Authentication::Sasl {
+                mechanism: mechanism.into(),
+                credentials,
+            },
+        }
+    }
+
+    #[must_use]
+    /// Encode the request as `LDAPMessage`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut request = Vec::new();
+        write_integer(&mut request, INTEGER, self.version);
+        write_tlv(&mut request, OCTET_STRING, self.name.as_bytes());
+        match &self.authentication {
+            Authentication::Simple(password) => write_tlv(&mut request, AUTH_SIMPLE, password),
+            Authentication::Sasl {
+                mechanism,
+                credentials,
+            } => {
+                let mut sasl = Vec::new();
+                write_tlv(&mut sasl, OCTET_STRING, mechanism.as_bytes());
+                if let Some(credentials) = credentials {
+                    write_tlv(&mut sasl, OCTET_STRING, credentials);
+                }
+                write_tlv(&mut request, AUTH_SASL, &sasl);
+            }
+        }
+        let mut operation = Vec::new();
+        write_tlv(&mut operation, BIND_REQUEST, &request);
+        write_message(self.message_id, &operation)
+    }
+
+    /// Decode a request from a complete `LDAPMessage`
+    ///
+    /// # Errors
+    /// Returns an error if `data` is not a valid `LDAPMessage` containing a bind request.
+    pub fn decode(data: &[u8]) -> Result<Self, LdapError> {
+        let (message_id, content) = read_message(data, BIND_REQUEST)?;
+        let mut request = Reader::new(content);
+        let version = request.integer(INTEGER, "invalid version")?;
+        let name = request.string("invalid name")?;
+        let authentication = match request.read()? {
+            (AUTH_SIMPLE, password) => Authentication::Simple(password.to_vec()),
+            (AUTH_SASL, sasl) => {
+                let mut sasl = Reader::new(sasl);
+                let mechanism = sasl.string("invalid mechanism")?;
+                let credentials = if sasl.peek().is_some() {
+                    Some(sasl.expect(OCTET_STRING, "invalid credentials")?.to_vec())
+                } else {
+                    None
+                };
+                sasl.finish()?;
+                Authentication::Sasl {
+                    mechanism,
+                    credentials,
+                }
+            }
+            _ => return Err(LdapError::Malformed("unsupported authentication choice")),
+        };
+        request.finish()?;
+        Ok(Self {
+            message_id,
+            version,
+            name,
+            authentication,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+/// A bind response
+///
+/// Referrals are skipped when decoding.
+pub struct BindResponse {
+    pub message_id: u32,
+    pub result_code: ResultCode,
+    pub matched_dn: String,
+    pub diagnostic_message: String,
+    pub server_sasl_creds: Option<Vec<u8>>,
+}
+
+impl BindResponse {
+    #[must_use]
+    pub const fn new(message_id: u32, result_code: ResultCode) -> Self {
+        Self {
+            message_id,
+            result_code,
+            matched_dn: String::new(),
+            diagnostic_message: String::new(),
+            server_sasl_creds: None,
+        }
+    }
+
+    #[must_use]
+    /// Encode the response as `LDAPMessage`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut response = Vec::new();
+        write_integer(&mut response, ENUMERATED, self.result_code.code());
+        write_tlv(&mut response, OCTET_STRING, self.matched_dn.as_bytes());
+        write_tlv(
+            &mut response,
+            OCTET_STRING,
+            self.diagnostic_message.as_bytes(),
+        );
+        if let Some(credentials) = &self.server_sasl_creds {
+            write_tlv(&mut response, SERVER_SASL_CREDS, credentials);
+        }
+        let mut operation = Vec::new();
+        write_tlv(&mut operation, BIND_RESPONSE, &response);
+        write_message(self.message_id, &operation)
+    }
+
+    /// Decode a response from a complete `LDAPMessage`
+    ///
+    /// # Errors
+    /// Returns an error if `data` is not a valid `LDAPMessage` containing a bind response.
+    pub fn decode(data: &[u8]) -> Result<Self, LdapError> {
+        let (message_id, content) = read_message(data, BIND_RESPONSE)?;
+        let mut response = Reader::new(content);
+        let result_code =
+            ResultCode::from_code(response.integer(ENUMERATED, "invalid resultCode")?);
+        let matched_dn = response.string("invalid matchedDN")?;
+        let diagnostic_message = response.string("invalid diagnosticMessage")?;
+        if response.peek() == Some(REFERRAL) {
+            response.read()?;
+        }
+        let server_sasl_creds = if response.peek().is_some() {
+            Some(
+                response
+                    .expect(SERVER_SASL_CREDS, "invalid serverSaslCreds")?
+                    .to_vec(),
+            )
+        } else {
+            None
+        };
+        response.finish()?;
+        Ok(Self {
+            message_id,
+            result_code,
+            matched_dn,
+            diagnostic_message,
+            server_sasl_creds,
+        })
+    }
+}
+
+/*
+ * Client
+ */
+
+/// Client side of an LDAP SASL bind
+///
+/// Message IDs are assigned by the caller, as they are shared with other operations on the
+/// connection.
+pub struct LdapClient<V: Validation = NoValidation, CB = NoChannelBindings> {
+    session: Session<V, CB>,
+    name: String,
+    state: Option<State>,
+}
+
+impl<V: Validation, CB: ChannelBindingCallback> fmt::Debug for LdapClient<V, CB> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LdapClient")
+            .field("mechanism", &self.session.get_mechname())
+            .field("name", &self.name)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V: Validation, CB: ChannelBindingCallback> LdapClient<V, CB> {
+    #[must_use]
+    pub const fn new(session: Session<V, CB>) -> Self {
+        Self {
+            session,
+            name: String::new(),
+            state: None,
+        }
+    }
+
+    #[must_use]
+    /// Set the name of the bind requests
+    ///
+    /// SASL binds usually leave the name empty, as the identity is given by the mechanism.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    #[must_use]
+    pub const fn session(&self) -> &Session<V, CB> {
+        &self.session
+    }
+
+    #[must_use]
+    pub fn into_session(self) -> Session<V, CB> {
+        self.session
+    }
+
+    fn request(&self, message_id: u32, credentials: Option<Vec<u8>>) -> BindRequest {
+        BindRequest::sasl(
+            message_id,
+            self.name.clone(),
+            self.session.get_mechname().as_str(),
+            credentials,
+        )
+    }
+
+    /// Returns the first bind request, carrying the initial response of client-first mechanisms
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism failed to produce its initial response.
+    pub fn start(&mut self, message_id: u32) -> Result<BindRequest, SessionError> {
+        let credentials = if self.session.are_we_first() {
+            let mut out = Vec::new();
+            self.state = Some(self.session.step(None, &mut out)?);
+            Some(out)
+        } else {
+            None
+        };
+        Ok(self.request(message_id, credentials))
+    }
+
+    /// Handle a bind response, returning the next bind request to send
+    ///
+    /// Returns `Ok(None)` once the bind succeeded. `message_id` is used for the next request.
+    ///
+    /// # Errors
+    /// Returns [`LdapError::Bind`] if the server rejected the bind, or an error if the mechanism
+    /// failed, e.g. because the server could not be authenticated.
+    pub fn response(
+        &mut self,
+        response: &BindResponse,
+        message_id: u32,
+    ) -> Result<Option<BindRequest>, LdapError> {
+        let credentials = response.server_sasl_creds.as_deref();
+        match response.result_code {
+            ResultCode::SaslBindInProgress => {
+                let mut out = Vec::new();
+                let input = credentials.unwrap_or_default();
+                self.state = Some(self.session.step(Some(input), &mut out)?);
+                Ok(Some(self.request(message_id, Some(out))))
+            }
+            ResultCode::Success => {
+                if credentials.is_some() || self.state.as_ref().map_or(true, State::is_running) {
+                    self.state = Some(self.session.step(credentials, &mut Vec::new())?);
+                }
+                Ok(None)
+            }
+            code => Err(LdapError::Bind {
+                code,
+                diagnostic_message: response.diagnostic_message.clone(),
+            }),
+        }
+    }
+}
+
+/*
+ * Server
+ */
+
+#[derive(Debug)]
+#[allow(clippy::exhaustive_enums)]
+/// Outcome of handling a bind request with [`LdapServer::bind`]
+pub enum ServerStep<T> {
+    /// Send this response with `saslBindInProgress` and wait for the next bind request
+    InProgress(BindResponse),
+    /// The bind is complete, send this response
+    ///
+    /// `validation` is `Some` if the bind succeeded.
+    Finished {
+        response: BindResponse,
+        validation: Option<T>,
+    },
+}
+
+/// Server side of LDAP SASL binds on one connection
+///
+/// Every SASL bind request is handed to [`bind`](Self::bind), which starts a new exchange if the
+/// request names a different mechanism than the exchange in progress.
+pub struct LdapServer<V: Validation> {
+    config: Arc<SASLConfig>,
+    policy: SecurityPolicy,
+    exchange: Option<(String, Session<V>)>,
+}
+
+impl<V: Validation> fmt::Debug for LdapServer<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LdapServer")
+            .field("config", &self.config)
+            .field("policy", &self.policy)
+            .field(
+                "mechanism",
+                &self.exchange.as_ref().map(|(mechanism, _)| mechanism),
+            )
+            .finish()
+    }
+}
+
+impl<V: Validation> LdapServer<V> {
+    #[must_use]
+    pub fn new(config: Arc<SASLConfig>) -> Self {
+        Self {
+            config,
+            policy: SecurityPolicy::new(),
+            exchange: None,
+        }
+    }
+
+    #[must_use]
+    /// Only accept mechanisms allowed by `policy`
+    pub const fn with_policy(mut self, policy: SecurityPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[must_use]
+    /// Returns `true` if a SASL bind is in progress
+    pub const fn in_progress(&self) -> bool {
+        self.exchange.is_some()
+    }
+
+    /// Handle a bind request
+    ///
+    /// Requests that are not version 3 SASL binds abort the exchange in progress and are
+    /// rejected, simple binds with `authMethodNotSupported`.
+    pub fn bind(&mut self, request: &BindRequest) -> ServerStep<V::Value> {
+        let message_id = request.message_id;
+        let finished = |code| ServerStep::Finished {
+            response: BindResponse::new(message_id, code),
+            validation: None,
+        };
+
+        let (mechanism, credentials) = match &request.authentication {
+            _ if request.version != 3 => {
+                self.exchange = None;
+                return finished(ResultCode::ProtocolError);
+            }
+            Authentication::Sasl {
+                mechanism,
+                credentials,
+            } => (mechanism, credentials.as_deref()),
+            Authentication::Simple(_) => {
+                self.exchange = None;
+                return finished(ResultCode::AuthMethodNotSupported);
+            }
+        };
+
+        let continued = matches!(&self.exchange, Some((current, _)) if current == mechanism);
+        let (_, mut session) = match self.exchange.take() {
+            Some(exchange) if continued => exchange,
+            _ => {
+                let Ok(mechname) = Mechname::parse(mechanism.as_bytes()) else {
+                    return finished(ResultCode::AuthMethodNotSupported);
+                };
+                match SASLServer::new(self.config.clone())
+                    .with_policy(self.policy)
+                    .start_suggested(mechname)
+                {
+                    Ok(session) => (mechanism.clone(), session),
+                    Err(error) => return finished(ResultCode::from_sasl_error(&error)),
+                }
+            }
+        };
+
+        // A client-first mechanism started without credentials asks for an empty challenge
+        let input = if !continued && credentials.is_none() && !session.are_we_first() {
+            self.exchange = Some((mechanism.clone(), session));
+            return ServerStep::InProgress(BindResponse::new(
+                message_id,
+                ResultCode::SaslBindInProgress,
+            ));
+        } else if continued {
+            Some(credentials.unwrap_or_default())
+        } else {
+            credentials
+        };
+
+        let mut out = Vec::new();
+        let state = match session.step(input, &mut out) {
+            Ok(state) => state,
+            Err(error) => return finished(ResultCode::from_session_error(&error)),
+        };
+        let mut response = BindResponse::new(message_id, ResultCode::SaslBindInProgress);
+        match state {
+            State::Running => {
+                response.server_sasl_creds = Some(out);
+                self.exchange = Some((mechanism.clone(), session));
+                ServerStep::InProgress(response)
+            }
+            State::Finished(sent) => {
+                let validation = session.validation();
+                if validation.is_some() {
+                    response.result_code = ResultCode::Success;
+                    response.server_sasl_creds = (sent == MessageSent::Yes).then_some(out);
+                } else {
+                    response.result_code = ResultCode::InvalidCredentials;
+                }
+                ServerStep::Finished {
+                    response,
+                    validation,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_bind_request() {
+        let request = BindRequest::sasl(1, "", "PLAIN", Some(b"\0a\0b".to_vec()));
+        let encoded = request.encode();
+        assert_eq!(
+            encoded,
+            [
+                0x30, 0x19, 0x02, 0x01, 0x01, 0x60, 0x14, 0x02, 0x01, 0x03, 0x04, 0x00, 0xA3, 0x0D,
+                0x04, 0x05, b'P', b'L', b'A', b'I', b'N', 0x04, 0x04, 0, b'a', 0, b'b'
+            ]
+        );
+        assert_eq!(
+            frame_length(&encoded, DEFAULT_MAX_MESSAGE_SIZE).unwrap(),
+            Some(encoded.len())
+        );
+        assert_eq!(BindRequest::decode(&encoded).unwrap(), request);
+
+        // Absent and empty credentials are distinct
+        let absent = BindRequest::sasl(128, "", "GSSAPI", None);
+        let empty = BindRequest::sasl(128, "", "GSSAPI", Some(Vec::new()));
+        assert_ne!(absent.encode(), empty.encode());
+        assert_eq!(&absent.encode()[2..6], [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(BindRequest::decode(&absent.encode()).unwrap(), absent);
+        assert_eq!(BindRequest::decode(&empty.encode()).unwrap(), empty);
+    }
+
+    #[test]
+    fn test_encode_bind_response() {
+        let mut response = BindResponse::new(MAX_MESSAGE_ID, ResultCode::SaslBindInProgress);
+        response.server_sasl_creds = Some([0xAA; 300].to_vec());
+        let encoded = response.encode();
+        // Long form lengths
+        assert_eq!(&encoded[..4], [0x30, 0x82, 0x01, 0x41]);
+        assert_eq!(
+            frame_length(&encoded[..3], DEFAULT_MAX_MESSAGE_SIZE).unwrap(),
+            None
+        );
+        assert_eq!(
+            frame_length(&encoded, DEFAULT_MAX_MESSAGE_SIZE).unwrap(),
+            Some(encoded.len())
+        );
+        assert!(matches!(
+            frame_length(&encoded, encoded.len() - 1),
+            Err(LdapError::TooLarge(size)) if size == encoded.len()
+        ));
+        assert_eq!(BindResponse::decode(&encoded).unwrap(), response);
+
+        response.server_sasl_creds = None;
+        response.result_code = ResultCode::Unrecognized(4711);
+        assert_eq!(BindResponse::decode(&response.encode()).unwrap(), response);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // Indefinite length
+        assert!(matches!(
+            BindRequest::decode(&[0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00]),
+            Err(LdapError::Malformed("indefinite length"))
+        ));
+        // Negative message ID
+        assert!(matches!(
+            BindResponse::decode(&[0x30, 0x03, 0x02, 0x01, 0xFF]),
+            Err(LdapError::Malformed("invalid messageID"))
+        ));
+        // Truncated content
+        let encoded = BindRequest::sasl(1, "", "PLAIN", None).encode();
+        assert!(matches!(
+            BindRequest::decode(&encoded[..encoded.len() - 1]),
+            Err(LdapError::Malformed(_))
+        ));
+        // A response is not a request
+        let encoded = BindResponse::new(1, ResultCode::Success).encode();
+        assert!(matches!(
+            BindRequest::decode(&encoded),
+            Err(LdapError::Malformed("unexpected protocol operation"))
+        ));
+        // Announcing 4 GiB
+        let huge = [0x30, 0x84, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert!(matches!(
+            frame_length(&huge, DEFAULT_MAX_MESSAGE_SIZE),
+            Err(LdapError::TooLarge(_))
+        ));
+        assert!(matches!(
+            BindRequest::decode(&huge),
+            Err(LdapError::Malformed(_))
+        ));
+    }
+
+    #[cfg(all(feature = "config_builder", feature = "plain"))]
+    mod exchange {
+        use super::super::*;
+        use crate::prelude::SASLClient;
+        use crate::test::fixture::{Callback, TestValidation};
+        use std::string::ToString;
+
+        fn server() -> LdapServer<TestValidation> {
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_callback(Callback)
+                .unwrap();
+            LdapServer::new(config)
+        }
+
+        /// Run a bind, passing all PDUs through their encoding
+        fn run(mechanism: &str, password: &str) -> Result<Option<String>, LdapError> {
+            let config =
+                SASLConfig::with_credentials(None, "alice".to_string(), password.to_string())
+                    .unwrap();
+            let session = SASLClient::new(config)
+                .start_suggested(&[Mechname::parse(mechanism.as_bytes()).unwrap()])
+                .unwrap();
+            let mut client = LdapClient::new(session);
+            let mut server = server();
+
+            let mut message_id = 1;
+            let mut request = client.start(message_id)?;
+            loop {
+                let request_pdu = BindRequest::decode(&request.encode())?;
+                let (response, validation) = match server.bind(&request_pdu) {
+                    ServerStep::InProgress(response) => (response, None),
+                    ServerStep::Finished {
+                        response,
+                        validation,
+                    } => (response, Some(validation)),
+                };
+                assert_eq!(response.message_id, message_id);
+                message_id += 1;
+                let response = BindResponse::decode(&response.encode())?;
+                match client.response(&response, message_id)? {
+                    Some(next) => request = next,
+                    None => return Ok(validation.flatten()),
+                }
+            }
+        }
+
+        #[test]
+        fn test_plain() {
+            assert_eq!(run("PLAIN", "secret").unwrap().as_deref(), Some("alice"));
+            assert!(matches!(
+                run("PLAIN", "wrong"),
+                Err(LdapError::Bind {
+                    code: ResultCode::InvalidCredentials,
+                    ..
+                })
+            ));
+        }
+
+        #[cfg(feature = "scram-sha-2")]
+        #[test]
+        fn test_scram() {
+            assert_eq!(
+                run("SCRAM-SHA-256", "secret").unwrap().as_deref(),
+                Some("alice")
+            );
+        }
+
+        #[test]
+        fn test_server_restart() {
+            let mut server = server();
+            // A client-first mechanism without credentials is answered with an empty challenge
+            let step = server.bind(&BindRequest::sasl(1, "", "PLAIN", None));
+            assert!(matches!(
+                step,
+                ServerStep::InProgress(BindResponse {
+                    result_code: ResultCode::SaslBindInProgress,
+                    server_sasl_creds: None,
+                    ..
+                })
+            ));
+            assert!(server.in_progress());
+
+            // An unknown mechanism aborts the exchange in progress
+            let step = server.bind(&BindRequest::sasl(2, "", "", None));
+            assert!(matches!(
+                step,
+                ServerStep::Finished {
+                    response: BindResponse {
+                        result_code: ResultCode::AuthMethodNotSupported,
+                        ..
+                    },
+                    validation: None,
+                }
+            ));
+            assert!(!server.in_progress());
+
+            let step = server.bind(&BindRequest::sasl(
+                3,
+                "",
+                "PLAIN",
+                Some(b"\0alice\0secret".to_vec()),
+            ));
+            assert!(matches!(
+                step,
+                ServerStep::Finished {
+                    response: BindResponse {
+                        result_code: ResultCode::Success,
+                        ..
+                    },
+                    validation: Some(_),
+                }
+            ));
+        }
+    }
+}
@@ -0,0 +1,879 @@
+//! `AUTHENTICATE` framing for line-based text protocols
+//!
+//! *requires feature `line_protocols`*
+//!
+//! IMAP, SMTP, POP3 and ManageSieve all run SASL exchanges by sending base64-encoded messages on
+//! lines of their own, differing mainly in how the command and continuation lines are framed:
+//!
+//! | [`Dialect`]    | Command                         | Challenge       | Success                 |
+//! |----------------|---------------------------------|-----------------|-------------------------|
+//! | IMAP           | `a1 AUTHENTICATE PLAIN [ir]`    | `+ <data>`      | `a1 OK`                 |
+//! | SMTP           | `AUTH PLAIN [ir]`               | `334 <data>`    | `235`                   |
+//! | POP3           | `AUTH PLAIN [ir]`               | `+ <data>`      | `+OK`                   |
+//! | ManageSieve    | `AUTHENTICATE "PLAIN" ["ir"]`   | `"<data>"`      | `OK (SASL "<data>")`    |
+//!
+//! A client answers each challenge with a line containing its base64-encoded response, or
+//! cancels the exchange by sending `*` (`"*"` for ManageSieve). An initial response sent with
+//! the command (IMAP with SASL-IR, RFC 4959; SMTP, RFC 4954; POP3, RFC 5034) that is empty is
+//! sent as `=` to tell it apart from an absent one. ManageSieve uses an empty string instead.
+//!
+//! Only ManageSieve can send additional data with its success response. The other dialects
+//! send it as a final challenge instead, which the client answers with an empty line.
+//!
+//! [`LineClient`] runs the complete exchange for the client side, including the command.
+//! [`LineServer`] runs the exchange after the server parsed the command, leaving the final
+//! response to the caller as it depends on the outcome of the validation.
+
+use crate::error::SessionError;
+use crate::prelude::{ChannelBindingCallback, Session};
+use crate::session::{MessageSent, State};
+use crate::validate::Validation;
+use std::io::{BufRead, Write};
+use std::string::{String, ToString};
+use std::vec::Vec;
+use thiserror::Error;
+
+/// Largest ManageSieve literal accepted from the other party, in bytes
+pub const MAX_LITERAL_LEN: usize = 1024 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+/// Framing of the exchange used by a protocol
+pub enum Dialect {
+    /// IMAP (RFC 9051), with SASL-IR (RFC 4959) for initial responses
+    Imap,
+    /// SMTP (RFC 4954)
+    Smtp,
+    /// POP3 (RFC 5034)
+    Pop3,
+    /// ManageSieve (RFC 5804)
+    ManageSieve,
+}
+
+impl Dialect {
+    /// Returns `true` if the dialect can send additional data with its success response
+    #[must_use]
+    pub const fn has_success_data(self) -> bool {
+        matches!(self, Self::ManageSieve)
+    }
+
+    fn write_challenge(self, writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::Imap | Self::Pop3 => writer.write_all(b"+ ")?,
+            Self::Smtp => writer.write_all(b"334 ")?,
+            Self::ManageSieve => return write_quoted(writer, data),
+        }
+        writer.write_all(data)?;
+        writer.write_all(b"\r\n")?;
+        writer.flush()
+    }
+
+    fn write_response(self, writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+        if self == Self::ManageSieve {
+            return write_quoted(writer, data);
+        }
+        writer.write_all(data)?;
+        writer.write_all(b"\r\n")?;
+        writer.flush()
+    }
+}
+
+fn write_quoted(writer: &mut impl Write, data: &[u8]) -> std::io::Result<()> {
+    // Base64 contains neither quotes nor backslashes, so no escaping is required
+    writer.write_all(b"\"")?;
+    writer.write_all(data)?;
+    writer.write_all(b"\"\r\n")?;
+    writer.flush()
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+/// Error returned by the line protocol drivers
+pub enum LineError {
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Session(#[from] SessionError),
+    #[error("unexpected line {0:?}")]
+    /// The other party sent a line not valid at this point of the exchange
+    Unexpected(String),
+    #[error("connection closed during the exchange")]
+    Closed,
+    #[error("literal of {0} bytes exceeds the maximum length")]
+    /// The other party announced a literal longer than [`MAX_LITERAL_LEN`]
+    LiteralTooLong(usize),
+}
+
+/// Read a line, stripping the line terminator
+fn read_line(reader: &mut impl BufRead) -> Result<String, LineError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(LineError::Closed);
+    }
+    let len = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+/// Read a ManageSieve string starting on `line`, given either quoted or as literal
+fn read_string(line: &str, reader: &mut impl BufRead) -> Result<String, LineError> {
+    let unexpected = || LineError::Unexpected(line.to_string());
+    if let Some(quoted) = line.strip_prefix('"') {
+        let (value, rest) = parse_quoted(quoted).ok_or_else(unexpected)?;
+        return if rest.trim().is_empty() {
+            Ok(value)
+        } else {
+            Err(unexpected())
+        };
+    }
+    let len = line
+        .strip_prefix('{')
+        .and_then(|literal| literal.strip_suffix('}'))
+        .map(|len| len.trim_end_matches('+'))
+        .and_then(|len| len.parse::<usize>().ok())
+        .ok_or_else(unexpected)?;
+    if len > MAX_LITERAL_LEN {
+        return Err(LineError::LiteralTooLong(len));
+    }
+    let mut value = vec![0; len];
+    reader.read_exact(&mut value)?;
+    // The literal is terminated by the end of the line it is on
+    if !read_line(reader)?.trim().is_empty() {
+        return Err(unexpected());
+    }
+    String::from_utf8(value).map_err(|_| unexpected())
+}
+
+/// Parse the remainder of a quoted string, returning its value and the rest of the line
+fn parse_quoted(s: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &s[i + 1..])),
+            '\\' => value.push(chars.next()?.1),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Strip `prefix` from `line` ignoring ASCII case
+fn strip_prefix_ci<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &line[prefix.len()..])
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Completion of an exchange run by [`LineClient::authenticate`]
+pub enum ClientOutcome {
+    /// The server accepted the authentication, with the line it sent
+    Success(String),
+    /// The server rejected the authentication, with the line it sent
+    Failure(String),
+}
+
+/// A server line seen by the client
+enum ServerLine {
+    Challenge(String),
+    Success(Option<String>),
+    Failure,
+    /// Untagged IMAP responses and intermediate lines of multi-line SMTP replies
+    Other,
+}
+
+#[derive(Clone, Debug)]
+/// Client side driver running an `AUTHENTICATE` exchange
+pub struct LineClient {
+    dialect: Dialect,
+    tag: String,
+    initial_response: bool,
+}
+
+impl LineClient {
+    #[must_use]
+    /// Construct a driver for `dialect`
+    ///
+    /// Initial responses are sent for all dialects except IMAP, where the server has to
+    /// advertise the `SASL-IR` capability first.
+    pub fn new(dialect: Dialect) -> Self {
+        Self {
+            dialect,
+            tag: String::from("A1"),
+            initial_response: dialect != Dialect::Imap,
+        }
+    }
+
+    #[must_use]
+    /// Use `tag` for the IMAP command
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = tag.into();
+        self
+    }
+
+    #[must_use]
+    /// Send the first message of client-first mechanisms with the command
+    pub const fn with_initial_response(mut self, initial_response: bool) -> Self {
+        self.initial_response = initial_response;
+        self
+    }
+
+    /// Send the command authenticating using `session` and run the exchange to completion
+    ///
+    /// If the mechanism fails during the exchange, the exchange is cancelled by sending `*`
+    /// (`"*"` for ManageSieve) and the response of the server to the cancellation is read before
+    /// the error of the mechanism is returned.
+    ///
+    /// # Errors
+    /// Returns an error if reading or writing failed, the server sent an unexpected line or
+    /// the mechanism failed, e.g. because the server could not be authenticated.
+    pub fn authenticate<V: Validation, CB: ChannelBindingCallback>(
+        &self,
+        session: &mut Session<V, CB>,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> Result<ClientOutcome, LineError> {
+        let mut state = State::Running;
+        // A first message not sent with the command is sent once the server asks for it
+        let mut pending = None;
+        let mut initial_response = None;
+        if session.are_we_first() {
+            let mut data = Vec::new();
+            state = session.step64(None, &mut data)?;
+            if !self.initial_response {
+                pending = Some(data);
+            } else if data.is_empty() && self.dialect != Dialect::ManageSieve {
+                initial_response = Some(b"=".to_vec());
+            } else {
+                initial_response = Some(data);
+            }
+        }
+        self.write_command(
+            &mut writer,
+            session.get_mechname().as_str(),
+            initial_response.as_deref(),
+        )?;
+
+        loop {
+            let line = read_line(&mut reader)?;
+            match self.parse_line(&line, &mut reader)? {
+                ServerLine::Challenge(challenge) => {
+                    let data = if let Some(data) = pending.take() {
+                        data
+                    } else {
+                        let mut data = Vec::new();
+                        match session.step64(Some(challenge.as_bytes()), &mut data) {
+                            Ok(next) => state = next,
+                            Err(error) => {
+                                self.cancel(&mut reader, &mut writer);
+                                return Err(error.into());
+                            }
+                        }
+                        data
+                    };
+                    self.dialect.write_response(&mut writer, &data)?;
+                }
+                ServerLine::Success(data) => {
+                    if data.is_some() || state.is_running() {
+                        session.step64(data.as_deref().map(str::as_bytes), &mut Vec::new())?;
+                    }
+                    return Ok(ClientOutcome::Success(line));
+                }
+                ServerLine::Failure => return Ok(ClientOutcome::Failure(line)),
+                ServerLine::Other => {}
+            }
+        }
+    }
+
+    /// Cancel the exchange and read the final response of the server
+    ///
+    /// Errors are ignored, as the caller returns the error that caused the cancellation.
+    fn cancel(&self, reader: &mut impl BufRead, writer: &mut impl Write) {
+        if self.dialect.write_response(writer, b"*").is_err() {
+            return;
+        }
+        while let Ok(line) = read_line(reader) {
+            if !matches!(self.parse_line(&line, reader), Ok(ServerLine::Other)) {
+                return;
+            }
+        }
+    }
+
+    fn write_command(
+        &self,
+        writer: &mut impl Write,
+        mechanism: &str,
+        initial_response: Option<&[u8]>,
+    ) -> std::io::Result<()> {
+        match self.dialect {
+            Dialect::Imap => write!(writer, "{} AUTHENTICATE {mechanism}", self.tag)?,
+            Dialect::Smtp | Dialect::Pop3 => write!(writer, "AUTH {mechanism}")?,
+            Dialect::ManageSieve => write!(writer, "AUTHENTICATE \"{mechanism}\"")?,
+        }
+        if let Some(initial_response) = initial_response {
+            if self.dialect == Dialect::ManageSieve {
+                writer.write_all(b" \"")?;
+                writer.write_all(initial_response)?;
+                writer.write_all(b"\"")?;
+            } else {
+                writer.write_all(b" ")?;
+                writer.write_all(initial_response)?;
+            }
+        }
+        writer.write_all(b"\r\n")?;
+        writer.flush()
+    }
+
+    fn parse_line(&self, line: &str, reader: &mut impl BufRead) -> Result<ServerLine, LineError> {
+        let parsed = match self.dialect {
+            Dialect::Imap => {
+                if let Some(challenge) = line.strip_prefix('+') {
+                    ServerLine::Challenge(challenge.trim_start().to_string())
+                } else if let Some(status) = line
+                    .strip_prefix(self.tag.as_str())
+                    .and_then(|rest| rest.strip_prefix(' '))
+                {
+                    if strip_prefix_ci(status, "OK").is_some() {
+                        ServerLine::Success(None)
+                    } else {
+                        ServerLine::Failure
+                    }
+                } else if line.starts_with("* ") {
+                    ServerLine::Other
+                } else {
+                    return Err(LineError::Unexpected(line.to_string()));
+                }
+            }
+            Dialect::Smtp => match line.get(..4) {
+                Some("334 ") => ServerLine::Challenge(line[4..].to_string()),
+                Some("334") if line.len() == 3 => ServerLine::Challenge(String::new()),
+                _ if line.len() == 3 || line.as_bytes().get(3) == Some(&b' ') => {
+                    if line.starts_with('2') {
+                        ServerLine::Success(None)
+                    } else {
+                        ServerLine::Failure
+                    }
+                }
+                _ if line.as_bytes().get(3) == Some(&b'-') => ServerLine::Other,
+                _ => return Err(LineError::Unexpected(line.to_string())),
+            },
+            Dialect::Pop3 => {
+                if strip_prefix_ci(line, "+OK").is_some() {
+                    ServerLine::Success(None)
+                } else if let Some(challenge) = line.strip_prefix('+') {
+                    ServerLine::Challenge(challenge.trim_start().to_string())
+                } else if strip_prefix_ci(line, "-ERR").is_some() {
+                    ServerLine::Failure
+                } else {
+                    return Err(LineError::Unexpected(line.to_string()));
+                }
+            }
+            Dialect::ManageSieve => {
+                if let Some(rest) = strip_prefix_ci(line, "OK") {
+                    let data = strip_prefix_ci(rest.trim_start(), "(SASL \"")
+                        .map(|data| {
+                            parse_quoted(data)
+                                .map(|(data, _)| data)
+                                .ok_or_else(|| LineError::Unexpected(line.to_string()))
+                        })
+                        .transpose()?;
+                    ServerLine::Success(data)
+                } else if strip_prefix_ci(line, "NO").is_some()
+                    || strip_prefix_ci(line, "BYE").is_some()
+                {
+                    ServerLine::Failure
+                } else {
+                    ServerLine::Challenge(read_string(line, reader)?)
+                }
+            }
+        };
+        Ok(parsed)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Outcome of an exchange run by [`LineServer::authenticate`]
+pub enum ServerOutcome {
+    /// The mechanism finished the exchange
+    ///
+    /// The caller must now check the validation of the session and send the success or failure
+    /// response. For dialects that [can send data with success](Dialect::has_success_data),
+    /// `additional_data` is the base64-encoded data to include with the success response, e.g.
+    /// `OK (SASL "<data>")`. Other dialects already sent it as a final challenge.
+    Finished { additional_data: Option<String> },
+    /// The client cancelled the exchange
+    ///
+    /// The caller must send a failure response, e.g. `BAD` for IMAP or `501` for SMTP.
+    Cancelled,
+}
+
+#[derive(Clone, Debug)]
+/// Server side driver running an `AUTHENTICATE` exchange
+pub struct LineServer {
+    dialect: Dialect,
+}
+
+impl LineServer {
+    #[must_use]
+    /// Construct a driver for `dialect`
+    pub const fn new(dialect: Dialect) -> Self {
+        Self { dialect }
+    }
+
+    /// Run the exchange of an `AUTHENTICATE` command the server received
+    ///
+    /// `initial_response` is the initial response argument of the command if one was sent,
+    /// e.g. `Some("=")` for an empty one. For ManageSieve this is the content of the string.
+    ///
+    /// # Errors
+    /// Returns an error if reading or writing failed, the client sent an invalid response or
+    /// the mechanism failed.
+    pub fn authenticate<V: Validation, CB: ChannelBindingCallback>(
+        &self,
+        session: &mut Session<V, CB>,
+        initial_response: Option<&str>,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> Result<ServerOutcome, LineError> {
+        let mut input = match initial_response {
+            Some("=") if self.dialect != Dialect::ManageSieve => Some(String::new()),
+            Some(initial_response) => Some(initial_response.to_string()),
+            None if !session.are_we_first() => {
+                // Ask the client for the first message it did not send with the command
+                self.dialect.write_challenge(&mut writer, &[])?;
+                match self.read_response(&mut reader)? {
+                    Some(response) => Some(response),
+                    None => return Ok(ServerOutcome::Cancelled),
+                }
+            }
+            None => None,
+        };
+
+        loop {
+            let mut data = Vec::new();
+            let state = session.step64(input.as_deref().map(str::as_bytes), &mut data)?;
+            match state {
+                State::Finished(MessageSent::No) => {
+                    return Ok(ServerOutcome::Finished {
+                        additional_data: None,
+                    })
+                }
+                State::Finished(MessageSent::Yes) if self.dialect.has_success_data() => {
+                    let data = String::from_utf8_lossy(&data).into_owned();
+                    return Ok(ServerOutcome::Finished {
+                        additional_data: Some(data),
+                    });
+                }
+                State::Running | State::Finished(MessageSent::Yes) => {}
+            }
+
+            self.dialect.write_challenge(&mut writer, &data)?;
+            let Some(response) = self.read_response(&mut reader)? else {
+                return Ok(ServerOutcome::Cancelled);
+            };
+            if state.is_finished() {
+                // The final challenge carried additional data, which is acknowledged by an
+                // empty response
+                return if response.is_empty() {
+                    Ok(ServerOutcome::Finished {
+                        additional_data: None,
+                    })
+                } else {
+                    Err(LineError::Unexpected(response))
+                };
+            }
+            input = Some(response);
+        }
+    }
+
+    /// Read a response of the client, returning `None` if it cancelled the exchange
+    fn read_response(&self, reader: &mut impl BufRead) -> Result<Option<String>, LineError> {
+        let line = read_line(reader)?;
+        let response = if self.dialect == Dialect::ManageSieve {
+            read_string(&line, reader)?
+        } else {
+            line
+        };
+        Ok((response != "*").then_some(response))
+    }
+}
+
+#[cfg(all(test, feature = "config_builder", feature = "plain"))]
+mod tests {
+    use super::*;
+    use crate::config::SASLConfig;
+    use crate::mechname::Mechname;
+    use crate::prelude::{SASLClient, SASLServer};
+    use crate::test::fixture::{Callback, TestValidation};
+    use std::io::Cursor;
+
+    fn client_session(mechanism: &str, password: &str) -> Session {
+        let config =
+            SASLConfig::with_credentials(None, "alice".to_string(), password.to_string()).unwrap();
+        SASLClient::new(config)
+            .start_suggested(&[Mechname::parse(mechanism.as_bytes()).unwrap()])
+            .unwrap()
+    }
+
+    fn server_session(mechanism: &str) -> Session<TestValidation> {
+        let config = SASLConfig::builder()
+            .with_defaults()
+            .with_callback(Callback)
+            .unwrap();
+        SASLServer::new(config)
+            .start_suggested(Mechname::parse(mechanism.as_bytes()).unwrap())
+            .unwrap()
+    }
+
+    /// Run a PLAIN client against the scripted lines of a server, returning the lines sent
+    fn client_script(client: &LineClient, server: &str) -> (ClientOutcome, String) {
+        let mut session = client_session("PLAIN", "secret");
+        let mut sent = Vec::new();
+        let outcome = client
+            .authenticate(&mut session, Cursor::new(server), &mut sent)
+            .unwrap();
+        (outcome, String::from_utf8(sent).unwrap())
+    }
+
+    const PLAIN: &str = "AGFsaWNlAHNlY3JldA==";
+
+    #[test]
+    fn test_client_initial_response() {
+        let (outcome, sent) = client_script(
+            &LineClient::new(Dialect::Smtp),
+            "235-2.7.0 Authentication\r\n235 2.7.0 successful\r\n",
+        );
+        assert_eq!(sent, format!("AUTH PLAIN {PLAIN}\r\n"));
+        assert_eq!(
+            outcome,
+            ClientOutcome::Success("235 2.7.0 successful".to_string())
+        );
+
+        let (outcome, sent) = client_script(
+            &LineClient::new(Dialect::ManageSieve),
+            "NO \"Authentication failed\"\r\n",
+        );
+        assert_eq!(sent, format!("AUTHENTICATE \"PLAIN\" \"{PLAIN}\"\r\n"));
+        assert!(matches!(outcome, ClientOutcome::Failure(_)));
+    }
+
+    #[test]
+    fn test_client_without_initial_response() {
+        let (outcome, sent) = client_script(
+            &LineClient::new(Dialect::Imap).with_tag("a7"),
+            "+ \r\n* CAPABILITY IMAP4rev2\r\na7 OK done\r\n",
+        );
+        assert_eq!(sent, format!("a7 AUTHENTICATE PLAIN\r\n{PLAIN}\r\n"));
+        assert_eq!(outcome, ClientOutcome::Success("a7 OK done".to_string()));
+
+        let (outcome, sent) = client_script(
+            &LineClient::new(Dialect::ManageSieve).with_initial_response(false),
+            "{0+}\r\n\r\nOK\r\n",
+        );
+        assert_eq!(sent, format!("AUTHENTICATE \"PLAIN\"\r\n\"{PLAIN}\"\r\n"));
+        assert_eq!(outcome, ClientOutcome::Success("OK".to_string()));
+    }
+
+    #[test]
+    fn test_client_unexpected() {
+        let mut session = client_session("PLAIN", "secret");
+        let result = LineClient::new(Dialect::Pop3).authenticate(
+            &mut session,
+            Cursor::new("garbage\r\n"),
+            Vec::new(),
+        );
+        assert!(matches!(result, Err(LineError::Unexpected(line)) if line == "garbage"));
+
+        let mut session = client_session("PLAIN", "secret");
+        let result =
+            LineClient::new(Dialect::Pop3).authenticate(&mut session, Cursor::new(""), Vec::new());
+        assert!(matches!(result, Err(LineError::Closed)));
+    }
+
+    #[test]
+    fn test_server() {
+        let server = LineServer::new(Dialect::Imap);
+        let mut session = server_session("PLAIN");
+        let mut sent = Vec::new();
+        let outcome = server
+            .authenticate(
+                &mut session,
+                None,
+                Cursor::new(format!("{PLAIN}\r\n")),
+                &mut sent,
+            )
+            .unwrap();
+        assert_eq!(sent, b"+ \r\n");
+        assert_eq!(
+            outcome,
+            ServerOutcome::Finished {
+                additional_data: None
+            }
+        );
+        assert_eq!(session.validation().as_deref(), Some("alice"));
+
+        let mut session = server_session("PLAIN");
+        let mut sent = Vec::new();
+        let outcome = LineServer::new(Dialect::Smtp)
+            .authenticate(&mut session, None, Cursor::new("*\r\n"), &mut sent)
+            .unwrap();
+        assert_eq!(sent, b"334 \r\n");
+        assert_eq!(outcome, ServerOutcome::Cancelled);
+
+        let mut session = server_session("PLAIN");
+        let outcome = LineServer::new(Dialect::ManageSieve)
+            .authenticate(&mut session, None, Cursor::new("\"*\"\r\n"), Vec::new())
+            .unwrap();
+        assert_eq!(outcome, ServerOutcome::Cancelled);
+    }
+
+    #[test]
+    fn test_server_empty_initial_response() {
+        // An empty message is not a valid PLAIN message, so `=` must reach the mechanism as
+        // an empty message and not as an absent one prompting an empty challenge.
+        let mut session = server_session("PLAIN");
+        let mut sent = Vec::new();
+        let result = LineServer::new(Dialect::Pop3).authenticate(
+            &mut session,
+            Some("="),
+            Cursor::new(""),
+            &mut sent,
+        );
+        assert!(matches!(result, Err(LineError::Session(_))));
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn test_client_cancel() {
+        // Not valid base64, so the mechanism fails on the challenge
+        let mut session = client_session("PLAIN", "secret");
+        let mut sent = Vec::new();
+        let server = "334 !!!\r\n501 5.7.0 cancelled\r\n";
+        let mut reader = Cursor::new(server);
+        let result =
+            LineClient::new(Dialect::Smtp).authenticate(&mut session, &mut reader, &mut sent);
+        assert!(matches!(result, Err(LineError::Session(_))));
+        assert_eq!(
+            String::from_utf8(sent).unwrap(),
+            format!("AUTH PLAIN {PLAIN}\r\n*\r\n")
+        );
+        // The response to the cancellation was consumed
+        assert_eq!(reader.position(), server.len() as u64);
+
+        let mut session = client_session("PLAIN", "secret");
+        let mut sent = Vec::new();
+        let result = LineClient::new(Dialect::ManageSieve).authenticate(
+            &mut session,
+            Cursor::new("\"!!!\"\r\nNO \"cancelled\"\r\n"),
+            &mut sent,
+        );
+        assert!(matches!(result, Err(LineError::Session(_))));
+        assert_eq!(
+            String::from_utf8(sent).unwrap(),
+            format!("AUTHENTICATE \"PLAIN\" \"{PLAIN}\"\r\n\"*\"\r\n")
+        );
+    }
+
+    #[test]
+    fn test_literal_too_long() {
+        let mut session = server_session("PLAIN");
+        let result = LineServer::new(Dialect::ManageSieve).authenticate(
+            &mut session,
+            None,
+            Cursor::new("{18446744073709551615+}\r\n"),
+            Vec::new(),
+        );
+        assert!(matches!(result, Err(LineError::LiteralTooLong(usize::MAX))));
+    }
+
+    #[cfg(feature = "scram-sha-2")]
+    mod scram {
+        use super::*;
+        use std::cell::RefCell;
+        use std::io::Read;
+        use std::rc::Rc;
+
+        const DIALECTS: [Dialect; 4] = [
+            Dialect::Imap,
+            Dialect::Smtp,
+            Dialect::Pop3,
+            Dialect::ManageSieve,
+        ];
+
+        /// In-memory connection to a peer answering each line written to it
+        struct Loopback<F> {
+            written: Rc<RefCell<Vec<u8>>>,
+            peer: F,
+            buffer: Cursor<Vec<u8>>,
+        }
+
+        impl<F: FnMut(&str) -> Vec<u8>> Loopback<F> {
+            fn new(peer: F) -> (Self, Writer) {
+                let written = Rc::new(RefCell::new(Vec::new()));
+                let loopback = Self {
+                    written: written.clone(),
+                    peer,
+                    buffer: Cursor::new(Vec::new()),
+                };
+                (loopback, Writer(written))
+            }
+        }
+
+        impl<F: FnMut(&str) -> Vec<u8>> Read for Loopback<F> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.fill_buf()?.read(buf)?;
+                self.consume(n);
+                Ok(n)
+            }
+        }
+
+        impl<F: FnMut(&str) -> Vec<u8>> BufRead for Loopback<F> {
+            fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+                if self.buffer.position() == self.buffer.get_ref().len() as u64 {
+                    let written = std::mem::take(&mut *self.written.borrow_mut());
+                    let mut answer = Vec::new();
+                    for line in String::from_utf8(written).unwrap().split_terminator("\r\n") {
+                        answer.extend((self.peer)(line));
+                    }
+                    self.buffer = Cursor::new(answer);
+                }
+                self.buffer.fill_buf()
+            }
+
+            fn consume(&mut self, amt: usize) {
+                self.buffer.consume(amt);
+            }
+        }
+
+        struct Writer(Rc<RefCell<Vec<u8>>>);
+        impl Write for Writer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        fn success(dialect: Dialect, data: &[u8]) -> Vec<u8> {
+            let data = String::from_utf8(data.to_vec()).unwrap();
+            let line = match dialect {
+                Dialect::Imap => "A1 OK done".to_string(),
+                Dialect::Smtp => "235 done".to_string(),
+                Dialect::Pop3 => "+OK done".to_string(),
+                Dialect::ManageSieve => format!("OK (SASL \"{data}\")"),
+            };
+            format!("{line}\r\n").into_bytes()
+        }
+
+        #[test]
+        fn test_client() {
+            for dialect in DIALECTS {
+                let server = LineServer::new(dialect);
+                let mut session: Option<Session<TestValidation>> = None;
+                let mut finished = false;
+                let (reader, writer) = Loopback::new(|line: &str| {
+                    let mut out = Vec::new();
+                    let input = if let Some(session) = &mut session {
+                        let response = server
+                            .read_response(&mut Cursor::new(format!("{line}\r\n")))
+                            .unwrap()
+                            .unwrap();
+                        if finished {
+                            assert!(response.is_empty());
+                            assert_eq!(session.validation().as_deref(), Some("alice"));
+                            return success(dialect, b"");
+                        }
+                        Some(response)
+                    } else {
+                        let args: Vec<&str> = line
+                            .split(' ')
+                            .skip(usize::from(dialect == Dialect::Imap))
+                            .skip(1)
+                            .map(|arg| arg.trim_matches('"'))
+                            .collect();
+                        session = Some(server_session(args[0]));
+                        match args.get(1) {
+                            Some(&"=") => Some(String::new()),
+                            Some(initial_response) => Some((*initial_response).to_string()),
+                            None => {
+                                dialect.write_challenge(&mut out, b"").unwrap();
+                                return out;
+                            }
+                        }
+                    };
+                    let session = session.as_mut().unwrap();
+                    let mut data = Vec::new();
+                    let state = session
+                        .step64(input.as_deref().map(str::as_bytes), &mut data)
+                        .unwrap();
+                    if state.is_finished() && dialect.has_success_data() {
+                        assert_eq!(session.validation().as_deref(), Some("alice"));
+                        return success(dialect, &data);
+                    }
+                    finished = state.is_finished();
+                    dialect.write_challenge(&mut out, &data).unwrap();
+                    out
+                });
+
+                let mut client = client_session("SCRAM-SHA-256", "secret");
+                let outcome = LineClient::new(dialect)
+                    .authenticate(&mut client, reader, writer)
+                    .unwrap();
+                assert!(matches!(outcome, ClientOutcome::Success(_)), "{dialect:?}");
+                assert!(finished || dialect.has_success_data());
+            }
+        }
+
+        #[test]
+        fn test_server() {
+            for dialect in DIALECTS {
+                let driver = LineClient::new(dialect);
+                let mut client = client_session("SCRAM-SHA-256", "secret");
+                let mut initial_response = Vec::new();
+                client.step64(None, &mut initial_response).unwrap();
+                let initial_response = String::from_utf8(initial_response).unwrap();
+
+                let (reader, writer) = Loopback::new(|line: &str| {
+                    let ServerLine::Challenge(challenge) =
+                        driver.parse_line(line, &mut Cursor::new("")).unwrap()
+                    else {
+                        panic!("expected a challenge");
+                    };
+                    let mut data = Vec::new();
+                    client
+                        .step64(Some(challenge.as_bytes()), &mut data)
+                        .unwrap();
+                    let mut out = Vec::new();
+                    dialect.write_response(&mut out, &data).unwrap();
+                    out
+                });
+
+                let mut session = server_session("SCRAM-SHA-256");
+                let outcome = LineServer::new(dialect)
+                    .authenticate(&mut session, Some(&initial_response), reader, writer)
+                    .unwrap();
+                let ServerOutcome::Finished { additional_data } = outcome else {
+                    panic!("exchange cancelled");
+                };
+                assert_eq!(additional_data.is_some(), dialect.has_success_data());
+                if let Some(data) = additional_data {
+                    assert!(client
+                        .step64(Some(data.as_bytes()), &mut Vec::new())
+                        .unwrap()
+                        .is_finished());
+                }
+                assert_eq!(
+                    session.validation().as_deref(),
+                    Some("alice"),
+                    "{dialect:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! # Protocol bindings
+//!
+//! SASL only defines the exchange of authentication messages; how these messages are framed,
+//! encoded and which outcome is signaled is left to the protocol using SASL. This module contains
+//! bindings of [`Session`](crate::prelude::Session) to commonly used protocols, taking care of the
+//! framing so that protocol implementations only have to move the produced messages over the
+//! wire.
+//!
+//! Each binding is enabled by its own feature flag.
+
+#[cfg(feature = "http")]
+pub mod http;
+
+#[cfg(feature = "line_protocols")]
+pub mod line;
+
+#[cfg(feature = "irc")]
+pub mod irc;
+
+#[cfg(feature = "xmpp")]
+pub mod xmpp;
+
+#[cfg(feature = "ldap")]
+pub mod ldap;
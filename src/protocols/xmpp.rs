@@ -0,0 +1,793 @@
+//! XMPP SASL (RFC 6120) and SASL2 (XEP-0388) elements
+//!
+//! *requires feature `xmpp`*
+//!
+//! XMPP carries the base64-encoded messages of an exchange as character data of the elements
+//! in the [`NS_SASL`] or [`NS_SASL2`] namespace. [`XmppClient`] and [`XmppServer`] map steps of a
+//! [`Session`] to these elements, which are represented by [`Sasl`] and [`Sasl2`] and serialized
+//! using their `Display` implementation.
+//!
+//! Parsing received elements is left to the XML parser of the XMPP implementation; the helpers
+//! are given the character data of the received element. Empty data sent with `<auth/>`,
+//! `<authenticate/>` or as additional data with success is transmitted as `=` to tell it apart
+//! from absent data, and the helpers handle both forms.
+//!
+//! Errors and failed validations on the server side are mapped to the failure conditions of
+//! RFC 6120 section 6.5, see [`Condition`].
+
+use crate::callback::CallbackError;
+use crate::channel_bindings::NoChannelBindings;
+use crate::error::{MechanismErrorKind, SASLError, SessionError};
+use crate::prelude::{ChannelBindingCallback, Session};
+use crate::session::{MessageSent, State};
+use crate::validate::{NoValidation, Validation};
+use core::fmt;
+use std::string::String;
+use std::vec::Vec;
+
+/// Namespace of the RFC 6120 SASL elements
+pub const NS_SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+/// Namespace of the XEP-0388 SASL2 elements
+pub const NS_SASL2: &str = "urn:xmpp:sasl:2";
+
+/// Normalize received character data, mapping `=` to empty data
+fn data(text: &str) -> &str {
+    match text.trim() {
+        "=" => "",
+        text => text,
+    }
+}
+
+/// Character data of an element whose empty data is transmitted as `=`
+fn marked(data: &str) -> &str {
+    if data.is_empty() {
+        "="
+    } else {
+        data
+    }
+}
+
+/// Escape a value for use in character data or attribute values
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.0;
+        while let Some(i) = rest.find(['&', '<', '>', '"', '\'']) {
+            f.write_str(&rest[..i])?;
+            f.write_str(match rest.as_bytes()[i] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                b'>' => "&gt;",
+                b'"' => "&quot;",
+                _ => "&apos;",
+            })?;
+            rest = &rest[i + 1..];
+        }
+        f.write_str(rest)
+    }
+}
+
+/// Write `<name xmlns='ns'>data</name>`, or an empty element if `data` is empty
+fn write_data(f: &mut fmt::Formatter<'_>, name: &str, ns: &str, data: &str) -> fmt::Result {
+    if data.is_empty() {
+        write!(f, "<{name} xmlns='{ns}'/>")
+    } else {
+        write!(f, "<{name} xmlns='{ns}'>{}</{name}>", Escaped(data))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+/// Defined failure conditions (RFC 6120 section 6.5)
+pub enum Condition {
+    Aborted,
+    AccountDisabled,
+    CredentialsExpired,
+    EncryptionRequired,
+    IncorrectEncoding,
+    InvalidAuthzid,
+    InvalidMechanism,
+    MalformedRequest,
+    MechanismTooWeak,
+    NotAuthorized,
+    TemporaryAuthFailure,
+}
+
+impl Condition {
+    const ALL: [Self; 11] = [
+        Self::Aborted,
+        Self::AccountDisabled,
+        Self::CredentialsExpired,
+        Self::EncryptionRequired,
+        Self::IncorrectEncoding,
+        Self::InvalidAuthzid,
+        Self::InvalidMechanism,
+        Self::MalformedRequest,
+        Self::MechanismTooWeak,
+        Self::NotAuthorized,
+        Self::TemporaryAuthFailure,
+    ];
+
+    #[must_use]
+    /// Returns the name of the element signaling the condition
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Aborted => "aborted",
+            Self::AccountDisabled => "account-disabled",
+            Self::CredentialsExpired => "credentials-expired",
+            Self::EncryptionRequired => "encryption-required",
+            Self::IncorrectEncoding => "incorrect-encoding",
+            Self::InvalidAuthzid => "invalid-authzid",
+            Self::InvalidMechanism => "invalid-mechanism",
+            Self::MalformedRequest => "malformed-request",
+            Self::MechanismTooWeak => "mechanism-too-weak",
+            Self::NotAuthorized => "not-authorized",
+            Self::TemporaryAuthFailure => "temporary-auth-failure",
+        }
+    }
+
+    #[must_use]
+    /// Returns the condition signaled by the element `name`
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|condition| condition.name() == name)
+    }
+
+    #[must_use]
+    /// Returns the condition to report for an error of the mechanism
+    ///
+    /// Malformed and invalid messages are reported as `malformed-request` or
+    /// `incorrect-encoding`, failed authentications and unknown users as `not-authorized`.
+    /// Errors of the server itself, e.g. of callbacks, are reported as `temporary-auth-failure`.
+    pub fn from_session_error(error: &SessionError) -> Self {
+        match error {
+            #[cfg(feature = "provider_base64")]
+            SessionError::Base64 { .. } => Self::IncorrectEncoding,
+            SessionError::InputDataRequired | SessionError::MechanismDone => Self::MalformedRequest,
+            SessionError::MechanismError(error) => match error.kind() {
+                MechanismErrorKind::Parse | MechanismErrorKind::Protocol => Self::MalformedRequest,
+                MechanismErrorKind::Outcome => Self::NotAuthorized,
+            },
+            SessionError::CallbackError(CallbackError::NoValue)
+            | SessionError::MutualAuthenticationFailed => Self::NotAuthorized,
+            _ => Self::TemporaryAuthFailure,
+        }
+    }
+
+    #[must_use]
+    /// Returns the condition to report if the mechanism selected by the client can not be used
+    pub const fn from_sasl_error(error: &SASLError) -> Self {
+        match error {
+            SASLError::NoSharedMechanism => Self::InvalidMechanism,
+            SASLError::PolicyViolation { .. } => Self::MechanismTooWeak,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Elements of the RFC 6120 SASL negotiation
+///
+/// Data is given base64-encoded as it is transmitted.
+pub enum Sasl {
+    /// `<auth/>`, selecting a mechanism and optionally carrying the initial response
+    Auth {
+        mechanism: String,
+        initial_response: Option<String>,
+    },
+    /// `<challenge/>`
+    Challenge(String),
+    /// `<response/>`
+    Response(String),
+    /// `<success/>`, optionally carrying additional data
+    Success(Option<String>),
+    /// `<failure/>`
+    Failure {
+        condition: Condition,
+        text: Option<String>,
+    },
+    /// `<abort/>`
+    Abort,
+}
+
+impl fmt::Display for Sasl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auth {
+                mechanism,
+                initial_response,
+            } => {
+                write!(
+                    f,
+                    "<auth xmlns='{NS_SASL}' mechanism='{}'",
+                    Escaped(mechanism)
+                )?;
+                match initial_response {
+                    Some(data) => write!(f, ">{}</auth>", Escaped(marked(data))),
+                    None => f.write_str("/>"),
+                }
+            }
+            Self::Challenge(data) => write_data(f, "challenge", NS_SASL, data),
+            Self::Response(data) => write_data(f, "response", NS_SASL, data),
+            Self::Success(data) => {
+                write_data(f, "success", NS_SASL, data.as_deref().map_or("", marked))
+            }
+            Self::Failure { condition, text } => {
+                write!(f, "<failure xmlns='{NS_SASL}'><{condition}/>")?;
+                if let Some(text) = text {
+                    write!(f, "<text>{}</text>", Escaped(text))?;
+                }
+                f.write_str("</failure>")
+            }
+            Self::Abort => write!(f, "<abort xmlns='{NS_SASL}'/>"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[allow(clippy::exhaustive_structs)]
+/// The `<user-agent/>` sent with a SASL2 `<authenticate/>`
+pub struct UserAgent {
+    /// Stable identifier of the client installation, usually a UUID
+    pub id: Option<String>,
+    /// Name of the client software
+    pub software: Option<String>,
+    /// Name of the device
+    pub device: Option<String>,
+}
+
+impl fmt::Display for UserAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<user-agent")?;
+        if let Some(id) = &self.id {
+            write!(f, " id='{}'", Escaped(id))?;
+        }
+        f.write_str(">")?;
+        if let Some(software) = &self.software {
+            write!(f, "<software>{}</software>", Escaped(software))?;
+        }
+        if let Some(device) = &self.device {
+            write!(f, "<device>{}</device>", Escaped(device))?;
+        }
+        f.write_str("</user-agent>")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::exhaustive_enums)]
+/// Elements of the XEP-0388 SASL2 negotiation
+///
+/// Data is given base64-encoded as it is transmitted.
+pub enum Sasl2 {
+    /// `<authenticate/>`, selecting a mechanism and optionally carrying the initial response
+    Authenticate {
+        mechanism: String,
+        initial_response: Option<String>,
+        user_agent: Option<UserAgent>,
+    },
+    /// `<challenge/>`
+    Challenge(String),
+    /// `<response/>`
+    Response(String),
+    /// `<success/>` with the identifier the client is now authorized as, usually its full JID
+    Success {
+        additional_data: Option<String>,
+        authorization_identifier: String,
+    },
+    /// `<continue/>`, asking the client to complete further tasks
+    Continue {
+        additional_data: Option<String>,
+        tasks: Vec<String>,
+        text: Option<String>,
+    },
+    /// `<failure/>`
+    Failure {
+        condition: Condition,
+        text: Option<String>,
+    },
+    /// `<abort/>`
+    Abort,
+}
+
+impl fmt::Display for Sasl2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn additional_data(f: &mut fmt::Formatter<'_>, data: Option<&String>) -> fmt::Result {
+            if let Some(data) = data {
+                write!(
+                    f,
+                    "<additional-data>{}</additional-data>",
+                    Escaped(marked(data))
+                )?;
+            }
+            Ok(())
+        }
+        fn text(f: &mut fmt::Formatter<'_>, text: Option<&String>) -> fmt::Result {
+            if let Some(text) = text {
+                write!(f, "<text>{}</text>", Escaped(text))?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Self::Authenticate {
+                mechanism,
+                initial_response,
+                user_agent,
+            } => {
+                write!(
+                    f,
+                    "<authenticate xmlns='{NS_SASL2}' mechanism='{}'>",
+                    Escaped(mechanism)
+                )?;
+                if let Some(data) = initial_response {
+                    write!(
+                        f,
+                        "<initial-response>{}</initial-response>",
+                        Escaped(marked(data))
+                    )?;
+                }
+                if let Some(user_agent) = user_agent {
+                    write!(f, "{user_agent}")?;
+                }
+                f.write_str("</authenticate>")
+            }
+            Self::Challenge(data) => write_data(f, "challenge", NS_SASL2, data),
+            Self::Response(data) => write_data(f, "response", NS_SASL2, data),
+            Self::Success {
+                additional_data: data,
+                authorization_identifier,
+            } => {
+                write!(f, "<success xmlns='{NS_SASL2}'>")?;
+                additional_data(f, data.as_ref())?;
+                write!(
+                    f,
+                    "<authorization-identifier>{}</authorization-identifier></success>",
+                    Escaped(authorization_identifier)
+                )
+            }
+            Self::Continue {
+                additional_data: data,
+                tasks,
+                text: message,
+            } => {
+                write!(f, "<continue xmlns='{NS_SASL2}'>")?;
+                additional_data(f, data.as_ref())?;
+                f.write_str("<tasks>")?;
+                for task in tasks {
+                    write!(f, "<task>{}</task>", Escaped(task))?;
+                }
+                f.write_str("</tasks>")?;
+                text(f, message.as_ref())?;
+                f.write_str("</continue>")
+            }
+            Self::Failure {
+                condition,
+                text: message,
+            } => {
+                write!(
+                    f,
+                    "<failure xmlns='{NS_SASL2}'><{condition} xmlns='{NS_SASL}'/>"
+                )?;
+                text(f, message.as_ref())?;
+                f.write_str("</failure>")
+            }
+            Self::Abort => write!(f, "<abort xmlns='{NS_SASL2}'/>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(clippy::exhaustive_enums)]
+/// Outcome of a step of [`XmppServer`]
+pub enum ServerStep<T> {
+    /// Send a challenge with this data
+    Challenge(String),
+    /// The client authenticated successfully
+    Success {
+        validation: T,
+        additional_data: Option<String>,
+    },
+    /// The authentication failed
+    Failure {
+        condition: Condition,
+        /// The error causing the failure, or `None` if the validation of the exchange failed
+        error: Option<SessionError>,
+    },
+}
+
+impl<T> ServerStep<T> {
+    #[must_use]
+    /// Returns the RFC 6120 element to send
+    pub fn to_sasl(&self) -> Sasl {
+        match self {
+            Self::Challenge(data) => Sasl::Challenge(data.clone()),
+            Self::Success {
+                additional_data, ..
+            } => Sasl::Success(additional_data.clone()),
+            Self::Failure { condition, .. } => Sasl::Failure {
+                condition: *condition,
+                text: None,
+            },
+        }
+    }
+
+    #[must_use]
+    /// Returns the SASL2 element to send
+    ///
+    /// `authorization_identifier` is only used for a successful authentication.
+    pub fn to_sasl2(&self, authorization_identifier: &str) -> Sasl2 {
+        match self {
+            Self::Challenge(data) => Sasl2::Challenge(data.clone()),
+            Self::Success {
+                additional_data, ..
+            } => Sasl2::Success {
+                additional_data: additional_data.clone(),
+                authorization_identifier: String::from(authorization_identifier),
+            },
+            Self::Failure { condition, .. } => Sasl2::Failure {
+                condition: *condition,
+                text: None,
+            },
+        }
+    }
+}
+
+/// Server side of an XMPP SASL or SASL2 negotiation
+pub struct XmppServer<V: Validation, CB = NoChannelBindings> {
+    session: Session<V, CB>,
+}
+
+impl<V: Validation, CB: ChannelBindingCallback> fmt::Debug for XmppServer<V, CB> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XmppServer")
+            .field("mechanism", &self.session.get_mechname())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V: Validation, CB: ChannelBindingCallback> XmppServer<V, CB> {
+    #[must_use]
+    /// Negotiate using `session`, started from the mechanism selected by the client
+    ///
+    /// If the mechanism can not be started, send the failure condition returned by
+    /// [`Condition::from_sasl_error`] instead.
+    pub const fn new(session: Session<V, CB>) -> Self {
+        Self { session }
+    }
+
+    #[must_use]
+    pub const fn session(&self) -> &Session<V, CB> {
+        &self.session
+    }
+
+    #[must_use]
+    pub fn into_session(self) -> Session<V, CB> {
+        self.session
+    }
+
+    /// Handle `<auth/>` or `<authenticate/>`, given the initial response if one was sent
+    pub fn auth(&mut self, initial_response: Option<&str>) -> ServerStep<V::Value> {
+        match initial_response {
+            Some(text) => self.step(Some(data(text))),
+            // Ask the client for the first message it did not send as initial response
+            None if !self.session.are_we_first() => ServerStep::Challenge(String::new()),
+            None => self.step(None),
+        }
+    }
+
+    /// Handle `<response/>`, given its character data
+    pub fn response(&mut self, text: &str) -> ServerStep<V::Value> {
+        self.step(Some(data(text)))
+    }
+
+    fn step(&mut self, input: Option<&str>) -> ServerStep<V::Value> {
+        let mut out = Vec::new();
+        let state = match self.session.step64(input.map(str::as_bytes), &mut out) {
+            Ok(state) => state,
+            Err(error) => {
+                return ServerStep::Failure {
+                    condition: Condition::from_session_error(&error),
+                    error: Some(error),
+                }
+            }
+        };
+        let out = String::from_utf8_lossy(&out).into_owned();
+        let State::Finished(sent) = state else {
+            return ServerStep::Challenge(out);
+        };
+        let Some(validation) = self.session.validation() else {
+            return ServerStep::Failure {
+                condition: Condition::NotAuthorized,
+                error: None,
+            };
+        };
+        ServerStep::Success {
+            validation,
+            additional_data: (sent == MessageSent::Yes).then_some(out),
+        }
+    }
+}
+
+/// Client side of an XMPP SASL or SASL2 negotiation
+///
+/// If a method returns an error the client should abort the negotiation by sending
+/// [`Sasl::Abort`] or [`Sasl2::Abort`].
+pub struct XmppClient<V: Validation = NoValidation, CB = NoChannelBindings> {
+    session: Session<V, CB>,
+    state: Option<State>,
+}
+
+impl<V: Validation, CB: ChannelBindingCallback> fmt::Debug for XmppClient<V, CB> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XmppClient")
+            .field("mechanism", &self.session.get_mechname())
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V: Validation, CB: ChannelBindingCallback> XmppClient<V, CB> {
+    #[must_use]
+    pub const fn new(session: Session<V, CB>) -> Self {
+        Self {
+            session,
+            state: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn session(&self) -> &Session<V, CB> {
+        &self.session
+    }
+
+    #[must_use]
+    pub fn into_session(self) -> Session<V, CB> {
+        self.session
+    }
+
+    fn initial_response(&mut self) -> Result<Option<String>, SessionError> {
+        if !self.session.are_we_first() {
+            return Ok(None);
+        }
+        let mut out = Vec::new();
+        self.state = Some(self.session.step64(None, &mut out)?);
+        Ok(Some(String::from_utf8_lossy(&out).into_owned()))
+    }
+
+    /// Returns the `<auth/>` element starting the RFC 6120 negotiation
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism failed to produce its initial response.
+    pub fn auth(&mut self) -> Result<Sasl, SessionError> {
+        Ok(Sasl::Auth {
+            mechanism: String::from(self.session.get_mechname().as_str()),
+            initial_response: self.initial_response()?,
+        })
+    }
+
+    /// Returns the `<authenticate/>` element starting the SASL2 negotiation
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism failed to produce its initial response.
+    pub fn authenticate(&mut self, user_agent: Option<UserAgent>) -> Result<Sasl2, SessionError> {
+        Ok(Sasl2::Authenticate {
+            mechanism: String::from(self.session.get_mechname().as_str()),
+            initial_response: self.initial_response()?,
+            user_agent,
+        })
+    }
+
+    /// Handle `<challenge/>`, returning the data to send in `<response/>`
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism failed.
+    pub fn challenge(&mut self, text: &str) -> Result<String, SessionError> {
+        let mut out = Vec::new();
+        self.state = Some(self.session.step64(Some(data(text).as_bytes()), &mut out)?);
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    }
+
+    /// Handle `<success/>`, given the additional data if any was sent
+    ///
+    /// For SASL2 this is the content of `<additional-data/>` of `<success/>` or `<continue/>`.
+    ///
+    /// # Errors
+    /// Returns an error if the mechanism rejected the end of the exchange, e.g. because the
+    /// server could not be authenticated.
+    pub fn success(&mut self, additional_data: Option<&str>) -> Result<(), SessionError> {
+        if additional_data.is_some() || self.state.as_ref().map_or(true, State::is_running) {
+            let input = additional_data.map(|text| data(text).as_bytes());
+            self.state = Some(self.session.step64(input, &mut Vec::new())?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition() {
+        for condition in Condition::ALL {
+            assert_eq!(Condition::parse(condition.name()), Some(condition));
+        }
+        assert_eq!(Condition::parse("bad-request"), None);
+        assert_eq!(
+            Condition::from_session_error(&SessionError::InputDataRequired),
+            Condition::MalformedRequest
+        );
+        assert_eq!(
+            Condition::from_session_error(&SessionError::CallbackError(CallbackError::NoValue)),
+            Condition::NotAuthorized
+        );
+        assert_eq!(
+            Condition::from_session_error(&SessionError::NoValidate),
+            Condition::TemporaryAuthFailure
+        );
+        assert_eq!(
+            Condition::from_sasl_error(&SASLError::NoSharedMechanism),
+            Condition::InvalidMechanism
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let auth = Sasl::Auth {
+            mechanism: String::from("PLAIN"),
+            initial_response: Some(String::new()),
+        };
+        assert_eq!(
+            auth.to_string(),
+            "<auth xmlns='urn:ietf:params:xml:ns:xmpp-sasl' mechanism='PLAIN'>=</auth>"
+        );
+        assert_eq!(
+            Sasl::Success(None).to_string(),
+            "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>"
+        );
+        let failure = Sasl::Failure {
+            condition: Condition::NotAuthorized,
+            text: Some(String::from("<wrong> & 'bad'")),
+        };
+        assert_eq!(
+            failure.to_string(),
+            "<failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/>\
+             <text>&lt;wrong&gt; &amp; &apos;bad&apos;</text></failure>"
+        );
+
+        let authenticate = Sasl2::Authenticate {
+            mechanism: String::from("SCRAM-SHA-256"),
+            initial_response: Some(String::from("biwsbj11c2VyLHI9")),
+            user_agent: Some(UserAgent {
+                id: Some(String::from("d4565fa7-4d72-4749-b3d3-740edbf87770")),
+                software: Some(String::from("AwesomeXMPP")),
+                device: None,
+            }),
+        };
+        assert_eq!(
+            authenticate.to_string(),
+            "<authenticate xmlns='urn:xmpp:sasl:2' mechanism='SCRAM-SHA-256'>\
+             <initial-response>biwsbj11c2VyLHI9</initial-response>\
+             <user-agent id='d4565fa7-4d72-4749-b3d3-740edbf87770'>\
+             <software>AwesomeXMPP</software></user-agent></authenticate>"
+        );
+        let success = Sasl2::Success {
+            additional_data: Some(String::from("dj1tc2c=")),
+            authorization_identifier: String::from("user@example.org/res"),
+        };
+        assert_eq!(
+            success.to_string(),
+            "<success xmlns='urn:xmpp:sasl:2'><additional-data>dj1tc2c=</additional-data>\
+             <authorization-identifier>user@example.org/res</authorization-identifier></success>"
+        );
+        let failure = Sasl2::Failure {
+            condition: Condition::Aborted,
+            text: None,
+        };
+        assert_eq!(
+            failure.to_string(),
+            "<failure xmlns='urn:xmpp:sasl:2'>\
+             <aborted xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/></failure>"
+        );
+    }
+
+    #[cfg(all(feature = "config_builder", feature = "plain"))]
+    mod exchange {
+        use super::super::*;
+        use crate::config::SASLConfig;
+        use crate::mechname::Mechname;
+        use crate::prelude::{SASLClient, SASLServer};
+        use crate::test::fixture::{Callback, TestValidation};
+        use std::string::ToString;
+
+        fn start(mechanism: &str, password: &str) -> (XmppClient, XmppServer<TestValidation>) {
+            let mechanism = Mechname::parse(mechanism.as_bytes()).unwrap();
+            let config =
+                SASLConfig::with_credentials(None, "alice".to_string(), password.to_string())
+                    .unwrap();
+            let client = SASLClient::new(config)
+                .start_suggested(&[mechanism])
+                .unwrap();
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_callback(Callback)
+                .unwrap();
+            let server = SASLServer::<TestValidation>::new(config)
+                .start_suggested(mechanism)
+                .unwrap();
+            (XmppClient::new(client), XmppServer::new(server))
+        }
+
+        /// Run an exchange, passing the character data of the elements
+        fn run(mechanism: &str, password: &str) -> Result<String, Sasl> {
+            let (mut client, mut server) = start(mechanism, password);
+            let Sasl::Auth {
+                initial_response, ..
+            } = client.auth().unwrap()
+            else {
+                unreachable!()
+            };
+            let mut step = server.auth(initial_response.as_deref().map(marked));
+            loop {
+                match step {
+                    ServerStep::Challenge(data) => {
+                        let response = client.challenge(&data).unwrap();
+                        step = server.response(&response);
+                    }
+                    ServerStep::Success {
+                        validation,
+                        additional_data,
+                    } => {
+                        client
+                            .success(additional_data.as_deref().map(marked))
+                            .unwrap();
+                        return Ok(validation);
+                    }
+                    failure @ ServerStep::Failure { .. } => return Err(failure.to_sasl()),
+                }
+            }
+        }
+
+        #[test]
+        fn test_plain() {
+            assert_eq!(run("PLAIN", "secret").unwrap(), "alice");
+            assert_eq!(
+                run("PLAIN", "wrong").unwrap_err(),
+                Sasl::Failure {
+                    condition: Condition::NotAuthorized,
+                    text: None
+                }
+            );
+        }
+
+        #[cfg(feature = "scram-sha-2")]
+        #[test]
+        fn test_scram() {
+            assert_eq!(run("SCRAM-SHA-256", "secret").unwrap(), "alice");
+        }
+
+        #[test]
+        fn test_malformed() {
+            let (_, mut server) = start("PLAIN", "secret");
+            let step = server.auth(Some("not base64!"));
+            assert!(matches!(
+                step,
+                ServerStep::Failure {
+                    condition: Condition::IncorrectEncoding,
+                    error: Some(_),
+                }
+            ));
+
+            let (_, mut server) = start("PLAIN", "secret");
+            assert!(matches!(server.auth(None), ServerStep::Challenge(data) if data.is_empty()));
+        }
+    }
+}
@@ -32,6 +32,7 @@ use core::fmt;
 
 use crate::config::SASLConfig;
 use crate::error::SASLError;
+use crate::policy::{Capabilities, SecurityFlags, SecurityPolicy};
 pub use crate::session::Side;
 #[allow(unused_imports)]
 #[cfg(feature = "registry_static")]
@@ -62,8 +63,9 @@ pub struct Mechanism {
     pub(crate) first: Side,
 
     pub(crate) select: fn(bool) -> Option<Selection>,
-    #[allow(dead_code)]
     pub(crate) offer: fn(bool) -> bool,
+
+    pub(crate) capabilities: Capabilities,
 }
 
 #[cfg(feature = "unstable_custom_mechanism")]
@@ -90,11 +92,28 @@ impl Mechanism {
             first,
             select,
             offer,
+            capabilities: Capabilities::new(SecurityFlags::NONE),
         }
     }
+
+    /// Describe the capabilities of a custom mechanism
+    ///
+    /// These are checked against the [`SecurityPolicy`](crate::policy::SecurityPolicy) in use.
+    /// Mechanisms constructed with [`build`](Self::build) have no capabilities set.
+    #[must_use]
+    pub const fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
 }
 
 impl Mechanism {
+    #[must_use]
+    /// Structured description of what this mechanism provides and requires
+    pub const fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
     #[must_use]
     pub fn client(&self) -> Option<Result<Box<dyn Authentication>, SASLError>> {
         self.client.map(|f| f())
@@ -231,6 +250,7 @@ impl Registry {
         &self,
         cb: bool,
         offered: impl Iterator<Item = &'a Mechname>,
+        policy: SecurityPolicy,
         mut fold: impl FnMut(Option<&'static Mechanism>, &'static Mechanism) -> Ordering,
     ) -> Result<(Box<dyn Authentication>, &'static Mechanism), SASLError> {
         // This looks like a terrible double-allocation as Selection contains a `Box<dyn Selector>`,
@@ -241,15 +261,38 @@ impl Registry {
         let mut selectors: Vec<Selection> =
             self.get_mechanisms().filter_map(|m| m.select(cb)).collect();
 
+        // Whether the other party offered a mechanism using channel binding that we could use
+        let mut binding_offered = false;
         for o in offered {
+            binding_offered |= cb
+                && self.get_mechanisms().any(|m| {
+                    m.mechanism == o
+                        && m.client.is_some()
+                        && m.capabilities
+                            .security
+                            .contains(SecurityFlags::CHANNEL_BINDING)
+                });
             for s in &mut selectors {
                 s.select(o);
             }
         }
 
-        let (mut s, m) = selectors
+        let mut rejected = None;
+        let selected = selectors
             .into_iter()
             .filter_map(|mut s| s.done().map(|m| (s, m)))
+            .filter(
+                |(_, m)| match policy.check_selection(m, cb, binding_offered) {
+                    Ok(()) => true,
+                    Err(reason) => {
+                        rejected.get_or_insert(SASLError::PolicyViolation {
+                            mechanism: m.mechanism,
+                            reason,
+                        });
+                        false
+                    }
+                },
+            )
             .fold(None, |acc, (s, m)| {
                 let accmech = acc.as_ref().map(|(_, m)| *m);
                 match fold(accmech, m) {
@@ -261,8 +304,9 @@ impl Registry {
                     // `Less` means the second parameter (m) was preferable
                     Ordering::Less => Some((s,m))
                 }
-            })
-            .ok_or(SASLError::NoSharedMechanism)?;
+            });
+        let (mut s, m) =
+            selected.ok_or_else(|| rejected.unwrap_or(SASLError::NoSharedMechanism))?;
         s.finalize().map(|a| (a, m))
     }
 }
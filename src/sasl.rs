@@ -3,6 +3,7 @@ use crate::config::SASLConfig;
 use core::fmt::{Debug, Formatter};
 
 use crate::mechname::Mechname;
+use crate::policy::SecurityPolicy;
 
 use crate::validate::{NoValidation, Validation};
 
@@ -15,11 +16,13 @@ use crate::typed::Tagged;
 /// A SASL Client starts authentication using the
 pub struct SASLClient<CB = NoChannelBindings> {
     inner: Sasl<NoValidation, CB>,
+    policy: SecurityPolicy,
 }
 
 #[allow(dead_code)]
 pub struct SASLServer<V: Validation, CB = NoChannelBindings> {
     inner: Sasl<V, CB>,
+    policy: SecurityPolicy,
 }
 
 /// SASL Provider context
@@ -43,10 +46,12 @@ impl<V: Validation + Debug, CB: Debug> Debug for Sasl<V, CB> {
 mod provider {
     use super::{
         Arc, Mechname, NoChannelBindings, NoValidation, SASLClient, SASLConfig, SASLServer, Sasl,
-        Tagged, Validation,
+        SecurityPolicy, Tagged, Validation,
     };
-    use crate::channel_bindings::ChannelBindingCallback;
+    use crate::channel_bindings::{has_channel_bindings, ChannelBindingCallback};
     use crate::error::SASLError;
+    #[cfg(feature = "session_export")]
+    use crate::export::{ExportError, ExportKey, StateReader};
     use crate::registry::Mechanism;
     use crate::session::{Session, Side};
 
@@ -59,6 +64,7 @@ mod provider {
         pub fn new(config: Arc<SASLConfig>) -> Self {
             Self {
                 inner: Sasl::client(config),
+                policy: SecurityPolicy::new(),
             }
         }
     }
@@ -73,9 +79,19 @@ mod provider {
         pub fn with_cb(config: Arc<SASLConfig>, cb: CB) -> Self {
             Self {
                 inner: Sasl::with_cb(config, cb),
+                policy: SecurityPolicy::new(),
             }
         }
 
+        #[must_use]
+        /// Only select offered mechanisms allowed by `policy`
+        ///
+        /// See the [`policy`](crate::policy) module for details.
+        pub const fn with_policy(mut self, policy: SecurityPolicy) -> Self {
+            self.policy = policy;
+            self
+        }
+
         #[inline(always)]
         /// Starts a authentication exchange as a client
         ///
@@ -99,11 +115,15 @@ mod provider {
         /// Depending on the mechanism chosen this may need additional data from the application, e.g.
         /// an authcid, optional authzid and password for PLAIN. This data is provided by the
         /// application via callbacks.
+        ///
+        /// # Errors
+        /// Returns [`SASLError::PolicyViolation`] if all shared mechanisms were rejected by the
+        /// [`SecurityPolicy`] in use.
         pub fn start_suggested_iter<'a>(
             self,
             offered: impl IntoIterator<Item = &'a Mechname>,
         ) -> Result<Session<NoValidation, CB>, SASLError> {
-            self.inner.client_start_suggested(offered)
+            self.inner.client_start_suggested(offered, self.policy)
         }
     }
 
@@ -116,6 +136,7 @@ mod provider {
         pub fn new(config: Arc<SASLConfig>) -> Self {
             Self {
                 inner: Sasl::server(config),
+                policy: SecurityPolicy::new(),
             }
         }
     }
@@ -123,11 +144,27 @@ mod provider {
         pub fn with_cb(config: Arc<SASLConfig>, cb: CB) -> Self {
             Self {
                 inner: Sasl::with_cb(config, cb),
+                policy: SecurityPolicy::new(),
             }
         }
 
+        #[must_use]
+        /// Only advertise and accept mechanisms allowed by `policy`
+        ///
+        /// See the [`policy`](crate::policy) module for details.
+        pub const fn with_policy(mut self, policy: SecurityPolicy) -> Self {
+            self.policy = policy;
+            self
+        }
+
+        /// Returns the mechanisms a server should advertise to clients
+        ///
+        /// Mechanisms not allowed by the [`SecurityPolicy`] in use are not returned.
         pub fn get_available(&self) -> impl IntoIterator<Item = &Mechanism> {
-            self.inner.get_available()
+            let channel_bindings = self.inner.has_channel_bindings();
+            self.inner
+                .get_available()
+                .filter(move |mech| self.policy.check(mech, channel_bindings).is_ok())
         }
 
         /// Starts a authentication exchange as the server role
@@ -136,8 +173,48 @@ mod provider {
         /// authentication data provided by the user.
         ///
         /// See [`SessionCallback`](crate::callback::SessionCallback) on how to implement callbacks.
+        ///
+        /// # Errors
+        /// Returns [`SASLError::PolicyViolation`] if the selected mechanism is not allowed by the
+        /// [`SecurityPolicy`] in use.
         pub fn start_suggested(self, selected: &Mechname) -> Result<Session<V, CB>, SASLError> {
-            self.inner.server_start_suggested(selected)
+            self.inner.server_start_suggested(selected, self.policy)
+        }
+
+        #[cfg(feature = "session_export")]
+        /// Resume a session exported using [`Session::export`]
+        ///
+        /// *requires feature `session_export`*
+        ///
+        /// The session continues the exchange where the exported session left off; the next call
+        /// to `step` is passed the next message of the client. See the
+        /// [`export`](crate::export) module for details.
+        ///
+        /// # Errors
+        /// Returns [`ExportError::Invalid`] if `exported` was modified or not exported using
+        /// `key`, [`ExportError::Expired`] if it is older than the time-to-live of `key`, and
+        /// [`ExportError::Unavailable`] if the mechanism of the exported session is not
+        /// available or not allowed by the [`SecurityPolicy`] in use.
+        pub fn resume(
+            self,
+            key: &ExportKey,
+            exported: &[u8],
+        ) -> Result<Session<V, CB>, ExportError> {
+            let state = key.open(exported)?;
+            let mut reader = StateReader::new(&state);
+            let (Some(mechname), Some(external_ssf), Some(mechanism_state)) =
+                (reader.bytes(), reader.u32(), reader.bytes())
+            else {
+                return Err(ExportError::Invalid);
+            };
+            reader.finish().ok_or(ExportError::Invalid)?;
+            let mechname = Mechname::parse(mechname).map_err(|_| ExportError::Invalid)?;
+
+            let mut session = self
+                .start_suggested(mechname)
+                .map_err(ExportError::Unavailable)?;
+            session.import(external_ssf, mechanism_state)?;
+            Ok(session)
         }
     }
 
@@ -175,17 +252,30 @@ mod provider {
         fn client_start_suggested<'a>(
             self,
             offered: impl IntoIterator<Item = &'a Mechname>,
+            policy: SecurityPolicy,
         ) -> Result<Session<V, CB>, SASLError> {
-            let (mechanism, mechanism_desc) = self.config.select_mechanism(offered)?;
+            let (mechanism, mechanism_desc) =
+                self.config
+                    .select_mechanism(offered, self.has_channel_bindings(), policy)?;
             Ok(Session::new(self, Side::Client, mechanism, *mechanism_desc))
         }
 
-        fn server_start_suggested(self, selected: &Mechname) -> Result<Session<V, CB>, SASLError> {
+        fn server_start_suggested(
+            self,
+            selected: &Mechname,
+            policy: SecurityPolicy,
+        ) -> Result<Session<V, CB>, SASLError> {
             let config = self.config.clone();
             let mech = self
                 .get_available()
                 .find(|mech| mech.mechanism == selected)
                 .ok_or(SASLError::NoSharedMechanism)?;
+            policy
+                .check(mech, self.has_channel_bindings())
+                .map_err(|reason| SASLError::PolicyViolation {
+                    mechanism: mech.mechanism,
+                    reason,
+                })?;
             let auth = mech
                 .server(config.as_ref())
                 .ok_or(SASLError::NoSharedMechanism)??;
@@ -195,6 +285,11 @@ mod provider {
         pub fn get_available<'a>(&self) -> impl Iterator<Item = &'a Mechanism> {
             self.config.mech_list().filter(|mech| mech.server.is_some())
         }
+
+        /// Returns true if channel binding data is available for sessions started from this
+        fn has_channel_bindings(&self) -> bool {
+            self.config.get_callback().enable_channel_binding() || has_channel_bindings(&self.cb)
+        }
     }
 }
 
@@ -203,4 +298,132 @@ mod tests {
     use super::*;
 
     static_assertions::assert_impl_all!(Sasl: Send, Sync);
+
+    #[test]
+    #[cfg(all(
+        feature = "config_builder",
+        feature = "scram-sha-2",
+        feature = "plain",
+        feature = "anonymous"
+    ))]
+    fn test_server_policy() {
+        use crate::channel_bindings::ThisCb;
+        use crate::error::SASLError;
+        use crate::mechanisms::{anonymous, plain, scram};
+        use crate::policy::PolicyViolation;
+        use crate::registry::{Mechanism, Registry};
+        use crate::test::EmptyCallback;
+
+        static MECHANISMS: [Mechanism; 4] = [
+            scram::SCRAM_SHA256_PLUS,
+            scram::SCRAM_SHA256,
+            plain::PLAIN,
+            anonymous::ANONYMOUS,
+        ];
+        fn names<V: Validation, CB: crate::prelude::ChannelBindingCallback>(
+            server: &SASLServer<V, CB>,
+        ) -> Vec<&'static str> {
+            server
+                .get_available()
+                .into_iter()
+                .map(|mech| mech.mechanism.as_str())
+                .collect()
+        }
+
+        let config =
+            SASLConfig::new(EmptyCallback, Registry::with_mechanisms(&MECHANISMS)).unwrap();
+        let policy = SecurityPolicy::new()
+            .no_plaintext()
+            .no_anonymous()
+            .plus_only_with_channel_bindings();
+
+        let server = SASLServer::<NoValidation>::new(config.clone());
+        assert_eq!(
+            names(&server),
+            ["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256", "PLAIN", "ANONYMOUS"]
+        );
+
+        let server = SASLServer::<NoValidation>::new(config.clone()).with_policy(policy);
+        assert_eq!(names(&server), ["SCRAM-SHA-256"]);
+        match server.start_suggested(Mechname::parse(b"PLAIN").unwrap()) {
+            Err(SASLError::PolicyViolation { mechanism, reason }) => {
+                assert_eq!(mechanism.as_str(), "PLAIN");
+                assert_eq!(reason, PolicyViolation::Plaintext);
+            }
+            _ => panic!("PLAIN was not rejected"),
+        }
+
+        let server =
+            SASLServer::<NoValidation>::new(config.clone()).with_policy(policy.with_tls(true));
+        assert_eq!(names(&server), ["SCRAM-SHA-256", "PLAIN"]);
+        assert!(server
+            .start_suggested(Mechname::parse(b"PLAIN").unwrap())
+            .is_ok());
+
+        let cb = ThisCb::new("tls-exporter", b"exporter".to_vec().into_boxed_slice());
+        let server = SASLServer::<NoValidation, _>::with_cb(config, cb)
+            .with_policy(policy.require_mutual_auth());
+        assert_eq!(names(&server), ["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256"]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "config_builder", feature = "scram-sha-2", feature = "plain"))]
+    fn test_client_policy() {
+        use crate::channel_bindings::ThisCb;
+        use crate::error::SASLError;
+        use crate::mechanisms::{plain, scram};
+        use crate::policy::PolicyViolation;
+        use crate::registry::{Mechanism, Registry};
+        use crate::test::EmptyCallback;
+
+        static MECHANISMS: [Mechanism; 3] =
+            [scram::SCRAM_SHA256_PLUS, scram::SCRAM_SHA256, plain::PLAIN];
+        let config =
+            SASLConfig::new(EmptyCallback, Registry::with_mechanisms(&MECHANISMS)).unwrap();
+        let plain = Mechname::parse(b"PLAIN").unwrap();
+        let scram = Mechname::parse(b"SCRAM-SHA-256").unwrap();
+        let scram_plus = Mechname::parse(b"SCRAM-SHA-256-PLUS").unwrap();
+
+        let session = SASLClient::new(config.clone())
+            .with_policy(SecurityPolicy::new().require_mutual_auth())
+            .start_suggested(&[plain, scram])
+            .unwrap();
+        assert_eq!(session.get_mechname(), scram);
+
+        match SASLClient::new(config.clone())
+            .with_policy(SecurityPolicy::new().no_plaintext())
+            .start_suggested(&[plain])
+        {
+            Err(SASLError::PolicyViolation { mechanism, reason }) => {
+                assert_eq!(mechanism, plain);
+                assert_eq!(reason, PolicyViolation::Plaintext);
+            }
+            _ => panic!("PLAIN was not rejected"),
+        }
+
+        match SASLClient::new(config.clone())
+            .with_policy(SecurityPolicy::new().require_dictionary_resistance())
+            .start_suggested(&[plain, scram])
+        {
+            Err(SASLError::PolicyViolation { mechanism, reason }) => {
+                assert_eq!(mechanism, scram);
+                assert_eq!(reason, PolicyViolation::NoDictionaryResistance);
+            }
+            _ => panic!("SCRAM-SHA-256 was not rejected"),
+        }
+
+        let policy = SecurityPolicy::new().refuse_downgrade();
+        let session = SASLClient::new(config.clone())
+            .with_policy(policy)
+            .start_suggested(&[plain, scram, scram_plus])
+            .unwrap();
+        assert_eq!(session.get_mechname(), scram);
+
+        let cb = ThisCb::new("tls-exporter", b"exporter".to_vec().into_boxed_slice());
+        let session = SASLClient::with_cb(config, cb)
+            .with_policy(policy)
+            .start_suggested(&[plain, scram, scram_plus])
+            .unwrap();
+        assert_eq!(session.get_mechname(), scram_plus);
+    }
 }
@@ -1,6 +1,8 @@
 use crate::callback::{Action, CallbackError, ClosureCR, Request, Satisfy, SessionCallback};
 use crate::channel_bindings::ChannelBindingCallback;
 use crate::context::{build_context, Provider, ProviderExt, ThisProvider};
+#[cfg(any(feature = "provider", feature = "testutils", test))]
+use crate::deferred::{Pending, PropertyRequest, Resume, ValidationRequest};
 use crate::error::SessionError;
 use crate::property::{ChannelBindingName, ChannelBindings, Property};
 use crate::registry::Mechanism;
@@ -23,11 +25,18 @@ mod provider {
         SessionError, Side, State, Validate,
     };
     use crate::alloc::boxed::Box;
+    use crate::alloc::vec::Vec;
     use crate::channel_bindings::NoChannelBindings;
+    use crate::deferred::{Pending, PropertyRequest, Resume, ValidationRequest};
+    #[cfg(feature = "session_export")]
+    use crate::export::{ExportError, ExportKey, StateWriter};
     use crate::mechanism::Authentication;
     use crate::mechname::Mechname;
+    use crate::policy::Protection;
     use crate::sasl::Sasl;
+    use crate::typed::Tagged;
     use crate::validate::{NoValidation, Validation};
+    use core::fmt;
     use core2::io::Write;
 
     /// This represents a single authentication exchange
@@ -42,6 +51,7 @@ mod provider {
         side: Side,
         mechanism: Box<dyn Authentication>,
         mechanism_desc: Mechanism,
+        external_ssf: u32,
     }
 
     impl<V: Validation, C: ChannelBindingCallback> Session<V, C> {
@@ -56,6 +66,7 @@ mod provider {
                 side,
                 mechanism,
                 mechanism_desc,
+                external_ssf: 0,
             }
         }
 
@@ -143,6 +154,120 @@ mod provider {
             Ok(state)
         }
 
+        /// Perform one step of SASL authentication, handing out requests the callback deferred.
+        ///
+        /// *requires feature `provider`*
+        ///
+        /// This behaves like [`step`](Self::step), but if the user callback defers a request by
+        /// returning [`CallbackError::Deferred`](crate::callback::CallbackError::Deferred) or
+        /// [`ValidationError::Deferred`](crate::validate::ValidationError::Deferred) the mechanism
+        /// is suspended and [`Step::Suspended`] is returned instead of treating the request as
+        /// unanswered. The protocol implementation can then obtain the value in whichever way
+        /// suits it, e.g. on a thread pool or by messaging an actor, and continue the step by
+        /// calling [`resume`](Self::resume). Data is only written into `writer` once the step
+        /// completed.
+        ///
+        /// Using [`DeferAll`](crate::callback::DeferAll) as callback hands out every request
+        /// instead of invoking a callback inline.
+        ///
+        /// If a request is deferred while a mechanism that can not be suspended is in use, e.g.
+        /// `GSSAPI`, [`SessionError::NotResumable`] is returned.
+        pub fn step_resumable(
+            &mut self,
+            input: Option<&[u8]>,
+            writer: &mut impl Write,
+        ) -> Result<Step, SessionError> {
+            let input = input.map(<[u8]>::to_vec);
+            self.continue_step(input, Resume::default(), writer)
+        }
+
+        /// Continue a suspended step once the deferred request was answered
+        ///
+        /// *requires feature `provider`*
+        ///
+        /// `suspended` must be the value returned by the last call to
+        /// [`step_resumable`](Self::step_resumable) or `resume` on this session. A property request
+        /// that was not satisfied is treated as if the callback did not answer it, and a
+        /// validation that was not finalized leaves [`validation`](Self::validation) empty.
+        ///
+        /// Further requests may be deferred during the same step, in which case another
+        /// [`Step::Suspended`] is returned.
+        pub fn resume(
+            &mut self,
+            suspended: Suspended,
+            writer: &mut impl Write,
+        ) -> Result<Step, SessionError> {
+            let Suspended {
+                input,
+                mut resume,
+                pending,
+            } = suspended;
+            self.answer(&mut resume, pending);
+            self.continue_step(input, resume, writer)
+        }
+
+        fn continue_step(
+            &mut self,
+            input: Option<Vec<u8>>,
+            mut resume: Resume,
+            writer: &mut impl Write,
+        ) -> Result<Step, SessionError> {
+            match self.step_resuming(input.as_deref(), &mut resume, writer)? {
+                Ok(state) => Ok(Step::Done(state)),
+                Err(pending) => Ok(Step::Suspended(Suspended {
+                    input,
+                    resume,
+                    pending,
+                })),
+            }
+        }
+
+        /// Step the mechanism once, restoring its previous state if a request was deferred
+        fn step_resuming(
+            &mut self,
+            input: Option<&[u8]>,
+            resume: &mut Resume,
+            writer: &mut impl Write,
+        ) -> Result<Result<State, Pending>, SessionError> {
+            let snapshot = self.mechanism.snapshot();
+            resume.restart();
+            let mut buffer = Vec::new();
+            let state = {
+                let validate = Validate::new::<V>(&mut self.sasl.validation);
+                let mut mechanism_data = MechanismData::new(
+                    self.sasl.config.get_callback(),
+                    &self.sasl.cb,
+                    validate,
+                    self.mechanism_desc,
+                    self.side,
+                )
+                .resuming(resume);
+                self.mechanism.step(&mut mechanism_data, input, &mut buffer)
+            };
+
+            if let Some(pending) = resume.take_pending() {
+                self.mechanism =
+                    snapshot.ok_or(SessionError::NotResumable(self.mechanism_desc.mechanism))?;
+                return Ok(Err(pending));
+            }
+            let state = state?;
+            writer.write_all(&buffer)?;
+            Ok(Ok(state))
+        }
+
+        /// Store the answer to a deferred request for the retried step
+        fn answer(&mut self, resume: &mut Resume, pending: Pending) {
+            match pending {
+                Pending::Property(request) => resume.answered(request),
+                Pending::Validation(mut request) => {
+                    if let Some(outcome) = request.take_outcome::<V>() {
+                        self.sasl.validation = Tagged(Some(outcome));
+                    }
+                    resume.validated();
+                }
+            }
+        }
+
         /// Extract the [`Validation`] result of an authentication exchange
         ///
         /// This are useful to e.g. indicate success or failure of the authentication exchange and
@@ -165,6 +290,56 @@ mod provider {
             self.mechanism.has_security_layer()
         }
 
+        /// Returns the maximum size of a single protected message the other party accepts
+        ///
+        /// Each call to [`encode`](Self::encode) produces at most one message of this size,
+        /// protecting only as much of the input as fits. Returns `None` if no security layer is
+        /// installed or the mechanism did not negotiate a limit.
+        pub fn max_buffer_size(&self) -> Option<usize> {
+            self.mechanism.max_buffer_size()
+        }
+
+        /// Returns the maximum size of a single protected message this side accepts
+        ///
+        /// Messages received from the other party that are larger than this should be rejected.
+        /// Returns `None` if no security layer is installed or the mechanism did not negotiate a
+        /// limit.
+        pub fn max_receive_size(&self) -> Option<usize> {
+            self.mechanism.max_receive_size()
+        }
+
+        /// Returns the protection provided by the security layer negotiated by the mechanism
+        ///
+        /// This is only meaningful after the authentication has finished.
+        pub fn protection(&self) -> Protection {
+            self.mechanism.protection()
+        }
+
+        /// Set the security strength factor of an external security layer, e.g. TLS
+        ///
+        /// Protocol implementations should set this to the key strength in bits of the TLS
+        /// cipher in use, or `0` (the default) if the connection is not protected.
+        pub fn set_external_ssf(&mut self, ssf: u32) {
+            self.external_ssf = ssf;
+        }
+
+        /// Returns the security strength factor of the external security layer
+        ///
+        /// See [`set_external_ssf`](Self::set_external_ssf).
+        pub const fn external_ssf(&self) -> u32 {
+            self.external_ssf
+        }
+
+        /// Returns the effective security strength factor of the connection
+        ///
+        /// This is the stronger of the security layer negotiated by the mechanism (see
+        /// [`protection`](Self::protection)) and the external security layer (see
+        /// [`set_external_ssf`](Self::set_external_ssf)). Nesting layers does not add their
+        /// strengths. A protocol can compare this against the SSF an operation requires.
+        pub fn ssf(&self) -> u32 {
+            self.protection().ssf().max(self.external_ssf)
+        }
+
         /// Encode given data for an established SASL security layer
         ///
         /// This operation is also often called `wrap`. If a security layer has been established this
@@ -212,6 +387,91 @@ mod provider {
         }
     }
 
+    #[cfg(feature = "async")]
+    impl<V: Validation, C: ChannelBindingCallback> Session<V, C> {
+        /// Perform one step of SASL authentication, answering deferred requests asynchronously.
+        ///
+        /// *requires feature `async`*
+        ///
+        /// This behaves like [`step`](Self::step), but if the config was built with an
+        /// [`AsyncSessionCallback`](crate::callback::AsyncSessionCallback) requests that its
+        /// synchronous methods deferred are answered by awaiting its `callback_async` and
+        /// `validate_async` methods. The mechanism is suspended
+        /// while the future is pending and the step is resumed with the answer. Data is only
+        /// written into `writer` once the step completed.
+        ///
+        /// If a request is deferred while a mechanism that can not be suspended is in use, e.g.
+        /// `GSSAPI`, [`SessionError::NotResumable`] is returned.
+        pub async fn step_async(
+            &mut self,
+            input: Option<&[u8]>,
+            writer: &mut impl Write,
+        ) -> Result<State, SessionError> {
+            let config = self.sasl.config.clone();
+            let Some(callback) = config.get_async_callback() else {
+                return self.step(input, writer);
+            };
+            let session_data = SessionData::new(self.mechanism_desc, self.side);
+            let mut resume = Resume::default();
+            loop {
+                let mut pending = match self.step_resuming(input, &mut resume, writer)? {
+                    Ok(state) => return Ok(state),
+                    Err(pending) => pending,
+                };
+                match &mut pending {
+                    Pending::Property(request) => {
+                        callback.callback_async(&session_data, request).await?;
+                    }
+                    Pending::Validation(request) => {
+                        callback.validate_async(&session_data, request).await?;
+                    }
+                }
+                self.answer(&mut resume, pending);
+            }
+        }
+    }
+
+    #[cfg(feature = "session_export")]
+    impl<V: Validation, C: ChannelBindingCallback> Session<V, C> {
+        /// Export the state of this session to an authenticated and encrypted blob
+        ///
+        /// *requires feature `session_export`*
+        ///
+        /// The exchange can then be continued on another instance by passing the blob to
+        /// [`SASLServer::resume`](crate::prelude::SASLServer::resume). A session should be
+        /// exported in between two calls to `step`, and not be stepped further afterwards. See
+        /// the [`export`](crate::export) module for details.
+        ///
+        /// # Errors
+        /// Returns [`ExportError::NotSupported`] if the mechanism in use can not export its
+        /// state, e.g. `GSSAPI`.
+        pub fn export(&self, key: &ExportKey) -> Result<Vec<u8>, ExportError> {
+            let state = self
+                .mechanism
+                .export_state()
+                .ok_or(ExportError::NotSupported(self.mechanism_desc.mechanism))?;
+            let mut writer = StateWriter::default();
+            writer
+                .str(self.mechanism_desc.mechanism.as_str())
+                .u32(self.external_ssf)
+                .bytes(&state);
+            Ok(key.seal(&writer.finish()))
+        }
+
+        /// Restore the state of a freshly started session from an exported blob
+        pub(crate) fn import(
+            &mut self,
+            external_ssf: u32,
+            state: &[u8],
+        ) -> Result<(), ExportError> {
+            if !self.mechanism.import_state(state) {
+                return Err(ExportError::Invalid);
+            }
+            self.external_ssf = external_ssf;
+            Ok(())
+        }
+    }
+
     #[cfg(feature = "provider_base64")]
     impl<V: Validation, C: ChannelBindingCallback> Session<V, C> {
         /// Perform one step of SASL authentication, base64 encoded.
@@ -245,6 +505,54 @@ mod provider {
         }
     }
 
+    #[derive(Debug)]
+    #[allow(clippy::exhaustive_enums, clippy::large_enum_variant)]
+    /// Result of a step performed with [`Session::step_resumable`] or [`Session::resume`]
+    pub enum Step {
+        /// The step completed; see [`Session::step`] on how to handle the contained state
+        Done(State),
+        /// The step was suspended since the user callback deferred a request
+        Suspended(Suspended),
+    }
+
+    /// A step suspended on a request deferred by the user callback
+    ///
+    /// The deferred request is accessed with [`property_request`](Self::property_request) or
+    /// [`validation_request`](Self::validation_request). Once it was answered the step is
+    /// continued by passing this value to [`Session::resume`]. It can be sent to another thread
+    /// in the meantime.
+    pub struct Suspended {
+        input: Option<Vec<u8>>,
+        resume: Resume,
+        pending: Pending,
+    }
+
+    impl Suspended {
+        /// Returns the deferred property request, if the callback deferred a property
+        pub fn property_request(&mut self) -> Option<&mut PropertyRequest> {
+            match &mut self.pending {
+                Pending::Property(request) => Some(request),
+                Pending::Validation(_) => None,
+            }
+        }
+
+        /// Returns the deferred validation, if the callback deferred validating the exchange
+        pub fn validation_request(&mut self) -> Option<&mut ValidationRequest> {
+            match &mut self.pending {
+                Pending::Validation(request) => Some(request),
+                Pending::Property(_) => None,
+            }
+        }
+    }
+
+    impl fmt::Debug for Suspended {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Suspended")
+                .field("pending", &self.pending)
+                .finish_non_exhaustive()
+        }
+    }
+
     impl<'a, 'b> MechanismData<'a, 'b> {
         fn new(
             callback: &'a dyn SessionCallback,
@@ -258,8 +566,14 @@ mod provider {
                 chanbind_cb,
                 validator,
                 session_data: SessionData::new(mechanism_desc, side),
+                resume: None,
             }
         }
+
+        fn resuming(mut self, resume: &'a Resume) -> Self {
+            self.resume = Some(resume);
+            self
+        }
     }
 
     impl SessionData {
@@ -300,22 +614,52 @@ mod provider {
     }
 }
 #[cfg(any(feature = "provider", feature = "testutils", test))]
-pub use provider::Session;
+#[allow(unused_imports)]
+pub use provider::{Session, Step, Suspended};
 
 pub struct MechanismData<'a, 'b> {
     callback: &'a dyn SessionCallback,
     chanbind_cb: &'a dyn ChannelBindingCallback,
     validator: &'a mut Validate<'b>,
     session_data: SessionData,
+    #[cfg(any(feature = "provider", feature = "testutils", test))]
+    resume: Option<&'a Resume>,
 }
 
 impl MechanismData<'_, '_> {
     pub fn validate(&mut self, provider: &dyn Provider) -> Result<(), ValidationError> {
         let context = build_context(provider);
+        #[cfg(any(feature = "provider", feature = "testutils", test))]
+        if let Some(resume) = self.resume {
+            if resume.is_validated() {
+                return Ok(());
+            }
+            let result = self
+                .callback
+                .validate(&self.session_data, context, self.validator);
+            match result {
+                Ok(()) => resume.validated(),
+                Err(ValidationError::Deferred) => {
+                    let request = ValidationRequest::new(self.validator, context);
+                    resume.suspend(Pending::Validation(request));
+                }
+                Err(_) => {}
+            }
+            return result;
+        }
         self.callback
             .validate(&self.session_data, context, self.validator)
     }
 
+    /// Returns the outcome a previous call to `validate` finalized the validation with
+    #[cfg(all(
+        any(feature = "scram-sha-1", feature = "scram-sha-2"),
+        any(feature = "plain", feature = "login")
+    ))]
+    pub(crate) fn validation_outcome(&self) -> Option<&dyn core::any::Any> {
+        self.validator.outcome()
+    }
+
     fn callback(
         &self,
         provider: &dyn Provider,
@@ -328,6 +672,40 @@ impl MechanismData<'_, '_> {
         }
     }
 
+    /// Ask the callback to satisfy a request for `P`
+    ///
+    /// A deferred request is treated as not answered unless the session is stepped with
+    /// `step_resumable` or asynchronously, in which case the step is suspended.
+    #[cfg_attr(
+        not(any(feature = "provider", feature = "testutils", test)),
+        allow(clippy::extra_unused_type_parameters)
+    )]
+    fn satisfy<P: for<'p> Property<'p>>(
+        &self,
+        provider: &dyn Provider,
+        request: &mut Request<'_>,
+    ) -> Result<(), SessionError> {
+        #[cfg(any(feature = "provider", feature = "testutils", test))]
+        if let Some(resume) = self.resume {
+            if let Some(answered) = resume.answer::<P>() {
+                return answered.apply(request);
+            }
+            let result = self.callback(provider, request);
+            if matches!(
+                result,
+                Err(SessionError::CallbackError(CallbackError::Deferred))
+            ) {
+                let request = PropertyRequest::new::<P>(build_context(provider));
+                resume.suspend(Pending::Property(request));
+            }
+            return result;
+        }
+        match self.callback(provider, request) {
+            Err(SessionError::CallbackError(CallbackError::Deferred)) => Ok(()),
+            result => result,
+        }
+    }
+
     pub fn action<'a, T>(
         &self,
         provider: &dyn Provider,
@@ -336,8 +714,19 @@ impl MechanismData<'_, '_> {
     where
         T: Property<'a>,
     {
+        // An action already performed before the step was suspended is not repeated on resume
+        #[cfg(any(feature = "provider", feature = "testutils", test))]
+        if let Some(resume) = self.resume {
+            if !resume.act() {
+                return Ok(());
+            }
+        }
         let mut tagged = Tagged::<'_, Action<T>>(Some(value));
-        self.callback(provider, Request::new_action::<T>(&mut tagged))?;
+        match self.callback(provider, Request::new_action::<T>(&mut tagged)) {
+            // Actions can not be deferred, so handle them as if they were not handled at all
+            Ok(()) | Err(SessionError::CallbackError(CallbackError::Deferred)) => {}
+            Err(error) => return Err(error),
+        }
         if tagged.is_some() {
             Err(SessionError::CallbackError(CallbackError::NoCallback(
                 type_name::<T>(),
@@ -367,7 +756,7 @@ impl MechanismData<'_, '_> {
     {
         let mut closurecr = ClosureCR::<P, _, _>::wrap(closure);
         let mut tagged = Tagged::<'_, tags::RefMut<Satisfy<P>>>(&mut closurecr);
-        match self.callback(provider, Request::new_satisfy::<P>(&mut tagged)) {
+        match self.satisfy::<P>(provider, Request::new_satisfy::<P>(&mut tagged)) {
             // explicitly ignore a `NoValue` error since that one *is actually okay*
             Ok(()) | Err(SessionError::CallbackError(CallbackError::NoValue)) => Ok(()),
             Err(error) => Err(error),
@@ -502,4 +891,456 @@ mod tests {
     use super::*;
 
     static_assertions::assert_impl_all!(Session: Send, Sync);
+    static_assertions::assert_impl_all!(Suspended: Send);
+
+    #[cfg(feature = "config_builder")]
+    mod step_resumable {
+        use crate::callback::DeferAll;
+        use crate::channel_bindings::NoChannelBindings;
+        use crate::config::SASLConfig;
+        use crate::property::{AuthId, Password};
+        use crate::registry::Mechanism;
+        use crate::sasl::Sasl;
+        use crate::session::{MessageSent, Session, Side, State, Step};
+        use crate::test::fixture::TestValidation;
+        use crate::typed::Tagged;
+
+        fn server_session(mechanism: &Mechanism) -> Session<TestValidation> {
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_callback(DeferAll)
+                .unwrap();
+            let mech = mechanism.server(&config).unwrap().unwrap();
+            let sasl = Sasl {
+                config,
+                cb: NoChannelBindings,
+                validation: Tagged(None),
+            };
+            Session::new(sasl, Side::Server, mech, *mechanism)
+        }
+
+        /// Answer deferred requests until the step completes
+        fn drive(
+            session: &mut Session<TestValidation>,
+            mut step: Step,
+            out: &mut Vec<u8>,
+        ) -> State {
+            loop {
+                let mut suspended = match step {
+                    Step::Done(state) => return state,
+                    Step::Suspended(suspended) => suspended,
+                };
+                if let Some(request) = suspended.property_request() {
+                    request.satisfy::<Password, _>(b"secret".to_vec());
+                }
+                if let Some(request) = suspended.validation_request() {
+                    let authid = request.context().get_ref::<AuthId>().unwrap().to_string();
+                    request.finalize::<TestValidation>(authid);
+                }
+                step = session.resume(suspended, out).unwrap();
+            }
+        }
+
+        #[cfg(feature = "plain")]
+        #[test]
+        fn test_plain() {
+            use crate::mechanisms::plain::PLAIN;
+
+            let mut server = server_session(&PLAIN);
+            let mut out = Vec::new();
+            let step = server
+                .step_resumable(Some(b"\0alice\0secret"), &mut out)
+                .unwrap();
+            let Step::Suspended(mut suspended) = step else {
+                panic!("validation was not deferred");
+            };
+            assert!(suspended.property_request().is_none());
+            let request = suspended.validation_request().unwrap();
+            assert_eq!(request.context().get_ref::<AuthId>(), Some("alice"));
+            assert_eq!(
+                request.context().get_ref::<Password>(),
+                Some(&b"secret"[..])
+            );
+            request.finalize::<TestValidation>("alice".to_string());
+
+            let step = server.resume(suspended, &mut out).unwrap();
+            let state = drive(&mut server, step, &mut out);
+            assert_eq!(state, State::Finished(MessageSent::No));
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+        }
+
+        #[cfg(feature = "plain")]
+        #[test]
+        fn test_unanswered() {
+            use crate::mechanisms::plain::PLAIN;
+
+            let mut server = server_session(&PLAIN);
+            let mut out = Vec::new();
+            let mut step = server
+                .step_resumable(Some(b"\0alice\0secret"), &mut out)
+                .unwrap();
+            let state = loop {
+                match step {
+                    Step::Done(state) => break state,
+                    Step::Suspended(suspended) => {
+                        step = server.resume(suspended, &mut out).unwrap();
+                    }
+                }
+            };
+            assert_eq!(state, State::Finished(MessageSent::No));
+            assert_eq!(server.validation(), None);
+        }
+
+        #[cfg(feature = "scram-sha-2")]
+        #[test]
+        fn test_scram() {
+            use crate::mechanisms::scram::SCRAM_SHA256;
+
+            let mut server = server_session(&SCRAM_SHA256);
+            let client_config =
+                SASLConfig::with_credentials(None, "alice".to_string(), "secret".to_string())
+                    .unwrap();
+            let mut client = crate::test::client_session(client_config, &SCRAM_SHA256);
+
+            let mut client_msg = Vec::new();
+            assert!(client.step(None, &mut client_msg).unwrap().is_running());
+            let mut server_msg = Vec::new();
+            let step = server
+                .step_resumable(Some(&client_msg), &mut server_msg)
+                .unwrap();
+            assert!(matches!(step, Step::Suspended(_)));
+            assert!(server_msg.is_empty());
+            assert!(drive(&mut server, step, &mut server_msg).is_running());
+            assert!(server_msg.starts_with(b"r="));
+
+            client_msg.clear();
+            client.step(Some(&server_msg), &mut client_msg).unwrap();
+            server_msg.clear();
+            let step = server
+                .step_resumable(Some(&client_msg), &mut server_msg)
+                .unwrap();
+            assert!(drive(&mut server, step, &mut server_msg).is_finished());
+            assert!(server_msg.starts_with(b"v="));
+            let mut out = Vec::new();
+            assert!(client
+                .step(Some(&server_msg), &mut out)
+                .unwrap()
+                .is_finished());
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+        }
+    }
+
+    #[cfg(all(feature = "async", feature = "config_builder"))]
+    mod step_async {
+        use crate::callback::{
+            AsyncSessionCallback, CallbackFuture, Context, PropertyRequest, SessionCallback,
+            SessionData, ValidationRequest,
+        };
+        use crate::channel_bindings::NoChannelBindings;
+        use crate::config::SASLConfig;
+        use crate::error::SessionError;
+        use crate::property::{AuthId, Password};
+        use crate::registry::Mechanism;
+        use crate::sasl::Sasl;
+        use crate::session::{Session, Side, State};
+        use crate::test::fixture::TestValidation;
+        use crate::typed::Tagged;
+        use crate::validate::{Validate, ValidationError};
+        use std::sync::{Arc, Mutex};
+
+        fn server_session(
+            config: Arc<SASLConfig>,
+            mechanism: &Mechanism,
+        ) -> Session<TestValidation> {
+            let mech = mechanism.server(&config).unwrap().unwrap();
+            let sasl = Sasl {
+                config,
+                cb: NoChannelBindings,
+                validation: Tagged(None),
+            };
+            Session::new(sasl, Side::Server, mech, *mechanism)
+        }
+
+        #[derive(Default)]
+        struct Database {
+            requests: Mutex<Vec<&'static str>>,
+        }
+        impl AsyncSessionCallback for Database {
+            fn callback_async<'a>(
+                &'a self,
+                _session_data: &'a SessionData,
+                request: &'a mut PropertyRequest,
+            ) -> CallbackFuture<'a> {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    self.requests.lock().unwrap().push(request.property_name());
+                    #[cfg(feature = "scram-sha-2")]
+                    {
+                        use crate::mechanisms::scram::credentials::{
+                            ScramCredentials, ScramDigest,
+                        };
+                        use crate::mechanisms::scram::properties::ScramStoredPassword;
+                        if request.context().get_ref::<AuthId>() == Some("alice") {
+                            let credentials = ScramCredentials::derive(
+                                ScramDigest::Sha256,
+                                b"secret",
+                                b"salt",
+                                4096,
+                            );
+                            request.satisfy_with::<ScramStoredPassword, _>(move |request| {
+                                request.satisfy::<ScramStoredPassword>(
+                                    &credentials.as_stored_password(),
+                                )?;
+                                Ok(())
+                            });
+                        }
+                    }
+                    Ok(())
+                })
+            }
+
+            fn validate_async<'a>(
+                &'a self,
+                _session_data: &'a SessionData,
+                validate: &'a mut ValidationRequest,
+            ) -> CallbackFuture<'a, ValidationError> {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    let authid = validate.context().get_ref::<AuthId>().unwrap().to_string();
+                    validate.finalize::<TestValidation>(authid);
+                    Ok(())
+                })
+            }
+        }
+
+        #[cfg(feature = "scram-sha-2")]
+        #[tokio::test]
+        async fn test_scram() {
+            use crate::mechanisms::scram::SCRAM_SHA256;
+
+            struct Shared(Arc<Database>);
+            impl AsyncSessionCallback for Shared {
+                fn callback_async<'a>(
+                    &'a self,
+                    session_data: &'a SessionData,
+                    request: &'a mut PropertyRequest,
+                ) -> CallbackFuture<'a> {
+                    self.0.callback_async(session_data, request)
+                }
+
+                fn validate_async<'a>(
+                    &'a self,
+                    session_data: &'a SessionData,
+                    validate: &'a mut ValidationRequest,
+                ) -> CallbackFuture<'a, ValidationError> {
+                    self.0.validate_async(session_data, validate)
+                }
+            }
+
+            let database = Arc::new(Database::default());
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_async_callback(Shared(database.clone()))
+                .unwrap();
+            let mut server = server_session(config, &SCRAM_SHA256);
+            let client_config =
+                SASLConfig::with_credentials(None, "alice".to_string(), "secret".to_string())
+                    .unwrap();
+            let mut client = crate::test::client_session(client_config, &SCRAM_SHA256);
+
+            let mut client_msg = Vec::new();
+            assert!(client.step(None, &mut client_msg).unwrap().is_running());
+            let mut server_msg = Vec::new();
+            let state = server
+                .step_async(Some(&client_msg), &mut server_msg)
+                .await
+                .unwrap();
+            assert!(state.is_running());
+            assert!(server_msg.starts_with(b"r="));
+
+            client_msg.clear();
+            client.step(Some(&server_msg), &mut client_msg).unwrap();
+            server_msg.clear();
+            let state = server
+                .step_async(Some(&client_msg), &mut server_msg)
+                .await
+                .unwrap();
+            assert!(state.is_finished());
+            assert!(server_msg.starts_with(b"v="));
+            assert!(client
+                .step(Some(&server_msg), &mut Vec::new())
+                .unwrap()
+                .is_finished());
+
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+            let requests = database.requests.lock().unwrap().clone();
+            assert!(requests
+                .iter()
+                .any(|name| name.contains("ScramStoredPassword")));
+            assert!(!requests.iter().any(|name| name.ends_with("::Password")));
+        }
+
+        #[cfg(feature = "plain")]
+        #[tokio::test]
+        async fn test_deferred_validation() {
+            use crate::mechanisms::plain::PLAIN;
+
+            fn assert_send<T: Send>(value: T) -> T {
+                value
+            }
+
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_async_callback(Database::default())
+                .unwrap();
+
+            let mut server = server_session(config.clone(), &PLAIN);
+            let state = server.step(Some(b"\0alice\0secret"), &mut Vec::new());
+            assert!(matches!(
+                state,
+                Err(SessionError::ValidationError(ValidationError::Deferred))
+            ));
+
+            let mut server = server_session(config, &PLAIN);
+            let mut out = Vec::new();
+            let future = server.step_async(Some(b"\0alice\0secret"), &mut out);
+            let state = assert_send(future).await.unwrap();
+            assert_eq!(state, State::Finished(crate::session::MessageSent::No));
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+        }
+
+        #[cfg(feature = "plain")]
+        #[tokio::test]
+        async fn test_sync_callback() {
+            use crate::mechanisms::plain::PLAIN;
+
+            struct Sync;
+            impl SessionCallback for Sync {
+                fn validate(
+                    &self,
+                    _session_data: &SessionData,
+                    context: &Context,
+                    validate: &mut Validate<'_>,
+                ) -> Result<(), ValidationError> {
+                    let password = context.get_ref::<Password>().unwrap();
+                    validate
+                        .finalize::<TestValidation>(String::from_utf8(password.to_vec()).unwrap());
+                    Ok(())
+                }
+            }
+
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_async_callback(Sync)
+                .unwrap();
+            let mut server = server_session(config, &PLAIN);
+            let state = server
+                .step_async(Some(b"\0alice\0secret"), &mut Vec::new())
+                .await
+                .unwrap();
+            assert!(state.is_finished());
+            assert_eq!(server.validation().as_deref(), Some("secret"));
+        }
+
+        #[cfg(feature = "plain")]
+        #[tokio::test]
+        async fn test_no_repeated_callbacks() {
+            use crate::callback::{CallbackError, Request};
+            use crate::context::EmptyProvider;
+            use crate::mechanism::{Authentication, MechanismData};
+            use crate::mechanisms::plain::PLAIN;
+            use crate::property::Property;
+            use crate::session::MessageSent;
+            use core2::io::Write;
+
+            struct Notified;
+            impl Property<'_> for Notified {
+                type Value = str;
+            }
+
+            /// Performs an action and a validation before requesting a deferred property
+            #[derive(Clone)]
+            struct Replay;
+            impl Authentication for Replay {
+                fn step(
+                    &mut self,
+                    session: &mut MechanismData,
+                    _input: Option<&[u8]>,
+                    writer: &mut dyn Write,
+                ) -> Result<State, SessionError> {
+                    session.action::<Notified>(&EmptyProvider, "alice")?;
+                    session.validate(&EmptyProvider)?;
+                    session.need_with::<Password, _, _>(&EmptyProvider, |password| {
+                        writer.write_all(password)?;
+                        Ok(())
+                    })?;
+                    Ok(State::Finished(MessageSent::Yes))
+                }
+
+                fn snapshot(&self) -> Option<Box<dyn Authentication>> {
+                    Some(Box::new(self.clone()))
+                }
+            }
+
+            #[derive(Clone, Default)]
+            struct Counting {
+                actions: Arc<Mutex<usize>>,
+                validations: Arc<Mutex<usize>>,
+            }
+            impl AsyncSessionCallback for Counting {
+                fn callback(
+                    &self,
+                    _session_data: &SessionData,
+                    _context: &Context,
+                    request: &mut Request,
+                ) -> Result<(), SessionError> {
+                    if request.get_action::<Notified>().is_some() {
+                        *self.actions.lock().unwrap() += 1;
+                        return Ok(());
+                    }
+                    Err(CallbackError::Deferred.into())
+                }
+
+                fn callback_async<'a>(
+                    &'a self,
+                    _session_data: &'a SessionData,
+                    request: &'a mut PropertyRequest,
+                ) -> CallbackFuture<'a> {
+                    request.satisfy::<Password, _>(b"secret".to_vec());
+                    Box::pin(core::future::ready(Ok(())))
+                }
+
+                fn validate(
+                    &self,
+                    _session_data: &SessionData,
+                    _context: &Context,
+                    validate: &mut Validate<'_>,
+                ) -> Result<(), ValidationError> {
+                    *self.validations.lock().unwrap() += 1;
+                    validate.finalize::<TestValidation>(String::from("alice"));
+                    Ok(())
+                }
+            }
+
+            let counting = Counting::default();
+            let config = SASLConfig::builder()
+                .with_defaults()
+                .with_async_callback(counting.clone())
+                .unwrap();
+            let sasl = Sasl {
+                config,
+                cb: NoChannelBindings,
+                validation: Tagged(None),
+            };
+            let mut server: Session<TestValidation> =
+                Session::new(sasl, Side::Server, Box::new(Replay), PLAIN);
+            let mut out = Vec::new();
+            let state = server.step_async(None, &mut out).await.unwrap();
+            assert!(state.is_finished());
+            assert_eq!(out, b"secret");
+            assert_eq!(server.validation().as_deref(), Some("alice"));
+            assert_eq!(*counting.actions.lock().unwrap(), 1);
+            assert_eq!(*counting.validations.lock().unwrap(), 1);
+        }
+    }
 }
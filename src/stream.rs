@@ -0,0 +1,521 @@
+//! Transparent security layer framing for established sessions
+//!
+//! *requires feature `provider`*
+//!
+//! Once an authentication exchange installed a security layer (i.e.
+//! [`Session::has_security_layer`] returns `true`) all further data exchanged must be protected
+//! by it. RFC 4422 section 3.7 specifies how protected data is sent: each buffer produced by the
+//! security layer is prefixed by its length as four octet big-endian unsigned integer.
+//!
+//! [`SaslStream`] wraps an underlying stream and performs this framing, wrapping and unwrapping
+//! transparently. It implements [`std::io::Read`] and [`std::io::Write`] if the underlying stream
+//! does, and with the feature `tokio` enabled also `tokio::io::AsyncRead` and
+//! `tokio::io::AsyncWrite`.
+//!
+//! ```no_run
+//! # use std::io::{Read, Write};
+//! # use std::net::TcpStream;
+//! # use rsasl::prelude::{Session, SessionError};
+//! # use rsasl::stream::SaslStream;
+//! # fn example(session: Session, tcp: TcpStream) -> Result<(), SessionError> {
+//! // `session` has finished authenticating and negotiated a security layer.
+//! let mut stream = SaslStream::new(session, tcp)?;
+//! stream.write_all(b"a001 NOOP\r\n")?;
+//! stream.flush()?;
+//! let mut response = [0; 1024];
+//! let read = stream.read(&mut response)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Like [`std::io::BufWriter`] written data is only guaranteed to be sent to the underlying stream
+//! after the stream is flushed.
+
+use crate::channel_bindings::NoChannelBindings;
+use crate::error::SessionError;
+use crate::prelude::{ChannelBindingCallback, Session};
+use crate::validate::{NoValidation, Validation};
+use core::fmt;
+use core::ops::Range;
+use std::io;
+
+/// Size of the length prefix of every frame
+const LENGTH_PREFIX: usize = 4;
+
+/// Maximum size of a received frame if the mechanism did not negotiate one
+///
+/// This is the largest buffer size that can be negotiated by mechanisms using the three octet
+/// encoding, e.g. `GSSAPI`.
+pub const DEFAULT_MAX_RECEIVE_SIZE: usize = 0x00FF_FFFF;
+
+/// A stream protected by the security layer of an authenticated [`Session`]
+///
+/// See the [module documentation](self) for details.
+pub struct SaslStream<S, V: Validation = NoValidation, C = NoChannelBindings> {
+    inner: S,
+    session: Session<V, C>,
+    max_receive_size: usize,
+    /// Received, still protected frame including its length prefix
+    frame: Vec<u8>,
+    frame_filled: usize,
+    /// Unprotected data that was not yet returned to the reader
+    plain: Vec<u8>,
+    plain_pos: usize,
+    /// Protected frames that were not yet written to the underlying stream
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl<S, V: Validation, C: ChannelBindingCallback> SaslStream<S, V, C> {
+    /// Wrap the given stream using the security layer of `session`
+    ///
+    /// Returns <code>Err([SessionError::NoSecurityLayer])</code> if the session has not installed
+    /// a security layer. In that case the underlying stream should be used directly.
+    pub fn new(session: Session<V, C>, inner: S) -> Result<Self, SessionError> {
+        if !session.has_security_layer() {
+            return Err(SessionError::NoSecurityLayer);
+        }
+        let max_receive_size = session
+            .max_receive_size()
+            .unwrap_or(DEFAULT_MAX_RECEIVE_SIZE);
+        Ok(Self {
+            inner,
+            session,
+            max_receive_size,
+            frame: Vec::new(),
+            frame_filled: 0,
+            plain: Vec::new(),
+            plain_pos: 0,
+            pending: Vec::new(),
+            pending_pos: 0,
+        })
+    }
+
+    /// Set the maximum size of a frame accepted from the other party
+    ///
+    /// Frames with a larger length prefix are rejected with an error of kind
+    /// [`io::ErrorKind::InvalidData`]. Defaults to the size returned by
+    /// [`Session::max_receive_size`], or [`DEFAULT_MAX_RECEIVE_SIZE`] if the mechanism did not
+    /// negotiate one.
+    #[must_use]
+    pub const fn with_max_receive_size(mut self, max_receive_size: usize) -> Self {
+        self.max_receive_size = max_receive_size;
+        self
+    }
+
+    /// Returns a reference to the underlying stream
+    pub const fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying stream
+    ///
+    /// Reading from or writing to the underlying stream directly will corrupt the framing.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns a reference to the wrapped session
+    pub const fn session(&self) -> &Session<V, C> {
+        &self.session
+    }
+
+    /// Unwrap this stream, returning the session and the underlying stream
+    ///
+    /// Any buffered but not yet flushed or read data is lost.
+    pub fn into_inner(self) -> (Session<V, C>, S) {
+        (self.session, self.inner)
+    }
+
+    /// Copy already unprotected data into `buf`, returning the number of bytes copied
+    fn read_plain(&mut self, buf: &mut [u8]) -> usize {
+        let available = &self.plain[self.plain_pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.plain_pos += len;
+        len
+    }
+
+    fn has_plain(&self) -> bool {
+        self.plain_pos < self.plain.len()
+    }
+
+    /// The range of the frame buffer the next read from the underlying stream should fill
+    fn frame_target(&mut self) -> Range<usize> {
+        let needed = if self.frame_filled < LENGTH_PREFIX {
+            LENGTH_PREFIX
+        } else {
+            self.frame.len()
+        };
+        if self.frame.len() < needed {
+            self.frame.resize(needed, 0);
+        }
+        self.frame_filled..needed
+    }
+
+    /// Account for `read` bytes read into the target returned by `frame_target`
+    ///
+    /// Once a complete frame has been received it is unprotected into the plain buffer.
+    fn advance_frame(&mut self, read: usize) -> io::Result<()> {
+        if read == 0 {
+            return if self.frame_filled == 0 {
+                Ok(())
+            } else {
+                Err(io::ErrorKind::UnexpectedEof.into())
+            };
+        }
+        self.frame_filled += read;
+        if self.frame_filled == LENGTH_PREFIX && self.frame.len() == LENGTH_PREFIX {
+            let mut prefix = [0; LENGTH_PREFIX];
+            prefix.copy_from_slice(&self.frame[..LENGTH_PREFIX]);
+            let len = u32::from_be_bytes(prefix) as usize;
+            if len > self.max_receive_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "received frame exceeds the maximum receive size",
+                ));
+            }
+            self.frame.resize(LENGTH_PREFIX + len, 0);
+        }
+        if self.frame_filled == self.frame.len() && self.frame_filled >= LENGTH_PREFIX {
+            self.plain.clear();
+            self.plain_pos = 0;
+            // Empty frames carry no protected data.
+            if self.frame.len() > LENGTH_PREFIX {
+                self.session
+                    .decode(&self.frame[LENGTH_PREFIX..], &mut self.plain)
+                    .map_err(into_io_error)?;
+            }
+            self.frame.clear();
+            self.frame_filled = 0;
+        }
+        Ok(())
+    }
+
+    /// `true` if no partial frame has been received
+    const fn at_frame_boundary(&self) -> bool {
+        self.frame_filled == 0
+    }
+
+    /// Protect as much of `buf` as fits into a single frame and buffer it for writing
+    fn encode_frame(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.clear();
+        self.pending_pos = 0;
+        self.pending.extend_from_slice(&[0; LENGTH_PREFIX]);
+        let consumed = self
+            .session
+            .encode(buf, &mut self.pending)
+            .map_err(into_io_error)?;
+        let len = u32::try_from(self.pending.len() - LENGTH_PREFIX).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "protected frame is too large")
+        })?;
+        self.pending[..LENGTH_PREFIX].copy_from_slice(&len.to_be_bytes());
+        Ok(consumed)
+    }
+
+    fn pending(&self) -> &[u8] {
+        &self.pending[self.pending_pos..]
+    }
+}
+
+fn into_io_error(error: SessionError) -> io::Error {
+    match error {
+        SessionError::Io { source } => source,
+        error => io::Error::new(io::ErrorKind::InvalidData, error),
+    }
+}
+
+impl<S: io::Read, V: Validation, C: ChannelBindingCallback> io::Read for SaslStream<S, V, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while !self.has_plain() {
+            let target = self.frame_target();
+            let read = self.inner.read(&mut self.frame[target])?;
+            if read == 0 && self.at_frame_boundary() {
+                return Ok(0);
+            }
+            self.advance_frame(read)?;
+        }
+        Ok(self.read_plain(buf))
+    }
+}
+
+impl<S: io::Write, V: Validation, C: ChannelBindingCallback> SaslStream<S, V, C> {
+    fn write_pending(&mut self) -> io::Result<()> {
+        while !self.pending().is_empty() {
+            let written = self.inner.write(&self.pending[self.pending_pos..])?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.pending_pos += written;
+        }
+        Ok(())
+    }
+}
+
+impl<S: io::Write, V: Validation, C: ChannelBindingCallback> io::Write for SaslStream<S, V, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_pending()?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.encode_frame(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod tokio_impl {
+    use super::SaslStream;
+    use crate::prelude::ChannelBindingCallback;
+    use crate::validate::Validation;
+    use core::pin::Pin;
+    use core::task::{ready, Context, Poll};
+    use std::io;
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    impl<S, V, C> SaslStream<S, V, C>
+    where
+        S: AsyncWrite + Unpin,
+        V: Validation,
+        C: ChannelBindingCallback,
+    {
+        fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            while !self.pending().is_empty() {
+                let written = ready!(
+                    Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..])
+                )?;
+                if written == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.pending_pos += written;
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<S, V, C> AsyncRead for SaslStream<S, V, C>
+    where
+        S: AsyncRead + Unpin,
+        V: Validation,
+        C: ChannelBindingCallback,
+        Self: Unpin,
+    {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            while !this.has_plain() {
+                let target = this.frame_target();
+                let mut target = ReadBuf::new(&mut this.frame[target]);
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut target))?;
+                let read = target.filled().len();
+                if read == 0 && this.at_frame_boundary() {
+                    return Poll::Ready(Ok(()));
+                }
+                this.advance_frame(read)?;
+            }
+            let read = this.read_plain(buf.initialize_unfilled());
+            buf.advance(read);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<S, V, C> AsyncWrite for SaslStream<S, V, C>
+    where
+        S: AsyncWrite + Unpin,
+        V: Validation,
+        C: ChannelBindingCallback,
+        Self: Unpin,
+    {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_pending(cx))?;
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            Poll::Ready(this.encode_frame(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_pending(cx))?;
+            Pin::new(&mut this.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            ready!(this.poll_write_pending(cx))?;
+            Pin::new(&mut this.inner).poll_shutdown(cx)
+        }
+    }
+}
+
+impl<S: fmt::Debug, V: Validation, C: ChannelBindingCallback> fmt::Debug for SaslStream<S, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SaslStream")
+            .field("inner", &self.inner)
+            .field("mechanism", &self.session.get_mechname())
+            .field("max_receive_size", &self.max_receive_size)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mechanism::{Authentication, MechanismData};
+    use crate::policy::{Capabilities, SecurityFlags};
+    use crate::prelude::{Mechname, State};
+    use crate::registry::Mechanism;
+    use crate::sasl::Sasl;
+    use crate::session::Side;
+    use crate::test::{client_config, EmptyCallback};
+    use crate::typed::Tagged;
+    use std::io::{Cursor, Read, Write};
+
+    /// Security layer obfuscating data by XORing, protecting at most `.0` bytes per frame
+    struct Xor(usize);
+    impl Authentication for Xor {
+        fn step(
+            &mut self,
+            _session: &mut MechanismData,
+            _input: Option<&[u8]>,
+            _writer: &mut dyn core2::io::Write,
+        ) -> Result<State, SessionError> {
+            Err(SessionError::MechanismDone)
+        }
+
+        fn encode(
+            &mut self,
+            input: &[u8],
+            writer: &mut dyn core2::io::Write,
+        ) -> Result<usize, SessionError> {
+            let len = input.len().min(self.0);
+            let wrapped: Vec<u8> = input[..len].iter().map(|b| b ^ 0x55).collect();
+            writer.write_all(&wrapped)?;
+            Ok(len)
+        }
+
+        fn decode(
+            &mut self,
+            input: &[u8],
+            writer: &mut dyn core2::io::Write,
+        ) -> Result<usize, SessionError> {
+            let unwrapped: Vec<u8> = input.iter().map(|b| b ^ 0x55).collect();
+            writer.write_all(&unwrapped)?;
+            Ok(input.len())
+        }
+
+        fn has_security_layer(&self) -> bool {
+            true
+        }
+
+        fn max_buffer_size(&self) -> Option<usize> {
+            Some(self.0)
+        }
+
+        fn max_receive_size(&self) -> Option<usize> {
+            Some(self.0)
+        }
+    }
+
+    static XOR: Mechanism = Mechanism {
+        mechanism: Mechname::const_new(b"X-XOR"),
+        priority: 0,
+        client: None,
+        server: None,
+        first: Side::Client,
+        select: |_| None,
+        offer: |_| false,
+        capabilities: Capabilities::new(SecurityFlags::NONE),
+    };
+
+    fn session(max: usize) -> Session {
+        let sasl = Sasl {
+            config: client_config(EmptyCallback),
+            cb: NoChannelBindings,
+            validation: Tagged(None),
+        };
+        Session::new(sasl, Side::Client, Box::new(Xor(max)), XOR)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut stream = SaslStream::new(session(4), Vec::new()).unwrap();
+        stream.write_all(b"hello world").unwrap();
+        stream.flush().unwrap();
+        let (_, framed) = stream.into_inner();
+        // Three frames of at most four protected bytes each
+        assert_eq!(framed.len(), 3 * 4 + 11);
+        assert_eq!(&framed[..4], &[0, 0, 0, 4]);
+        assert_eq!(
+            &framed[4..8],
+            &[b'h' ^ 0x55, b'e' ^ 0x55, b'l' ^ 0x55, b'l' ^ 0x55]
+        );
+
+        let mut stream = SaslStream::new(session(4), Cursor::new(framed)).unwrap();
+        let mut plain = Vec::new();
+        stream.read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, b"hello world");
+    }
+
+    #[test]
+    fn test_empty_frame() {
+        let framed = vec![0, 0, 0, 0, 0, 0, 0, 1, b'a' ^ 0x55];
+        let mut stream = SaslStream::new(session(4), Cursor::new(framed)).unwrap();
+        let mut plain = Vec::new();
+        stream.read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, b"a");
+    }
+
+    #[test]
+    fn test_receive_limits() {
+        let framed = vec![0, 0, 0, 5, 1, 2, 3, 4, 5];
+        let mut stream = SaslStream::new(session(4), Cursor::new(framed.clone())).unwrap();
+        let error = stream.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut stream = SaslStream::new(session(4), Cursor::new(framed))
+            .unwrap()
+            .with_max_receive_size(5);
+        let mut plain = Vec::new();
+        stream.read_to_end(&mut plain).unwrap();
+        assert_eq!(plain, [1 ^ 0x55, 2 ^ 0x55, 3 ^ 0x55, 4 ^ 0x55, 5 ^ 0x55]);
+
+        let truncated = vec![0, 0, 0, 4, 1, 2];
+        let mut stream = SaslStream::new(session(4), Cursor::new(truncated)).unwrap();
+        let error = stream.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_roundtrip() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = SaslStream::new(session(4), client).unwrap();
+        let mut server = SaslStream::new(session(4), server).unwrap();
+        client.write_all(b"hello world").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut plain = Vec::new();
+        server.read_to_end(&mut plain).await.unwrap();
+        assert_eq!(plain, b"hello world");
+    }
+}
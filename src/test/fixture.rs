@@ -0,0 +1,55 @@
+//! Fixtures shared by the unit tests
+
+use crate::callback::{Context, Request, SessionCallback, SessionData};
+use crate::error::SessionError;
+use crate::property::{AuthId, Password};
+use crate::validate::{Validate, Validation, ValidationError};
+use std::string::{String, ToString};
+
+/// Validation yielding the authentication id of a successful exchange
+pub struct TestValidation;
+impl Validation for TestValidation {
+    type Value = String;
+}
+
+/// Server callback accepting any user with the password "secret"
+///
+/// The password is answered as [`Password`] and, if a SCRAM-SHA-2 mechanism is enabled, as
+/// `ScramStoredPassword` derived using the salt "salt" and 4096 iterations. An exchange is
+/// validated as [`TestValidation`] unless it provided a different password.
+pub struct Callback;
+impl SessionCallback for Callback {
+    fn callback(
+        &self,
+        _session_data: &SessionData,
+        _context: &Context,
+        request: &mut Request<'_>,
+    ) -> Result<(), SessionError> {
+        #[cfg(feature = "scram-sha-2")]
+        {
+            use crate::mechanisms::scram::credentials::{ScramCredentials, ScramDigest};
+            use crate::mechanisms::scram::properties::ScramStoredPassword;
+            let credentials =
+                ScramCredentials::derive(ScramDigest::Sha256, b"secret", b"salt", 4096);
+            request.satisfy::<ScramStoredPassword>(&credentials.as_stored_password())?;
+        }
+        request.satisfy::<Password>(b"secret")?;
+        Ok(())
+    }
+
+    fn validate(
+        &self,
+        _session_data: &SessionData,
+        context: &Context,
+        validate: &mut Validate<'_>,
+    ) -> Result<(), ValidationError> {
+        if context
+            .get_ref::<Password>()
+            .map_or(true, |password| password == b"secret")
+        {
+            let authid = context.get_ref::<AuthId>().unwrap().to_string();
+            validate.finalize::<TestValidation>(authid);
+        }
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@
 //! multi-step mechanism and does not rely on further input by an user.
 
 use crate::mechanism::{Authentication, MechanismData};
+use crate::policy::{Capabilities, SecurityFlags};
 use crate::prelude::*;
 use crate::registry::{Matches, Mechanism, Named, Side};
 use core2::io::Write;
@@ -117,6 +118,7 @@ pub static RSASLTEST_CF: Mechanism = Mechanism {
     first: Side::Client,
     select: |_| Some(Matches::<SelectCF>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(SecurityFlags::NONE),
 };
 
 struct SelectCF;
@@ -134,6 +136,7 @@ pub static RSASLTEST_SF: Mechanism = Mechanism {
 
     select: |_| Some(Matches::<Select>::name()),
     offer: |_| true,
+    capabilities: Capabilities::new(SecurityFlags::NONE),
 };
 
 struct Select;
@@ -4,6 +4,10 @@
 //! protocol implementations and user code
 
 mod config;
+// Not every fixture is used with every selection of features
+#[cfg(test)]
+#[allow(dead_code)]
+pub(crate) mod fixture;
 mod mechanism;
 
 use crate::channel_bindings::NoChannelBindings;
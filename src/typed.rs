@@ -5,15 +5,30 @@
 //! authentication mechanism needs to query information from both the user callback (e.g.
 //! username, password) and from the protocol implementation (e.g. channel binding data).
 
-use core::any::TypeId;
+use core::any::{Any, TypeId};
 use core::ops::Deref;
 use core::ops::DerefMut;
 
 pub mod tags {
+    use core::any::Any;
     use core::marker::PhantomData;
 
     pub trait Type<'a>: 'static + Sized {
         type Reified: 'a;
+
+        /// Returns the answer held by the reified value, if it is `'static`
+        ///
+        /// Only types reified as an `Option` that is filled in later need to override this.
+        #[cfg_attr(
+            not(all(
+                any(feature = "scram-sha-1", feature = "scram-sha-2"),
+                any(feature = "plain", feature = "login")
+            )),
+            allow(dead_code)
+        )]
+        fn outcome(_value: &Self::Reified) -> Option<&dyn Any> {
+            None
+        }
     }
 
     pub trait MaybeSizedType<'a>: 'static + Sized {
@@ -58,11 +73,23 @@ impl<'a, T: tags::Type<'a>> DerefMut for Tagged<'a, T> {
 /// behind [`Context`](crate::context::Context) and [`Request`](crate::callback::Request).
 pub trait Erased<'a>: 'a {
     fn tag_id(&self) -> TypeId;
+    #[cfg_attr(
+        not(all(
+            any(feature = "scram-sha-1", feature = "scram-sha-2"),
+            any(feature = "plain", feature = "login")
+        )),
+        allow(dead_code)
+    )]
+    fn outcome(&self) -> Option<&dyn Any>;
 }
 impl<'a, T: tags::Type<'a>> Erased<'a> for Tagged<'a, T> {
     fn tag_id(&self) -> TypeId {
         TypeId::of::<T>()
     }
+
+    fn outcome(&self) -> Option<&dyn Any> {
+        T::outcome(&self.0)
+    }
 }
 impl dyn Erased<'_> {
     #[inline]
@@ -47,7 +47,7 @@
 
 use crate::alloc::boxed::Box;
 use crate::typed::{tags, Erased, Tagged};
-use core::any::TypeId;
+use core::any::{Any, TypeId};
 use thiserror::Error;
 
 /// Marker trait to define the type returned by `validation`
@@ -109,6 +109,10 @@ pub trait Validation: 'static {
 }
 impl<V: Validation> tags::Type<'_> for V {
     type Reified = Option<V::Value>;
+
+    fn outcome(value: &Self::Reified) -> Option<&dyn Any> {
+        value.as_ref().map(|value| value as &dyn Any)
+    }
 }
 
 #[derive(Debug)]
@@ -140,6 +144,20 @@ impl Validate<'_> {
         self.0.tag_id() == TypeId::of::<T>()
     }
 
+    #[cfg(any(feature = "provider", feature = "testutils", test))]
+    pub(crate) fn validation_id(&self) -> TypeId {
+        self.0.tag_id()
+    }
+
+    /// Returns the outcome the validation was finalized with
+    #[cfg(all(
+        any(feature = "scram-sha-1", feature = "scram-sha-2"),
+        any(feature = "plain", feature = "login")
+    ))]
+    pub(crate) fn outcome(&self) -> Option<&dyn Any> {
+        self.0.outcome()
+    }
+
     /// Finalize the authentication exchange by providing a last value to the mechanism
     ///
     /// The requested value of a [`Validation`] depends on the protocol implementation. It's
@@ -168,6 +186,13 @@ impl Validate<'_> {
 pub enum ValidationError {
     #[error("A required property was not provided")]
     MissingRequiredProperty,
+    /// The callback defers validating the authentication exchange
+    ///
+    /// Callbacks that validate asynchronously return this error to have the validation passed to
+    /// `AsyncSessionCallback::validate_async`, or handed out by `Session::step_resumable`. If the
+    /// session is stepped with `step` this error is returned from the step.
+    #[error("callback deferred the validation")]
+    Deferred,
     #[error(transparent)]
     Boxed(Box<dyn std::error::Error + Send + Sync>),
 }